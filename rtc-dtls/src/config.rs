@@ -3,10 +3,12 @@ use crate::conn::{DEFAULT_REPLAY_PROTECTION_WINDOW, INITIAL_TICKER_INTERVAL};
 use crate::crypto::*;
 use crate::extension::extension_use_srtp::SrtpProtectionProfile;
 use crate::signature_hash_algorithm::{
-    parse_signature_schemes, SignatureHashAlgorithm, SignatureScheme,
+    parse_signature_schemes, HashAlgorithm, SignatureHashAlgorithm, SignatureScheme,
 };
 use log::warn;
+use rand::Rng;
 use shared::error::*;
+use shared::EcnCodepoint;
 use std::collections::HashMap;
 use std::fmt;
 use std::net::SocketAddr;
@@ -19,8 +21,10 @@ use std::time::Duration;
 pub struct ConfigBuilder {
     certificates: Vec<Certificate>,
     cipher_suites: Vec<CipherSuiteId>,
+    prefer_server_cipher_order: bool,
     signature_schemes: Vec<SignatureScheme>,
     srtp_protection_profiles: Vec<SrtpProtectionProfile>,
+    srtp_mki: Vec<u8>,
     client_auth: ClientAuthType,
     extended_master_secret: ExtendedMasterSecretType,
     flight_interval: Duration,
@@ -35,6 +39,16 @@ pub struct ConfigBuilder {
     server_name: String,
     mtu: usize,
     replay_protection_window: usize,
+    max_outgoing_packets: usize,
+    ecn: Option<EcnCodepoint>,
+    handshake_timeout: Duration,
+    max_half_open_handshakes: usize,
+    max_fragment_buffer_size: usize,
+    max_fragment_buffer_fragments: usize,
+    max_handshake_message_size: usize,
+    max_handshake_cache_size: usize,
+    #[cfg(feature = "dtls13")]
+    dtls13: bool,
 }
 
 impl Default for ConfigBuilder {
@@ -42,8 +56,10 @@ impl Default for ConfigBuilder {
         Self {
             certificates: vec![],
             cipher_suites: vec![],
+            prefer_server_cipher_order: false,
             signature_schemes: vec![],
             srtp_protection_profiles: vec![],
+            srtp_mki: vec![],
             client_auth: ClientAuthType::default(),
             extended_master_secret: ExtendedMasterSecretType::default(),
             flight_interval: Duration::default(),
@@ -58,6 +74,16 @@ impl Default for ConfigBuilder {
             server_name: String::default(),
             mtu: 0,
             replay_protection_window: 0,
+            max_outgoing_packets: 0,
+            ecn: None,
+            handshake_timeout: Duration::default(),
+            max_half_open_handshakes: 0,
+            max_fragment_buffer_size: 0,
+            max_fragment_buffer_fragments: 0,
+            max_handshake_message_size: 0,
+            max_handshake_cache_size: 0,
+            #[cfg(feature = "dtls13")]
+            dtls13: false,
         }
     }
 }
@@ -78,6 +104,14 @@ impl ConfigBuilder {
         self
     }
 
+    /// prefer_server_cipher_order controls how a server selects the cipher suite.
+    /// If true, the server picks the first of its own cipher_suites the client supports.
+    /// Otherwise it honors the client's order. (default is false)
+    pub fn with_prefer_server_cipher_order(mut self, prefer_server_cipher_order: bool) -> Self {
+        self.prefer_server_cipher_order = prefer_server_cipher_order;
+        self
+    }
+
     /// signature_schemes contains the signature and hash schemes that the peer requests to verify.
     pub fn with_signature_schemes(mut self, signature_schemes: Vec<SignatureScheme>) -> Self {
         self.signature_schemes = signature_schemes;
@@ -95,6 +129,14 @@ impl ConfigBuilder {
         self
     }
 
+    /// srtp_mki is the SRTP Master Key Identifier a client offers via use_srtp. Servers
+    /// always echo the MKI the client offered. It must be at most 255 bytes. (default is
+    /// empty, which means MKI is not used)
+    pub fn with_srtp_mki(mut self, srtp_mki: Vec<u8>) -> Self {
+        self.srtp_mki = srtp_mki;
+        self
+    }
+
     /// client_auth determines the server's policy for
     /// TLS Client Authentication. The default is NoClientCert.
     pub fn with_client_auth(mut self, client_auth: ClientAuthType) -> Self {
@@ -216,9 +258,94 @@ impl ConfigBuilder {
         self.replay_protection_window = replay_protection_window;
         self
     }
+
+    /// max_outgoing_packets is the number of outgoing packets a connection may
+    /// buffer before write returns ErrBufferFull, signaling the application to
+    /// drain pending transmits before writing more. (default is 1024)
+    pub fn with_max_outgoing_packets(mut self, max_outgoing_packets: usize) -> Self {
+        self.max_outgoing_packets = max_outgoing_packets;
+        self
+    }
+
+    /// ecn is the ECN codepoint set on outbound transmits of the connection.
+    /// Inbound codepoints are counted in the connection stats regardless. (default is None)
+    pub fn with_ecn(mut self, ecn: Option<EcnCodepoint>) -> Self {
+        self.ecn = ecn;
+        self
+    }
+
+    /// handshake_timeout is the overall time a handshake may take. If it has not
+    /// completed by then, the connection is torn down and handle_timeout returns
+    /// ErrHandshakeTimeout. (default is 30 seconds)
+    pub fn with_handshake_timeout(mut self, handshake_timeout: Duration) -> Self {
+        self.handshake_timeout = handshake_timeout;
+        self
+    }
+
+    /// max_half_open_handshakes is the number of handshakes an Endpoint may have in
+    /// progress at once. Packets from new remotes are dropped while the limit is reached,
+    /// bounding the state a flood of ClientHellos can allocate. Only the server
+    /// configuration of an Endpoint is consulted. (default is 1024)
+    pub fn with_max_half_open_handshakes(mut self, max_half_open_handshakes: usize) -> Self {
+        self.max_half_open_handshakes = max_half_open_handshakes;
+        self
+    }
+
+    /// max_fragment_buffer_size is the number of bytes of incomplete handshake messages
+    /// a connection buffers while waiting for the remaining fragments. Records that
+    /// would exceed it are silently discarded. (default is 2MB)
+    pub fn with_max_fragment_buffer_size(mut self, max_fragment_buffer_size: usize) -> Self {
+        self.max_fragment_buffer_size = max_fragment_buffer_size;
+        self
+    }
+
+    /// max_fragment_buffer_fragments is the number of handshake fragments a connection
+    /// buffers while waiting for the remaining fragments. Records that would exceed it are
+    /// silently discarded, so a peer can't pin memory with many tiny fragments. (default is 1024)
+    pub fn with_max_fragment_buffer_fragments(
+        mut self,
+        max_fragment_buffer_fragments: usize,
+    ) -> Self {
+        self.max_fragment_buffer_fragments = max_fragment_buffer_fragments;
+        self
+    }
+
+    /// max_handshake_message_size is the largest length a peer may declare for a handshake
+    /// message. Larger messages are rejected as decode errors before anything is allocated
+    /// for them. (default is 64KB, enough for a typical certificate chain)
+    pub fn with_max_handshake_message_size(mut self, max_handshake_message_size: usize) -> Self {
+        self.max_handshake_message_size = max_handshake_message_size;
+        self
+    }
+
+    /// max_handshake_cache_size is the number of bytes of handshake messages a connection
+    /// keeps to compute the Finished verify_data. Messages that would exceed it are dropped,
+    /// and the cache is cleared once the handshake completes. (default is 256KB)
+    pub fn with_max_handshake_cache_size(mut self, max_handshake_cache_size: usize) -> Self {
+        self.max_handshake_cache_size = max_handshake_cache_size;
+        self
+    }
+
+    /// dtls13 offers DTLS 1.3 in the supported_versions extension and accepts it from peers
+    /// that offer it. The DTLS 1.3 handshake is not implemented yet, so once 1.3 is negotiated
+    /// the handshake fails with ErrDtls13NotImplemented instead of falling back. Peers that
+    /// only offer DTLS 1.2 are unaffected. (default is false)
+    #[cfg(feature = "dtls13")]
+    pub fn with_dtls13(mut self, dtls13: bool) -> Self {
+        self.dtls13 = dtls13;
+        self
+    }
 }
 
 pub(crate) const DEFAULT_MTU: usize = 1228; // bytes
+pub(crate) const DEFAULT_MAX_OUTGOING_PACKETS: usize = 1024;
+pub(crate) const DEFAULT_HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(30);
+pub(crate) const DEFAULT_MAX_HALF_OPEN_HANDSHAKES: usize = 1024;
+pub(crate) const DEFAULT_MAX_FRAGMENT_BUFFER_SIZE: usize = 2_000_000; // bytes
+pub(crate) const DEFAULT_MAX_FRAGMENT_BUFFER_FRAGMENTS: usize = 1024;
+pub(crate) const DEFAULT_MAX_HANDSHAKE_MESSAGE_SIZE: usize = 64 * 1024; // bytes
+pub(crate) const DEFAULT_MAX_HANDSHAKE_CACHE_SIZE: usize = 256 * 1024; // bytes
+pub(crate) const COOKIE_SECRET_LENGTH: usize = 32;
 
 /// PSKCallback is called once we have the remote's psk_identity_hint.
 /// If the remote provided none it will be nil
@@ -264,11 +391,15 @@ impl ConfigBuilder {
             return Err(Error::ErrIdentityNoPsk);
         }
 
+        if self.srtp_mki.len() > u8::MAX as usize {
+            return Err(Error::ErrSrtpMkiTooLong);
+        }
+
         for cert in &self.certificates {
             match cert.private_key.kind {
                 CryptoPrivateKeyKind::Ed25519(_) => {}
                 CryptoPrivateKeyKind::Ecdsa256(_) => {}
-                _ => return Err(Error::ErrInvalidPrivateKey),
+                CryptoPrivateKeyKind::Rsa256(_) => {}
             }
         }
 
@@ -308,6 +439,48 @@ impl ConfigBuilder {
             self.replay_protection_window
         };
 
+        let max_outgoing_packets = if self.max_outgoing_packets == 0 {
+            DEFAULT_MAX_OUTGOING_PACKETS
+        } else {
+            self.max_outgoing_packets
+        };
+
+        let handshake_timeout = if self.handshake_timeout == Duration::from_secs(0) {
+            DEFAULT_HANDSHAKE_TIMEOUT
+        } else {
+            self.handshake_timeout
+        };
+
+        let max_half_open_handshakes = if self.max_half_open_handshakes == 0 {
+            DEFAULT_MAX_HALF_OPEN_HANDSHAKES
+        } else {
+            self.max_half_open_handshakes
+        };
+
+        let max_fragment_buffer_size = if self.max_fragment_buffer_size == 0 {
+            DEFAULT_MAX_FRAGMENT_BUFFER_SIZE
+        } else {
+            self.max_fragment_buffer_size
+        };
+
+        let max_fragment_buffer_fragments = if self.max_fragment_buffer_fragments == 0 {
+            DEFAULT_MAX_FRAGMENT_BUFFER_FRAGMENTS
+        } else {
+            self.max_fragment_buffer_fragments
+        };
+
+        let max_handshake_message_size = if self.max_handshake_message_size == 0 {
+            DEFAULT_MAX_HANDSHAKE_MESSAGE_SIZE
+        } else {
+            self.max_handshake_message_size
+        };
+
+        let max_handshake_cache_size = if self.max_handshake_cache_size == 0 {
+            DEFAULT_MAX_HANDSHAKE_CACHE_SIZE
+        } else {
+            self.max_handshake_cache_size
+        };
+
         let mut server_name = self.server_name.clone();
 
         // Use host from conn address when server_name is not provided
@@ -320,13 +493,29 @@ impl ConfigBuilder {
             }
         }
 
+        // The server verifies client certificates against client_cas
+        let client_cert_verifier = match self.client_auth {
+            ClientAuthType::RequireAndVerifyClientCert => Some(
+                rustls::server::AllowAnyAuthenticatedClient::new(self.client_cas.clone()).boxed(),
+            ),
+            ClientAuthType::VerifyClientCertIfGiven => Some(
+                rustls::server::AllowAnyAnonymousOrAuthenticatedClient::new(
+                    self.client_cas.clone(),
+                )
+                .boxed(),
+            ),
+            _ => None,
+        };
+
         Ok(HandshakeConfig {
             local_psk_callback: self.psk.take(),
             local_psk_identity_hint: self.psk_identity_hint.take(),
             local_cipher_suites,
+            prefer_server_cipher_order: self.prefer_server_cipher_order,
             local_signature_schemes,
             extended_master_secret: self.extended_master_secret,
             local_srtp_protection_profiles: self.srtp_protection_profiles,
+            local_srtp_mki: self.srtp_mki,
             server_name,
             client_auth: self.client_auth,
             local_certificates: self.certificates,
@@ -338,11 +527,21 @@ impl ConfigBuilder {
                 rustls::RootCertStore::empty(),
                 None,
             )),
-            client_cert_verifier: None,
+            client_cert_verifier,
             retransmit_interval,
             initial_epoch: 0,
             maximum_transmission_unit,
             replay_protection_window,
+            max_outgoing_packets,
+            ecn: self.ecn,
+            handshake_timeout,
+            max_half_open_handshakes,
+            max_fragment_buffer_size,
+            max_fragment_buffer_fragments,
+            max_handshake_message_size,
+            max_handshake_cache_size,
+            #[cfg(feature = "dtls13")]
+            dtls13: self.dtls13,
             ..Default::default()
         })
     }
@@ -356,9 +555,11 @@ pub struct HandshakeConfig {
     pub(crate) local_psk_callback: Option<PskCallback>,
     pub(crate) local_psk_identity_hint: Option<Vec<u8>>,
     pub(crate) local_cipher_suites: Vec<CipherSuiteId>, // Available CipherSuites
+    pub(crate) prefer_server_cipher_order: bool, // If we are a server should our cipher suite order win
     pub(crate) local_signature_schemes: Vec<SignatureHashAlgorithm>, // Available signature schemes
     pub(crate) extended_master_secret: ExtendedMasterSecretType, // Policy for the Extended Master Support extension
     pub(crate) local_srtp_protection_profiles: Vec<SrtpProtectionProfile>, // Available SRTPProtectionProfiles, if empty no SRTP support
+    pub(crate) local_srtp_mki: Vec<u8>, // srtp_mki offered by a client, if empty no MKI
     pub(crate) server_name: String,
     pub(crate) client_auth: ClientAuthType, // If we are a client should we request a client certificate
     pub(crate) local_certificates: Vec<Certificate>,
@@ -374,19 +575,36 @@ pub struct HandshakeConfig {
     pub(crate) maximum_transmission_unit: usize,
     pub(crate) maximum_retransmit_number: usize,
     pub(crate) replay_protection_window: usize,
+    pub(crate) max_outgoing_packets: usize,
+    pub(crate) ecn: Option<EcnCodepoint>,
+    pub(crate) handshake_timeout: std::time::Duration,
+    pub(crate) max_half_open_handshakes: usize,
+    pub(crate) max_fragment_buffer_size: usize,
+    pub(crate) max_fragment_buffer_fragments: usize,
+    pub(crate) max_handshake_message_size: usize,
+    pub(crate) max_handshake_cache_size: usize,
+    #[cfg(feature = "dtls13")]
+    pub(crate) dtls13: bool,
+    pub(crate) cookie_secret: Vec<u8>, // server secret for HelloVerifyRequest cookies
 }
 
 impl fmt::Debug for HandshakeConfig {
     fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
-        fmt.debug_struct("HandshakeConfig<T>")
+        let mut debug_struct = fmt.debug_struct("HandshakeConfig<T>");
+        debug_struct
             .field("local_psk_identity_hint", &self.local_psk_identity_hint)
             .field("local_cipher_suites", &self.local_cipher_suites)
+            .field(
+                "prefer_server_cipher_order",
+                &self.prefer_server_cipher_order,
+            )
             .field("local_signature_schemes", &self.local_signature_schemes)
             .field("extended_master_secret", &self.extended_master_secret)
             .field(
                 "local_srtp_protection_profiles",
                 &self.local_srtp_protection_profiles,
             )
+            .field("local_srtp_mki", &self.local_srtp_mki)
             .field("server_name", &self.server_name)
             .field("client_auth", &self.client_auth)
             .field("local_certificates", &self.local_certificates)
@@ -399,7 +617,23 @@ impl fmt::Debug for HandshakeConfig {
             .field("maximum_transmission_unit", &self.maximum_transmission_unit)
             .field("maximum_retransmit_number", &self.maximum_retransmit_number)
             .field("replay_protection_window", &self.replay_protection_window)
-            .finish()
+            .field("max_outgoing_packets", &self.max_outgoing_packets)
+            .field("ecn", &self.ecn)
+            .field("handshake_timeout", &self.handshake_timeout)
+            .field("max_half_open_handshakes", &self.max_half_open_handshakes)
+            .field("max_fragment_buffer_size", &self.max_fragment_buffer_size)
+            .field(
+                "max_fragment_buffer_fragments",
+                &self.max_fragment_buffer_fragments,
+            )
+            .field(
+                "max_handshake_message_size",
+                &self.max_handshake_message_size,
+            )
+            .field("max_handshake_cache_size", &self.max_handshake_cache_size);
+        #[cfg(feature = "dtls13")]
+        debug_struct.field("dtls13", &self.dtls13);
+        debug_struct.finish()
     }
 }
 
@@ -409,9 +643,11 @@ impl Default for HandshakeConfig {
             local_psk_callback: None,
             local_psk_identity_hint: None,
             local_cipher_suites: vec![],
+            prefer_server_cipher_order: false,
             local_signature_schemes: vec![],
             extended_master_secret: ExtendedMasterSecretType::Disable,
             local_srtp_protection_profiles: vec![],
+            local_srtp_mki: vec![],
             server_name: String::new(),
             client_auth: ClientAuthType::NoClientCert,
             local_certificates: vec![],
@@ -430,11 +666,67 @@ impl Default for HandshakeConfig {
             maximum_transmission_unit: DEFAULT_MTU,
             maximum_retransmit_number: 7,
             replay_protection_window: DEFAULT_REPLAY_PROTECTION_WINDOW,
+            max_outgoing_packets: DEFAULT_MAX_OUTGOING_PACKETS,
+            ecn: None,
+            handshake_timeout: DEFAULT_HANDSHAKE_TIMEOUT,
+            max_half_open_handshakes: DEFAULT_MAX_HALF_OPEN_HANDSHAKES,
+            max_fragment_buffer_size: DEFAULT_MAX_FRAGMENT_BUFFER_SIZE,
+            max_fragment_buffer_fragments: DEFAULT_MAX_FRAGMENT_BUFFER_FRAGMENTS,
+            max_handshake_message_size: DEFAULT_MAX_HANDSHAKE_MESSAGE_SIZE,
+            max_handshake_cache_size: DEFAULT_MAX_HANDSHAKE_CACHE_SIZE,
+            #[cfg(feature = "dtls13")]
+            dtls13: false,
+            cookie_secret: {
+                let mut cookie_secret = vec![0; COOKIE_SECRET_LENGTH];
+                rand::thread_rng().fill(cookie_secret.as_mut_slice());
+                cookie_secret
+            },
         }
     }
 }
 
 impl HandshakeConfig {
+    /// Returns the fingerprints of all local certificates, in the order they were configured.
+    ///
+    /// See [`certificate_fingerprint`] for the format and supported algorithms.
+    pub fn local_certificate_fingerprints(&self, algo: HashAlgorithm) -> Result<Vec<String>> {
+        self.local_certificates
+            .iter()
+            .map(|cert| certificate_fingerprint(cert, algo))
+            .collect()
+    }
+
+    /// Picks the certificate for server_name, unless its key can't sign with any of the
+    /// signature schemes the client advertised while another certificate's key can, e.g. an
+    /// RSA certificate for a client that only offers RSA schemes.
+    pub(crate) fn select_certificate(
+        &self,
+        server_name: &str,
+        remote_signature_schemes: &[SignatureHashAlgorithm],
+    ) -> Result<Certificate> {
+        let certificate = self.get_certificate(server_name)?;
+        if remote_signature_schemes.is_empty() {
+            return Ok(certificate);
+        }
+
+        let can_sign = |certificate: &Certificate| {
+            remote_signature_schemes.iter().any(|ss| {
+                self.local_signature_schemes.contains(ss)
+                    && ss.is_compatible(&certificate.private_key)
+            })
+        };
+        if can_sign(&certificate) {
+            return Ok(certificate);
+        }
+
+        Ok(self
+            .local_certificates
+            .iter()
+            .find(|certificate| can_sign(certificate))
+            .cloned()
+            .unwrap_or(certificate))
+    }
+
     pub(crate) fn get_certificate(&self, server_name: &str) -> Result<Certificate> {
         if self.local_certificates.is_empty() {
             return Err(Error::ErrNoCertificates);
@@ -1,9 +1,11 @@
 #[cfg(test)]
 mod conn_test;
+pub mod stats;
 
 use crate::alert::*;
 use crate::application_data::*;
 use crate::content::*;
+use crate::crypto::fingerprint_digest;
 use crate::curve::named_curve::NamedCurve;
 use crate::extension::extension_use_srtp::*;
 use crate::flight::flight0::*;
@@ -18,8 +20,10 @@ use crate::handshake::*;
 use crate::handshaker::*;
 use crate::record_layer::record_layer_header::*;
 use crate::record_layer::*;
+use crate::signature_hash_algorithm::HashAlgorithm;
 use crate::state::*;
-use std::collections::VecDeque;
+use stats::{DtlsConnCounters, DtlsConnStats};
+use std::collections::{HashMap, VecDeque};
 
 use shared::{error::*, replay_detector::*};
 
@@ -29,6 +33,7 @@ use log::*;
 use std::io::{BufReader, BufWriter};
 use std::sync::Arc;
 use std::time::{Duration, Instant};
+use subtle::ConstantTimeEq;
 
 pub(crate) const INITIAL_TICKER_INTERVAL: Duration = Duration::from_secs(1);
 pub(crate) const COOKIE_LENGTH: usize = 20;
@@ -36,6 +41,8 @@ pub(crate) const DEFAULT_NAMED_CURVE: NamedCurve = NamedCurve::X25519;
 pub(crate) const INBOUND_BUFFER_SIZE: usize = 8192;
 // Default replay protection window is specified by RFC 6347 Section 4.1.2.6
 pub(crate) const DEFAULT_REPLAY_PROTECTION_WINDOW: usize = 64;
+// How many times a record that can't be handled yet is queued before it is dropped
+pub(crate) const MAX_INCOMING_PACKET_ATTEMPTS: usize = 8;
 
 pub(crate) static INVALID_KEYING_LABELS: &[&str] = &[
     "client finished",
@@ -44,15 +51,27 @@ pub(crate) static INVALID_KEYING_LABELS: &[&str] = &[
     "key expansion",
 ];
 
+// Error isn't Clone, so a failure is kept as the alert or the message of its error
+pub(crate) fn failure_of(err: &Error) -> Error {
+    match err {
+        Error::DtlsAlert { level, description } => Error::DtlsAlert {
+            level: *level,
+            description: *description,
+        },
+        err => Error::Other(err.to_string()),
+    }
+}
+
 // Conn represents a DTLS connection
 pub struct DTLSConn {
     is_client: bool,
     maximum_transmission_unit: usize,
     pub(crate) maximum_retransmit_number: usize,
     replay_protection_window: usize,
+    max_outgoing_packets: usize,
     replay_detector: Vec<Box<dyn ReplayDetector>>,
     incoming_decrypted_packets: VecDeque<BytesMut>, // Decrypted Application Data or error, pull by calling `Read`
-    incoming_encrypted_packets: VecDeque<Vec<u8>>,
+    incoming_encrypted_packets: VecDeque<(Vec<u8>, usize)>, // records and how many times they were queued
     fragment_buffer: FragmentBuffer,
     pub(crate) cache: HandshakeCache, // caching of handshake messages for verifyData generation
     pub(crate) outgoing_packets: VecDeque<Packet>,
@@ -62,6 +81,8 @@ pub struct DTLSConn {
     pub(crate) state: State, // Internal state
 
     handshake_completed: bool,
+    // error that terminated the handshake, if it failed
+    handshake_failure: Option<Error>,
     connection_closed_by_user: bool,
     // closeLock              sync.Mutex
     closed: bool, //  *closer.Closer
@@ -78,6 +99,8 @@ pub struct DTLSConn {
     cancelHandshakeReader func()
     */
     pub(crate) current_handshake_state: HandshakeState,
+    // When the state machine first entered each state for the current flight
+    pub(crate) handshake_state_entered_at: HashMap<HandshakeState, Instant>,
     pub(crate) current_retransmit_timer: Option<Instant>,
     pub(crate) current_retransmit_count: usize,
 
@@ -86,6 +109,11 @@ pub struct DTLSConn {
     pub(crate) handshake_config: Arc<HandshakeConfig>,
     pub(crate) retransmit: bool,
     pub(crate) handshake_rx: Option<()>,
+
+    handshake_started_at: Instant,
+    pub(crate) last_received_at: Instant,
+    pub(crate) stats: DtlsConnStats,
+    counters: DtlsConnCounters,
 }
 
 impl DTLSConn {
@@ -124,21 +152,28 @@ impl DTLSConn {
             maximum_transmission_unit: handshake_config.maximum_transmission_unit,
             maximum_retransmit_number: handshake_config.maximum_retransmit_number,
             replay_protection_window: handshake_config.replay_protection_window,
+            max_outgoing_packets: handshake_config.max_outgoing_packets,
             replay_detector: vec![],
             incoming_decrypted_packets: VecDeque::new(),
             incoming_encrypted_packets: VecDeque::new(),
-            fragment_buffer: FragmentBuffer::new(),
+            fragment_buffer: FragmentBuffer::new(
+                handshake_config.max_fragment_buffer_size,
+                handshake_config.max_fragment_buffer_fragments,
+                handshake_config.max_handshake_message_size,
+            ),
             outgoing_packets: VecDeque::new(),
             outgoing_queued_packets: VecDeque::new(),
             outgoing_compacted_raw_packets: VecDeque::new(),
 
-            cache: HandshakeCache::new(),
+            cache: HandshakeCache::with_max_size(handshake_config.max_handshake_cache_size),
             state,
             handshake_completed: false,
+            handshake_failure: None,
             connection_closed_by_user: false,
             closed: false,
 
             current_handshake_state: initial_fsm_state,
+            handshake_state_entered_at: HashMap::from([(initial_fsm_state, Instant::now())]),
             current_retransmit_timer: None,
             current_retransmit_count: 0,
 
@@ -147,6 +182,11 @@ impl DTLSConn {
             handshake_config,
             retransmit: false,
             handshake_rx: None,
+
+            handshake_started_at: Instant::now(),
+            last_received_at: Instant::now(),
+            stats: DtlsConnStats::default(),
+            counters: DtlsConnCounters::default(),
         }
     }
 
@@ -159,6 +199,23 @@ impl DTLSConn {
         }
     }
 
+    // Peek returns the front decrypted packet without removing it, so it can be parsed in place.
+    // Call consume to drop it, or incoming_application_data to take ownership of it.
+    pub fn peek(&self) -> Option<&[u8]> {
+        if !self.is_handshake_completed() {
+            None
+        } else {
+            self.incoming_decrypted_packets.front().map(|pkt| &pkt[..])
+        }
+    }
+
+    // Consume drops the front decrypted packet returned by peek.
+    pub fn consume(&mut self) {
+        if self.is_handshake_completed() {
+            self.incoming_decrypted_packets.pop_front();
+        }
+    }
+
     pub fn outgoing_raw_packet(&mut self) -> Option<BytesMut> {
         if let Err(err) = self.handle_outgoing_packets() {
             warn!(
@@ -170,12 +227,29 @@ impl DTLSConn {
         self.outgoing_compacted_raw_packets.pop_front()
     }
 
-    // Write writes p to the DTLS connection
+    /// flush_outgoing marshals, fragments, sequences and encrypts all queued outgoing packets
+    /// and returns the datagrams ready to be sent, compacted up to the MTU. Unlike
+    /// outgoing_raw_packet, errors such as a sequence number overflow are returned to the caller.
+    pub fn flush_outgoing(&mut self) -> Result<Vec<Vec<u8>>> {
+        self.handle_outgoing_packets()?;
+        Ok(self
+            .outgoing_compacted_raw_packets
+            .drain(..)
+            .map(|raw| raw.to_vec())
+            .collect())
+    }
+
+    // Write writes p to the DTLS connection, or returns ErrBufferFull when more than
+    // max_outgoing_packets are still waiting to be drained by outgoing_raw_packet
     pub fn write(&mut self, p: &[u8]) -> Result<()> {
         if self.is_connection_closed() {
             return Err(Error::ErrConnClosed);
         }
 
+        if self.outgoing_packets_len() >= self.max_outgoing_packets {
+            return Err(Error::ErrBufferFull);
+        }
+
         let pkt = Packet {
             record: RecordLayer::new(
                 PROTOCOL_VERSION1_2,
@@ -185,7 +259,6 @@ impl DTLSConn {
                 }),
             ),
             should_encrypt: true,
-            reset_local_sequence_number: false,
         };
 
         if self.is_handshake_completed() {
@@ -197,13 +270,20 @@ impl DTLSConn {
         Ok(())
     }
 
+    // Number of outgoing packets buffered and not yet drained by outgoing_raw_packet
+    pub fn outgoing_packets_len(&self) -> usize {
+        self.outgoing_packets.len()
+            + self.outgoing_queued_packets.len()
+            + self.outgoing_compacted_raw_packets.len()
+    }
+
     // Close closes the connection.
     pub fn close(&mut self) {
         if !self.closed {
             self.closed = true;
 
-            // Discard error from notify() to return non-error on the first user call of Close()
-            // even if the underlying connection is already closed.
+            // notify() only queues the CloseNotify alert, the caller must drain it with
+            // outgoing_raw_packet() before dropping the connection.
             self.notify(AlertLevel::Warning, AlertDescription::CloseNotify);
         }
     }
@@ -214,8 +294,78 @@ impl DTLSConn {
         &self.state
     }
 
-    // selected_srtp_protection_profile returns the selected SRTPProtectionProfile
-    pub(crate) fn selected_srtp_protection_profile(&self) -> SrtpProtectionProfile {
+    /// verify_remote_fingerprint checks the certificate presented by the peer against the
+    /// fingerprint received via signaling (e.g. the SDP `a=fingerprint` attribute).
+    ///
+    /// `expected` is a colon-separated hex string and is matched case-insensitively. The digests
+    /// are compared in constant time. This check is independent of certificate chain
+    /// verification, so it also applies when `insecure_skip_verify` is set.
+    pub fn verify_remote_fingerprint(&self, algo: HashAlgorithm, expected: &str) -> Result<()> {
+        let peer_certificate = self
+            .state
+            .peer_certificates
+            .first()
+            .ok_or(Error::ErrNoCertificates)?;
+        let actual = fingerprint_digest(peer_certificate, algo)?;
+
+        let expected: Vec<u8> = match expected
+            .split(':')
+            .map(|x| {
+                if x.len() == 2 {
+                    u8::from_str_radix(x, 16).ok()
+                } else {
+                    None
+                }
+            })
+            .collect::<Option<Vec<u8>>>()
+        {
+            Some(expected) => expected,
+            None => return Err(Error::ErrFingerprintMismatch),
+        };
+
+        if bool::from(actual.ct_eq(&expected)) {
+            Ok(())
+        } else {
+            Err(Error::ErrFingerprintMismatch)
+        }
+    }
+
+    /// Get statistics of this connection
+    pub fn stats(&self) -> DtlsConnStats {
+        let mut stats = self.stats;
+        stats.set_epoch(self.state.local_epoch, self.state.remote_epoch);
+        stats.set_cipher_suite_id(self.state.cipher_suite.as_ref().map(|c| c.id()));
+        stats
+    }
+
+    /// Get the counters of the inbound records this connection dropped as duplicated, of a
+    /// future epoch or failing to decrypt
+    pub fn counters(&self) -> &DtlsConnCounters {
+        &self.counters
+    }
+
+    /// handshake_cache_len returns the number of handshake messages cached for the Finished
+    /// verify_data. The cache is cleared once the handshake completes.
+    pub fn handshake_cache_len(&self) -> usize {
+        self.cache.len()
+    }
+
+    /// handshake_cache_size returns the number of bytes of cached handshake messages.
+    pub fn handshake_cache_size(&self) -> usize {
+        self.cache.size()
+    }
+
+    /// local_srtp_protection_profiles returns the SRTP protection profiles offered by this
+    /// side of the connection, in order of preference.
+    pub fn local_srtp_protection_profiles(&self) -> &[SrtpProtectionProfile] {
+        &self.handshake_config.local_srtp_protection_profiles
+    }
+
+    /// selected_srtp_protection_profile returns the negotiated SRTP protection profile, or
+    /// SrtpProtectionProfile::Unsupported when none was negotiated (yet). A server selects it
+    /// as soon as it processes the ClientHello, before the handshake completes, while a client
+    /// learns it from the ServerHello.
+    pub fn selected_srtp_protection_profile(&self) -> SrtpProtectionProfile {
         self.state.srtp_protection_profile
     }
 
@@ -230,7 +380,6 @@ impl DTLSConn {
                 }),
             ),
             should_encrypt: self.is_handshake_completed(),
-            reset_local_sequence_number: false,
         }]);
     }
 
@@ -263,13 +412,15 @@ impl DTLSConn {
                     p.record.record_layer_header.epoch,
                     h.handshake_header.message_sequence
                 );
-                self.cache.push(
-                    handshake_raw[RECORD_LAYER_HEADER_SIZE..].to_vec(),
-                    p.record.record_layer_header.epoch,
-                    h.handshake_header.message_sequence,
-                    h.handshake_header.handshake_type,
-                    self.is_client,
-                );
+                if !self.is_handshake_completed() {
+                    self.cache.push(
+                        handshake_raw[RECORD_LAYER_HEADER_SIZE..].to_vec(),
+                        p.record.record_layer_header.epoch,
+                        h.handshake_header.message_sequence,
+                        h.handshake_header.handshake_type,
+                        self.is_client,
+                    );
+                }
 
                 let raw_handshake_packets = self.process_handshake_packet(&p, h)?;
                 raw_packets.extend_from_slice(&raw_handshake_packets);
@@ -326,6 +477,7 @@ impl DTLSConn {
 
         if p.should_encrypt {
             if let Some(cipher_suite) = &self.state.cipher_suite {
+                self.stats.add_bytes_encrypted(raw_packet.len());
                 raw_packet = cipher_suite.encrypt(&p.record.record_layer_header, &raw_packet)?;
             }
         }
@@ -340,6 +492,8 @@ impl DTLSConn {
 
         let epoch = p.record.record_layer_header.epoch as usize;
 
+        // Sequence numbers of a new epoch start at 0 [RFC6347 Section-4.1], and keep counting
+        // up across retransmissions so that none is ever reused within an epoch
         while self.state.local_sequence_number.len() <= epoch {
             self.state.local_sequence_number.push(0);
         }
@@ -375,6 +529,7 @@ impl DTLSConn {
             raw_packet.extend_from_slice(handshake_fragment);
             if p.should_encrypt {
                 if let Some(cipher_suite) = &self.state.cipher_suite {
+                    self.stats.add_bytes_encrypted(raw_packet.len());
                     raw_packet = cipher_suite.encrypt(&record_layer_header, &raw_packet)?;
                 }
             }
@@ -430,43 +585,133 @@ impl DTLSConn {
         Ok(fragmented_handshakes)
     }
 
+    /// reset_handshake discards the progress of an unfinished handshake, e.g. after it failed
+    /// with a recoverable error: the partially reassembled fragments, the cached handshake
+    /// messages and the negotiated parameters are dropped, and the FSM goes back to Preparing,
+    /// so that the next call to handshake() starts over with the first flight. Record sequence
+    /// numbers keep increasing. It does nothing once the handshake completed.
+    pub fn reset_handshake(&mut self) {
+        if self.is_handshake_completed() {
+            return;
+        }
+
+        self.clear_handshake_buffers();
+        self.incoming_encrypted_packets.clear();
+        self.replay_detector.clear();
+
+        self.state = State {
+            is_client: self.is_client,
+            remote_addr: self.state.remote_addr,
+            local_sequence_number: std::mem::take(&mut self.state.local_sequence_number),
+            ..Default::default()
+        };
+        self.current_flight = if self.is_client {
+            Box::new(Flight1 {}) as Box<dyn Flight>
+        } else {
+            Box::new(Flight0 {}) as Box<dyn Flight>
+        };
+        self.handshake_state_entered_at.clear();
+        self.set_handshake_state(HandshakeState::Preparing);
+        self.current_retransmit_timer = None;
+        self.current_retransmit_count = 0;
+        self.flights = None;
+        self.retransmit = false;
+        self.handshake_rx = None;
+        self.handshake_failure = None;
+        self.handshake_started_at = Instant::now();
+    }
+
+    /// Returns whether the connection is ready to carry application data, or why it never will
+    /// be, in a single status instead of several flags.
+    pub fn poll_handshake_complete(&self) -> HandshakeStatus {
+        if self.is_handshake_completed() {
+            HandshakeStatus::Complete
+        } else if let Some(err) = &self.handshake_failure {
+            HandshakeStatus::Failed(failure_of(err))
+        } else if self.current_handshake_state == HandshakeState::Preparing {
+            HandshakeStatus::NotStarted
+        } else {
+            HandshakeStatus::InProgress
+        }
+    }
+
+    /// Returns the current flight and state of the handshake and how long it has been in that
+    /// state, to diagnose a handshake that stalls.
+    pub fn handshake_progress(&self) -> HandshakeProgress {
+        let entered_at = self
+            .handshake_state_entered_at
+            .get(&self.current_handshake_state)
+            .copied()
+            .unwrap_or(self.handshake_started_at);
+        HandshakeProgress {
+            flight: self.current_flight.to_string(),
+            state: self.current_handshake_state,
+            time_in_state: entered_at.elapsed(),
+        }
+    }
+
+    // Keeps the first error of an unfinished handshake for poll_handshake_complete
+    pub(crate) fn record_handshake_failure(&mut self, err: &Error) {
+        if !self.is_handshake_completed() && self.handshake_failure.is_none() {
+            self.handshake_failure = Some(failure_of(err));
+        }
+    }
+
+    // Drops the partially reassembled fragments and the cached messages of the handshake
+    pub(crate) fn clear_handshake_buffers(&mut self) {
+        self.fragment_buffer = FragmentBuffer::new(
+            self.handshake_config.max_fragment_buffer_size,
+            self.handshake_config.max_fragment_buffer_fragments,
+            self.handshake_config.max_handshake_message_size,
+        );
+        self.cache.clear();
+    }
+
     pub(crate) fn set_handshake_completed(&mut self) {
         self.handshake_completed = true;
+        // The cached messages were only needed to compute and check the Finished verify_data
+        self.cache.clear();
+        self.stats
+            .set_handshake_duration(self.handshake_started_at.elapsed());
     }
 
     pub(crate) fn is_handshake_completed(&self) -> bool {
         self.handshake_completed
     }
 
-    pub fn read(&mut self, buf: &[u8]) -> Result<()> {
-        for pkt in unpack_datagram(buf)? {
-            let (hs, alert, err) = self.handle_incoming_packet(pkt, true);
-            if let Some(alert) = alert {
-                self.outgoing_packets.push_back(Packet {
-                    record: RecordLayer::new(
-                        PROTOCOL_VERSION1_2,
-                        self.state.local_epoch,
-                        Content::Alert(Alert {
-                            alert_level: alert.alert_level,
-                            alert_description: alert.alert_description,
-                        }),
-                    ),
-                    should_encrypt: self.is_handshake_completed(),
-                    reset_local_sequence_number: false,
-                });
+    // Whether the handshake failed for good, either with an error or after exhausting its
+    // retransmissions, so the connection will never carry application data
+    pub(crate) fn is_handshake_failed(&self) -> bool {
+        !self.is_handshake_completed()
+            && (self.handshake_failure.is_some()
+                || self.current_handshake_state == HandshakeState::Errored)
+    }
 
-                if alert.alert_level == AlertLevel::Fatal
-                    || alert.alert_description == AlertDescription::CloseNotify
-                {
-                    return Err(Error::ErrAlertFatalOrClose);
-                }
-            }
+    // Instant by which the handshake must complete, or None once it has completed
+    pub(crate) fn handshake_deadline(&self) -> Option<Instant> {
+        if self.handshake_completed {
+            None
+        } else {
+            Some(self.handshake_started_at + self.handshake_config.handshake_timeout)
+        }
+    }
 
-            if let Some(err) = err {
-                return Err(err);
-            }
+    /// Queues data as if it had been received and decrypted from the peer, so it is returned
+    /// by incoming_application_data once the handshake is completed.
+    #[cfg(any(test, feature = "test-utils"))]
+    pub fn push_decrypted_for_test(&mut self, data: BytesMut) {
+        self.incoming_decrypted_packets.push_back(data);
+    }
+
+    /// Marks the handshake as completed without a real peer.
+    #[cfg(any(test, feature = "test-utils"))]
+    pub fn set_handshake_completed_for_test(&mut self) {
+        self.set_handshake_completed();
+    }
 
-            if hs {
+    pub fn read(&mut self, buf: &[u8]) -> Result<()> {
+        for pkt in unpack_datagram(buf) {
+            if self.process_incoming_packet(pkt, 0, true)? {
                 self.handshake_rx = Some(());
             }
         }
@@ -474,43 +719,109 @@ impl DTLSConn {
         Ok(())
     }
 
-    pub(crate) fn handle_incoming_queued_packets(&mut self) -> Result<()> {
-        if self.is_handshake_completed() {
-            while let Some(p) = self.incoming_encrypted_packets.pop_front() {
-                let (_, alert, err) = self.handle_incoming_packet(p, false); // don't re-enqueue
-                if let Some(alert) = alert {
-                    self.outgoing_packets.push_back(Packet {
-                        record: RecordLayer::new(
-                            PROTOCOL_VERSION1_2,
-                            self.state.local_epoch,
-                            Content::Alert(Alert {
-                                alert_level: alert.alert_level,
-                                alert_description: alert.alert_description,
-                            }),
-                        ),
-                        should_encrypt: self.is_handshake_completed(),
-                        reset_local_sequence_number: false,
-                    });
-
-                    if alert.alert_level == AlertLevel::Fatal
-                        || alert.alert_description == AlertDescription::CloseNotify
-                    {
-                        return Err(Error::ErrAlertFatalOrClose);
-                    }
-                }
+    // Handles a record and queues the alert it triggers, if any.
+    // Returns whether it was a handshake record.
+    fn process_incoming_packet(
+        &mut self,
+        pkt: Vec<u8>,
+        attempts: usize,
+        enqueue: bool,
+    ) -> Result<bool> {
+        let (hs, alert, err) = self.handle_incoming_packet(pkt, attempts, enqueue);
+        if let Some(alert) = alert {
+            self.outgoing_packets.push_back(Packet {
+                record: RecordLayer::new(
+                    PROTOCOL_VERSION1_2,
+                    self.state.local_epoch,
+                    Content::Alert(Alert {
+                        alert_level: alert.alert_level,
+                        alert_description: alert.alert_description,
+                    }),
+                ),
+                should_encrypt: self.is_handshake_completed(),
+            });
+
+            if alert.alert_level == AlertLevel::Fatal
+                || alert.alert_description == AlertDescription::CloseNotify
+            {
+                let err = Self::alert_error(err);
+                self.record_handshake_failure(&err);
+                return Err(err);
+            }
+        }
 
-                if let Some(err) = err {
-                    return Err(err);
+        if let Some(err) = err {
+            self.record_handshake_failure(&err);
+            return Err(err);
+        }
+
+        Ok(hs)
+    }
+
+    // An alert received from the peer is reported as such, while one we are sending because of
+    // a local error is reported as ErrAlertFatalOrClose.
+    fn alert_error(err: Option<Error>) -> Error {
+        match err {
+            Some(err @ Error::DtlsAlert { .. }) => err,
+            _ => Error::ErrAlertFatalOrClose,
+        }
+    }
+
+    pub(crate) fn handle_incoming_queued_packets(&mut self) -> Result<()> {
+        if !self.is_handshake_completed() {
+            // Records queued before the handshake derived the keys, e.g. the peer's Finished
+            // sent in the same flight as its key exchange, can be handled as soon as the
+            // cipher suite is initialized instead of waiting for a retransmission
+            if !self.is_cipher_suite_initialized() || self.incoming_encrypted_packets.is_empty() {
+                return Ok(());
+            }
+            for (p, attempts) in std::mem::take(&mut self.incoming_encrypted_packets) {
+                if self.process_incoming_packet(p, attempts, true)? {
+                    self.handshake_rx = Some(());
                 }
             }
+            if self.handshake_rx.is_none() {
+                return Ok(());
+            }
+            self.handshake()?;
+            if !self.is_handshake_completed() {
+                return Ok(());
+            }
+        }
+
+        while let Some((p, attempts)) = self.incoming_encrypted_packets.pop_front() {
+            self.process_incoming_packet(p, attempts, false)?; // don't re-enqueue
         }
 
         Ok(())
     }
 
+    fn is_cipher_suite_initialized(&self) -> bool {
+        self.state
+            .cipher_suite
+            .as_ref()
+            .is_some_and(|cipher_suite| cipher_suite.is_initialized())
+    }
+
+    // Queues a record that can't be handled yet, unless it was already queued too many times,
+    // e.g. because the ChangeCipherSpec or the key exchange it depends on was lost.
+    fn enqueue_incoming_packet(&mut self, pkt: Vec<u8>, attempts: usize) {
+        if attempts >= MAX_INCOMING_PACKET_ATTEMPTS {
+            debug!(
+                "{}: discarded packet queued {} times",
+                srv_cli_str(self.is_client),
+                attempts
+            );
+            return;
+        }
+        self.incoming_encrypted_packets
+            .push_back((pkt, attempts + 1));
+    }
+
     fn handle_incoming_packet(
         &mut self,
         mut pkt: Vec<u8>,
+        attempts: usize,
         enqueue: bool,
     ) -> (bool, Option<Alert>, Option<Error>) {
         let mut reader = BufReader::new(pkt.as_slice());
@@ -538,6 +849,7 @@ impl DTLSConn {
                     h.epoch,
                     h.sequence_number,
                 );
+                self.counters.inc_future_epoch_packets(epoch);
                 return (false, None, None);
             }
             if enqueue {
@@ -545,11 +857,26 @@ impl DTLSConn {
                     "{}: received packet of next epoch, queuing packet",
                     srv_cli_str(self.is_client)
                 );
-                self.incoming_encrypted_packets.push_back(pkt);
+                self.enqueue_incoming_packet(pkt, attempts);
             }
             return (false, None, None);
         }
 
+        // Application data must not overtake the records queued before it during the
+        // handshake, so it is queued behind them and delivered in order once it completes
+        if enqueue
+            && h.content_type == ContentType::ApplicationData
+            && !self.is_handshake_completed()
+            && !self.incoming_encrypted_packets.is_empty()
+        {
+            debug!(
+                "{}: handshake not finished, queuing application data",
+                srv_cli_str(self.is_client)
+            );
+            self.enqueue_incoming_packet(pkt, attempts);
+            return (false, None, None);
+        }
+
         // Anti-replay protection
         while self.replay_detector.len() <= h.epoch as usize {
             self.replay_detector
@@ -567,6 +894,7 @@ impl DTLSConn {
                 h.epoch,
                 h.sequence_number,
             );
+            self.counters.inc_duplicate_packets(h.epoch);
             return (false, None, None);
         }
 
@@ -585,16 +913,20 @@ impl DTLSConn {
                         "{}: handshake not finished, queuing packet",
                         srv_cli_str(self.is_client)
                     );
-                    self.incoming_encrypted_packets.push_back(pkt);
+                    self.enqueue_incoming_packet(pkt, attempts);
                 }
                 return (false, None, None);
             }
 
             if let Some(cipher_suite) = &self.state.cipher_suite {
                 pkt = match cipher_suite.decrypt(&pkt) {
-                    Ok(pkt) => pkt,
+                    Ok(pkt) => {
+                        self.stats.add_bytes_decrypted(pkt.len());
+                        pkt
+                    }
                     Err(err) => {
                         debug!("{}: decrypt failed: {}", srv_cli_str(self.is_client), err);
+                        self.counters.inc_decrypt_failed_packets(h.epoch);
 
                         // If we get an error for PSK we need to return an error.
                         if cipher_suite.is_psk() {
@@ -632,7 +964,10 @@ impl DTLSConn {
             while let Ok((out, epoch)) = self.fragment_buffer.pop() {
                 //log::debug!("Extension Debug: out.len()={}", out.len());
                 let mut reader = BufReader::new(out.as_slice());
-                let raw_handshake = match Handshake::unmarshal(&mut reader) {
+                let raw_handshake = match Handshake::unmarshal_with_max_size(
+                    &mut reader,
+                    self.handshake_config.max_handshake_message_size,
+                ) {
                     Ok(rh) => {
                         debug!(
                             "Recv [handshake:{}] -> {} (epoch: {}, seq: {})",
@@ -653,13 +988,15 @@ impl DTLSConn {
                     }
                 };
 
-                self.cache.push(
-                    out,
-                    epoch,
-                    raw_handshake.handshake_header.message_sequence,
-                    raw_handshake.handshake_header.handshake_type,
-                    !self.is_client,
-                );
+                if !self.is_handshake_completed() {
+                    self.cache.push(
+                        out,
+                        epoch,
+                        raw_handshake.handshake_header.message_sequence,
+                        raw_handshake.handshake_header.handshake_type,
+                        !self.is_client,
+                    );
+                }
             }
 
             return (true, None, None);
@@ -681,21 +1018,24 @@ impl DTLSConn {
         };
 
         match r.content {
-            Content::Alert(mut a) => {
+            Content::Alert(a) => {
                 debug!("{}: <- {}", srv_cli_str(self.is_client), a.to_string());
-                if a.alert_description == AlertDescription::CloseNotify {
-                    // Respond with a close_notify [RFC5246 Section 7.2.1]
-                    a = Alert {
+                let err = Error::DtlsAlert {
+                    level: a.alert_level,
+                    description: a.alert_description,
+                };
+                // Respond with a close_notify [RFC5246 Section 7.2.1], other alerts are not
+                // answered so the peer keeps the reason it reported
+                let reply = if a.alert_description == AlertDescription::CloseNotify {
+                    Some(Alert {
                         alert_level: AlertLevel::Warning,
                         alert_description: AlertDescription::CloseNotify,
-                    };
-                }
+                    })
+                } else {
+                    None
+                };
                 self.replay_detector[h.epoch as usize].accept();
-                return (
-                    false,
-                    Some(a),
-                    Some(Error::Other(format!("Error of Alert {a}"))),
-                );
+                return (false, reply, Some(err));
             }
             Content::ChangeCipherSpec(_) => {
                 let invalid_cipher_suite = {
@@ -712,7 +1052,7 @@ impl DTLSConn {
                             "{}: CipherSuite not initialized, queuing packet",
                             srv_cli_str(self.is_client)
                         );
-                        self.incoming_encrypted_packets.push_back(pkt);
+                        self.enqueue_incoming_packet(pkt, attempts);
                     }
                     return (false, None, None);
                 }
@@ -779,6 +1119,19 @@ impl DTLSConn {
     pub(crate) fn get_local_epoch(&self) -> u16 {
         self.state.local_epoch
     }
+
+    // Number of records that can still be sent in epoch before the sequence number would
+    // overflow. RFC 6347 Section 4.1 requires the association to be abandoned or renegotiated
+    // before that happens, so applications can use this to trigger their own renegotiation.
+    pub fn sequence_number_remaining(&self, epoch: u16) -> u64 {
+        let next = self
+            .state
+            .local_sequence_number
+            .get(epoch as usize)
+            .copied()
+            .unwrap_or(0);
+        (MAX_SEQUENCE_NUMBER + 1).saturating_sub(next)
+    }
 }
 
 fn compact_raw_packets(raw_packets: &[Vec<u8>], maximum_transmission_unit: usize) -> Vec<BytesMut> {
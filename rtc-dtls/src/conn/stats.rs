@@ -0,0 +1,160 @@
+use crate::cipher_suite::CipherSuiteId;
+use shared::EcnCodepoint;
+use std::time::Duration;
+
+/// DTLS connection statistics
+#[derive(Default, Debug, Copy, Clone)]
+pub struct DtlsConnStats {
+    handshake_duration: Option<Duration>,
+    n_bytes_encrypted: u64,
+    n_bytes_decrypted: u64,
+    n_retransmits: u64,
+    n_ect0_packets: u64,
+    n_ect1_packets: u64,
+    n_ce_packets: u64,
+    local_epoch: u16,
+    remote_epoch: u16,
+    cipher_suite_id: Option<CipherSuiteId>,
+}
+
+impl DtlsConnStats {
+    pub(crate) fn set_handshake_duration(&mut self, duration: Duration) {
+        self.handshake_duration = Some(duration);
+    }
+
+    /// Time taken from the start of the handshake until it completed, or None if it has not
+    /// completed yet
+    pub fn get_handshake_duration(&self) -> Option<Duration> {
+        self.handshake_duration
+    }
+
+    pub(crate) fn add_bytes_encrypted(&mut self, n: usize) {
+        self.n_bytes_encrypted += n as u64;
+    }
+
+    pub fn get_num_bytes_encrypted(&self) -> u64 {
+        self.n_bytes_encrypted
+    }
+
+    pub(crate) fn add_bytes_decrypted(&mut self, n: usize) {
+        self.n_bytes_decrypted += n as u64;
+    }
+
+    pub fn get_num_bytes_decrypted(&self) -> u64 {
+        self.n_bytes_decrypted
+    }
+
+    pub(crate) fn inc_retransmits(&mut self) {
+        self.n_retransmits += 1;
+    }
+
+    /// Number of handshake flights retransmitted
+    pub fn get_num_retransmits(&self) -> u64 {
+        self.n_retransmits
+    }
+
+    pub(crate) fn inc_ecn(&mut self, ecn: EcnCodepoint) {
+        match ecn {
+            EcnCodepoint::Ect0 => self.n_ect0_packets += 1,
+            EcnCodepoint::Ect1 => self.n_ect1_packets += 1,
+            EcnCodepoint::Ce => self.n_ce_packets += 1,
+        }
+    }
+
+    /// Number of inbound packets marked ECT(0)
+    pub fn get_num_ect0_packets(&self) -> u64 {
+        self.n_ect0_packets
+    }
+
+    /// Number of inbound packets marked ECT(1)
+    pub fn get_num_ect1_packets(&self) -> u64 {
+        self.n_ect1_packets
+    }
+
+    /// Number of inbound packets marked CE (congestion experienced)
+    pub fn get_num_ce_packets(&self) -> u64 {
+        self.n_ce_packets
+    }
+
+    pub(crate) fn set_epoch(&mut self, local_epoch: u16, remote_epoch: u16) {
+        self.local_epoch = local_epoch;
+        self.remote_epoch = remote_epoch;
+    }
+
+    pub fn get_local_epoch(&self) -> u16 {
+        self.local_epoch
+    }
+
+    pub fn get_remote_epoch(&self) -> u16 {
+        self.remote_epoch
+    }
+
+    pub(crate) fn set_cipher_suite_id(&mut self, cipher_suite_id: Option<CipherSuiteId>) {
+        self.cipher_suite_id = cipher_suite_id;
+    }
+
+    /// Negotiated cipher suite, or None if a cipher suite hasn't been chosen
+    pub fn get_cipher_suite_id(&self) -> Option<CipherSuiteId> {
+        self.cipher_suite_id
+    }
+}
+
+/// Inbound records dropped by a DTLS connection in one epoch
+#[derive(Default, Debug, Copy, Clone, PartialEq, Eq)]
+pub struct DtlsEpochCounters {
+    /// Records rejected by the replay protection window
+    pub duplicate_packets: u64,
+    /// Records of an epoch later than the next one
+    pub future_epoch_packets: u64,
+    /// Records that failed to decrypt or authenticate
+    pub decrypt_failed_packets: u64,
+}
+
+/// Counters of the inbound records dropped by a DTLS connection, per epoch. Records of a
+/// future epoch are counted under the remote epoch they were received in.
+#[derive(Default, Debug, Clone, PartialEq, Eq)]
+pub struct DtlsConnCounters {
+    epochs: Vec<DtlsEpochCounters>,
+}
+
+impl DtlsConnCounters {
+    fn epoch_mut(&mut self, epoch: u16) -> &mut DtlsEpochCounters {
+        let epoch = epoch as usize;
+        if self.epochs.len() <= epoch {
+            self.epochs.resize(epoch + 1, DtlsEpochCounters::default());
+        }
+        &mut self.epochs[epoch]
+    }
+
+    pub(crate) fn inc_duplicate_packets(&mut self, epoch: u16) {
+        self.epoch_mut(epoch).duplicate_packets += 1;
+    }
+
+    pub(crate) fn inc_future_epoch_packets(&mut self, epoch: u16) {
+        self.epoch_mut(epoch).future_epoch_packets += 1;
+    }
+
+    pub(crate) fn inc_decrypt_failed_packets(&mut self, epoch: u16) {
+        self.epoch_mut(epoch).decrypt_failed_packets += 1;
+    }
+
+    /// Counters of the given epoch, all zero if nothing was dropped in it
+    pub fn get_epoch(&self, epoch: u16) -> DtlsEpochCounters {
+        self.epochs.get(epoch as usize).copied().unwrap_or_default()
+    }
+
+    /// Counters summed over all epochs
+    pub fn get_total(&self) -> DtlsEpochCounters {
+        self.epochs
+            .iter()
+            .fold(DtlsEpochCounters::default(), |total, counters| {
+                DtlsEpochCounters {
+                    duplicate_packets: total.duplicate_packets + counters.duplicate_packets,
+                    future_epoch_packets: total.future_epoch_packets
+                        + counters.future_epoch_packets,
+                    decrypt_failed_packets: total.decrypt_failed_packets
+                        + counters.decrypt_failed_packets,
+                }
+            })
+    }
+}
@@ -4,6 +4,7 @@ use x509_parser::pem::Pem;
 
 use super::crypto_ccm::*;
 use super::*;
+use crate::config::HandshakeConfig;
 use crate::content::ContentType;
 use crate::record_layer::record_layer_header::{ProtocolVersion, RECORD_LAYER_HEADER_SIZE};
 
@@ -37,6 +38,41 @@ JPhfPySIPG4UmwE4gW8t79vfOKxnUu2fDD1ZXUYopan6EckACNH/
 -----END RSA PRIVATE KEY-----
 ";
 
+// Self-signed certificate for RAW_PRIVATE_KEY, since rcgen can't generate RSA keys
+pub(crate) fn generate_rsa_certificate() -> Result<Certificate> {
+    let reader = Cursor::new(RAW_PRIVATE_KEY.as_bytes());
+    let pem = match Pem::read(reader) {
+        Ok((pem, _)) => pem,
+        Err(_) => return Err(Error::Other("Pem::read error".to_owned())),
+    };
+
+    // wrap the PKCS#1 RSAPrivateKey into a PKCS#8 PrivateKeyInfo
+    let der_len = |len: usize| vec![0x82, (len >> 8) as u8, len as u8];
+    let mut private_key = vec![0x04];
+    private_key.extend(der_len(pem.contents.len()));
+    private_key.extend(&pem.contents);
+    let mut info = vec![
+        0x02, 0x01, 0x00, // version
+        0x30, 0x0d, 0x06, 0x09, 0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x01, 0x01, 0x05,
+        0x00, // rsaEncryption
+    ];
+    info.extend(private_key);
+    let mut pkcs8 = vec![0x30];
+    pkcs8.extend(der_len(info.len()));
+    pkcs8.extend(info);
+
+    let key_pair = rcgen::KeyPair::from_der(&pkcs8)?;
+    let mut params = rcgen::CertificateParams::new(vec!["localhost".to_owned()]);
+    params.alg = &rcgen::PKCS_RSA_SHA256;
+    params.key_pair = Some(key_pair);
+    let cert = rcgen::Certificate::from_params(params)?;
+
+    Ok(Certificate {
+        certificate: vec![rustls::Certificate(cert.serialize_der()?)],
+        private_key: CryptoPrivateKey::try_from(cert.get_key_pair())?,
+    })
+}
+
 #[test]
 fn test_generate_key_signature() -> Result<()> {
     let reader = Cursor::new(RAW_PRIVATE_KEY.as_bytes());
@@ -219,3 +255,43 @@ fn test_certificate_verify() -> Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn test_certificate_fingerprint() -> Result<()> {
+    let mut certificate = Certificate::generate_self_signed(vec!["localhost".to_owned()])?;
+    certificate.certificate = vec![rustls::Certificate(b"abc".to_vec())];
+
+    assert_eq!(
+        certificate_fingerprint(&certificate, HashAlgorithm::Sha256)?,
+        "BA:78:16:BF:8F:01:CF:EA:41:41:40:DE:5D:AE:22:23:B0:03:61:A3:96:17:7A:9C:B4:10:FF:61:F2:00:15:AD"
+    );
+    assert_eq!(
+        certificate_fingerprint(&certificate, HashAlgorithm::Sha1)?,
+        "A9:99:3E:36:47:06:81:6A:BA:3E:25:71:78:50:C2:6C:9C:D0:D8:9D"
+    );
+    assert_eq!(
+        certificate_fingerprint(&certificate, HashAlgorithm::Md5),
+        Err(Error::ErrInvalidHashAlgorithm)
+    );
+
+    let other = Certificate::generate_self_signed(vec!["example.com".to_owned()])?;
+    let handshake_config = HandshakeConfig {
+        local_certificates: vec![certificate.clone(), other.clone()],
+        ..Default::default()
+    };
+    assert_eq!(
+        handshake_config.local_certificate_fingerprints(HashAlgorithm::Sha256)?,
+        vec![
+            certificate_fingerprint(&certificate, HashAlgorithm::Sha256)?,
+            certificate_fingerprint(&other, HashAlgorithm::Sha256)?,
+        ]
+    );
+
+    certificate.certificate.clear();
+    assert_eq!(
+        certificate_fingerprint(&certificate, HashAlgorithm::Sha256),
+        Err(Error::ErrNoCertificates)
+    );
+
+    Ok(())
+}
@@ -1,5 +1,5 @@
 #[cfg(test)]
-mod crypto_test;
+pub(crate) mod crypto_test;
 
 pub mod crypto_cbc;
 pub mod crypto_ccm;
@@ -116,6 +116,38 @@ impl Certificate {
     }
 }
 
+/// Computes the fingerprint of the leaf certificate of `cert` with the given hash algorithm,
+/// formatted as colon-separated uppercase hex pairs as used by the SDP `a=fingerprint`
+/// attribute (RFC 8122).
+///
+/// Only SHA-1 and the SHA-2 family are supported; other algorithms return
+/// [`Error::ErrInvalidHashAlgorithm`].
+pub fn certificate_fingerprint(cert: &Certificate, algo: HashAlgorithm) -> Result<String> {
+    let leaf = cert.certificate.first().ok_or(Error::ErrNoCertificates)?;
+    fingerprint(leaf.as_ref(), algo)
+}
+
+pub(crate) fn fingerprint(der: &[u8], algo: HashAlgorithm) -> Result<String> {
+    let digest = fingerprint_digest(der, algo)?;
+    let values: Vec<String> = digest.iter().map(|x| format!("{x:02X}")).collect();
+    Ok(values.join(":"))
+}
+
+pub(crate) fn fingerprint_digest(der: &[u8], algo: HashAlgorithm) -> Result<Vec<u8>> {
+    use sha2::Digest;
+
+    let digest = match algo {
+        HashAlgorithm::Sha1 => sha1::Sha1::digest(der).to_vec(),
+        HashAlgorithm::Sha224 => sha2::Sha224::digest(der).to_vec(),
+        HashAlgorithm::Sha256 => sha2::Sha256::digest(der).to_vec(),
+        HashAlgorithm::Sha384 => sha2::Sha384::digest(der).to_vec(),
+        HashAlgorithm::Sha512 => sha2::Sha512::digest(der).to_vec(),
+        _ => return Err(Error::ErrInvalidHashAlgorithm),
+    };
+
+    Ok(digest)
+}
+
 pub(crate) fn value_key_message(
     client_random: &[u8],
     server_random: &[u8],
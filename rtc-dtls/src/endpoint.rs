@@ -1,22 +1,75 @@
-use crate::conn::DTLSConn;
+use crate::conn::{
+    failure_of,
+    stats::{DtlsConnCounters, DtlsConnStats},
+    DTLSConn,
+};
 use shared::error::{Error, Result};
 use shared::{EcnCodepoint, TransportContext};
 use shared::{Protocol, Transmit};
 
 use crate::config::HandshakeConfig;
+use crate::content::ContentType;
+use crate::handshake::HandshakeType;
+use crate::handshaker::{HandshakeProgress, HandshakeStatus};
+use crate::record_layer::record_layer_header::RECORD_LAYER_HEADER_SIZE;
 use crate::state::State;
 use bytes::BytesMut;
+use log::debug;
 use std::collections::hash_map::Keys;
 use std::collections::{hash_map::Entry::Vacant, HashMap, VecDeque};
 use std::net::SocketAddr;
 use std::sync::Arc;
-use std::time::Instant;
+use std::time::{Duration, Instant};
+
+// Maximum number of failures kept by an Endpoint, so that a flood of failing handshakes
+// from spoofed addresses can't grow its memory within the failure TTL
+pub(crate) const MAX_FAILURES: usize = 1024;
 
 pub enum EndpointEvent {
     HandshakeComplete,
     ApplicationData(BytesMut),
 }
 
+/// Snapshot of a connection of an Endpoint, as returned by `Endpoint::connections`
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct ConnectionSummary {
+    /// Whether the handshake has completed
+    pub handshake_completed: bool,
+    /// Epoch used for outbound records
+    pub local_epoch: u16,
+    /// Epoch of the most recent inbound records
+    pub remote_epoch: u16,
+    /// Instant of the last datagram read from the remote, or of connection creation if
+    /// nothing has been read yet
+    pub last_received_at: Instant,
+}
+
+/// Datagrams to the same remote coalesced into one buffer, so they can be sent with a single
+/// UDP generic segmentation offload (GSO) write, as returned by `Endpoint::poll_transmit_batch`
+#[derive(Debug)]
+pub struct TransmitBatch {
+    pub now: Instant,
+    pub transport: TransportContext,
+    /// Size of every datagram in contents, except the last one which may be shorter
+    pub segment_size: usize,
+    /// The datagrams, back to back
+    pub contents: BytesMut,
+}
+
+impl TransmitBatch {
+    /// Iterate over the datagrams of the batch
+    pub fn segments(&self) -> impl Iterator<Item = &[u8]> {
+        self.contents.chunks(self.segment_size)
+    }
+}
+
+impl ConnectionSummary {
+    /// Time since the last datagram was read from the remote
+    pub fn idle_time(&self, now: Instant) -> Duration {
+        now.saturating_duration_since(self.last_received_at)
+    }
+}
+
 /// The main entry point to the library
 ///
 /// This object performs no I/O whatsoever. Instead, it generates a stream of packets to send via
@@ -28,6 +81,8 @@ pub struct Endpoint {
     transmits: VecDeque<Transmit<BytesMut>>,
     connections: HashMap<SocketAddr, DTLSConn>,
     server_config: Option<Arc<HandshakeConfig>>,
+    failure_ttl: Option<Duration>,
+    failures: HashMap<SocketAddr, (Instant, Error)>,
 }
 
 impl Endpoint {
@@ -45,6 +100,8 @@ impl Endpoint {
             transmits: VecDeque::new(),
             connections: HashMap::new(),
             server_config,
+            failure_ttl: None,
+            failures: HashMap::new(),
         }
     }
 
@@ -53,17 +110,122 @@ impl Endpoint {
         self.server_config = server_config;
     }
 
+    /// Keep the error that made the handshake with a remote fail, e.g. the alert it sent, for
+    /// failure_ttl, so that it can be queried with `last_failure`. It is disabled (None) by
+    /// default, and at most 1024 failures are kept, the oldest being dropped first.
+    pub fn set_failure_ttl(&mut self, failure_ttl: Option<Duration>) {
+        self.failure_ttl = failure_ttl;
+        if failure_ttl.is_none() {
+            self.failures.clear();
+        }
+    }
+
+    /// Get when and why the last handshake with remote failed, or None if it didn't fail
+    /// within the failure TTL, see `set_failure_ttl`. An alert is returned as
+    /// `Error::DtlsAlert`, any other error as `Error::Other` with its message.
+    pub fn last_failure(&self, remote: SocketAddr, now: Instant) -> Option<(Instant, Error)> {
+        let failure_ttl = self.failure_ttl?;
+        self.failures
+            .get(&remote)
+            .filter(|(failed_at, _)| now.saturating_duration_since(*failed_at) < failure_ttl)
+            .map(|(failed_at, err)| (*failed_at, failure_of(err)))
+    }
+
+    // Keeps the error of a failed handshake with remote when failure retention is enabled,
+    // dropping the failures older than the TTL, then the oldest one when MAX_FAILURES are kept
+    fn record_failure(&mut self, remote: SocketAddr, now: Instant, err: &Error) {
+        let Some(failure_ttl) = self.failure_ttl else {
+            return;
+        };
+        self.failures
+            .retain(|_, (failed_at, _)| now.saturating_duration_since(*failed_at) < failure_ttl);
+        if self.failures.len() >= MAX_FAILURES && !self.failures.contains_key(&remote) {
+            if let Some(oldest) = self
+                .failures
+                .iter()
+                .min_by_key(|(_, (failed_at, _))| *failed_at)
+                .map(|(addr, _)| *addr)
+            {
+                self.failures.remove(&oldest);
+            }
+        }
+        self.failures.insert(remote, (now, failure_of(err)));
+    }
+
     /// Get the next packet to transmit
     #[must_use]
     pub fn poll_transmit(&mut self) -> Option<Transmit<BytesMut>> {
         self.transmits.pop_front()
     }
 
+    /// Returns the number of bytes queued for remote that `poll_transmit` has not returned yet,
+    /// so the caller can apply backpressure before writing more.
+    pub fn pending_transmit_bytes(&self, remote: SocketAddr) -> usize {
+        self.transmits
+            .iter()
+            .filter(|transmit| transmit.transport.peer_addr == remote)
+            .map(|transmit| transmit.message.len())
+            .sum()
+    }
+
+    /// Get the next packets to transmit, coalescing up to max_segments consecutive datagrams
+    /// to the same remote with the same ECN codepoint into a single buffer. A batch ends at
+    /// the first datagram that is shorter than the ones before it, as GSO requires.
+    #[must_use]
+    pub fn poll_transmit_batch(&mut self, max_segments: usize) -> Option<TransmitBatch> {
+        let first = self.transmits.pop_front()?;
+        let segment_size = first.message.len();
+        let mut batch = TransmitBatch {
+            now: first.now,
+            transport: first.transport,
+            segment_size,
+            contents: first.message,
+        };
+
+        let mut segments = 1;
+        while segments < max_segments
+            && segment_size != 0
+            && batch.contents.len() % segment_size == 0
+        {
+            match self.transmits.pop_front() {
+                Some(next)
+                    if next.transport == batch.transport && next.message.len() <= segment_size =>
+                {
+                    batch.contents.extend_from_slice(&next.message);
+                    segments += 1;
+                }
+                Some(next) => {
+                    self.transmits.push_front(next);
+                    break;
+                }
+                None => break,
+            }
+        }
+
+        Some(batch)
+    }
+
     /// Get keys of Connections
     pub fn get_connections_keys(&self) -> Keys<'_, SocketAddr, DTLSConn> {
         self.connections.keys()
     }
 
+    /// Get a summary of every connection, so callers can e.g. reap idle connections
+    /// without looking each one up
+    pub fn connections(&self) -> impl Iterator<Item = (&SocketAddr, ConnectionSummary)> {
+        self.connections.iter().map(|(remote, conn)| {
+            (
+                remote,
+                ConnectionSummary {
+                    handshake_completed: conn.is_handshake_completed(),
+                    local_epoch: conn.state.local_epoch,
+                    remote_epoch: conn.state.remote_epoch,
+                    last_received_at: conn.last_received_at,
+                },
+            )
+        })
+    }
+
     /// Get Connection State
     pub fn get_connection_state(&self, remote: SocketAddr) -> Option<&State> {
         if let Some(conn) = self.connections.get(&remote) {
@@ -73,6 +235,65 @@ impl Endpoint {
         }
     }
 
+    /// Get whether the handshake with remote is completed, or None if there is no such connection
+    pub fn is_handshake_completed(&self, remote: SocketAddr) -> Option<bool> {
+        self.connections
+            .get(&remote)
+            .map(|conn| conn.is_handshake_completed())
+    }
+
+    /// Get the handshake status of the connection with remote, or None if there is no such
+    /// connection
+    pub fn handshake_status(&self, remote: SocketAddr) -> Option<HandshakeStatus> {
+        self.connections
+            .get(&remote)
+            .map(|conn| conn.poll_handshake_complete())
+    }
+
+    /// Get the progress of the handshake with remote, or None if there is no such connection
+    pub fn handshake_progress(&self, remote: SocketAddr) -> Option<HandshakeProgress> {
+        self.connections
+            .get(&remote)
+            .map(|conn| conn.handshake_progress())
+    }
+
+    /// Get the (local, remote) epoch of the connection with remote, or None if there is no such
+    /// connection
+    pub fn epoch(&self, remote: SocketAddr) -> Option<(u16, u16)> {
+        self.connections
+            .get(&remote)
+            .map(|conn| (conn.state.local_epoch, conn.state.remote_epoch))
+    }
+
+    /// Get statistics of the connection with remote, or None if there is no such connection
+    pub fn connection_stats(&self, remote: SocketAddr) -> Option<DtlsConnStats> {
+        self.connections.get(&remote).map(|conn| conn.stats())
+    }
+
+    /// Get the counters of the inbound records dropped by the connection with remote, or None
+    /// if there is no such connection
+    pub fn connection_counters(&self, remote: SocketAddr) -> Option<DtlsConnCounters> {
+        self.connections
+            .get(&remote)
+            .map(|conn| conn.counters().clone())
+    }
+
+    /// Get the number of connections whose handshake has not completed yet
+    pub fn half_open_handshakes(&self) -> usize {
+        self.connections
+            .values()
+            .filter(|conn| !conn.is_handshake_completed())
+            .count()
+    }
+
+    /// Get the number of records that can still be sent to remote in epoch before the sequence
+    /// number overflows, or None if there is no such connection
+    pub fn sequence_number_remaining(&self, remote: SocketAddr, epoch: u16) -> Option<u64> {
+        self.connections
+            .get(&remote)
+            .map(|conn| conn.sequence_number_remaining(epoch))
+    }
+
     /// Initiate an Association
     pub fn connect(
         &mut self,
@@ -94,7 +315,7 @@ impl Endpoint {
                     transport: TransportContext {
                         local_addr: self.local_addr,
                         peer_addr: remote,
-                        ecn: None,
+                        ecn: conn.handshake_config.ecn,
                         protocol: self.protocol,
                     },
                     message: payload,
@@ -117,7 +338,7 @@ impl Endpoint {
                     transport: TransportContext {
                         local_addr: self.local_addr,
                         peer_addr: remote,
-                        ecn: None,
+                        ecn: conn.handshake_config.ecn,
                         protocol: self.protocol,
                     },
                     message: payload,
@@ -127,6 +348,25 @@ impl Endpoint {
         self.connections.remove(&remote)
     }
 
+    /// Stop and remove every connection the remote has not sent a datagram on for longer
+    /// than max_idle, returning their addresses so the caller can clean up. A connection that
+    /// is still receiving traffic is never removed. Meant to be called on the same timer that
+    /// drives `handle_timeout`.
+    pub fn reap_idle(&mut self, now: Instant, max_idle: Duration) -> Vec<SocketAddr> {
+        let idle: Vec<SocketAddr> = self
+            .connections()
+            .filter(|(_, summary)| summary.idle_time(now) > max_idle)
+            .map(|(remote, _)| *remote)
+            .collect();
+
+        for remote in &idle {
+            debug!("reap connection with {} after idle timeout", remote);
+            self.stop(*remote);
+        }
+
+        idle
+    }
+
     /// Process close
     pub fn close(&mut self) -> Result<()> {
         for (remote_addr, conn) in self.connections.iter_mut() {
@@ -137,7 +377,7 @@ impl Endpoint {
                     transport: TransportContext {
                         local_addr: self.local_addr,
                         peer_addr: *remote_addr,
-                        ecn: None,
+                        ecn: conn.handshake_config.ecn,
                         protocol: self.protocol,
                     },
                     message: payload,
@@ -157,10 +397,39 @@ impl Endpoint {
         ecn: Option<EcnCodepoint>,
         data: BytesMut,
     ) -> Result<Vec<EndpointEvent>> {
+        // A peer retrying after a failed handshake starts over with a new ClientHello, which
+        // the failed connection would reject again, so it is replaced by a fresh one
+        if self.server_config.is_some()
+            && is_client_hello(&data)
+            && self
+                .connections
+                .get(&remote)
+                .is_some_and(|conn| !conn.state.is_client && conn.is_handshake_failed())
+        {
+            debug!(
+                "restart failed handshake with {} on a new ClientHello",
+                remote
+            );
+            self.connections.remove(&remote);
+        }
+
+        let half_open_handshakes = if self.connections.contains_key(&remote) {
+            0
+        } else {
+            self.half_open_handshakes()
+        };
         if let Vacant(e) = self.connections.entry(remote) {
             if let Some(server_config) = &self.server_config {
+                if half_open_handshakes >= server_config.max_half_open_handshakes {
+                    debug!(
+                        "drop packet from {}: {} handshakes already in progress",
+                        remote, half_open_handshakes
+                    );
+                    return Ok(vec![]);
+                }
                 let handshake_config = server_config.clone();
-                let conn = DTLSConn::new(handshake_config, false, None);
+                let mut conn = DTLSConn::new(handshake_config, false, None);
+                conn.state.remote_addr = Some(remote);
                 e.insert(conn);
             } else {
                 return Err(Error::NoServerConfig);
@@ -170,11 +439,36 @@ impl Endpoint {
         // Handle packet on existing association, if any
         let mut messages = vec![];
         if let Some(conn) = self.connections.get_mut(&remote) {
+            conn.last_received_at = now;
+            if let Some(ecn) = ecn {
+                conn.stats.inc_ecn(ecn);
+            }
             let is_handshake_completed_before = conn.is_handshake_completed();
-            conn.read(&data)?;
-            if !conn.is_handshake_completed() {
-                conn.handshake()?;
-                conn.handle_incoming_queued_packets()?;
+            let result = conn.read(&data).and_then(|_| {
+                if !conn.is_handshake_completed() {
+                    conn.handshake()?;
+                    conn.handle_incoming_queued_packets()?;
+                }
+                Ok(())
+            });
+            if let Err(err) = result {
+                // flush any fatal alert queued for the failure so the peer learns about it
+                while let Some(payload) = conn.outgoing_raw_packet() {
+                    self.transmits.push_back(Transmit {
+                        now,
+                        transport: TransportContext {
+                            local_addr: self.local_addr,
+                            peer_addr: remote,
+                            ecn: conn.handshake_config.ecn,
+                            protocol: self.protocol,
+                        },
+                        message: payload,
+                    });
+                }
+                if !conn.is_handshake_completed() {
+                    self.record_failure(remote, now, &err);
+                }
+                return Err(err);
             }
             if !is_handshake_completed_before && conn.is_handshake_completed() {
                 messages.push(EndpointEvent::HandshakeComplete)
@@ -188,7 +482,7 @@ impl Endpoint {
                     transport: TransportContext {
                         local_addr: self.local_addr,
                         peer_addr: remote,
-                        ecn,
+                        ecn: conn.handshake_config.ecn,
                         protocol: self.protocol,
                     },
                     message: payload,
@@ -208,7 +502,30 @@ impl Endpoint {
                     transport: TransportContext {
                         local_addr: self.local_addr,
                         peer_addr: remote,
-                        ecn: None,
+                        ecn: conn.handshake_config.ecn,
+                        protocol: self.protocol,
+                    },
+                    message: payload,
+                });
+            }
+            Ok(())
+        } else {
+            Err(Error::InvalidRemoteAddress(remote))
+        }
+    }
+
+    /// Retransmits the current handshake flight to remote immediately, see
+    /// [`DTLSConn::retransmit_now`].
+    pub fn retransmit_now(&mut self, remote: SocketAddr, now: Instant) -> Result<()> {
+        if let Some(conn) = self.connections.get_mut(&remote) {
+            conn.retransmit_now()?;
+            while let Some(payload) = conn.outgoing_raw_packet() {
+                self.transmits.push_back(Transmit {
+                    now,
+                    transport: TransportContext {
+                        local_addr: self.local_addr,
+                        peer_addr: remote,
+                        ecn: conn.handshake_config.ecn,
                         protocol: self.protocol,
                     },
                     message: payload,
@@ -220,14 +537,56 @@ impl Endpoint {
         }
     }
 
+    /// Restarts the unfinished handshake with remote from its first flight, see
+    /// [`DTLSConn::reset_handshake`].
+    pub fn reset_handshake(&mut self, remote: SocketAddr, now: Instant) -> Result<()> {
+        if let Some(conn) = self.connections.get_mut(&remote) {
+            if conn.is_handshake_completed() {
+                return Ok(());
+            }
+            conn.reset_handshake();
+            conn.handshake()?;
+            while let Some(payload) = conn.outgoing_raw_packet() {
+                self.transmits.push_back(Transmit {
+                    now,
+                    transport: TransportContext {
+                        local_addr: self.local_addr,
+                        peer_addr: remote,
+                        ecn: conn.handshake_config.ecn,
+                        protocol: self.protocol,
+                    },
+                    message: payload,
+                });
+            }
+            Ok(())
+        } else {
+            Err(Error::InvalidRemoteAddress(remote))
+        }
+    }
+
+    /// Handle the expired timers of the connection with remote. If its handshake did not
+    /// complete within the configured handshake timeout, the connection is removed and
+    /// `ErrHandshakeTimeout` is returned.
     pub fn handle_timeout(&mut self, remote: SocketAddr, now: Instant) -> Result<()> {
         if let Some(conn) = self.connections.get_mut(&remote) {
+            if conn
+                .handshake_deadline()
+                .is_some_and(|handshake_deadline| now >= handshake_deadline)
+            {
+                self.connections.remove(&remote);
+                self.record_failure(remote, now, &Error::ErrHandshakeTimeout);
+                return Err(Error::ErrHandshakeTimeout);
+            }
+
             if let Some(current_retransmit_timer) = &conn.current_retransmit_timer {
                 if now >= *current_retransmit_timer {
                     if conn.current_retransmit_timer.take().is_some()
                         && !conn.is_handshake_completed()
                     {
-                        conn.handshake_timeout(now)?;
+                        if let Err(err) = conn.handshake_timeout(now) {
+                            self.record_failure(remote, now, &err);
+                            return Err(err);
+                        }
                     }
                     while let Some(payload) = conn.outgoing_raw_packet() {
                         self.transmits.push_back(Transmit {
@@ -235,7 +594,7 @@ impl Endpoint {
                             transport: TransportContext {
                                 local_addr: self.local_addr,
                                 peer_addr: remote,
-                                ecn: None,
+                                ecn: conn.handshake_config.ecn,
                                 protocol: self.protocol,
                             },
                             message: payload,
@@ -256,9 +615,23 @@ impl Endpoint {
                     *eto = *current_retransmit_timer;
                 }
             }
+            if let Some(handshake_deadline) = conn.handshake_deadline() {
+                if handshake_deadline < *eto {
+                    *eto = handshake_deadline;
+                }
+            }
             Ok(())
         } else {
             Err(Error::InvalidRemoteAddress(remote))
         }
     }
 }
+
+// Whether the datagram starts with a ClientHello opening a handshake, i.e. an epoch 0
+// handshake record carrying a ClientHello message
+fn is_client_hello(data: &[u8]) -> bool {
+    data.len() > RECORD_LAYER_HEADER_SIZE
+        && ContentType::from(data[0]) == ContentType::Handshake
+        && data[3..5] == [0, 0]
+        && HandshakeType::from(data[RECORD_LAYER_HEADER_SIZE]) == HandshakeType::ClientHello
+}
@@ -0,0 +1,61 @@
+#[cfg(test)]
+mod extension_supported_versions_test;
+
+use super::*;
+use crate::record_layer::record_layer_header::ProtocolVersion;
+
+// https://www.rfc-editor.org/rfc/rfc8446#section-4.2.1
+// A ClientHello carries the list of supported versions, a ServerHello carries the single
+// selected version. Both forms are accepted by unmarshal, marshal always writes the list form.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ExtensionSupportedVersions {
+    pub(crate) versions: Vec<ProtocolVersion>,
+}
+
+impl ExtensionSupportedVersions {
+    pub fn extension_value(&self) -> ExtensionValue {
+        ExtensionValue::SupportedVersions
+    }
+
+    pub fn size(&self) -> usize {
+        2 + 1 + self.versions.len() * 2
+    }
+
+    pub fn marshal<W: Write>(&self, writer: &mut W) -> Result<()> {
+        writer.write_u16::<BigEndian>(1 + 2 * self.versions.len() as u16)?;
+        writer.write_u8(2 * self.versions.len() as u8)?;
+        for v in &self.versions {
+            writer.write_u8(v.major)?;
+            writer.write_u8(v.minor)?;
+        }
+
+        Ok(writer.flush()?)
+    }
+
+    pub fn unmarshal<R: Read>(reader: &mut R) -> Result<Self> {
+        let extension_len = reader.read_u16::<BigEndian>()? as usize;
+
+        // ServerHello form: a single selected_version
+        if extension_len == 2 {
+            let major = reader.read_u8()?;
+            let minor = reader.read_u8()?;
+            return Ok(ExtensionSupportedVersions {
+                versions: vec![ProtocolVersion { major, minor }],
+            });
+        }
+
+        let versions_len = reader.read_u8()? as usize;
+        if versions_len % 2 != 0 || versions_len + 1 != extension_len {
+            return Err(Error::ErrInvalidPacketLength);
+        }
+
+        let mut versions = vec![];
+        for _ in 0..versions_len / 2 {
+            let major = reader.read_u8()?;
+            let minor = reader.read_u8()?;
+            versions.push(ProtocolVersion { major, minor });
+        }
+
+        Ok(ExtensionSupportedVersions { versions })
+    }
+}
@@ -0,0 +1,43 @@
+use super::*;
+use crate::record_layer::record_layer_header::{PROTOCOL_VERSION1_2, PROTOCOL_VERSION1_3};
+
+use std::io::{BufReader, BufWriter};
+
+#[test]
+fn test_extension_supported_versions() -> Result<()> {
+    let raw_extension_supported_versions = vec![0x00, 0x05, 0x04, 0xfe, 0xfc, 0xfe, 0xfd];
+    let parsed_extension_supported_versions = ExtensionSupportedVersions {
+        versions: vec![PROTOCOL_VERSION1_3, PROTOCOL_VERSION1_2],
+    };
+
+    let mut raw = vec![];
+    {
+        let mut writer = BufWriter::<&mut Vec<u8>>::new(raw.as_mut());
+        parsed_extension_supported_versions.marshal(&mut writer)?;
+    }
+
+    assert_eq!(
+        raw, raw_extension_supported_versions,
+        "extensionSupportedVersions marshal: got {raw:?}, want {raw_extension_supported_versions:?}"
+    );
+
+    let mut reader = BufReader::new(raw.as_slice());
+    let new_extension_supported_versions = ExtensionSupportedVersions::unmarshal(&mut reader)?;
+
+    assert_eq!(
+        new_extension_supported_versions, parsed_extension_supported_versions,
+        "extensionSupportedVersions unmarshal: got {new_extension_supported_versions:?}, want {parsed_extension_supported_versions:?}"
+    );
+
+    // ServerHello carries only the selected version
+    let raw_selected_version = [0x00, 0x02, 0xfe, 0xfc];
+    let mut reader = BufReader::new(&raw_selected_version[..]);
+    assert_eq!(
+        ExtensionSupportedVersions::unmarshal(&mut reader)?,
+        ExtensionSupportedVersions {
+            versions: vec![PROTOCOL_VERSION1_3],
+        }
+    );
+
+    Ok(())
+}
@@ -34,6 +34,7 @@ const EXTENSION_USE_SRTPHEADER_SIZE: usize = 6;
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct ExtensionUseSrtp {
     pub(crate) protection_profiles: Vec<SrtpProtectionProfile>,
+    pub(crate) mki: Vec<u8>, // srtp_mki, empty unless MKI is in use
 }
 
 impl ExtensionUseSrtp {
@@ -42,20 +43,20 @@ impl ExtensionUseSrtp {
     }
 
     pub fn size(&self) -> usize {
-        2 + 2 + self.protection_profiles.len() * 2 + 1
+        2 + 2 + self.protection_profiles.len() * 2 + 1 + self.mki.len()
     }
 
     pub fn marshal<W: Write>(&self, writer: &mut W) -> Result<()> {
         writer.write_u16::<BigEndian>(
-            2 + /* MKI Length */ 1 + 2 * self.protection_profiles.len() as u16,
+            2 + /* MKI Length */ 1 + 2 * self.protection_profiles.len() as u16 + self.mki.len() as u16,
         )?;
         writer.write_u16::<BigEndian>(2 * self.protection_profiles.len() as u16)?;
         for v in &self.protection_profiles {
             writer.write_u16::<BigEndian>(*v as u16)?;
         }
 
-        /* MKI Length */
-        writer.write_u8(0x00)?;
+        writer.write_u8(self.mki.len() as u8)?;
+        writer.write_all(&self.mki)?;
 
         Ok(writer.flush()?)
     }
@@ -70,11 +71,13 @@ impl ExtensionUseSrtp {
             protection_profiles.push(protection_profile);
         }
 
-        /* MKI Length */
-        let _ = reader.read_u8()?;
+        let mki_len = reader.read_u8()? as usize;
+        let mut mki = vec![0; mki_len];
+        reader.read_exact(&mut mki)?;
 
         Ok(ExtensionUseSrtp {
             protection_profiles,
+            mki,
         })
     }
 }
@@ -7,6 +7,7 @@ fn test_extension_use_srtp() -> Result<()> {
     let raw_use_srtp = vec![0x00, 0x05, 0x00, 0x02, 0x00, 0x01, 0x00]; //0x00, 0x0e,
     let parsed_use_srtp = ExtensionUseSrtp {
         protection_profiles: vec![SrtpProtectionProfile::Srtp_Aes128_Cm_Hmac_Sha1_80],
+        mki: vec![],
     };
 
     let mut raw = vec![];
@@ -30,3 +31,39 @@ fn test_extension_use_srtp() -> Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn test_extension_use_srtp_mki() -> Result<()> {
+    let raw_use_srtp = vec![
+        0x00, 0x09, 0x00, 0x04, 0x00, 0x01, 0x00, 0x07, 0x02, 0xab, 0xcd,
+    ];
+    let parsed_use_srtp = ExtensionUseSrtp {
+        protection_profiles: vec![
+            SrtpProtectionProfile::Srtp_Aes128_Cm_Hmac_Sha1_80,
+            SrtpProtectionProfile::Srtp_Aead_Aes_128_Gcm,
+        ],
+        mki: vec![0xab, 0xcd],
+    };
+
+    let mut raw = vec![];
+    {
+        let mut writer = BufWriter::<&mut Vec<u8>>::new(raw.as_mut());
+        parsed_use_srtp.marshal(&mut writer)?;
+    }
+
+    assert_eq!(
+        raw, raw_use_srtp,
+        "extensionUseSRTP marshal: got {raw:?}, want {raw_use_srtp:?}"
+    );
+    assert_eq!(parsed_use_srtp.size(), raw_use_srtp.len());
+
+    let mut reader = BufReader::new(raw.as_slice());
+    let new_use_srtp = ExtensionUseSrtp::unmarshal(&mut reader)?;
+
+    assert_eq!(
+        new_use_srtp, parsed_use_srtp,
+        "extensionUseSRTP unmarshal: got {new_use_srtp:?}, want {parsed_use_srtp:?}"
+    );
+
+    Ok(())
+}
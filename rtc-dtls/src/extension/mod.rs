@@ -2,6 +2,8 @@ pub mod extension_server_name;
 pub mod extension_supported_elliptic_curves;
 pub mod extension_supported_point_formats;
 pub mod extension_supported_signature_algorithms;
+#[cfg(feature = "dtls13")]
+pub mod extension_supported_versions;
 pub mod extension_use_extended_master_secret;
 pub mod extension_use_srtp;
 pub mod renegotiation_info;
@@ -10,6 +12,8 @@ use extension_server_name::*;
 use extension_supported_elliptic_curves::*;
 use extension_supported_point_formats::*;
 use extension_supported_signature_algorithms::*;
+#[cfg(feature = "dtls13")]
+use extension_supported_versions::*;
 use extension_use_extended_master_secret::*;
 use extension_use_srtp::*;
 
@@ -28,6 +32,8 @@ pub enum ExtensionValue {
     SupportedSignatureAlgorithms = 13,
     UseSrtp = 14,
     UseExtendedMasterSecret = 23,
+    #[cfg(feature = "dtls13")]
+    SupportedVersions = 43,
     RenegotiationInfo = 65281,
     Unsupported,
 }
@@ -41,6 +47,8 @@ impl From<u16> for ExtensionValue {
             13 => ExtensionValue::SupportedSignatureAlgorithms,
             14 => ExtensionValue::UseSrtp,
             23 => ExtensionValue::UseExtendedMasterSecret,
+            #[cfg(feature = "dtls13")]
+            43 => ExtensionValue::SupportedVersions,
             65281 => ExtensionValue::RenegotiationInfo,
             _ => ExtensionValue::Unsupported,
         }
@@ -55,6 +63,8 @@ pub enum Extension {
     SupportedSignatureAlgorithms(ExtensionSupportedSignatureAlgorithms),
     UseSrtp(ExtensionUseSrtp),
     UseExtendedMasterSecret(ExtensionUseExtendedMasterSecret),
+    #[cfg(feature = "dtls13")]
+    SupportedVersions(ExtensionSupportedVersions),
     RenegotiationInfo(ExtensionRenegotiationInfo),
 }
 
@@ -67,6 +77,8 @@ impl Extension {
             Extension::SupportedSignatureAlgorithms(ext) => ext.extension_value(),
             Extension::UseSrtp(ext) => ext.extension_value(),
             Extension::UseExtendedMasterSecret(ext) => ext.extension_value(),
+            #[cfg(feature = "dtls13")]
+            Extension::SupportedVersions(ext) => ext.extension_value(),
             Extension::RenegotiationInfo(ext) => ext.extension_value(),
         }
     }
@@ -81,6 +93,8 @@ impl Extension {
             Extension::SupportedSignatureAlgorithms(ext) => ext.size(),
             Extension::UseSrtp(ext) => ext.size(),
             Extension::UseExtendedMasterSecret(ext) => ext.size(),
+            #[cfg(feature = "dtls13")]
+            Extension::SupportedVersions(ext) => ext.size(),
             Extension::RenegotiationInfo(ext) => ext.size(),
         };
 
@@ -96,6 +110,8 @@ impl Extension {
             Extension::SupportedSignatureAlgorithms(ext) => ext.marshal(writer),
             Extension::UseSrtp(ext) => ext.marshal(writer),
             Extension::UseExtendedMasterSecret(ext) => ext.marshal(writer),
+            #[cfg(feature = "dtls13")]
+            Extension::SupportedVersions(ext) => ext.marshal(writer),
             Extension::RenegotiationInfo(ext) => ext.marshal(writer),
         }
     }
@@ -121,6 +137,10 @@ impl Extension {
             ExtensionValue::UseExtendedMasterSecret => Ok(Extension::UseExtendedMasterSecret(
                 ExtensionUseExtendedMasterSecret::unmarshal(reader)?,
             )),
+            #[cfg(feature = "dtls13")]
+            ExtensionValue::SupportedVersions => Ok(Extension::SupportedVersions(
+                ExtensionSupportedVersions::unmarshal(reader)?,
+            )),
             ExtensionValue::RenegotiationInfo => Ok(Extension::RenegotiationInfo(
                 ExtensionRenegotiationInfo::unmarshal(reader)?,
             )),
@@ -9,7 +9,6 @@ use crate::*;
 use shared::error::Error;
 
 use log::debug;
-use rand::Rng;
 use std::fmt;
 
 #[derive(Debug, PartialEq)]
@@ -69,11 +68,17 @@ impl Flight for Flight0 {
                 ));
             }
 
+            #[cfg(feature = "dtls13")]
+            check_supported_versions(&client_hello.extensions, cfg)?;
+
             state.remote_random = client_hello.random.clone();
 
-            if let Ok(id) =
+            let matching_cipher_suite = if cfg.prefer_server_cipher_order {
+                find_matching_cipher_suite(&cfg.local_cipher_suites, &client_hello.cipher_suites)
+            } else {
                 find_matching_cipher_suite(&client_hello.cipher_suites, &cfg.local_cipher_suites)
-            {
+            };
+            if let Ok(id) = matching_cipher_suite {
                 if let Ok(cipher_suite) = cipher_suite_for_id(id) {
                     debug!(
                         "[handshake:{}] use cipher suite: {}",
@@ -112,13 +117,14 @@ impl Flight for Flight0 {
                             &cfg.local_srtp_protection_profiles,
                         ) {
                             state.srtp_protection_profile = profile;
+                            state.srtp_mki.clone_from(&e.mki);
                         } else {
                             return Err((
                                 Some(Alert {
                                     alert_level: AlertLevel::Fatal,
                                     alert_description: AlertDescription::InsufficientSecurity,
                                 }),
-                                Some(Error::ErrServerNoMatchingSrtpProfile),
+                                Some(Error::ErrNoMutualSrtpProfile),
                             ));
                         }
                     }
@@ -130,6 +136,11 @@ impl Flight for Flight0 {
                     Extension::ServerName(e) => {
                         state.server_name.clone_from(&e.server_name); // remote server name
                     }
+                    Extension::SupportedSignatureAlgorithms(e) => {
+                        state
+                            .remote_signature_schemes
+                            .clone_from(&e.signature_hash_algorithms);
+                    }
                     _ => {}
                 }
             }
@@ -180,8 +191,7 @@ impl Flight for Flight0 {
         _cfg: &HandshakeConfig,
     ) -> Result<Vec<Packet>, (Option<Alert>, Option<Error>)> {
         // Initialize
-        state.cookie = vec![0; COOKIE_LENGTH];
-        rand::thread_rng().fill(state.cookie.as_mut_slice());
+        state.cookie = vec![];
 
         state.local_epoch = 0;
         state.remote_epoch = 0;
@@ -9,6 +9,8 @@ use crate::extension::extension_server_name::*;
 use crate::extension::extension_supported_elliptic_curves::*;
 use crate::extension::extension_supported_point_formats::*;
 use crate::extension::extension_supported_signature_algorithms::*;
+#[cfg(feature = "dtls13")]
+use crate::extension::extension_supported_versions::*;
 use crate::extension::extension_use_extended_master_secret::*;
 use crate::extension::extension_use_srtp::*;
 use crate::extension::*;
@@ -145,6 +147,7 @@ impl Flight for Flight1 {
         if !cfg.local_srtp_protection_profiles.is_empty() {
             extensions.push(Extension::UseSrtp(ExtensionUseSrtp {
                 protection_profiles: cfg.local_srtp_protection_profiles.clone(),
+                mki: cfg.local_srtp_mki.clone(),
             }));
         }
 
@@ -162,6 +165,13 @@ impl Flight for Flight1 {
             }));
         }
 
+        #[cfg(feature = "dtls13")]
+        if cfg.dtls13 {
+            extensions.push(Extension::SupportedVersions(ExtensionSupportedVersions {
+                versions: local_supported_versions(cfg),
+            }));
+        }
+
         Ok(vec![Packet {
             record: RecordLayer::new(
                 PROTOCOL_VERSION1_2,
@@ -179,7 +189,6 @@ impl Flight for Flight1 {
                 ))),
             ),
             should_encrypt: false,
-            reset_local_sequence_number: false,
         }])
     }
 }
@@ -8,7 +8,17 @@ use crate::record_layer::record_layer_header::*;
 use shared::error::Error;
 
 use crate::config::HandshakeConfig;
+use crate::conn::COOKIE_LENGTH;
+use crate::handshake::handshake_random::HandshakeRandom;
+use crate::handshaker::srv_cli_str;
+use hmac::{Hmac, Mac};
+use log::debug;
+use sha2::Sha256;
 use std::fmt;
+use std::io::BufWriter;
+use std::net::SocketAddr;
+
+type HmacSha256 = Hmac<Sha256>;
 
 #[derive(Debug, PartialEq)]
 pub(crate) struct Flight2;
@@ -75,18 +85,17 @@ impl Flight for Flight2 {
                 ));
             }
 
-            if client_hello.cookie.is_empty() {
-                return Err((None, None));
-            }
-
-            if state.cookie != client_hello.cookie {
-                return Err((
-                    Some(Alert {
-                        alert_level: AlertLevel::Fatal,
-                        alert_description: AlertDescription::AccessDenied,
-                    }),
-                    Some(Error::ErrCookieMismatch),
-                ));
+            // RFC 6347 Section 4.2.1
+            // If the cookie is absent or invalid, treat the ClientHello as if it did not
+            // contain a cookie and send a new HelloVerifyRequest instead of proceeding.
+            if client_hello.cookie.is_empty() || state.cookie != client_hello.cookie {
+                debug!(
+                    "[handshake:{}] {} cookie mismatch, sending a new HelloVerifyRequest",
+                    srv_cli_str(state.is_client),
+                    self
+                );
+                state.remote_random = client_hello.random.clone();
+                return Ok(Box::new(Flight2 {}));
             }
 
             Ok(Box::new(Flight4 {}))
@@ -105,8 +114,19 @@ impl Flight for Flight2 {
         &self,
         state: &mut State,
         _cache: &HandshakeCache,
-        _cfg: &HandshakeConfig,
+        cfg: &HandshakeConfig,
     ) -> Result<Vec<Packet>, (Option<Alert>, Option<Error>)> {
+        state.cookie = generate_cookie(&cfg.cookie_secret, state.remote_addr, &state.remote_random)
+            .map_err(|err| {
+                (
+                    Some(Alert {
+                        alert_level: AlertLevel::Fatal,
+                        alert_description: AlertDescription::InternalError,
+                    }),
+                    Some(err),
+                )
+            })?;
+
         state.handshake_send_sequence = 0;
         Ok(vec![Packet {
             record: RecordLayer::new(
@@ -120,7 +140,111 @@ impl Flight for Flight2 {
                 ))),
             ),
             should_encrypt: false,
-            reset_local_sequence_number: false,
         }])
     }
 }
+
+// generate_cookie computes a stateless HelloVerifyRequest cookie as
+// HMAC(secret, client address || client random), so a client can only return
+// a valid cookie if it can receive packets at the address it claims.
+pub(crate) fn generate_cookie(
+    secret: &[u8],
+    remote_addr: Option<SocketAddr>,
+    client_random: &HandshakeRandom,
+) -> Result<Vec<u8>, Error> {
+    let mut mac = HmacSha256::new_from_slice(secret).map_err(|e| Error::Other(e.to_string()))?;
+    if let Some(remote_addr) = remote_addr {
+        mac.update(remote_addr.to_string().as_bytes());
+    }
+    let mut random = vec![];
+    {
+        let mut writer = BufWriter::<&mut Vec<u8>>::new(random.as_mut());
+        client_random.marshal(&mut writer)?;
+    }
+    mac.update(&random);
+
+    let mut cookie = mac.finalize().into_bytes().to_vec();
+    cookie.truncate(COOKIE_LENGTH);
+    Ok(cookie)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compression_methods::*;
+    use crate::handshake::handshake_message_client_hello::*;
+
+    fn push_client_hello(cache: &mut HandshakeCache, message_sequence: u16, cookie: Vec<u8>) {
+        let mut h = Handshake::new(HandshakeMessage::ClientHello(HandshakeMessageClientHello {
+            version: PROTOCOL_VERSION1_2,
+            random: HandshakeRandom::default(),
+            cookie,
+            cipher_suites: vec![],
+            compression_methods: CompressionMethods { ids: vec![] },
+            extensions: vec![],
+        }));
+        h.handshake_header.message_sequence = message_sequence;
+
+        let mut raw = vec![];
+        {
+            let mut writer = BufWriter::<&mut Vec<u8>>::new(raw.as_mut());
+            h.marshal(&mut writer).unwrap();
+        }
+        cache.push(raw, 0, message_sequence, HandshakeType::ClientHello, true);
+    }
+
+    #[test]
+    fn test_flight2_cookie_bound_to_remote_addr() -> Result<(), Error> {
+        let secret = vec![0xAB; 32];
+        let random = HandshakeRandom::default();
+        let a = generate_cookie(&secret, Some("192.168.0.1:1000".parse().unwrap()), &random)?;
+        let b = generate_cookie(&secret, Some("192.168.0.2:1000".parse().unwrap()), &random)?;
+        assert_eq!(a.len(), COOKIE_LENGTH);
+        assert_ne!(a, b);
+        assert_eq!(
+            a,
+            generate_cookie(&secret, Some("192.168.0.1:1000".parse().unwrap()), &random)?
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_flight2_cookie_mismatch_sends_hello_verify_request() {
+        let cfg = HandshakeConfig::default();
+        let mut state = State {
+            remote_addr: Some("192.168.0.1:1000".parse().unwrap()),
+            ..Default::default()
+        };
+        let pkts = Flight2 {}.generate(&mut state, &HandshakeCache::new(), &cfg);
+        assert!(pkts.is_ok());
+        let cookie = state.cookie.clone();
+        assert_eq!(cookie.len(), COOKIE_LENGTH);
+
+        // absent cookie
+        let mut cache = HandshakeCache::new();
+        state.handshake_recv_sequence = 1;
+        push_client_hello(&mut cache, 1, vec![]);
+        let next = Flight2 {}.parse(&mut state, &cache, &cfg);
+        assert_eq!(
+            next.map(|f| f.to_string()).ok(),
+            Some("Flight 2".to_owned())
+        );
+
+        // forged cookie
+        push_client_hello(&mut cache, 2, vec![0; COOKIE_LENGTH]);
+        let next = Flight2 {}.parse(&mut state, &cache, &cfg);
+        assert_eq!(
+            next.map(|f| f.to_string()).ok(),
+            Some("Flight 2".to_owned())
+        );
+
+        // valid cookie
+        push_client_hello(&mut cache, 3, cookie);
+        let next = Flight2 {}.parse(&mut state, &cache, &cfg);
+        assert_eq!(
+            next.map(|f| f.to_string()).ok(),
+            Some("Flight 4".to_owned())
+        );
+    }
+}
@@ -8,6 +8,8 @@ use crate::extension::extension_server_name::*;
 use crate::extension::extension_supported_elliptic_curves::*;
 use crate::extension::extension_supported_point_formats::*;
 use crate::extension::extension_supported_signature_algorithms::*;
+#[cfg(feature = "dtls13")]
+use crate::extension::extension_supported_versions::*;
 use crate::extension::extension_use_extended_master_secret::*;
 use crate::extension::extension_use_srtp::*;
 use crate::extension::*;
@@ -183,6 +185,9 @@ impl Flight for Flight3 {
                 ));
             }
 
+            #[cfg(feature = "dtls13")]
+            check_supported_versions(&h.extensions, cfg)?;
+
             for extension in &h.extensions {
                 match extension {
                     Extension::UseSrtp(e) => {
@@ -197,11 +202,23 @@ impl Flight for Flight3 {
                                         alert_level: AlertLevel::Fatal,
                                         alert_description: AlertDescription::IllegalParameter,
                                     }),
-                                    Some(Error::ErrClientNoMatchingSrtpProfile),
+                                    Some(Error::ErrNoMutualSrtpProfile),
                                 ))
                             }
                         };
+                        // A non-empty MKI in the response must echo the one we offered
+                        // [RFC5764 Section-4.1.1]
+                        if !e.mki.is_empty() && e.mki != cfg.local_srtp_mki {
+                            return Err((
+                                Some(Alert {
+                                    alert_level: AlertLevel::Fatal,
+                                    alert_description: AlertDescription::IllegalParameter,
+                                }),
+                                Some(Error::ErrClientNoMatchingSrtpMki),
+                            ));
+                        }
                         state.srtp_protection_profile = profile;
+                        state.srtp_mki.clone_from(&e.mki);
                     }
                     Extension::UseExtendedMasterSecret(_) => {
                         if cfg.extended_master_secret != ExtendedMasterSecretType::Disable {
@@ -362,6 +379,7 @@ impl Flight for Flight3 {
         if !cfg.local_srtp_protection_profiles.is_empty() {
             extensions.push(Extension::UseSrtp(ExtensionUseSrtp {
                 protection_profiles: cfg.local_srtp_protection_profiles.clone(),
+                mki: cfg.local_srtp_mki.clone(),
             }));
         }
 
@@ -379,6 +397,13 @@ impl Flight for Flight3 {
             }));
         }
 
+        #[cfg(feature = "dtls13")]
+        if cfg.dtls13 {
+            extensions.push(Extension::SupportedVersions(ExtensionSupportedVersions {
+                versions: local_supported_versions(cfg),
+            }));
+        }
+
         Ok(vec![Packet {
             record: RecordLayer::new(
                 PROTOCOL_VERSION1_2,
@@ -396,7 +421,6 @@ impl Flight for Flight3 {
                 ))),
             ),
             should_encrypt: false,
-            reset_local_sequence_number: false,
         }])
     }
 }
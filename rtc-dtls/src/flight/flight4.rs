@@ -514,6 +514,7 @@ impl Flight for Flight4 {
         if state.srtp_protection_profile != SrtpProtectionProfile::Unsupported {
             extensions.push(Extension::UseSrtp(ExtensionUseSrtp {
                 protection_profiles: vec![state.srtp_protection_profile],
+                mki: state.srtp_mki.clone(),
             }));
         }
 
@@ -549,22 +550,22 @@ impl Flight for Flight4 {
                 ))),
             ),
             should_encrypt: false,
-            reset_local_sequence_number: false,
         }];
 
         if cfg.local_psk_callback.is_none() {
-            let certificate = match cfg.get_certificate(&cfg.server_name) {
-                Ok(cert) => cert,
-                Err(err) => {
-                    return Err((
-                        Some(Alert {
-                            alert_level: AlertLevel::Fatal,
-                            alert_description: AlertDescription::HandshakeFailure,
-                        }),
-                        Some(err),
-                    ))
-                }
-            };
+            let certificate =
+                match cfg.select_certificate(&cfg.server_name, &state.remote_signature_schemes) {
+                    Ok(cert) => cert,
+                    Err(err) => {
+                        return Err((
+                            Some(Alert {
+                                alert_level: AlertLevel::Fatal,
+                                alert_description: AlertDescription::HandshakeFailure,
+                            }),
+                            Some(err),
+                        ))
+                    }
+                };
 
             pkts.push(Packet {
                 record: RecordLayer::new(
@@ -581,7 +582,6 @@ impl Flight for Flight4 {
                     ))),
                 ),
                 should_encrypt: false,
-                reset_local_sequence_number: false,
             });
 
             let mut server_random = vec![];
@@ -595,11 +595,22 @@ impl Flight for Flight4 {
                 let _ = state.remote_random.marshal(&mut writer);
             }
 
-            // Find compatible signature scheme
-            let signature_hash_algo = match select_signature_scheme(
-                &cfg.local_signature_schemes,
-                &certificate.private_key,
-            ) {
+            // Find compatible signature scheme, preferring one the client advertised
+            let offered_signature_schemes: Vec<SignatureHashAlgorithm> = cfg
+                .local_signature_schemes
+                .iter()
+                .filter(|ss| state.remote_signature_schemes.contains(ss))
+                .copied()
+                .collect();
+            let selected_signature_scheme =
+                select_signature_scheme(&offered_signature_schemes, &certificate.private_key)
+                    .or_else(|_| {
+                        select_signature_scheme(
+                            &cfg.local_signature_schemes,
+                            &certificate.private_key,
+                        )
+                    });
+            let signature_hash_algo = match selected_signature_scheme {
                 Ok(s) => s,
                 Err(err) => {
                     return Err((
@@ -653,7 +664,6 @@ impl Flight for Flight4 {
                         ))),
                     ),
                     should_encrypt: false,
-                    reset_local_sequence_number: false,
                 });
             }
 
@@ -673,7 +683,6 @@ impl Flight for Flight4 {
                         ))),
                     ),
                     should_encrypt: false,
-                    reset_local_sequence_number: false,
                 });
             }
         } else if let Some(local_psk_identity_hint) = &cfg.local_psk_identity_hint {
@@ -701,7 +710,6 @@ impl Flight for Flight4 {
                     ))),
                 ),
                 should_encrypt: false,
-                reset_local_sequence_number: false,
             });
         }
 
@@ -714,7 +722,6 @@ impl Flight for Flight4 {
                 ))),
             ),
             should_encrypt: false,
-            reset_local_sequence_number: false,
         });
 
         Ok(pkts)
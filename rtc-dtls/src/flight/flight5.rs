@@ -207,7 +207,6 @@ impl Flight for Flight5 {
                     ))),
                 ),
                 should_encrypt: false,
-                reset_local_sequence_number: false,
             });
         }
 
@@ -236,7 +235,6 @@ impl Flight for Flight5 {
                 ))),
             ),
             should_encrypt: false,
-            reset_local_sequence_number: false,
         });
 
         let server_key_exchange_data = cache.pull_and_merge(&[HandshakeCachePullRule {
@@ -438,7 +436,6 @@ impl Flight for Flight5 {
                     ))),
                 ),
                 should_encrypt: false,
-                reset_local_sequence_number: false,
             };
 
             let h = match &mut p.record.content {
@@ -482,7 +479,6 @@ impl Flight for Flight5 {
                 Content::ChangeCipherSpec(ChangeCipherSpec {}),
             ),
             should_encrypt: false,
-            reset_local_sequence_number: false,
         });
 
         if state.local_verify_data.is_empty() {
@@ -582,7 +578,6 @@ impl Flight for Flight5 {
                 ))),
             ),
             should_encrypt: true,
-            reset_local_sequence_number: true,
         });
 
         Ok(pkts)
@@ -76,7 +76,6 @@ impl Flight for Flight6 {
                 Content::ChangeCipherSpec(ChangeCipherSpec {}),
             ),
             should_encrypt: false,
-            reset_local_sequence_number: false,
         }];
 
         if state.local_verify_data.is_empty() {
@@ -174,7 +173,6 @@ impl Flight for Flight6 {
                 ))),
             ),
             should_encrypt: true,
-            reset_local_sequence_number: true,
         });
 
         Ok(pkts)
@@ -14,6 +14,10 @@ use crate::state::*;
 use shared::error::Error;
 
 use crate::config::HandshakeConfig;
+#[cfg(feature = "dtls13")]
+use crate::extension::Extension;
+#[cfg(feature = "dtls13")]
+use crate::record_layer::record_layer_header::*;
 use std::fmt;
 
 /*
@@ -53,7 +57,6 @@ use std::fmt;
 pub(crate) struct Packet {
     pub(crate) record: RecordLayer,
     pub(crate) should_encrypt: bool,
-    pub(crate) reset_local_sequence_number: bool,
 }
 
 pub(crate) trait Flight: fmt::Display + fmt::Debug {
@@ -82,3 +85,53 @@ pub(crate) trait Flight: fmt::Display + fmt::Debug {
         cfg: &HandshakeConfig,
     ) -> Result<Vec<Packet>, (Option<Alert>, Option<Error>)>;
 }
+
+/// local_supported_versions returns the protocol versions offered in the supported_versions
+/// extension, most preferred first.
+#[cfg(feature = "dtls13")]
+pub(crate) fn local_supported_versions(cfg: &HandshakeConfig) -> Vec<ProtocolVersion> {
+    if cfg.dtls13 {
+        vec![PROTOCOL_VERSION1_3, PROTOCOL_VERSION1_2]
+    } else {
+        vec![PROTOCOL_VERSION1_2]
+    }
+}
+
+/// check_supported_versions negotiates the protocol version from the peer's supported_versions
+/// extension: the versions offered in a ClientHello or the version selected in a ServerHello.
+/// Without the extension DTLS 1.2 is used. Only the DTLS 1.2 handshake is implemented, so
+/// negotiating DTLS 1.3 fails the handshake with ErrDtls13NotImplemented.
+#[cfg(feature = "dtls13")]
+pub(crate) fn check_supported_versions(
+    extensions: &[Extension],
+    cfg: &HandshakeConfig,
+) -> Result<(), (Option<Alert>, Option<Error>)> {
+    let offered = match extensions.iter().find_map(|extension| match extension {
+        Extension::SupportedVersions(e) => Some(&e.versions),
+        _ => None,
+    }) {
+        Some(offered) => offered,
+        None => return Ok(()),
+    };
+
+    match local_supported_versions(cfg)
+        .into_iter()
+        .find(|v| offered.contains(v))
+    {
+        Some(PROTOCOL_VERSION1_2) => Ok(()),
+        Some(_) => Err((
+            Some(Alert {
+                alert_level: AlertLevel::Fatal,
+                alert_description: AlertDescription::ProtocolVersion,
+            }),
+            Some(Error::ErrDtls13NotImplemented),
+        )),
+        None => Err((
+            Some(Alert {
+                alert_level: AlertLevel::Fatal,
+                alert_description: AlertDescription::ProtocolVersion,
+            }),
+            Some(Error::ErrUnsupportedProtocolVersion),
+        )),
+    }
+}
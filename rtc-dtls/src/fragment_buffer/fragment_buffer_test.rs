@@ -1,4 +1,8 @@
 use super::*;
+use crate::config::{
+    DEFAULT_MAX_FRAGMENT_BUFFER_FRAGMENTS, DEFAULT_MAX_FRAGMENT_BUFFER_SIZE,
+    DEFAULT_MAX_HANDSHAKE_MESSAGE_SIZE,
+};
 
 #[test]
 fn test_fragment_buffer() -> Result<()> {
@@ -121,7 +125,11 @@ fn test_fragment_buffer() -> Result<()> {
     ];
 
     for (name, inputs, expects, expected_epoch) in tests {
-        let mut fragment_buffer = FragmentBuffer::new();
+        let mut fragment_buffer = FragmentBuffer::new(
+            DEFAULT_MAX_FRAGMENT_BUFFER_SIZE,
+            DEFAULT_MAX_FRAGMENT_BUFFER_FRAGMENTS,
+            DEFAULT_MAX_HANDSHAKE_MESSAGE_SIZE,
+        );
         for frag in inputs {
             let status = fragment_buffer.push(&frag)?;
             assert!(
@@ -155,7 +163,11 @@ fn test_fragment_buffer() -> Result<()> {
 
 #[test]
 fn test_fragment_buffer_overflow() -> Result<()> {
-    let mut fragment_buffer = FragmentBuffer::new();
+    let mut fragment_buffer = FragmentBuffer::new(
+        DEFAULT_MAX_FRAGMENT_BUFFER_SIZE,
+        DEFAULT_MAX_FRAGMENT_BUFFER_FRAGMENTS,
+        DEFAULT_MAX_HANDSHAKE_MESSAGE_SIZE,
+    );
 
     fragment_buffer.push(&[
         0x16, 0xfe, 0xff, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x0F, 0x03, 0x00,
@@ -172,3 +184,82 @@ fn test_fragment_buffer_overflow() -> Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn test_fragment_buffer_flood() -> Result<()> {
+    let max_fragments = 64;
+    let mut fragment_buffer = FragmentBuffer::new(
+        DEFAULT_MAX_FRAGMENT_BUFFER_SIZE,
+        max_fragments,
+        DEFAULT_MAX_HANDSHAKE_MESSAGE_SIZE,
+    );
+
+    // One byte fragments of a 64KB ClientHello, leaving a gap after each so the
+    // message can never be reassembled
+    let mut rejected = 0;
+    for i in 0..10_000u32 {
+        let offset = (i * 2) % 0xFFFF;
+        let mut frag = vec![
+            0x16, 0xfe, 0xff, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x0D, 0x01,
+            0x00, 0xFF, 0xFF, 0x00, 0x00,
+        ];
+        frag.extend_from_slice(&offset.to_be_bytes()[1..]);
+        frag.extend_from_slice(&[0x00, 0x00, 0x01, 0xAA]);
+
+        match fragment_buffer.push(&frag) {
+            Ok(status) => assert!(status, "fragment_buffer didn't accept fragment {i}"),
+            Err(Error::ErrFragmentBufferTooManyFragments { max_count, .. }) => {
+                assert_eq!(max_count, max_fragments);
+                rejected += 1;
+            }
+            Err(err) => panic!("unexpected error for fragment {i}: {err}"),
+        }
+
+        assert!(fragment_buffer.num_fragments() <= max_fragments);
+        assert!(fragment_buffer.size() <= max_fragments);
+    }
+
+    assert_eq!(rejected, 10_000 - max_fragments);
+    assert!(
+        fragment_buffer.pop().is_err(),
+        "fragment_buffer popped a message with gaps"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_fragment_buffer_oversized_message() -> Result<()> {
+    let max_message_size = 1024;
+    let mut fragment_buffer = FragmentBuffer::new(
+        DEFAULT_MAX_FRAGMENT_BUFFER_SIZE,
+        DEFAULT_MAX_FRAGMENT_BUFFER_FRAGMENTS,
+        max_message_size,
+    );
+
+    // First fragment of a ClientHello declaring a 16MB length
+    let frag = vec![
+        0x16, 0xfe, 0xff, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x0D, 0x01, 0xFF,
+        0xFF, 0xFF, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01, 0xAA,
+    ];
+    assert_eq!(
+        fragment_buffer.push(&frag),
+        Err(Error::ErrHandshakeMessageTooLarge {
+            length: 0xFFFFFF,
+            max_length: max_message_size,
+        })
+    );
+    assert_eq!(fragment_buffer.num_fragments(), 0);
+
+    // The same declared length is rejected by the message parser
+    let mut reader = Cursor::new(&frag[RECORD_LAYER_HEADER_SIZE..]);
+    assert_eq!(
+        crate::handshake::Handshake::unmarshal_with_max_size(&mut reader, max_message_size).err(),
+        Some(Error::ErrHandshakeMessageTooLarge {
+            length: 0xFFFFFF,
+            max_length: max_message_size,
+        })
+    );
+
+    Ok(())
+}
@@ -9,9 +9,6 @@ use shared::error::*;
 use std::collections::HashMap;
 use std::io::{BufWriter, Cursor};
 
-// 2 mb max buffer size
-const FRAGMENT_BUFFER_MAX_SIZE: usize = 2_000_000;
-
 pub(crate) struct Fragment {
     record_layer_header: RecordLayerHeader,
     handshake_header: HandshakeHeader,
@@ -23,13 +20,21 @@ pub(crate) struct FragmentBuffer {
     cache: HashMap<u16, Vec<Fragment>>,
 
     current_message_sequence_number: u16,
+
+    // bounds on what a peer can make us hold while a message is incomplete
+    max_size: usize,
+    max_fragments: usize,
+    max_message_size: usize,
 }
 
 impl FragmentBuffer {
-    pub fn new() -> Self {
+    pub fn new(max_size: usize, max_fragments: usize, max_message_size: usize) -> Self {
         FragmentBuffer {
             cache: HashMap::new(),
             current_message_sequence_number: 0,
+            max_size,
+            max_fragments,
+            max_message_size,
         }
     }
 
@@ -38,10 +43,10 @@ impl FragmentBuffer {
     // when an error returns it is fatal, and the DTLS connection should be stopped
     pub fn push(&mut self, mut buf: &[u8]) -> Result<bool> {
         let current_size = self.size();
-        if current_size + buf.len() >= FRAGMENT_BUFFER_MAX_SIZE {
+        if current_size + buf.len() >= self.max_size {
             return Err(Error::ErrFragmentBufferOverflow {
                 new_size: current_size + buf.len(),
-                max_size: FRAGMENT_BUFFER_MAX_SIZE,
+                max_size: self.max_size,
             });
         }
 
@@ -53,14 +58,19 @@ impl FragmentBuffer {
             return Ok(false);
         }
 
+        // Parse every fragment before buffering any, so a record that would cross
+        // the limits is rejected as a whole
+        let mut fragments = vec![];
         buf = &buf[RECORD_LAYER_HEADER_SIZE..];
         while !buf.is_empty() {
             let mut reader = Cursor::new(buf);
             let handshake_header = HandshakeHeader::unmarshal(&mut reader)?;
-
-            self.cache
-                .entry(handshake_header.message_sequence)
-                .or_default();
+            if handshake_header.length as usize > self.max_message_size {
+                return Err(Error::ErrHandshakeMessageTooLarge {
+                    length: handshake_header.length as usize,
+                    max_length: self.max_message_size,
+                });
+            }
 
             // end index should be the length of handshake header but if the handshake
             // was fragmented, we should keep them all
@@ -72,16 +82,29 @@ impl FragmentBuffer {
             // Discard all headers, when rebuilding the packet we will re-build
             let data = buf[HANDSHAKE_HEADER_LENGTH..end].to_vec();
 
-            if let Some(x) = self.cache.get_mut(&handshake_header.message_sequence) {
-                x.push(Fragment {
-                    record_layer_header,
-                    handshake_header,
-                    data,
-                });
-            }
+            fragments.push(Fragment {
+                record_layer_header,
+                handshake_header,
+                data,
+            });
             buf = &buf[end..];
         }
 
+        let current_fragments = self.num_fragments();
+        if current_fragments + fragments.len() > self.max_fragments {
+            return Err(Error::ErrFragmentBufferTooManyFragments {
+                new_count: current_fragments + fragments.len(),
+                max_count: self.max_fragments,
+            });
+        }
+
+        for fragment in fragments {
+            self.cache
+                .entry(fragment.handshake_header.message_sequence)
+                .or_default()
+                .push(fragment);
+        }
+
         Ok(true)
     }
 
@@ -131,6 +154,10 @@ impl FragmentBuffer {
             .map(|fragment| fragment.iter().map(|f| f.data.len()).sum::<usize>())
             .sum()
     }
+
+    fn num_fragments(&self) -> usize {
+        self.cache.values().map(|fragments| fragments.len()).sum()
+    }
 }
 
 fn append_message(target_offset: u32, frags: &[Fragment], raw_message: &mut Vec<u8>) -> bool {
@@ -4,6 +4,7 @@ mod handshake_cache_test;
 use crate::cipher_suite::*;
 use crate::handshake::*;
 
+use log::debug;
 use std::collections::HashMap;
 use std::io::BufReader;
 
@@ -29,11 +30,38 @@ pub(crate) struct HandshakeCachePullRule {
 #[derive(Clone)]
 pub(crate) struct HandshakeCache {
     cache: Vec<HandshakeCacheItem>,
+    size: usize,
+    max_size: usize,
 }
 
 impl HandshakeCache {
     pub(crate) fn new() -> Self {
-        HandshakeCache { cache: vec![] }
+        HandshakeCache::with_max_size(usize::MAX)
+    }
+
+    // with_max_size creates a cache that drops messages which would grow it over max_size bytes
+    pub(crate) fn with_max_size(max_size: usize) -> Self {
+        HandshakeCache {
+            cache: vec![],
+            size: 0,
+            max_size,
+        }
+    }
+
+    // len returns the number of cached handshake messages
+    pub(crate) fn len(&self) -> usize {
+        self.cache.len()
+    }
+
+    // size returns the number of bytes of cached handshake messages
+    pub(crate) fn size(&self) -> usize {
+        self.size
+    }
+
+    // clear drops all cached handshake messages
+    pub(crate) fn clear(&mut self) {
+        self.cache.clear();
+        self.size = 0;
     }
 
     pub(crate) fn push(
@@ -50,6 +78,15 @@ impl HandshakeCache {
             }
         }
 
+        if self.size + data.len() > self.max_size {
+            debug!(
+                "drop {} handshake message: cache would exceed {} bytes",
+                typ, self.max_size
+            );
+            return false;
+        }
+        self.size += data.len();
+
         self.cache.push(HandshakeCacheItem {
             typ,
             is_client,
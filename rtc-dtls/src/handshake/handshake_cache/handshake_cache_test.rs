@@ -654,3 +654,20 @@ fn test_handshake_cache_session_hash() -> Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn test_handshake_cache_max_size() -> Result<()> {
+    let mut h = HandshakeCache::with_max_size(8);
+    assert!(h.push(vec![0x00; 6], 0, 0, HandshakeType::ClientHello, true));
+    assert!(!h.push(vec![0x01; 6], 0, 1, HandshakeType::ServerHello, false));
+    assert!(h.push(vec![0x02; 2], 0, 1, HandshakeType::ServerHello, false));
+    assert_eq!(h.len(), 2);
+    assert_eq!(h.size(), 8);
+
+    h.clear();
+    assert_eq!(h.len(), 0);
+    assert_eq!(h.size(), 0);
+    assert!(h.push(vec![0x01; 6], 0, 1, HandshakeType::ServerHello, false));
+
+    Ok(())
+}
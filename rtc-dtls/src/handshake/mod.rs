@@ -194,7 +194,22 @@ impl Handshake {
     }
 
     pub fn unmarshal<R: Read>(reader: &mut R) -> Result<Self> {
+        Self::unmarshal_with_max_size(reader, usize::MAX)
+    }
+
+    /// Unmarshals a handshake message, rejecting one whose declared length is greater than
+    /// max_message_size before its body is parsed.
+    pub fn unmarshal_with_max_size<R: Read>(
+        reader: &mut R,
+        max_message_size: usize,
+    ) -> Result<Self> {
         let handshake_header = HandshakeHeader::unmarshal(reader)?;
+        if handshake_header.length as usize > max_message_size {
+            return Err(Error::ErrHandshakeMessageTooLarge {
+                length: handshake_header.length as usize,
+                max_length: max_message_size,
+            });
+        }
 
         let handshake_message = match handshake_header.handshake_type {
             HandshakeType::ClientHello => {
@@ -4,7 +4,7 @@ use shared::error::*;
 
 use log::*;
 use std::fmt;
-use std::time::Instant;
+use std::time::{Duration, Instant};
 
 //use std::io::BufWriter;
 
@@ -43,8 +43,9 @@ use std::time::Instant;
 //              Read retransmit
 //           Retransmit last flight
 
-#[derive(Copy, Clone, PartialEq)]
-pub(crate) enum HandshakeState {
+/// State of the handshake state machine of a DTLSConn.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum HandshakeState {
     Errored,
     Preparing,
     Sending,
@@ -64,6 +65,33 @@ impl fmt::Display for HandshakeState {
     }
 }
 
+/// Status of the handshake of a DTLSConn, as returned by `DTLSConn::poll_handshake_complete`.
+#[derive(Debug, PartialEq)]
+pub enum HandshakeStatus {
+    /// No flight has been sent or received yet.
+    NotStarted,
+    /// Flights are being exchanged.
+    InProgress,
+    /// The connection is ready to carry application data.
+    Complete,
+    /// The handshake was terminated by this error, e.g. the alert received from the peer or
+    /// ErrAllRetransmissionsFailed once a flight was retransmitted too many times.
+    Failed(Error),
+}
+
+/// Progress of the handshake of a DTLSConn, as returned by `DTLSConn::handshake_progress`, to
+/// tell which flight a stalled handshake is stuck on.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HandshakeProgress {
+    /// Name of the current flight, e.g. "Flight 3".
+    pub flight: String,
+    /// Current state of the handshake state machine.
+    pub state: HandshakeState,
+    /// Time since the state machine first entered the state for the current flight, so
+    /// retransmissions of the flight do not reset it.
+    pub time_in_state: Duration,
+}
+
 pub(crate) fn srv_cli_str(is_client: bool) -> String {
     if is_client {
         return "client".to_owned();
@@ -73,6 +101,14 @@ pub(crate) fn srv_cli_str(is_client: bool) -> String {
 
 impl DTLSConn {
     pub(crate) fn handshake(&mut self) -> Result<()> {
+        let result = self.run_handshake();
+        if let Err(err) = &result {
+            self.record_handshake_failure(err);
+        }
+        result
+    }
+
+    fn run_handshake(&mut self) -> Result<()> {
         loop {
             debug!(
                 "[handshake:{}] {}: {}",
@@ -93,13 +129,14 @@ impl DTLSConn {
             }
 
             let previous_handshake_state = self.current_handshake_state;
-            self.current_handshake_state = match previous_handshake_state {
+            let next_handshake_state = match previous_handshake_state {
                 HandshakeState::Preparing => self.prepare()?,
                 HandshakeState::Sending => self.send()?,
                 HandshakeState::Waiting => self.wait()?,
                 HandshakeState::Finished => self.finish()?,
-                _ => return Err(Error::ErrInvalidFsmTransition),
+                HandshakeState::Errored => return Err(Error::ErrAllRetransmissionsFailed),
             };
+            self.set_handshake_state(next_handshake_state);
 
             if previous_handshake_state == self.current_handshake_state
                 && previous_handshake_state == HandshakeState::Waiting
@@ -110,6 +147,14 @@ impl DTLSConn {
         }
     }
 
+    // Moves the state machine to state, keeping when it first entered it for the current flight
+    pub(crate) fn set_handshake_state(&mut self, state: HandshakeState) {
+        self.current_handshake_state = state;
+        self.handshake_state_entered_at
+            .entry(state)
+            .or_insert_with(Instant::now);
+    }
+
     fn prepare(&mut self) -> Result<HandshakeState> {
         self.flights = None;
 
@@ -215,6 +260,7 @@ impl DTLSConn {
                         return Ok(HandshakeState::Finished);
                     }
                     self.current_flight = next_flight;
+                    self.handshake_state_entered_at.clear();
                     return Ok(HandshakeState::Preparing);
                 }
             }
@@ -248,6 +294,29 @@ impl DTLSConn {
         Ok(HandshakeState::Finished)
     }
 
+    /// Queues the packets of the current flight again right away instead of waiting for the
+    /// retransmit timer, e.g. when ICE reports that the path just became connected and the
+    /// first flight was likely dropped. The retransmit timer is left untouched.
+    pub fn retransmit_now(&mut self) -> Result<()> {
+        if self.current_handshake_state != HandshakeState::Waiting
+            && self.current_handshake_state != HandshakeState::Finished
+        {
+            return Ok(());
+        }
+
+        if let Some(pkts) = self.flights.clone() {
+            debug!(
+                "[handshake:{}] {} retransmit_now",
+                srv_cli_str(self.state.is_client),
+                self.current_flight
+            );
+            self.stats.inc_retransmits();
+            self.write_packets(pkts);
+        }
+
+        Ok(())
+    }
+
     pub(crate) fn handshake_timeout(&mut self, _now: Instant) -> Result<()> {
         let next_handshake_state = if self.current_handshake_state == HandshakeState::Waiting {
             debug!(
@@ -265,8 +334,10 @@ impl DTLSConn {
             if self.retransmit {
                 self.current_retransmit_count += 1;
                 if self.current_retransmit_count > self.maximum_retransmit_number {
+                    self.clear_handshake_buffers();
                     Some(HandshakeState::Errored)
                 } else {
+                    self.stats.inc_retransmits();
                     Some(HandshakeState::Sending)
                 }
             } else {
@@ -276,13 +347,14 @@ impl DTLSConn {
             }
         } else if self.current_handshake_state == HandshakeState::Finished {
             // Retransmit last flight
+            self.stats.inc_retransmits();
             Some(HandshakeState::Sending)
         } else {
             None
         };
 
         if let Some(next_handshake_state) = next_handshake_state {
-            self.current_handshake_state = next_handshake_state;
+            self.set_handshake_state(next_handshake_state);
             self.handshake()
         } else {
             Ok(())
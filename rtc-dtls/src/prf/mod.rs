@@ -24,6 +24,8 @@ pub(crate) const PRF_KEY_EXPANSION_LABEL: &str = "key expansion";
 pub(crate) const PRF_VERIFY_DATA_CLIENT_LABEL: &str = "client finished";
 pub(crate) const PRF_VERIFY_DATA_SERVER_LABEL: &str = "server finished";
 
+pub(crate) const MASTER_SECRET_LENGTH: usize = 48;
+
 #[derive(PartialEq, Debug, Clone)]
 pub(crate) struct EncryptionKeys {
     pub(crate) master_secret: Vec<u8>,
@@ -182,7 +184,7 @@ pub(crate) fn prf_extended_master_secret(
 ) -> Result<Vec<u8>> {
     let mut seed = PRF_EXTENDED_MASTER_SECRET_LABEL.as_bytes().to_vec();
     seed.extend_from_slice(session_hash);
-    prf_p_hash(pre_master_secret, &seed, 48, h)
+    prf_p_hash(pre_master_secret, &seed, MASTER_SECRET_LENGTH, h)
 }
 
 pub(crate) fn prf_master_secret(
@@ -194,7 +196,7 @@ pub(crate) fn prf_master_secret(
     let mut seed = PRF_MASTER_SECRET_LABEL.as_bytes().to_vec();
     seed.extend_from_slice(client_random);
     seed.extend_from_slice(server_random);
-    prf_p_hash(pre_master_secret, &seed, 48, h)
+    prf_p_hash(pre_master_secret, &seed, MASTER_SECRET_LENGTH, h)
 }
 
 pub(crate) fn prf_encryption_keys(
@@ -8,6 +8,7 @@ use crate::alert::Alert;
 use crate::application_data::ApplicationData;
 use crate::change_cipher_spec::ChangeCipherSpec;
 use crate::handshake::Handshake;
+use log::debug;
 use record_layer_header::*;
 use shared::error::*;
 
@@ -82,25 +83,38 @@ impl RecordLayer {
 // two DTLS messages into the same datagram: in the same record or in
 // separate records.
 // https://tools.ietf.org/html/rfc6347#section-4.2.3
-pub(crate) fn unpack_datagram(buf: &[u8]) -> Result<Vec<Vec<u8>>> {
+//
+// A record can't span datagrams, so a record whose declared length exceeds the rest of the
+// datagram is malformed: it is discarded along with the rest of the datagram, which can't be
+// delimited anymore, and the records before it are kept [RFC6347 Section-4.1.2.7].
+pub(crate) fn unpack_datagram(buf: &[u8]) -> Vec<Vec<u8>> {
     let mut out = vec![];
 
     let mut offset = 0;
     while buf.len() != offset {
         if buf.len() - offset <= RECORD_LAYER_HEADER_SIZE {
-            return Err(Error::ErrInvalidPacketLength);
+            debug!(
+                "discarded truncated record header ({} bytes)",
+                buf.len() - offset
+            );
+            break;
         }
 
         let pkt_len = RECORD_LAYER_HEADER_SIZE
             + (((buf[offset + RECORD_LAYER_HEADER_SIZE - 2] as usize) << 8)
                 | buf[offset + RECORD_LAYER_HEADER_SIZE - 1] as usize);
         if offset + pkt_len > buf.len() {
-            return Err(Error::ErrInvalidPacketLength);
+            debug!(
+                "discarded truncated record ({} bytes, {} declared)",
+                buf.len() - offset,
+                pkt_len
+            );
+            break;
         }
 
         out.push(buf[offset..offset + pkt_len].to_vec());
         offset += pkt_len
     }
 
-    Ok(out)
+    out
 }
@@ -10,6 +10,10 @@ pub const MAX_SEQUENCE_NUMBER: u64 = 0x0000FFFFFFFFFFFF;
 
 pub const DTLS1_2MAJOR: u8 = 0xfe;
 pub const DTLS1_2MINOR: u8 = 0xfd;
+#[cfg(feature = "dtls13")]
+pub const DTLS1_3MAJOR: u8 = 0xfe;
+#[cfg(feature = "dtls13")]
+pub const DTLS1_3MINOR: u8 = 0xfc;
 
 pub const DTLS1_0MAJOR: u8 = 0xfe;
 pub const DTLS1_0MINOR: u8 = 0xff;
@@ -26,6 +30,11 @@ pub const PROTOCOL_VERSION1_2: ProtocolVersion = ProtocolVersion {
     major: DTLS1_2MAJOR,
     minor: DTLS1_2MINOR,
 };
+#[cfg(feature = "dtls13")]
+pub const PROTOCOL_VERSION1_3: ProtocolVersion = ProtocolVersion {
+    major: DTLS1_3MAJOR,
+    minor: DTLS1_3MINOR,
+};
 
 // https://tools.ietf.org/html/rfc4346#section-6.2.1
 #[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
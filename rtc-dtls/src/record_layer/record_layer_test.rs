@@ -15,7 +15,6 @@ fn test_udp_decode() -> Result<()> {
             vec![vec![
                 0x14, 0xfe, 0xff, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x12, 0x00, 0x01, 0x01,
             ]],
-            None,
         ),
         (
             "Change Cipher Spec, multi packet",
@@ -33,40 +32,33 @@ fn test_udp_decode() -> Result<()> {
                     0x01,
                 ],
             ],
-            None,
-        ),
-        (
-            "Invalid packet length",
-            vec![0x14, 0xfe],
-            vec![],
-            Some(Error::ErrInvalidPacketLength),
         ),
+        ("Invalid packet length", vec![0x14, 0xfe], vec![]),
         (
             "Packet declared invalid length",
             vec![
                 0x14, 0xfe, 0xff, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x12, 0x00, 0xFF, 0x01,
             ],
             vec![],
-            Some(Error::ErrInvalidPacketLength),
+        ),
+        (
+            "Packet truncated after a whole packet",
+            vec![
+                0x14, 0xfe, 0xff, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x12, 0x00, 0x01, 0x01,
+                0x14, 0xfe, 0xff, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x13, 0x00, 0x02, 0x01,
+            ],
+            vec![vec![
+                0x14, 0xfe, 0xff, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x12, 0x00, 0x01, 0x01,
+            ]],
         ),
     ];
 
-    for (name, data, wanted, wanted_err) in tests {
-        let dtls_pkts = unpack_datagram(&data);
-        if let Some(err) = wanted_err {
-            if let Err(dtls) = dtls_pkts {
-                assert_eq!(err.to_string(), dtls.to_string());
-            } else {
-                panic!("something wrong for {name} when wanted_err is Some");
-            }
-        } else if let Ok(pkts) = dtls_pkts {
-            assert_eq!(
-                wanted, pkts,
-                "{name} UDP decode: got {pkts:?}, want {wanted:?}",
-            );
-        } else {
-            panic!("something wrong for {name} when wanted_err is None");
-        }
+    for (name, data, wanted) in tests {
+        let pkts = unpack_datagram(&data);
+        assert_eq!(
+            wanted, pkts,
+            "{name} UDP decode: got {pkts:?}, want {wanted:?}",
+        );
     }
 
     Ok(())
@@ -4,11 +4,14 @@ use super::curve::named_curve::*;
 use super::extension::extension_use_srtp::SrtpProtectionProfile;
 use super::handshake::handshake_random::*;
 use super::prf::*;
+use super::record_layer::record_layer_header::MAX_SEQUENCE_NUMBER;
+use super::signature_hash_algorithm::SignatureHashAlgorithm;
 use shared::error::*;
 
 use serde::{Deserialize, Serialize};
 use shared::crypto::KeyingMaterialExporter;
 use std::io::{BufWriter, Cursor};
+use std::net::SocketAddr;
 
 // State holds the dtls connection state and implements both encoding.BinaryMarshaler and encoding.BinaryUnmarshaler
 pub struct State {
@@ -21,6 +24,7 @@ pub struct State {
     pub(crate) cipher_suite: Option<Box<dyn CipherSuite>>, // nil if a cipher_suite hasn't been chosen
 
     pub(crate) srtp_protection_profile: SrtpProtectionProfile, // Negotiated srtp_protection_profile
+    pub(crate) srtp_mki: Vec<u8>, // Negotiated srtp_mki, empty if MKI is not in use
     pub peer_certificates: Vec<Vec<u8>>,
     pub identity_hint: Vec<u8>,
 
@@ -32,9 +36,11 @@ pub struct State {
     pub(crate) named_curve: NamedCurve,
     pub(crate) local_keypair: Option<NamedCurveKeypair>,
     pub(crate) cookie: Vec<u8>,
+    pub(crate) remote_addr: Option<SocketAddr>, // used to bind the HelloVerifyRequest cookie to the client
     pub(crate) handshake_send_sequence: isize,
     pub(crate) handshake_recv_sequence: isize,
     pub(crate) server_name: String,
+    pub(crate) remote_signature_schemes: Vec<SignatureHashAlgorithm>, // from the ClientHello
     pub(crate) remote_requested_certificate: bool, // Did we get a CertificateRequest
     pub(crate) local_certificates_verify: Vec<u8>, // cache CertificateVerify
     pub(crate) local_verify_data: Vec<u8>,         // cached VerifyData
@@ -53,6 +59,7 @@ struct SerializedState {
     master_secret: Vec<u8>,
     sequence_number: u64,
     srtp_protection_profile: u16,
+    srtp_mki: Vec<u8>,
     peer_certificates: Vec<Vec<u8>>,
     identity_hint: Vec<u8>,
     is_client: bool,
@@ -70,6 +77,7 @@ impl Default for State {
             cipher_suite: None, // nil if a cipher_suite hasn't been chosen
 
             srtp_protection_profile: SrtpProtectionProfile::Unsupported, // Negotiated srtp_protection_profile
+            srtp_mki: vec![],
             peer_certificates: vec![],
             identity_hint: vec![],
 
@@ -81,9 +89,11 @@ impl Default for State {
             named_curve: NamedCurve::Unsupported,
             local_keypair: None,
             cookie: vec![],
+            remote_addr: None,
             handshake_send_sequence: 0,
             handshake_recv_sequence: 0,
             server_name: "".to_string(),
+            remote_signature_schemes: vec![],
             remote_requested_certificate: false, // Did we get a CertificateRequest
             local_certificates_verify: vec![],   // cache CertificateVerify
             local_verify_data: vec![],           // cached VerifyData
@@ -132,6 +142,7 @@ impl State {
             master_secret: self.master_secret.clone(),
             sequence_number,
             srtp_protection_profile: self.srtp_protection_profile as u16,
+            srtp_mki: self.srtp_mki.clone(),
             peer_certificates: self.peer_certificates.clone(),
             identity_hint: self.identity_hint.clone(),
             is_client: self.is_client,
@@ -166,6 +177,7 @@ impl State {
         self.cipher_suite = Some(cipher_suite_for_id(serialized.cipher_suite_id.into())?);
 
         self.srtp_protection_profile = serialized.srtp_protection_profile.into();
+        self.srtp_mki.clone_from(&serialized.srtp_mki);
 
         // Set remote certificate
         self.peer_certificates
@@ -175,6 +187,47 @@ impl State {
         Ok(())
     }
 
+    /// Builds the state of a server connection resuming a session the application kept from a
+    /// prior handshake, to be passed to `DTLSConn::new` which then skips to Flight6.
+    /// The master secret must be the 48 bytes negotiated in that session, and the randoms are
+    /// the server (local) and client (remote) randoms of its hellos. The epoch and the next
+    /// sequence number are those the session stopped at, so that no record sequence number is
+    /// reused with its keys.
+    pub fn for_resumption(
+        master_secret: Vec<u8>,
+        cipher_suite_id: CipherSuiteId,
+        srtp_protection_profile: SrtpProtectionProfile,
+        local_random: HandshakeRandom,
+        remote_random: HandshakeRandom,
+        epoch: u16,
+        next_sequence_number: u64,
+    ) -> Result<Self> {
+        if master_secret.len() != MASTER_SECRET_LENGTH {
+            return Err(Error::ErrInvalidMasterSecret);
+        }
+        if next_sequence_number > MAX_SEQUENCE_NUMBER {
+            return Err(Error::ErrSequenceNumberOverflow);
+        }
+
+        let mut local_sequence_number = vec![0; epoch as usize + 1];
+        local_sequence_number[epoch as usize] = next_sequence_number;
+        let mut state = State {
+            local_epoch: epoch,
+            remote_epoch: epoch,
+            local_sequence_number,
+            local_random,
+            remote_random,
+            master_secret,
+            cipher_suite: Some(cipher_suite_for_id(cipher_suite_id)?),
+            srtp_protection_profile,
+            is_client: false,
+            ..Default::default()
+        };
+        state.init_cipher_suite()?;
+
+        Ok(state)
+    }
+
     pub fn init_cipher_suite(&mut self) -> Result<()> {
         if let Some(cipher_suite) = &mut self.cipher_suite {
             if cipher_suite.is_initialized() {
@@ -227,6 +280,11 @@ impl State {
     pub fn srtp_protection_profile(&self) -> SrtpProtectionProfile {
         self.srtp_protection_profile
     }
+
+    /// Negotiated SRTP Master Key Identifier, empty if MKI is not in use
+    pub fn srtp_mki(&self) -> &[u8] {
+        &self.srtp_mki
+    }
 }
 
 impl KeyingMaterialExporter for State {
@@ -15,6 +15,14 @@ pub(crate) const DEFAULT_DISCONNECTED_TIMEOUT: Duration = Duration::from_secs(5)
 /// The default time till an Agent transitions to failed after disconnected.
 pub(crate) const DEFAULT_FAILED_TIMEOUT: Duration = Duration::from_secs(25);
 
+/// The default time till consent to send to the remote agent expires without a response to a
+/// consent check (RFC 7675 Section 5.1).
+pub(crate) const DEFAULT_CONSENT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// The default value of the SOFTWARE attribute of outbound STUN messages.
+pub(crate) const DEFAULT_SOFTWARE: &str =
+    concat!(env!("CARGO_PKG_NAME"), " ", env!("CARGO_PKG_VERSION"));
+
 /// Wait time before nominating a host candidate.
 pub(crate) const DEFAULT_HOST_ACCEPTANCE_MIN_WAIT: Duration = Duration::from_secs(0);
 
@@ -33,9 +41,21 @@ pub(crate) const DEFAULT_MAX_BINDING_REQUESTS: u16 = 7;
 /// The number of bytes that can be buffered before we start to error.
 pub(crate) const MAX_BUFFER_SIZE: usize = 1000 * 1000; // 1MB
 
+/// Initial retransmission timeout of an unanswered binding request.
+pub(crate) const DEFAULT_BINDING_REQUEST_RTO: Duration = Duration::from_millis(500);
+
 /// Wait time before binding requests can be deleted.
 pub(crate) const MAX_BINDING_REQUEST_TIMEOUT: Duration = Duration::from_millis(4000);
 
+/// Max number of binding requests waiting for a response, the oldest one is dropped beyond.
+pub(crate) const MAX_PENDING_BINDING_REQUESTS: usize = 512;
+
+/// Max length of a ufrag in characters, RFC 8445 Section 5.3.
+pub(crate) const MAX_UFRAG_LEN: usize = 256;
+
+/// Max length of a pwd in characters, RFC 8445 Section 5.3.
+pub(crate) const MAX_PWD_LEN: usize = 256;
+
 pub(crate) fn default_candidate_types() -> Vec<CandidateType> {
     vec![
         CandidateType::Host,
@@ -44,6 +64,9 @@ pub(crate) fn default_candidate_types() -> Vec<CandidateType> {
     ]
 }
 
+/// Decides whether a local candidate may be used, returning false to reject it.
+pub type CandidateFilterFn = Arc<dyn Fn(&Candidate) -> bool + Send + Sync>;
+
 /// Collects the arguments to `ice::Agent` construction into a single structure, for
 /// future-proofness of the interface.
 #[derive(Default)]
@@ -72,23 +95,89 @@ pub struct AgentConfig {
     /// A keepalive interval of 0 means we never send keepalive packets
     pub keepalive_interval: Option<Duration>,
 
+    /// Fails the connection when no binding request on the selected pair got a response for
+    /// this long, as consent to send is then revoked (RFC 7675). Consent checks are sent every
+    /// sixth of it, i.e. every 5 seconds by default.
+    /// Defaults to 30 seconds when this property is nil.
+    /// If the duration is 0, consent never expires.
+    pub consent_timeout: Option<Duration>,
+
     /// An optional configuration for disabling or enabling support for specific candidate types.
     pub candidate_types: Vec<CandidateType>,
 
+    /// An optional policy consulted by `add_local_candidate` for every local candidate (host,
+    /// srflx or relay alike), e.g. to exclude VPN interfaces, IPv6 or a specific subnet.
+    /// Candidates it rejects are skipped and never paired.
+    pub candidate_filter: Option<CandidateFilterFn>,
+
+    /// Caps the number of local candidates, bounding both the signaling size and the number
+    /// of candidate pairs to check. Candidates rejected by `candidate_filter` don't count
+    /// towards it. Once the cap is reached, `add_local_candidate` ignores a new candidate,
+    /// unless no candidate has been paired yet and it has a higher priority than one already
+    /// added whose `Event::NewCandidate` was not polled yet, which it then replaces along with
+    /// that event. When this is nil, local candidates are not capped.
+    pub max_local_candidates: Option<usize>,
+
     /// Controls how often our internal task loop runs when in the connecting state.
     /// Only useful for testing.
     pub check_interval: Duration,
 
+    /// Paces connectivity checks with the Ta timer of RFC 8445 Section 14: at most one binding
+    /// request is sent per ta_interval, cycling through the pairs in priority order. When this
+    /// is nil, every pair that needs a check is pinged on each check interval.
+    pub ta_interval: Option<Duration>,
+
     /// The max amount of binding requests the agent will send over a candidate pair for validation
     /// or nomination, if after max_binding_requests the candidate is yet to answer a binding
     /// request or a nomination we set the pair as failed.
     pub max_binding_requests: Option<u16>,
 
+    /// Initial retransmission timeout (RTO) of a binding request, defaults to 500 milliseconds.
+    /// An unanswered request is resent with the same transaction id after RTO, 2*RTO, 4*RTO...
+    /// until it expires. An RTO of 0 disables retransmissions.
+    pub binding_request_rto: Option<Duration>,
+
     pub is_controlling: bool,
 
     /// lite agents do not perform connectivity check and only provide host candidates.
     pub lite: bool,
 
+    /// Enables ICE renomination, letting the controlling agent move the selected pair to
+    /// another valid pair without an ICE restart. It is only used once the remote agent has
+    /// advertised support too, see `Agent::set_remote_renomination`.
+    pub enable_renomination: bool,
+
+    /// Includes the NETWORK-COST attribute of the local candidate in binding requests, and
+    /// makes the controlled agent prefer the nominated pair with the lowest network cost, as
+    /// Chrome does. It is a non-standard extension of Google-flavored ICE.
+    pub enable_network_cost: bool,
+
+    /// Appends the FINGERPRINT attribute to every outbound STUN message, defaults to true when
+    /// this property is nil. Disabling it is only meant for interop with legacy ICE
+    /// implementations that mishandle it; inbound messages are accepted either way.
+    pub include_fingerprint: Option<bool>,
+
+    /// Value of the SOFTWARE attribute (RFC 5389 Section 15.10) of outbound STUN messages,
+    /// identifying this stack in packet captures. Defaults to the crate name and version when
+    /// this property is nil, an empty string omits the attribute.
+    pub software: Option<String>,
+
+    /// Sends STUN binding indications as keepalives on the selected pair instead of binding
+    /// requests. Indications need no response, so they reduce keepalive traffic, but they
+    /// don't refresh consent (RFC 7675): consent checks are still sent as binding requests.
+    pub keepalive_uses_indication: bool,
+
+    /// Announces support for GOOG-PING in binding requests and responses, and once the remote
+    /// agent announced it too, sends keepalives on the selected pair as compact GOOG-PING
+    /// requests that only carry a 32 bit message integrity, as Chrome does. It is a
+    /// non-standard extension of Google-flavored ICE.
+    pub enable_goog_ping: bool,
+
+    /// Number of components of the media stream: 1 with RTCP multiplexed on the RTP component,
+    /// or 2 for separate RTP (1) and RTCP (2) components. The agent only reports Connected once
+    /// every component has a selected pair. Defaults to 1 if this property is 0.
+    pub components: usize,
+
     /// Specify a minimum wait time before selecting host candidates.
     pub host_acceptance_min_wait: Option<Duration>,
 
@@ -104,4 +193,9 @@ pub struct AgentConfig {
     /// Controls if self-signed certificates are accepted when connecting to TURN servers via TLS or
     /// DTLS.
     pub insecure_skip_verify: bool,
+
+    /// Seeds the random number generator used for the local ufrag/pwd, the tie-breaker, peer
+    /// reflexive candidate ids and STUN transaction ids, making them reproducible.
+    /// Only useful for testing. When this is nil, the thread RNG is used.
+    pub rng_seed: Option<u64>,
 }
@@ -0,0 +1,216 @@
+use crate::agent::Agent;
+use log::{debug, error, trace, warn};
+use std::net::SocketAddr;
+use stun::error_code::*;
+use stun::integrity::*;
+use stun::message::*;
+
+use crate::attributes::goog_misc_info::*;
+use shared::error::*;
+
+impl Agent {
+    /// Pings the selected pair to keep it alive and refresh consent, with a compact GOOG-PING
+    /// once the remote agent announced support for it on the pair, with a full binding request
+    /// otherwise.
+    pub(crate) fn keepalive_ping(&mut self, local_index: usize, remote_index: usize) {
+        let goog_ping = self.enable_goog_ping
+            && self
+                .find_pair(local_index, remote_index)
+                .is_some_and(|pair_index| {
+                    self.candidate_pairs[pair_index].remote_supports_goog_ping
+                });
+        if goog_ping {
+            self.send_goog_ping(local_index, remote_index);
+        } else {
+            self.ping_candidate(local_index, remote_index);
+        }
+    }
+
+    // GOOG-PING requests only carry a 32 bit message integrity, the remote agent authenticates
+    // them with the credentials of the binding requests it already answered on the pair
+    fn send_goog_ping(&mut self, local_index: usize, remote_index: usize) {
+        let transaction_id = self.new_transaction_id();
+        let Some(remote_credentials) = &self.ufrag_pwd.remote_credentials else {
+            error!("ufrag_pwd.remote_credentials is none");
+            return;
+        };
+
+        let setters: Vec<Box<dyn Setter>> = vec![
+            Box::new(MessageType::new(METHOD_GOOG_PING, CLASS_REQUEST)),
+            Box::new(transaction_id),
+            Box::new(MessageIntegrity32::new_short_term_integrity(
+                remote_credentials.pwd.clone(),
+            )),
+        ];
+        let mut msg = Message::new();
+        if let Err(err) = msg.build(&setters) {
+            error!("{}", err);
+            return;
+        }
+
+        trace!(
+            "[{}]: GOOG-PING from {} to {}",
+            self.get_name(),
+            local_index,
+            remote_index
+        );
+        self.send_binding_request(&msg, local_index, remote_index);
+    }
+
+    // GOOG-MISC-INFO setter announcing GOOG-PING support in binding requests, if enabled
+    pub(crate) fn goog_misc_info(&self) -> Option<Box<dyn Setter>> {
+        self.enable_goog_ping
+            .then(|| Box::new(GoogMiscInfoAttr::goog_ping()) as Box<dyn Setter>)
+    }
+
+    // GOOG-MISC-INFO setter of the response to a binding request, announcing GOOG-PING support
+    // back to a remote agent that announced it
+    pub(crate) fn goog_misc_info_response(&self, m: &Message) -> Option<Box<dyn Setter>> {
+        let mut goog_misc_info = GoogMiscInfoAttr::default();
+        if self.enable_goog_ping
+            && goog_misc_info.get_from(m).is_ok()
+            && goog_misc_info.supports_goog_ping()
+        {
+            self.goog_misc_info()
+        } else {
+            None
+        }
+    }
+
+    /// Records whether the remote agent announced GOOG-PING support in a success response to
+    /// a binding request on the pair.
+    pub(crate) fn update_remote_goog_ping_support(
+        &mut self,
+        m: &Message,
+        local_index: usize,
+        remote_index: usize,
+    ) {
+        if !self.enable_goog_ping {
+            return;
+        }
+
+        let mut goog_misc_info = GoogMiscInfoAttr::default();
+        let supports_goog_ping =
+            goog_misc_info.get_from(m).is_ok() && goog_misc_info.supports_goog_ping();
+        if let Some(pair_index) = self.find_pair(local_index, remote_index) {
+            self.candidate_pairs[pair_index].remote_supports_goog_ping = supports_goog_ping;
+        }
+    }
+
+    /// Processes a GOOG-PING request or response from a remote candidate. A request is answered
+    /// on a known pair and rejected otherwise, so that the remote agent falls back to binding
+    /// requests. A response is handled like the one to a binding request, and an error response
+    /// makes the agent fall back to binding requests on the pair.
+    pub(crate) fn handle_inbound_goog_ping(
+        &mut self,
+        m: &mut Message,
+        local_index: usize,
+        remote_addr: SocketAddr,
+    ) -> Result<()> {
+        let Some(remote_credentials) = &self.ufrag_pwd.remote_credentials else {
+            debug!(
+                "[{}]: ufrag_pwd.remote_credentials.is_none",
+                self.get_name(),
+            );
+            return Err(Error::ErrPasswordEmpty);
+        };
+        let key = if m.typ.class == CLASS_REQUEST {
+            self.ufrag_pwd.local_credentials.pwd.clone()
+        } else {
+            remote_credentials.pwd.clone()
+        };
+        if let Err(err) = MessageIntegrity32::new_short_term_integrity(key).check(m) {
+            warn!(
+                "[{}]: discard GOOG-PING from ({}), {}",
+                self.get_name(),
+                remote_addr,
+                err
+            );
+            return Err(err);
+        }
+
+        let remote_index = self.find_remote_candidate(remote_addr);
+        if m.typ.class == CLASS_REQUEST {
+            let known_pair = remote_index
+                .and_then(|remote_index| self.find_pair(local_index, remote_index))
+                .is_some();
+            self.send_goog_ping_response(m, local_index, remote_addr, known_pair);
+        } else if m.typ.class == CLASS_SUCCESS_RESPONSE {
+            let Some(remote_index) = remote_index else {
+                warn!(
+                    "[{}]: discard GOOG-PING response from ({}), no such remote",
+                    self.get_name(),
+                    remote_addr
+                );
+                return Err(Error::ErrUnhandledStunpacket);
+            };
+            self.handle_success_response(m, local_index, remote_index, remote_addr);
+        } else if m.typ.class == CLASS_ERROR_RESPONSE {
+            let Some(pending_request) = self.handle_inbound_binding_success(m.transaction_id)
+            else {
+                warn!(
+                    "[{}]: discard GOOG-PING error response from ({}), unknown TransactionID 0x{:?}",
+                    self.get_name(),
+                    remote_addr,
+                    m.transaction_id
+                );
+                return Err(Error::ErrUnhandledStunpacket);
+            };
+            debug!(
+                "[{}]: GOOG-PING rejected by ({}), falling back to binding requests",
+                self.get_name(),
+                remote_addr
+            );
+            if let Some(pair_index) =
+                self.find_pair(pending_request.local_index, pending_request.remote_index)
+            {
+                self.candidate_pairs[pair_index].remote_supports_goog_ping = false;
+            }
+        } else {
+            return Err(Error::ErrUnhandledStunpacket);
+        }
+
+        if let Some(remote_index) = remote_index {
+            self.remote_candidates[remote_index].seen(false);
+        }
+
+        Ok(())
+    }
+
+    fn send_goog_ping_response(
+        &mut self,
+        m: &Message,
+        local_index: usize,
+        remote_addr: SocketAddr,
+        success: bool,
+    ) {
+        let mut setters: Vec<Box<dyn Setter>> = vec![Box::new(m.clone())];
+        if success {
+            setters.push(Box::new(MessageType::new(
+                METHOD_GOOG_PING,
+                CLASS_SUCCESS_RESPONSE,
+            )));
+        } else {
+            setters.push(Box::new(MessageType::new(
+                METHOD_GOOG_PING,
+                CLASS_ERROR_RESPONSE,
+            )));
+            setters.push(Box::new(CODE_BAD_REQUEST));
+        }
+        setters.push(Box::new(MessageIntegrity32::new_short_term_integrity(
+            self.ufrag_pwd.local_credentials.pwd.clone(),
+        )));
+
+        let mut out = Message::new();
+        if let Err(err) = out.build(&setters) {
+            warn!(
+                "[{}]: Failed to answer GOOG-PING from: {} error: {}",
+                self.get_name(),
+                remote_addr,
+                err
+            );
+            return;
+        }
+        self.transmit_stun(&out, local_index, remote_addr);
+    }
+}
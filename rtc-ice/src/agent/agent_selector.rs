@@ -3,12 +3,13 @@ use log::{debug, error, trace, warn};
 use std::net::SocketAddr;
 use std::time::{Duration, Instant};
 use stun::attributes::*;
-use stun::fingerprint::*;
 use stun::integrity::*;
 use stun::message::*;
 use stun::textattrs::*;
 
-use crate::attributes::{control::*, priority::*, use_candidate::*};
+use crate::attributes::{
+    control::*, network_cost::*, nomination::*, priority::*, use_candidate::*,
+};
 use crate::candidate::{candidate_pair::*, *};
 
 trait ControllingSelector {
@@ -86,7 +87,11 @@ impl Agent {
         }
     }
 
-    fn nominate_pair(&mut self) {
+    pub(crate) fn nominate_pair(&mut self) {
+        // With renomination, the controlled agent follows the highest nomination
+        // rather than the highest priority nominated pair
+        let nomination = self.is_renomination_enabled().then_some(self.nomination);
+        let transaction_id = self.new_transaction_id();
         let result = {
             let Some(remote_credentials) = &self.ufrag_pwd.remote_credentials else {
                 error!("ufrag_pwd.remote_credentials is none");
@@ -103,19 +108,29 @@ impl Agent {
                     let username = remote_credentials.ufrag.clone()
                         + ":"
                         + self.ufrag_pwd.local_credentials.ufrag.as_str();
-                    let mut msg = Message::new();
-                    let result = msg.build(&[
+                    let mut setters: Vec<Box<dyn Setter>> = vec![
                         Box::new(BINDING_REQUEST),
-                        Box::new(TransactionId::new()),
+                        Box::new(transaction_id),
                         Box::new(Username::new(ATTR_USERNAME, username)),
                         Box::<UseCandidateAttr>::default(),
                         Box::new(AttrControlling(self.tie_breaker)),
                         Box::new(PriorityAttr(pair.local_priority)),
-                        Box::new(MessageIntegrity::new_short_term_integrity(
-                            remote_credentials.pwd.clone(),
-                        )),
-                        Box::new(FINGERPRINT),
-                    ]);
+                    ];
+                    if let Some(nomination) = nomination {
+                        setters.push(Box::new(NominationAttr(nomination)));
+                    }
+                    if let Some(network_cost) = self.network_cost_attr(pair.local_index) {
+                        setters.push(Box::new(network_cost));
+                    }
+                    setters.extend(self.goog_misc_info());
+                    setters.extend(self.software());
+                    setters.push(Box::new(MessageIntegrity::new_short_term_integrity(
+                        remote_credentials.pwd.clone(),
+                    )));
+                    setters.extend(self.fingerprint());
+
+                    let mut msg = Message::new();
+                    let result = msg.build(&setters);
                     (msg, result)
                 };
 
@@ -142,6 +157,133 @@ impl Agent {
         }
     }
 
+    /// Selects the highest-priority pair of a component that has been nominated by the
+    /// controlling agent and produced a successful connectivity check, unless the current
+    /// selection of the component is already better.
+    fn select_best_nominated_pair(&mut self, component: u16) {
+        let Some(best_pair_index) = self.get_best_nominated_candidate_pair(component) else {
+            return;
+        };
+
+        let should_select = match self.selected_pair_of(component) {
+            Some(selected_pair_index) => {
+                selected_pair_index != best_pair_index
+                    && self.is_preferred_nominated_pair(best_pair_index, selected_pair_index)
+            }
+            None => true,
+        };
+
+        if should_select {
+            self.set_selected_pair(Some(best_pair_index));
+        }
+    }
+
+    /// Returns whether the nominated pair at pair_index is better than the one at other_index.
+    /// With the NETWORK-COST extension, the pair with the lowest network cost is preferred, and
+    /// the priority only decides between pairs of equal cost.
+    pub(crate) fn is_preferred_nominated_pair(
+        &self,
+        pair_index: usize,
+        other_index: usize,
+    ) -> bool {
+        let (p, other) = (
+            &self.candidate_pairs[pair_index],
+            &self.candidate_pairs[other_index],
+        );
+        if self.enable_network_cost {
+            let network_cost = |p: &CandidatePair| {
+                u32::from(self.local_candidates[p.local_index].network_cost())
+                    + u32::from(self.remote_candidates[p.remote_index].network_cost())
+            };
+            if network_cost(p) != network_cost(other) {
+                return network_cost(p) < network_cost(other);
+            }
+        }
+        p.priority(self.is_controlling) > other.priority(self.is_controlling)
+    }
+
+    /// Records the network cost the remote agent advertised for its candidate in the
+    /// NETWORK-COST attribute of a binding request.
+    pub(crate) fn update_remote_network_cost(&mut self, m: &Message, remote_index: usize) {
+        if !self.enable_network_cost {
+            return;
+        }
+
+        let mut network_cost = NetworkCostAttr::default();
+        if network_cost.get_from(m).is_ok() {
+            let c = &mut self.remote_candidates[remote_index];
+            c.network_id = network_cost.network_id;
+            c.network_cost = network_cost.network_cost;
+        }
+    }
+
+    // NETWORK-COST attribute of a local candidate, if the extension is enabled
+    fn network_cost_attr(&self, local_index: usize) -> Option<NetworkCostAttr> {
+        self.enable_network_cost.then(|| {
+            let c = &self.local_candidates[local_index];
+            NetworkCostAttr {
+                network_id: c.network_id(),
+                network_cost: c.network_cost(),
+            }
+        })
+    }
+
+    /// Returns whether a binding request carries a renomination newer than any seen before,
+    /// recording its value. Renominations are ignored unless both agents support them.
+    fn accept_renomination(&mut self, m: &Message) -> bool {
+        if !self.is_renomination_enabled() {
+            return false;
+        }
+
+        let mut nomination = NominationAttr::default();
+        if nomination.get_from(m).is_err() || nomination.0 <= self.remote_nomination {
+            return false;
+        }
+
+        self.remote_nomination = nomination.0;
+        true
+    }
+
+    /// Returns whether a nomination request sent by the controlling agent carries its latest
+    /// nomination value. Without renomination every nomination is the latest.
+    fn is_latest_nomination(&self, m: &Message) -> bool {
+        let mut nomination = NominationAttr::default();
+        !self.is_renomination_enabled()
+            || nomination.get_from(m).is_err()
+            || nomination.0 == self.nomination
+    }
+
+    /// Selects a pair the controlling agent renominated, whatever the priority of the current
+    /// selection.
+    fn select_renominated_pair(&mut self, pair_index: usize) {
+        if self.selected_pair_of(self.pair_component(pair_index)) != Some(pair_index) {
+            self.set_selected_pair(Some(pair_index));
+        }
+    }
+
+    /// Sends a triggered check on the pair a binding request was received on
+    /// (RFC 8445 Section 7.3.1.4). A pair that already succeeded is not checked again, and a
+    /// failed pair gets a fresh budget of binding requests.
+    fn triggered_check(&mut self, pair_index: usize) {
+        let p = &mut self.candidate_pairs[pair_index];
+        match p.state {
+            CandidatePairState::Succeeded => return,
+            CandidatePairState::Failed => p.binding_request_count = 0,
+            _ => {}
+        }
+        p.state = CandidatePairState::InProgress;
+        p.binding_request_count += 1;
+
+        let (local_index, remote_index) = (p.local_index, p.remote_index);
+        trace!(
+            "[{}]: triggered check from {} to {}",
+            self.get_name(),
+            local_index,
+            remote_index
+        );
+        self.ping_candidate(local_index, remote_index);
+    }
+
     pub(crate) fn start(&mut self) {
         if self.is_controlling {
             ControllingSelector::start(self);
@@ -215,32 +357,42 @@ impl ControllingSelector for Agent {
     }
 
     fn contact_candidates(&mut self) {
-        // A lite selector should not contact candidates
-        if self.lite {
-            // This only happens if both peers are lite. See RFC 8445 S6.1.1 and S6.2
-            trace!("now falling back to full agent");
-        }
-
         let nominated_pair_is_some = self.nominated_pair.is_some();
+        // Components are nominated one at a time, in order
+        let pending_component = self.pending_component();
 
-        if self.get_selected_pair().is_some() {
+        // A lite selector should not contact candidates
+        if self.lite {
+            self.validate_selected_pair();
+        } else if let (None, Some(selected_pair_index)) =
+            (pending_component, self.get_selected_pair())
+        {
             if self.validate_selected_pair() {
                 self.check_keepalive();
             }
-        } else if nominated_pair_is_some {
+            // Retransmit a renomination until the controlled agent acknowledges it
+            if nominated_pair_is_some && self.nominated_pair != Some(selected_pair_index) {
+                self.nominate_pair();
+            }
+        } else if nominated_pair_is_some
+            && self
+                .nominated_pair
+                .map(|pair_index| self.pair_component(pair_index))
+                == pending_component
+        {
             self.nominate_pair();
-        } else {
-            let has_nominated_pair = if let Some(pair_index) = self.get_best_valid_candidate_pair()
-            {
-                let p = self.candidate_pairs[pair_index];
-                self.is_nominatable(p.local_index, true)
-                    && self.is_nominatable(p.remote_index, false)
-            } else {
-                false
-            };
+        } else if let Some(component) = pending_component {
+            let has_nominated_pair =
+                if let Some(pair_index) = self.get_best_valid_candidate_pair(component) {
+                    let p = self.candidate_pairs[pair_index];
+                    self.is_nominatable(p.local_index, true)
+                        && self.is_nominatable(p.remote_index, false)
+                } else {
+                    false
+                };
 
             if has_nominated_pair {
-                if let Some(pair_index) = self.get_best_valid_candidate_pair() {
+                if let Some(pair_index) = self.get_best_valid_candidate_pair(component) {
                     let p = &mut self.candidate_pairs[pair_index];
                     trace!(
                         "Nominatable pair found, nominating ({}, {})",
@@ -248,17 +400,19 @@ impl ControllingSelector for Agent {
                         self.remote_candidates[p.remote_index],
                     );
                     p.nominated = true;
+                    self.nomination += 1;
                     self.nominated_pair = Some(pair_index);
                 }
 
                 self.nominate_pair();
             } else {
-                self.ping_all_candidates();
+                self.ping_candidates();
             }
         }
     }
 
     fn ping_candidate(&mut self, local_index: usize, remote_index: usize) {
+        let transaction_id = self.new_transaction_id();
         let (msg, result) = {
             let Some(remote_credentials) = &self.ufrag_pwd.remote_credentials else {
                 error!("ufrag_pwd.remote_credentials is none");
@@ -268,18 +422,25 @@ impl ControllingSelector for Agent {
             let username = remote_credentials.ufrag.clone()
                 + ":"
                 + self.ufrag_pwd.local_credentials.ufrag.as_str();
-            let mut msg = Message::new();
-            let result = msg.build(&[
+            let mut setters: Vec<Box<dyn Setter>> = vec![
                 Box::new(BINDING_REQUEST),
-                Box::new(TransactionId::new()),
+                Box::new(transaction_id),
                 Box::new(Username::new(ATTR_USERNAME, username)),
                 Box::new(AttrControlling(self.tie_breaker)),
                 Box::new(PriorityAttr(self.local_candidates[local_index].priority())),
-                Box::new(MessageIntegrity::new_short_term_integrity(
-                    remote_credentials.pwd.clone(),
-                )),
-                Box::new(FINGERPRINT),
-            ]);
+            ];
+            if let Some(network_cost) = self.network_cost_attr(local_index) {
+                setters.push(Box::new(network_cost));
+            }
+            setters.extend(self.goog_misc_info());
+            setters.extend(self.software());
+            setters.push(Box::new(MessageIntegrity::new_short_term_integrity(
+                remote_credentials.pwd.clone(),
+            )));
+            setters.extend(self.fingerprint());
+
+            let mut msg = Message::new();
+            let result = msg.build(&setters);
             (msg, result)
         };
 
@@ -312,11 +473,20 @@ impl ControllingSelector for Agent {
                 remote_index,
                 local_index
             );
-            let selected_pair_is_none = self.get_selected_pair().is_none();
-
             if let Some(pair_index) = self.find_pair(local_index, remote_index) {
+                let selected_pair_is_none = self
+                    .selected_pair_of(self.pair_component(pair_index))
+                    .is_none();
+                // With renomination, the controlled agent ignores nominations older than one it
+                // already followed, so only the latest one is concluded
+                let concludes_nomination = pending_request.is_use_candidate
+                    && self.is_latest_nomination(&pending_request.message);
                 let p = &mut self.candidate_pairs[pair_index];
                 p.state = CandidatePairState::Succeeded;
+                p.consent_refreshed_at = Some(Instant::now());
+                if let Some(rtt) = pending_request.round_trip_time() {
+                    p.update_round_trip_time(rtt);
+                }
                 trace!(
                     "Found valid candidate pair: {}, p.state: {}, isUseCandidate: {}, {}",
                     *p,
@@ -324,8 +494,18 @@ impl ControllingSelector for Agent {
                     pending_request.is_use_candidate,
                     selected_pair_is_none
                 );
-                if pending_request.is_use_candidate && selected_pair_is_none {
-                    self.set_selected_pair(Some(pair_index));
+
+                // Only a successful response to a check that carried USE-CANDIDATE
+                // concludes the nomination of this pair.
+                if concludes_nomination {
+                    p.nominated = true;
+                    if selected_pair_is_none {
+                        self.set_selected_pair(Some(pair_index));
+                    } else if self.is_renomination_enabled()
+                        && self.nominated_pair == Some(pair_index)
+                    {
+                        self.select_renominated_pair(pair_index);
+                    }
                 }
             } else {
                 // This shouldn't happen
@@ -343,6 +523,26 @@ impl ControllingSelector for Agent {
         self.send_binding_success(m, local_index, remote_index);
         trace!("controllingSelector: sendBindingSuccess");
 
+        // A lite agent never sends checks of its own, so it can't nominate. Instead the pair
+        // of the first request it successfully responded to becomes the selected pair.
+        if self.lite {
+            if self.find_pair(local_index, remote_index).is_none() {
+                self.add_pair(local_index, remote_index);
+            }
+            if let Some(pair_index) = self.find_pair(local_index, remote_index) {
+                let p = &mut self.candidate_pairs[pair_index];
+                p.state = CandidatePairState::Succeeded;
+                p.nominated = true;
+                if self
+                    .selected_pair_of(self.pair_component(pair_index))
+                    .is_none()
+                {
+                    self.set_selected_pair(Some(pair_index));
+                }
+            }
+            return;
+        }
+
         if let Some(pair_index) = self.find_pair(local_index, remote_index) {
             let p = &self.candidate_pairs[pair_index];
             let nominated_pair_is_none = self.nominated_pair.is_none();
@@ -358,7 +558,9 @@ impl ControllingSelector for Agent {
                 && nominated_pair_is_none
                 && self.get_selected_pair().is_none()
             {
-                if let Some(best_pair_index) = self.get_best_available_candidate_pair() {
+                if let Some(best_pair_index) =
+                    self.get_best_available_candidate_pair(self.pair_component(pair_index))
+                {
                     trace!(
                         "controllingSelector: getBestAvailableCandidatePair {}",
                         best_pair_index
@@ -369,16 +571,22 @@ impl ControllingSelector for Agent {
                     {
                         trace!("The candidate ({}, {}) is the best candidate available, marking it as nominated",
                             p.local_index, p.remote_index);
+                        self.nomination += 1;
                         self.nominated_pair = Some(pair_index);
                         self.nominate_pair();
                     }
                 } else {
                     trace!("No best pair available");
                 }
+            } else {
+                self.triggered_check(pair_index);
             }
         } else {
             trace!("controllingSelector: addPair");
             self.add_pair(local_index, remote_index);
+            if let Some(pair_index) = self.find_pair(local_index, remote_index) {
+                self.triggered_check(pair_index);
+            }
         }
     }
 }
@@ -390,16 +598,17 @@ impl ControlledSelector for Agent {
         // A lite selector should not contact candidates
         if self.lite {
             self.validate_selected_pair();
-        } else if self.get_selected_pair().is_some() {
+        } else if self.pending_component().is_none() {
             if self.validate_selected_pair() {
                 self.check_keepalive();
             }
         } else {
-            self.ping_all_candidates();
+            self.ping_candidates();
         }
     }
 
     fn ping_candidate(&mut self, local_index: usize, remote_index: usize) {
+        let transaction_id = self.new_transaction_id();
         let (msg, result) = {
             let Some(remote_credentials) = &self.ufrag_pwd.remote_credentials else {
                 error!("ufrag_pwd.remote_credentials is none");
@@ -409,18 +618,25 @@ impl ControlledSelector for Agent {
             let username = remote_credentials.ufrag.clone()
                 + ":"
                 + self.ufrag_pwd.local_credentials.ufrag.as_str();
-            let mut msg = Message::new();
-            let result = msg.build(&[
+            let mut setters: Vec<Box<dyn Setter>> = vec![
                 Box::new(BINDING_REQUEST),
-                Box::new(TransactionId::new()),
+                Box::new(transaction_id),
                 Box::new(Username::new(ATTR_USERNAME, username)),
                 Box::new(AttrControlled(self.tie_breaker)),
                 Box::new(PriorityAttr(self.local_candidates[local_index].priority())),
-                Box::new(MessageIntegrity::new_short_term_integrity(
-                    remote_credentials.pwd.clone(),
-                )),
-                Box::new(FINGERPRINT),
-            ]);
+            ];
+            if let Some(network_cost) = self.network_cost_attr(local_index) {
+                setters.push(Box::new(network_cost));
+            }
+            setters.extend(self.goog_misc_info());
+            setters.extend(self.software());
+            setters.push(Box::new(MessageIntegrity::new_short_term_integrity(
+                remote_credentials.pwd.clone(),
+            )));
+            setters.extend(self.fingerprint());
+
+            let mut msg = Message::new();
+            let result = msg.build(&setters);
             (msg, result)
         };
 
@@ -463,7 +679,28 @@ impl ControlledSelector for Agent {
             if let Some(pair_index) = self.find_pair(local_index, remote_index) {
                 let p = &mut self.candidate_pairs[pair_index];
                 p.state = CandidatePairState::Succeeded;
-                trace!("Found valid candidate pair: {}", *p);
+                p.consent_refreshed_at = Some(Instant::now());
+                if let Some(rtt) = pending_request.round_trip_time() {
+                    p.update_round_trip_time(rtt);
+                }
+                trace!(
+                    "Found valid candidate pair: {}, nominated: {}",
+                    *p,
+                    p.nominated
+                );
+
+                // https://tools.ietf.org/html/rfc8445#section-7.3.1.5
+                // The triggered check for a pair nominated by the controlling agent
+                // succeeded, so the pair is now both valid and nominated.
+                let nominated = p.nominated;
+                if self.is_renomination_enabled() && self.remote_nomination > 0 {
+                    // Only the latest nomination of the controlling agent is followed
+                    if self.nominated_pair == Some(pair_index) {
+                        self.select_renominated_pair(pair_index);
+                    }
+                } else if nominated {
+                    self.select_best_nominated_pair(self.pair_component(pair_index));
+                }
             } else {
                 // This shouldn't happen
                 error!("Success response from invalid candidate pair");
@@ -482,18 +719,44 @@ impl ControlledSelector for Agent {
         }
 
         if let Some(pair_index) = self.find_pair(local_index, remote_index) {
-            let p = &self.candidate_pairs[pair_index];
-            let use_candidate = m.contains(ATTR_USE_CANDIDATE);
-            if use_candidate {
+            let mut use_candidate = m.contains(ATTR_USE_CANDIDATE);
+            let renominated = use_candidate && self.accept_renomination(m);
+            if renominated {
+                self.nominated_pair = Some(pair_index);
+            } else if use_candidate && self.is_renomination_enabled() && m.contains(ATTR_NOMINATION)
+            {
+                // An outdated nomination, the pair is checked like any other
+                use_candidate = false;
+            }
+
+            if self.lite {
+                // A lite agent never sends checks of its own (RFC 8445 Section 2.5). The pair
+                // becomes valid by responding, and is selected once the full agent nominates it.
+                self.send_binding_success(m, local_index, remote_index);
+                if use_candidate {
+                    let p = &mut self.candidate_pairs[pair_index];
+                    p.state = CandidatePairState::Succeeded;
+                    p.nominated = true;
+                    if renominated {
+                        self.select_renominated_pair(pair_index);
+                    } else {
+                        self.select_best_nominated_pair(self.pair_component(pair_index));
+                    }
+                }
+            } else if use_candidate {
                 // https://tools.ietf.org/html/rfc8445#section-7.3.1.5
+                let p = &mut self.candidate_pairs[pair_index];
+                p.nominated = true;
 
                 if p.state == CandidatePairState::Succeeded {
                     // If the state of this pair is Succeeded, it means that the check
                     // previously sent by this pair produced a successful response and
                     // generated a valid pair (Section 7.2.5.3.2).  The agent sets the
                     // nominated flag value of the valid pair to true.
-                    if self.get_selected_pair().is_none() {
-                        self.set_selected_pair(Some(pair_index));
+                    if renominated {
+                        self.select_renominated_pair(pair_index);
+                    } else {
+                        self.select_best_nominated_pair(self.pair_component(pair_index));
                     }
                     self.send_binding_success(m, local_index, remote_index);
                 } else {
@@ -505,11 +768,12 @@ impl ControlledSelector for Agent {
                     // MUST remove the candidate pair from the valid list, set the
                     // candidate pair state to Failed, and set the checklist state to
                     // Failed.
-                    self.ping_candidate(local_index, remote_index);
+                    self.send_binding_success(m, local_index, remote_index);
+                    self.triggered_check(pair_index);
                 }
             } else {
                 self.send_binding_success(m, local_index, remote_index);
-                self.ping_candidate(local_index, remote_index);
+                self.triggered_check(pair_index);
             }
         }
     }
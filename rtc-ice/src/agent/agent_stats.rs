@@ -65,6 +65,9 @@ pub struct CandidatePairStats {
     /// including those that are sent for consent verification.
     pub current_round_trip_time: f64,
 
+    /// The exponentially weighted moving average of the round trip time measurements in seconds.
+    pub smoothed_round_trip_time: f64,
+
     /// It is calculated by the underlying congestion control by combining the available bitrate for
     /// all the outgoing RTP streams using this candidate pair. The bitrate measurement does not
     /// count the size of the IP or other transport layers like TCP or UDP. It is similar to the
@@ -130,6 +133,7 @@ impl Default for CandidatePairStats {
             last_response_timestamp: Instant::now(),
             total_round_trip_time: 0.0,
             current_round_trip_time: 0.0,
+            smoothed_round_trip_time: 0.0,
             available_outgoing_bitrate: 0.0,
             available_incoming_bitrate: 0.0,
             circuit_breaker_trigger_count: 0,
@@ -221,6 +225,14 @@ impl Agent {
                 remote_candidate_id: self.remote_candidates[cp.remote_index].id(),
                 state: cp.state,
                 nominated: cp.nominated,
+                total_round_trip_time: cp.total_round_trip_time.as_secs_f64(),
+                current_round_trip_time: cp
+                    .current_round_trip_time
+                    .map_or(0.0, |rtt| rtt.as_secs_f64()),
+                smoothed_round_trip_time: cp
+                    .smoothed_round_trip_time
+                    .map_or(0.0, |rtt| rtt.as_secs_f64()),
+                responses_received: cp.responses_received,
                 ..CandidatePairStats::default()
             };
             res.push(stat);
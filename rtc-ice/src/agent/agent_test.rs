@@ -1,4 +1,6 @@
+use std::net::SocketAddrV6;
 use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use stun::message::*;
 use stun::textattrs::Username;
 
@@ -22,7 +24,7 @@ fn test_pair_search() -> Result<()> {
         "TestPairSearch is only a valid test if a.validPairs is empty on construction"
     );
 
-    let cp = a.get_best_available_candidate_pair();
+    let cp = a.get_best_available_candidate_pair(COMPONENT_RTP);
     assert!(cp.is_none(), "No Candidate pairs should exist");
 
     a.close()?;
@@ -118,7 +120,7 @@ fn test_pair_priority() -> Result<()> {
                 a.candidate_pairs[p].state = CandidatePairState::Succeeded;
             }
 
-            if let Some(best_pair) = a.get_best_available_candidate_pair() {
+            if let Some(best_pair) = a.get_best_available_candidate_pair(COMPONENT_RTP) {
                 assert_eq!(
                     a.candidate_pairs[best_pair].to_string(),
                     CandidatePair::new(
@@ -300,6 +302,83 @@ fn test_handle_peer_reflexive_udp_pflx_candidate() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_peer_reflexive_candidate_replaced_when_signaled() -> Result<()> {
+    // A remote candidate learned from a connectivity check has no known base
+    let prflx = CandidatePeerReflexiveConfig {
+        base_config: CandidateConfig {
+            network: "udp".to_owned(),
+            address: "192.168.0.2".to_owned(),
+            port: 2000,
+            component: 1,
+            ..Default::default()
+        },
+        rel_addr: "".to_owned(),
+        rel_port: 0,
+    }
+    .new_candidate_peer_reflexive()?;
+    assert_eq!(prflx.candidate_type(), CandidateType::PeerReflexive);
+    assert_eq!(prflx.related_address(), None);
+    assert_eq!(
+        prflx.priority() >> 24,
+        u32::from(CandidateType::PeerReflexive.preference())
+    );
+
+    // The signaled candidate may be added alone or in a batch
+    for bulk in [false, true] {
+        // The controlled agent only learns the candidate of the controlling one from its checks
+        let (mut a_agent, mut b_agent) = pipe(None, None)?;
+        a_agent.add_local_candidate(new_host_candidate("192.168.0.1", 1000)?)?;
+        b_agent.add_local_candidate(new_host_candidate("192.168.0.2", 2000)?)?;
+        a_agent.add_remote_candidate(new_host_candidate("192.168.0.2", 2000)?)?;
+        let a_credentials = a_agent.get_local_credentials().clone();
+        let b_credentials = b_agent.get_local_credentials().clone();
+        a_agent.start_connectivity_checks(true, b_credentials.ufrag, b_credentials.pwd)?;
+        b_agent.start_connectivity_checks(false, a_credentials.ufrag, a_credentials.pwd)?;
+        assert!(deliver_transmits(&mut a_agent, &mut b_agent) > 0);
+        assert_eq!(b_agent.remote_candidates.len(), 1);
+        assert_eq!(
+            b_agent.remote_candidates[0].candidate_type(),
+            CandidateType::PeerReflexive
+        );
+        let last_received = b_agent.remote_candidates[0].last_received();
+
+        // The candidate signaled later replaces the peer reflexive one, keeping its pair
+        let host = new_host_candidate("192.168.0.1", 1000)?;
+        let host_priority = host.priority();
+        if bulk {
+            b_agent.set_remote_candidates(vec![host])?;
+        } else {
+            b_agent.add_remote_candidate(host)?;
+        }
+        assert_eq!(b_agent.remote_candidates.len(), 1);
+        assert_eq!(
+            b_agent.remote_candidates[0].candidate_type(),
+            CandidateType::Host
+        );
+        assert_eq!(b_agent.remote_candidates[0].last_received(), last_received);
+        assert_eq!(b_agent.candidate_pairs.len(), 1);
+        assert_eq!(b_agent.candidate_pairs[0].remote_priority, host_priority);
+
+        for _ in 0..16 {
+            let delivered = deliver_transmits(&mut a_agent, &mut b_agent)
+                + deliver_transmits(&mut b_agent, &mut a_agent);
+            if delivered == 0 {
+                break;
+            }
+        }
+        for agent in [&a_agent, &b_agent] {
+            assert_eq!(agent.connection_state, ConnectionState::Connected);
+        }
+        assert_eq!(b_agent.remote_candidates.len(), 1);
+
+        a_agent.close()?;
+        b_agent.close()?;
+    }
+
+    Ok(())
+}
+
 #[test]
 fn test_handle_peer_reflexive_unknown_remote() -> Result<()> {
     let mut a = Agent::new(Arc::new(AgentConfig::default()))?;
@@ -308,12 +387,16 @@ fn test_handle_peer_reflexive_unknown_remote() -> Result<()> {
     tid.0[..3].copy_from_slice("ABC".as_bytes());
 
     let remote_pwd = {
-        a.pending_binding_requests = vec![BindingRequest {
-            timestamp: Instant::now(),
-            transaction_id: tid,
-            destination: SocketAddr::from_str("0.0.0.0:0")?,
-            is_use_candidate: false,
-        }];
+        a.pending_binding_requests = HashMap::from([(
+            tid,
+            BindingRequest {
+                timestamp: Instant::now(),
+                transaction_id: tid,
+                destination: SocketAddr::from_str("0.0.0.0:0")?,
+                is_use_candidate: false,
+                ..Default::default()
+            },
+        )]);
         a.ufrag_pwd.remote_credentials = Some(Credentials {
             ufrag: "".to_string(),
             pwd: "".to_string(),
@@ -358,6 +441,2363 @@ fn test_handle_peer_reflexive_unknown_remote() -> Result<()> {
     Ok(())
 }
 
+fn new_host_candidate(address: &str, port: u16) -> Result<Candidate> {
+    let host_config = CandidateHostConfig {
+        base_config: CandidateConfig {
+            network: "udp".to_owned(),
+            address: address.to_owned(),
+            port,
+            component: 1,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    host_config.new_candidate_host()
+}
+
+// Delivers all pending transmits of one agent to the other one, returns how many were delivered
+fn deliver_transmits(from: &mut Agent, to: &mut Agent) -> usize {
+    let mut delivered = 0;
+    while let Some(transmit) = from.poll_transmit() {
+        let _ = to.handle_read(Transmit {
+            now: transmit.now,
+            transport: TransportContext {
+                local_addr: transmit.transport.peer_addr,
+                peer_addr: transmit.transport.local_addr,
+                ecn: transmit.transport.ecn,
+                protocol: transmit.transport.protocol,
+            },
+            message: transmit.message,
+        });
+        delivered += 1;
+    }
+    delivered
+}
+
+// Exchanges host candidates and credentials, then runs connectivity checks until both agents
+// are quiet. a_agent is controlling, b_agent is controlled.
+fn connect_agents(a_agent: &mut Agent, b_agent: &mut Agent) -> Result<()> {
+    a_agent.add_local_candidate(new_host_candidate("192.168.0.1", 1000)?)?;
+    b_agent.add_local_candidate(new_host_candidate("192.168.0.2", 2000)?)?;
+    a_agent.add_remote_candidate(new_host_candidate("192.168.0.2", 2000)?)?;
+    b_agent.add_remote_candidate(new_host_candidate("192.168.0.1", 1000)?)?;
+
+    let a_credentials = a_agent.get_local_credentials().clone();
+    let b_credentials = b_agent.get_local_credentials().clone();
+    a_agent.start_connectivity_checks(true, b_credentials.ufrag, b_credentials.pwd)?;
+    b_agent.start_connectivity_checks(false, a_credentials.ufrag, a_credentials.pwd)?;
+
+    for _ in 0..16 {
+        let delivered = deliver_transmits(a_agent, b_agent) + deliver_transmits(b_agent, a_agent);
+        if delivered == 0 {
+            break;
+        }
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_nomination_selects_pair_on_both_sides() -> Result<()> {
+    let (mut a_agent, mut b_agent) = pipe(None, None)?;
+
+    connect_agents(&mut a_agent, &mut b_agent)?;
+
+    for agent in [&a_agent, &b_agent] {
+        let pair_index = agent
+            .get_selected_pair()
+            .expect("selected pair should be set after nomination");
+        let p = &agent.candidate_pairs[pair_index];
+        assert_eq!(p.state, CandidatePairState::Succeeded);
+        assert!(p.nominated, "selected pair must be nominated");
+        assert_eq!(agent.connection_state, ConnectionState::Connected);
+    }
+
+    a_agent.close()?;
+    b_agent.close()?;
+    Ok(())
+}
+
+#[test]
+fn test_default_component_candidates_connect() -> Result<()> {
+    let (mut a_agent, mut b_agent) = pipe(None, None)?;
+    let new_default_host_candidate = |address: &str, port: u16| {
+        CandidateHostConfig {
+            base_config: CandidateConfig {
+                network: "udp".to_owned(),
+                address: address.to_owned(),
+                port,
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+        .new_candidate_host()
+    };
+
+    let mut a_candidate = new_default_host_candidate("192.168.0.1", 1000)?;
+    let mut b_candidate = new_default_host_candidate("192.168.0.2", 2000)?;
+    assert_eq!(a_candidate.component(), COMPONENT_RTP);
+    assert_eq!(b_candidate.component(), COMPONENT_RTP);
+    // Candidates may still be set to component 0 after they were built
+    a_candidate.set_component(0);
+    b_candidate.set_component(0);
+
+    a_agent.add_local_candidate(a_candidate.clone())?;
+    b_agent.add_local_candidate(b_candidate.clone())?;
+    a_agent.add_remote_candidate(b_candidate)?;
+    b_agent.add_remote_candidate(a_candidate)?;
+
+    let a_credentials = a_agent.get_local_credentials().clone();
+    let b_credentials = b_agent.get_local_credentials().clone();
+    a_agent.start_connectivity_checks(true, b_credentials.ufrag, b_credentials.pwd)?;
+    b_agent.start_connectivity_checks(false, a_credentials.ufrag, a_credentials.pwd)?;
+    for _ in 0..16 {
+        if deliver_transmits(&mut a_agent, &mut b_agent)
+            + deliver_transmits(&mut b_agent, &mut a_agent)
+            == 0
+        {
+            break;
+        }
+    }
+
+    for agent in [&a_agent, &b_agent] {
+        assert!(agent.get_selected_pair().is_some());
+        assert!(agent.component_pairs.is_empty());
+        assert_eq!(agent.connection_state, ConnectionState::Connected);
+    }
+
+    a_agent.close()?;
+    b_agent.close()?;
+    Ok(())
+}
+
+#[test]
+fn test_pair_component_zero_matches_rtp() -> Result<()> {
+    let mut a = Agent::new(Arc::new(AgentConfig::default()))?;
+
+    let mut local = new_host_candidate("192.168.0.1", 1000)?;
+    local.set_component(0);
+    let mut remote_rtcp = new_host_candidate("192.168.0.2", 2001)?;
+    remote_rtcp.set_component(COMPONENT_RTCP);
+    a.add_local_candidate(local)?;
+    a.add_remote_candidate(new_host_candidate("192.168.0.2", 2000)?)?;
+    a.add_remote_candidate(remote_rtcp)?;
+
+    assert_eq!(a.candidate_pairs.len(), 1);
+    let remote_index = a.candidate_pairs[0].remote_index;
+    assert_eq!(a.remote_candidates[remote_index].port(), 2000);
+    assert_eq!(a.pair_component(0), COMPONENT_RTP);
+
+    a.close()?;
+    Ok(())
+}
+
+#[test]
+fn test_controlled_selects_highest_priority_nominated_pair() -> Result<()> {
+    let mut a = Agent::new(Arc::new(AgentConfig::default()))?;
+
+    a.add_local_candidate(new_host_candidate("192.168.0.2", 777)?)?;
+
+    let srflx_config = CandidateServerReflexiveConfig {
+        base_config: CandidateConfig {
+            network: "udp".to_owned(),
+            address: "10.10.10.2".to_owned(),
+            port: 19218,
+            component: 1,
+            ..Default::default()
+        },
+        rel_addr: "4.3.2.1".to_owned(),
+        rel_port: 43212,
+    };
+    a.add_remote_candidate(srflx_config.new_candidate_server_reflexive()?)?;
+    a.add_remote_candidate(new_host_candidate("192.168.0.3", 888)?)?;
+
+    let (low_pair, high_pair) = (a.find_pair(0, 0).unwrap(), a.find_pair(0, 1).unwrap());
+    assert!(
+        a.candidate_pairs[low_pair].priority(a.is_controlling)
+            < a.candidate_pairs[high_pair].priority(a.is_controlling)
+    );
+    for p in &mut a.candidate_pairs {
+        p.state = CandidatePairState::Succeeded;
+    }
+
+    let mut msg = Message::new();
+    msg.build(&[
+        Box::new(BINDING_REQUEST),
+        Box::new(TransactionId::new()),
+        Box::new(UseCandidateAttr::new()),
+    ])?;
+
+    a.handle_binding_request(&msg, 0, 0);
+    assert_eq!(a.get_selected_pair(), Some(low_pair));
+
+    a.handle_binding_request(&msg, 0, 1);
+    assert_eq!(a.get_selected_pair(), Some(high_pair));
+
+    // A later nomination of a lower priority pair must not replace the selection
+    a.handle_binding_request(&msg, 0, 0);
+    assert_eq!(a.get_selected_pair(), Some(high_pair));
+
+    a.close()?;
+    Ok(())
+}
+
+#[test]
+fn test_on_stun_packet() -> Result<()> {
+    let mut a = Agent::new(Arc::new(AgentConfig::default()))?;
+
+    let observed = Arc::new(std::sync::Mutex::new(vec![]));
+    let observed2 = Arc::clone(&observed);
+    a.on_stun_packet(move |direction, m, addr| {
+        observed2.lock().unwrap().push((direction, m.typ, addr));
+    });
+
+    let local_candidate = new_host_candidate("192.168.0.2", 777)?;
+    let local_priority = local_candidate.priority();
+    a.add_local_candidate(local_candidate)?;
+
+    let remote_addr = SocketAddr::from_str("192.168.0.3:999")?;
+    a.add_remote_candidate(new_host_candidate("192.168.0.3", 999)?)?;
+
+    a.ufrag_pwd.remote_credentials = Some(Credentials {
+        ufrag: "".to_string(),
+        pwd: "".to_string(),
+    });
+    let username = a.ufrag_pwd.local_credentials.ufrag.to_owned() + ":";
+    let local_pwd = a.ufrag_pwd.local_credentials.pwd.clone();
+
+    let mut msg = Message::new();
+    msg.build(&[
+        Box::new(BINDING_REQUEST),
+        Box::new(TransactionId::new()),
+        Box::new(Username::new(ATTR_USERNAME, username)),
+        Box::new(AttrControlling(a.tie_breaker)),
+        Box::new(PriorityAttr(local_priority)),
+        Box::new(MessageIntegrity::new_short_term_integrity(local_pwd)),
+        Box::new(FINGERPRINT),
+    ])?;
+
+    a.handle_inbound(&mut msg, 0, remote_addr)?;
+
+    // The controlled agent answers the request and triggers its own check.
+    assert_eq!(
+        *observed.lock().unwrap(),
+        vec![
+            (StunPacketDirection::Inbound, BINDING_REQUEST, remote_addr),
+            (StunPacketDirection::Outbound, BINDING_SUCCESS, remote_addr),
+            (StunPacketDirection::Outbound, BINDING_REQUEST, remote_addr),
+        ]
+    );
+
+    a.close()?;
+    Ok(())
+}
+
+#[test]
+fn test_gathering_state() -> Result<()> {
+    let stun_url = Url {
+        scheme: SchemeType::Stun,
+        host: "1.2.3.4".to_owned(),
+        port: 3478,
+        proto: ProtoType::Udp,
+        ..Default::default()
+    };
+    let mut a = Agent::new(Arc::new(AgentConfig {
+        urls: vec![stun_url],
+        candidate_types: vec![CandidateType::Host, CandidateType::ServerReflexive],
+        ..Default::default()
+    }))?;
+
+    let completed = Arc::new(AtomicUsize::new(0));
+    let completed2 = Arc::clone(&completed);
+    a.on_gathering_complete(move || {
+        completed2.fetch_add(1, Ordering::SeqCst);
+    });
+
+    assert!(a.gathering_state() == GatheringState::New);
+    a.gather_candidates()?;
+    assert!(a.gathering_state() == GatheringState::Gathering);
+    assert!(a.gather_candidates().is_err(), "gathering twice must fail");
+    assert!(a
+        .restart(String::new(), String::new(), false, false)
+        .is_err());
+
+    a.add_local_candidate(new_host_candidate("192.168.0.2", 777)?)?;
+    a.candidate_type_gathered(CandidateType::Host);
+    assert!(a.gathering_state() == GatheringState::Gathering);
+    assert_eq!(completed.load(Ordering::SeqCst), 0);
+
+    a.candidate_type_gathered(CandidateType::ServerReflexive);
+    assert!(a.gathering_state() == GatheringState::Complete);
+    assert_eq!(completed.load(Ordering::SeqCst), 1);
+
+    // Restart resets the gathering process
+    a.restart(String::new(), String::new(), false, false)?;
+    assert!(a.gathering_state() == GatheringState::New);
+
+    a.close()?;
+    Ok(())
+}
+
+#[test]
+fn test_credentials_validation() -> Result<()> {
+    let mut a = Agent::new(Arc::new(AgentConfig::default()))?;
+
+    let ufrag = "a".repeat(256);
+    let pwd = "b+/".repeat(85);
+    a.restart(ufrag.clone(), pwd.clone(), false, false)?;
+    a.set_remote_credentials(ufrag, pwd)?;
+
+    assert_eq!(
+        a.restart("a".repeat(257), String::new(), false, false),
+        Err(Error::ErrLocalUfragTooLong)
+    );
+    assert_eq!(
+        a.restart(String::new(), "b".repeat(257), false, false),
+        Err(Error::ErrLocalPwdTooLong)
+    );
+    assert_eq!(
+        a.restart("ufrag-1".to_owned(), String::new(), false, false),
+        Err(Error::ErrLocalUfragInvalidChars)
+    );
+    assert_eq!(
+        a.restart(
+            String::new(),
+            "pwd with spaces 0123".to_owned(),
+            false,
+            false
+        ),
+        Err(Error::ErrLocalPwdInvalidChars)
+    );
+
+    assert_eq!(
+        a.set_remote_credentials("a".repeat(257), "pwd".to_owned()),
+        Err(Error::ErrRemoteUfragTooLong)
+    );
+    assert_eq!(
+        a.set_remote_credentials("ufrag".to_owned(), "b".repeat(257)),
+        Err(Error::ErrRemotePwdTooLong)
+    );
+    assert_eq!(
+        a.set_remote_credentials("ufrag:1".to_owned(), "pwd".to_owned()),
+        Err(Error::ErrRemoteUfragInvalidChars)
+    );
+    assert_eq!(
+        a.set_remote_credentials("ufrag".to_owned(), "pwd\r\n".to_owned()),
+        Err(Error::ErrRemotePwdInvalidChars)
+    );
+
+    a.close()?;
+    Ok(())
+}
+
+#[test]
+fn test_rng_seed() -> Result<()> {
+    let new_agent = |rng_seed| {
+        Agent::new(Arc::new(AgentConfig {
+            rng_seed,
+            ..Default::default()
+        }))
+    };
+    let credentials = |agent: &Agent| {
+        let credentials = agent.get_local_credentials();
+        (credentials.ufrag.clone(), credentials.pwd.clone())
+    };
+
+    let mut a = new_agent(Some(1))?;
+    let mut b = new_agent(Some(1))?;
+    assert_eq!(credentials(&a), credentials(&b));
+    assert_eq!(a.tie_breaker, b.tie_breaker);
+    assert_eq!(a.new_transaction_id(), b.new_transaction_id());
+
+    a.restart(String::new(), String::new(), false, false)?;
+    b.restart(String::new(), String::new(), false, false)?;
+    assert_eq!(credentials(&a), credentials(&b));
+
+    let mut c = new_agent(Some(2))?;
+    assert_ne!(credentials(&a), credentials(&c));
+
+    a.close()?;
+    b.close()?;
+    c.close()?;
+    Ok(())
+}
+
+#[test]
+fn test_agent_role() -> Result<()> {
+    let mut a = Agent::new(Arc::new(AgentConfig {
+        is_controlling: true,
+        ..Default::default()
+    }))?;
+    assert!(a.is_controlling());
+    assert_eq!(a.role(), IceRole::Controlling);
+
+    let mut b = Agent::new(Arc::new(AgentConfig::default()))?;
+    assert!(!b.is_controlling());
+    assert_eq!(b.role(), IceRole::Controlled);
+
+    a.close()?;
+    b.close()?;
+    Ok(())
+}
+
+#[test]
+fn test_network_cost() -> Result<()> {
+    let host_candidate = |address: &str, port, network_cost| {
+        CandidateHostConfig {
+            base_config: CandidateConfig {
+                network: "udp".to_owned(),
+                address: address.to_owned(),
+                port,
+                component: 1,
+                network_id: 1,
+                network_cost,
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+        .new_candidate_host()
+    };
+
+    // The controlled agent learns the cost of the remote candidate from the binding requests
+    let config = || AgentConfig {
+        enable_network_cost: true,
+        ..Default::default()
+    };
+    let (mut a_agent, mut b_agent) = pipe(Some(config()), Some(config()))?;
+    a_agent.add_local_candidate(host_candidate("192.168.0.1", 1000, 50)?)?;
+    b_agent.add_local_candidate(host_candidate("192.168.0.2", 2000, 10)?)?;
+    a_agent.add_remote_candidate(new_host_candidate("192.168.0.2", 2000)?)?;
+    b_agent.add_remote_candidate(new_host_candidate("192.168.0.1", 1000)?)?;
+
+    let a_credentials = a_agent.get_local_credentials().clone();
+    let b_credentials = b_agent.get_local_credentials().clone();
+    a_agent.start_connectivity_checks(true, b_credentials.ufrag, b_credentials.pwd)?;
+    b_agent.start_connectivity_checks(false, a_credentials.ufrag, a_credentials.pwd)?;
+    for _ in 0..16 {
+        let delivered = deliver_transmits(&mut a_agent, &mut b_agent)
+            + deliver_transmits(&mut b_agent, &mut a_agent);
+        if delivered == 0 {
+            break;
+        }
+    }
+    assert_eq!(b_agent.remote_candidates[0].network_id(), 1);
+    assert_eq!(b_agent.remote_candidates[0].network_cost(), 50);
+    assert_eq!(a_agent.remote_candidates[0].network_cost(), 10);
+
+    // Among nominated pairs, the cheapest one wins over the highest priority one
+    let best_nominated_pair = |enable_network_cost| -> Result<Option<u16>> {
+        let mut agent = Agent::new(Arc::new(AgentConfig {
+            enable_network_cost,
+            ..Default::default()
+        }))?;
+        let mut expensive = host_candidate("192.168.0.1", 1000, 50)?;
+        expensive.local_preference_override = Some(u16::MAX);
+        agent.add_local_candidate(expensive)?;
+        agent.add_local_candidate(host_candidate("192.168.0.3", 3000, 10)?)?;
+        agent.add_remote_candidate(new_host_candidate("192.168.0.2", 2000)?)?;
+        agent.add_pair(0, 0);
+        agent.add_pair(1, 0);
+        for p in agent.candidate_pairs.iter_mut() {
+            p.state = CandidatePairState::Succeeded;
+            p.nominated = true;
+        }
+
+        let best = agent
+            .get_best_nominated_candidate_pair(COMPONENT_RTP)
+            .map(|pair_index| {
+                agent.local_candidates[agent.candidate_pairs[pair_index].local_index].network_cost()
+            });
+        agent.close()?;
+        Ok(best)
+    };
+    assert_eq!(best_nominated_pair(true)?, Some(10));
+    assert_eq!(best_nominated_pair(false)?, Some(50));
+
+    a_agent.close()?;
+    b_agent.close()?;
+    Ok(())
+}
+
+#[test]
+fn test_include_fingerprint() -> Result<()> {
+    let (mut a_agent, mut b_agent) = pipe(
+        Some(AgentConfig {
+            include_fingerprint: Some(false),
+            ..Default::default()
+        }),
+        None,
+    )?;
+    a_agent.add_local_candidate(new_host_candidate("192.168.0.1", 1000)?)?;
+    b_agent.add_local_candidate(new_host_candidate("192.168.0.2", 2000)?)?;
+    a_agent.add_remote_candidate(new_host_candidate("192.168.0.2", 2000)?)?;
+    b_agent.add_remote_candidate(new_host_candidate("192.168.0.1", 1000)?)?;
+
+    let a_credentials = a_agent.get_local_credentials().clone();
+    let b_credentials = b_agent.get_local_credentials().clone();
+    a_agent.start_connectivity_checks(true, b_credentials.ufrag, b_credentials.pwd)?;
+    b_agent.start_connectivity_checks(false, a_credentials.ufrag, a_credentials.pwd)?;
+
+    // Only the agent with include_fingerprint disabled omits FINGERPRINT, and both agents
+    // accept each other's messages
+    let has_fingerprint = |agent: &Agent| -> Result<Vec<bool>> {
+        let mut fingerprints = vec![];
+        for transmit in &agent.transmits {
+            let mut m = Message::new();
+            m.unmarshal_binary(&transmit.message)?;
+            fingerprints.push(m.contains(ATTR_FINGERPRINT));
+        }
+        Ok(fingerprints)
+    };
+    for _ in 0..16 {
+        assert!(has_fingerprint(&a_agent)?.iter().all(|f| !f));
+        assert!(has_fingerprint(&b_agent)?.iter().all(|f| *f));
+
+        let delivered = deliver_transmits(&mut a_agent, &mut b_agent)
+            + deliver_transmits(&mut b_agent, &mut a_agent);
+        if delivered == 0 {
+            break;
+        }
+    }
+    for agent in [&a_agent, &b_agent] {
+        assert!(agent.get_selected_pair().is_some());
+        assert_eq!(agent.connection_state, ConnectionState::Connected);
+    }
+
+    a_agent.close()?;
+    b_agent.close()?;
+    Ok(())
+}
+
+#[test]
+fn test_software() -> Result<()> {
+    let (mut a_agent, mut b_agent) = pipe(
+        None,
+        Some(AgentConfig {
+            software: Some(String::new()),
+            ..Default::default()
+        }),
+    )?;
+    a_agent.add_local_candidate(new_host_candidate("192.168.0.1", 1000)?)?;
+    b_agent.add_local_candidate(new_host_candidate("192.168.0.2", 2000)?)?;
+    a_agent.add_remote_candidate(new_host_candidate("192.168.0.2", 2000)?)?;
+    b_agent.add_remote_candidate(new_host_candidate("192.168.0.1", 1000)?)?;
+
+    let a_credentials = a_agent.get_local_credentials().clone();
+    let b_credentials = b_agent.get_local_credentials().clone();
+    a_agent.start_connectivity_checks(true, b_credentials.ufrag, b_credentials.pwd)?;
+    b_agent.start_connectivity_checks(false, a_credentials.ufrag, a_credentials.pwd)?;
+
+    // SOFTWARE comes before MESSAGE-INTEGRITY, in the requests and responses of the agent with
+    // the default software only
+    let software = |agent: &Agent| -> Result<Vec<Option<String>>> {
+        let mut software = vec![];
+        for transmit in &agent.transmits {
+            let mut m = Message::new();
+            m.unmarshal_binary(&transmit.message)?;
+            let types: Vec<AttrType> = m.attributes.0.iter().map(|a| a.typ).collect();
+            if let Some(index) = types.iter().position(|&t| t == ATTR_SOFTWARE) {
+                assert!(types[index..].contains(&ATTR_MESSAGE_INTEGRITY));
+                software.push(Some(Software::get_from_as(&m, ATTR_SOFTWARE)?.text));
+            } else {
+                software.push(None);
+            }
+        }
+        Ok(software)
+    };
+    let mut sent = 0;
+    for _ in 0..16 {
+        let a_software = software(&a_agent)?;
+        sent += a_software.len();
+        assert!(a_software
+            .iter()
+            .all(|s| s.as_deref() == Some(DEFAULT_SOFTWARE)));
+        assert!(software(&b_agent)?.iter().all(|s| s.is_none()));
+
+        let delivered = deliver_transmits(&mut a_agent, &mut b_agent)
+            + deliver_transmits(&mut b_agent, &mut a_agent);
+        if delivered == 0 {
+            break;
+        }
+    }
+    assert!(sent > 0);
+    assert!(a_agent.get_selected_pair().is_some());
+
+    // The software is limited by the size of the attribute
+    assert!(Agent::new(Arc::new(AgentConfig {
+        software: Some("x".repeat(764)),
+        ..Default::default()
+    }))
+    .is_err());
+
+    a_agent.close()?;
+    b_agent.close()?;
+    Ok(())
+}
+
+#[test]
+fn test_agent_use_after_close() -> Result<()> {
+    let mut a = Agent::new(Arc::new(AgentConfig::default()))?;
+    assert!(!a.is_closed());
+    a.close()?;
+    assert!(a.is_closed());
+
+    assert_eq!(
+        a.add_local_candidate(new_host_candidate("192.168.0.1", 1000)?),
+        Err(Error::ErrClosed)
+    );
+    assert_eq!(
+        a.add_remote_candidate(new_host_candidate("192.168.0.2", 2000)?),
+        Err(Error::ErrClosed)
+    );
+    assert_eq!(
+        a.set_remote_candidates(vec![new_host_candidate("192.168.0.2", 2000)?]),
+        Err(Error::ErrClosed)
+    );
+    assert!(a.local_candidates.is_empty());
+    assert!(a.remote_candidates.is_empty());
+    assert!(a.candidate_pairs.is_empty());
+
+    assert_eq!(
+        a.restart(String::new(), String::new(), false, false),
+        Err(Error::ErrClosed)
+    );
+    assert!(a.is_closed());
+
+    Ok(())
+}
+
+#[test]
+fn test_link_local_candidates() -> Result<()> {
+    let (mut a_agent, mut b_agent) = pipe(None, None)?;
+
+    a_agent.add_local_candidate(new_host_candidate("fe80::1%2", 1000)?)?;
+    b_agent.add_local_candidate(new_host_candidate("fe80::2%2", 2000)?)?;
+    // The candidates received through signaling don't carry a zone
+    a_agent.add_remote_candidate(new_host_candidate("fe80::2", 2000)?)?;
+    b_agent.add_remote_candidate(new_host_candidate("fe80::1", 1000)?)?;
+
+    let a_credentials = a_agent.get_local_credentials().clone();
+    let b_credentials = b_agent.get_local_credentials().clone();
+    a_agent.start_connectivity_checks(true, b_credentials.ufrag, b_credentials.pwd)?;
+    b_agent.start_connectivity_checks(false, a_credentials.ufrag, a_credentials.pwd)?;
+
+    let transmit = a_agent
+        .poll_transmit()
+        .expect("connectivity check should be sent");
+    assert_eq!(
+        transmit.transport.peer_addr,
+        SocketAddr::V6(SocketAddrV6::new("fe80::2".parse().unwrap(), 2000, 0, 2))
+    );
+    b_agent.handle_read(Transmit {
+        now: transmit.now,
+        transport: TransportContext {
+            local_addr: transmit.transport.peer_addr,
+            peer_addr: transmit.transport.local_addr,
+            ecn: transmit.transport.ecn,
+            protocol: transmit.transport.protocol,
+        },
+        message: transmit.message,
+    })?;
+
+    for _ in 0..16 {
+        let delivered = deliver_transmits(&mut a_agent, &mut b_agent)
+            + deliver_transmits(&mut b_agent, &mut a_agent);
+        if delivered == 0 {
+            break;
+        }
+    }
+    for agent in [&a_agent, &b_agent] {
+        assert_eq!(agent.connection_state, ConnectionState::Connected);
+        assert_eq!(
+            agent.remote_candidates.len(),
+            1,
+            "no peer reflexive candidate"
+        );
+    }
+
+    a_agent.close()?;
+    b_agent.close()?;
+    Ok(())
+}
+
+#[test]
+fn test_keepalive_uses_indication() -> Result<()> {
+    let config = || AgentConfig {
+        keepalive_interval: Some(Duration::from_millis(1)),
+        keepalive_uses_indication: true,
+        ..Default::default()
+    };
+    let (mut a_agent, mut b_agent) = pipe(Some(config()), Some(config()))?;
+    connect_agents(&mut a_agent, &mut b_agent)?;
+    assert_eq!(a_agent.connection_state, ConnectionState::Connected);
+
+    std::thread::sleep(Duration::from_millis(5));
+    let pending_binding_requests = a_agent.pending_binding_requests.len();
+    a_agent.check_keepalive();
+    assert_eq!(
+        a_agent.pending_binding_requests.len(),
+        pending_binding_requests,
+        "indications must not be tracked as pending requests"
+    );
+
+    let transmit = a_agent.poll_transmit().expect("keepalive should be sent");
+    assert!(a_agent.poll_transmit().is_none());
+    let mut m = Message::new();
+    m.raw = transmit.message.to_vec();
+    m.decode()?;
+    assert_eq!(m.typ, MessageType::new(METHOD_BINDING, CLASS_INDICATION));
+    assert!(m.contains(ATTR_FINGERPRINT));
+    assert!(!m.contains(ATTR_MESSAGE_INTEGRITY));
+
+    // The indication is accepted without any response
+    b_agent.handle_read(Transmit {
+        now: transmit.now,
+        transport: TransportContext {
+            local_addr: transmit.transport.peer_addr,
+            peer_addr: transmit.transport.local_addr,
+            ecn: transmit.transport.ecn,
+            protocol: transmit.transport.protocol,
+        },
+        message: transmit.message,
+    })?;
+    assert!(b_agent.poll_transmit().is_none());
+
+    a_agent.close()?;
+    b_agent.close()?;
+    Ok(())
+}
+
+#[test]
+fn test_goog_ping() -> Result<()> {
+    let config = |enable_goog_ping| AgentConfig {
+        keepalive_interval: Some(Duration::from_millis(1)),
+        enable_goog_ping,
+        ..Default::default()
+    };
+    let keepalive = |agent: &mut Agent| -> Result<Message> {
+        std::thread::sleep(Duration::from_millis(5));
+        agent.check_keepalive();
+        let transmit = agent.poll_transmit().expect("keepalive should be sent");
+        let mut m = Message::new();
+        m.raw = transmit.message.to_vec();
+        m.decode()?;
+        agent.transmits.push_front(transmit);
+        Ok(m)
+    };
+
+    // Without support on the remote side, keepalives stay binding requests
+    let (mut a_agent, mut b_agent) = pipe(Some(config(true)), Some(config(false)))?;
+    connect_agents(&mut a_agent, &mut b_agent)?;
+    assert_eq!(a_agent.connection_state, ConnectionState::Connected);
+    assert_eq!(keepalive(&mut a_agent)?.typ, BINDING_REQUEST);
+    a_agent.close()?;
+    b_agent.close()?;
+
+    let (mut a_agent, mut b_agent) = pipe(Some(config(true)), Some(config(true)))?;
+    connect_agents(&mut a_agent, &mut b_agent)?;
+    assert_eq!(a_agent.connection_state, ConnectionState::Connected);
+
+    // Once both agents announced support, keepalives are compact GOOG-PINGs
+    let m = keepalive(&mut a_agent)?;
+    assert_eq!(m.typ, MessageType::new(METHOD_GOOG_PING, CLASS_REQUEST));
+    assert!(m.contains(ATTR_GOOG_MESSAGE_INTEGRITY_32));
+    assert!(!m.contains(ATTR_USERNAME));
+    assert_eq!(m.raw.len(), MESSAGE_HEADER_SIZE + 8);
+    assert_eq!(deliver_transmits(&mut a_agent, &mut b_agent), 1);
+
+    let pair_index = a_agent.get_selected_pair().unwrap();
+    a_agent.candidate_pairs[pair_index].consent_refreshed_at = None;
+    assert_eq!(deliver_transmits(&mut b_agent, &mut a_agent), 1);
+    assert!(a_agent.pending_binding_requests.is_empty());
+    assert!(a_agent.candidate_pairs[pair_index]
+        .consent_refreshed_at
+        .is_some());
+
+    // A GOOG-PING on a pair the remote agent doesn't know anymore is rejected, and the agent
+    // falls back to binding requests
+    b_agent.remove_remote_candidate(&new_host_candidate("192.168.0.1", 1000)?)?;
+    while b_agent.poll_transmit().is_some() {}
+    let m = keepalive(&mut a_agent)?;
+    assert_eq!(m.typ, MessageType::new(METHOD_GOOG_PING, CLASS_REQUEST));
+    assert_eq!(deliver_transmits(&mut a_agent, &mut b_agent), 1);
+    let transmit = b_agent
+        .poll_transmit()
+        .expect("GOOG-PING should be answered");
+    let mut response = Message::new();
+    response.raw = transmit.message.to_vec();
+    response.decode()?;
+    assert_eq!(
+        response.typ,
+        MessageType::new(METHOD_GOOG_PING, CLASS_ERROR_RESPONSE)
+    );
+    b_agent.transmits.push_front(transmit);
+    assert_eq!(deliver_transmits(&mut b_agent, &mut a_agent), 1);
+    assert!(!a_agent.candidate_pairs[pair_index].remote_supports_goog_ping);
+    assert_eq!(keepalive(&mut a_agent)?.typ, BINDING_REQUEST);
+
+    a_agent.close()?;
+    b_agent.close()?;
+    Ok(())
+}
+
+#[test]
+fn test_checklist_order() -> Result<()> {
+    let mut a = Agent::new(Arc::new(AgentConfig::default()))?;
+    assert!(!a.is_controlling);
+
+    // Formed while the agent is controlled
+    for (local_priority, remote_priority) in [(100, 300), (50, 50), (300, 100), (200, 200)] {
+        a.candidate_pairs.push(CandidatePair::new(
+            a.candidate_pairs.len(),
+            0,
+            local_priority,
+            remote_priority,
+            a.is_controlling,
+        ));
+    }
+    assert_eq!(a.checklist_order(), vec![3, 0, 2, 1]);
+
+    // The order follows the current role, not the one the pairs were formed with
+    a.is_controlling = true;
+    assert_eq!(a.checklist_order(), vec![3, 2, 0, 1]);
+
+    a.close()?;
+    Ok(())
+}
+
+#[test]
+fn test_components_all_selected_before_connected() -> Result<()> {
+    let component_candidate = |address: &str, port: u16, component: u16| {
+        CandidateHostConfig {
+            base_config: CandidateConfig {
+                network: "udp".to_owned(),
+                address: address.to_owned(),
+                port,
+                component,
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+        .new_candidate_host()
+    };
+
+    for components in [1, 2] {
+        let config = || AgentConfig {
+            components,
+            ..Default::default()
+        };
+        let (mut a_agent, mut b_agent) = pipe(Some(config()), Some(config()))?;
+
+        for component in 1..=components as u16 {
+            let (a_port, b_port) = (1000 + component, 2000 + component);
+            a_agent.add_local_candidate(component_candidate("192.168.0.1", a_port, component)?)?;
+            b_agent.add_local_candidate(component_candidate("192.168.0.2", b_port, component)?)?;
+            a_agent.add_remote_candidate(component_candidate("192.168.0.2", b_port, component)?)?;
+            b_agent.add_remote_candidate(component_candidate("192.168.0.1", a_port, component)?)?;
+        }
+
+        let a_credentials = a_agent.get_local_credentials().clone();
+        let b_credentials = b_agent.get_local_credentials().clone();
+        a_agent.start_connectivity_checks(true, b_credentials.ufrag, b_credentials.pwd)?;
+        b_agent.start_connectivity_checks(false, a_credentials.ufrag, a_credentials.pwd)?;
+
+        for _ in 0..16 {
+            a_agent.contact(Instant::now());
+            b_agent.contact(Instant::now());
+            while deliver_transmits(&mut a_agent, &mut b_agent)
+                + deliver_transmits(&mut b_agent, &mut a_agent)
+                > 0
+            {}
+        }
+
+        // Connected is only reported once every component has a selected pair
+        for agent in [&mut a_agent, &mut b_agent] {
+            let mut selected_components = vec![];
+            while let Some(event) = agent.poll_event() {
+                match event {
+                    Event::SelectedCandidatePairChange(local, _) => {
+                        selected_components.push(local.component());
+                    }
+                    Event::ConnectionStateChange(ConnectionState::Connected) => {
+                        // Followed by the change of the pair that completed the stream
+                        if let Some(Event::SelectedCandidatePairChange(local, _)) =
+                            agent.poll_event()
+                        {
+                            selected_components.push(local.component());
+                        }
+                        selected_components.sort_unstable();
+                        selected_components.dedup();
+                        assert_eq!(selected_components.len(), components);
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        for agent in [&a_agent, &b_agent] {
+            assert_eq!(agent.connection_state, ConnectionState::Connected);
+            for component in 1..=components as u16 {
+                let (local, remote) = agent
+                    .get_selected_candidate_pair_of(component)
+                    .expect("every component should have a selected pair");
+                assert_eq!(local.component(), component);
+                assert_eq!(remote.component(), component);
+            }
+        }
+        assert!(
+            a_agent
+                .get_selected_candidate_pair_of(COMPONENT_RTCP)
+                .is_some()
+                == (components == 2)
+        );
+
+        a_agent.close()?;
+        b_agent.close()?;
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_write_read_application_data() -> Result<()> {
+    let (mut a_agent, mut b_agent) = pipe(None, None)?;
+    assert_eq!(a_agent.write(b"early"), Err(Error::ErrNoCandidatePairs));
+
+    connect_agents(&mut a_agent, &mut b_agent)?;
+    assert_eq!(a_agent.connection_state, ConnectionState::Connected);
+    assert_eq!(b_agent.connection_state, ConnectionState::Connected);
+
+    assert_eq!(a_agent.write(b"ping")?, 4);
+    let transmit = a_agent.poll_transmit().expect("data should be sent");
+    assert_eq!(&transmit.message[..], b"ping");
+    assert_eq!(
+        transmit.transport.peer_addr,
+        "192.168.0.2:2000".parse::<SocketAddr>().unwrap()
+    );
+    b_agent.handle_read(Transmit {
+        now: transmit.now,
+        transport: TransportContext {
+            local_addr: transmit.transport.peer_addr,
+            peer_addr: transmit.transport.local_addr,
+            ecn: transmit.transport.ecn,
+            protocol: transmit.transport.protocol,
+        },
+        message: transmit.message,
+    })?;
+
+    b_agent.write(b"pong")?;
+    deliver_transmits(&mut b_agent, &mut a_agent);
+    assert_eq!(b_agent.read().as_deref(), Some(&b"ping"[..]));
+    assert_eq!(a_agent.read().as_deref(), Some(&b"pong"[..]));
+    assert!(a_agent.read().is_none());
+    assert!(b_agent.read().is_none());
+
+    // Data from an unknown address is discarded
+    let result = a_agent.handle_read(Transmit {
+        now: Instant::now(),
+        transport: TransportContext {
+            local_addr: "192.168.0.1:1000".parse().unwrap(),
+            peer_addr: "192.168.0.3:3000".parse().unwrap(),
+            ecn: None,
+            protocol: Protocol::UDP,
+        },
+        message: BytesMut::from(&b"spoofed"[..]),
+    });
+    assert_eq!(result, Err(Error::ErrNonStunmessage));
+    assert!(a_agent.read().is_none());
+
+    a_agent.close()?;
+    b_agent.close()?;
+    Ok(())
+}
+
+#[test]
+fn test_last_sent_only_updated_on_successful_send() -> Result<()> {
+    let (mut a_agent, mut b_agent) = pipe(None, None)?;
+    connect_agents(&mut a_agent, &mut b_agent)?;
+
+    let pair_index = a_agent.get_selected_pair().unwrap();
+    let (local_index, remote_index) = (
+        a_agent.candidate_pairs[pair_index].local_index,
+        a_agent.candidate_pairs[pair_index].remote_index,
+    );
+    let last_sent = a_agent.local_candidates[local_index].last_sent();
+    std::thread::sleep(Duration::from_millis(2));
+
+    a_agent.local_candidates[local_index].set_writer(|_, _| Err(Error::ErrClosed));
+    a_agent.ping_candidate(local_index, remote_index);
+    assert_eq!(a_agent.local_candidates[local_index].last_sent(), last_sent);
+
+    a_agent.local_candidates[local_index].set_writer(|_, _| Ok(()));
+    a_agent.ping_candidate(local_index, remote_index);
+    assert!(a_agent.local_candidates[local_index].last_sent() > last_sent);
+
+    a_agent.close()?;
+    b_agent.close()?;
+    Ok(())
+}
+
+#[test]
+fn test_consent_expiry() -> Result<()> {
+    let config = || AgentConfig {
+        keepalive_interval: Some(Duration::from_secs(0)),
+        consent_timeout: Some(Duration::from_millis(120)),
+        ..Default::default()
+    };
+    let (mut a_agent, mut b_agent) = pipe(Some(config()), Some(config()))?;
+    connect_agents(&mut a_agent, &mut b_agent)?;
+    assert_eq!(a_agent.connection_state, ConnectionState::Connected);
+
+    // Consent checks are sent even though keepalives are disabled
+    a_agent.check_keepalive();
+    assert!(a_agent.poll_transmit().is_none(), "consent is still fresh");
+    std::thread::sleep(Duration::from_millis(30));
+    a_agent.check_keepalive();
+    let transmit = a_agent
+        .poll_transmit()
+        .expect("consent check should be sent");
+    let mut m = Message::new();
+    m.raw = transmit.message.to_vec();
+    m.decode()?;
+    assert_eq!(m.typ, BINDING_REQUEST);
+
+    // The response refreshes consent
+    let pair_index = a_agent.get_selected_pair().unwrap();
+    let consent_refreshed_at = a_agent.candidate_pairs[pair_index].consent_refreshed_at;
+    b_agent.handle_read(Transmit {
+        now: transmit.now,
+        transport: TransportContext {
+            local_addr: transmit.transport.peer_addr,
+            peer_addr: transmit.transport.local_addr,
+            ecn: transmit.transport.ecn,
+            protocol: transmit.transport.protocol,
+        },
+        message: transmit.message,
+    })?;
+    deliver_transmits(&mut b_agent, &mut a_agent);
+    assert!(a_agent.candidate_pairs[pair_index].consent_refreshed_at > consent_refreshed_at);
+    assert!(a_agent.validate_selected_pair());
+
+    // Without responses, consent expires and the connection fails
+    std::thread::sleep(Duration::from_millis(150));
+    assert!(!a_agent.validate_selected_pair());
+    assert_eq!(a_agent.connection_state, ConnectionState::Failed);
+    assert!(a_agent.get_selected_pair().is_none());
+
+    a_agent.close()?;
+    b_agent.close()?;
+    Ok(())
+}
+
+#[test]
+fn test_role_conflict_error_response() -> Result<()> {
+    let (mut a_agent, mut b_agent) = pipe(None, None)?;
+    a_agent.add_local_candidate(new_host_candidate("192.168.0.1", 1000)?)?;
+    a_agent.add_remote_candidate(new_host_candidate("192.168.0.2", 2000)?)?;
+    let b_credentials = b_agent.get_local_credentials().clone();
+    a_agent.start_connectivity_checks(true, b_credentials.ufrag, b_credentials.pwd.clone())?;
+    a_agent.contact(Instant::now());
+
+    let transmit = a_agent.poll_transmit().expect("check should be sent");
+    while a_agent.poll_transmit().is_some() {}
+    let mut request = Message::new();
+    request.raw = transmit.message.to_vec();
+    request.decode()?;
+    assert!(request.contains(ATTR_ICE_CONTROLLING));
+
+    // The remote agent is controlling as well and won the tie-breaker
+    let mut response = Message::new();
+    response.build(&[
+        Box::new(request.clone()),
+        Box::new(BINDING_ERROR),
+        Box::new(CODE_ROLE_CONFLICT),
+        Box::new(MessageIntegrity::new_short_term_integrity(
+            b_credentials.pwd.clone(),
+        )),
+        Box::new(FINGERPRINT),
+    ])?;
+    let response_transmit = || Transmit {
+        now: transmit.now,
+        transport: TransportContext {
+            local_addr: transmit.transport.local_addr,
+            peer_addr: transmit.transport.peer_addr,
+            ecn: None,
+            protocol: transmit.transport.protocol,
+        },
+        message: BytesMut::from(&response.raw[..]),
+    };
+    a_agent.handle_read(response_transmit())?;
+    assert!(!a_agent.is_controlling());
+    assert_eq!(a_agent.role(), IceRole::Controlled);
+
+    // The pair is checked again in the new role
+    let transmit = a_agent.poll_transmit().expect("check should be retried");
+    let mut retry = Message::new();
+    retry.raw = transmit.message.to_vec();
+    retry.decode()?;
+    assert_eq!(retry.typ, BINDING_REQUEST);
+    assert!(retry.contains(ATTR_ICE_CONTROLLED));
+    assert!(!retry.contains(ATTR_ICE_CONTROLLING));
+
+    // A duplicate response doesn't switch the role back
+    assert_eq!(
+        a_agent.handle_read(response_transmit()),
+        Err(Error::ErrUnhandledStunpacket)
+    );
+    assert!(!a_agent.is_controlling());
+
+    a_agent.close()?;
+    b_agent.close()?;
+    Ok(())
+}
+
+#[test]
+fn test_max_local_candidates() -> Result<()> {
+    let mut a = Agent::new(Arc::new(AgentConfig {
+        max_local_candidates: Some(2),
+        candidate_filter: Some(Arc::new(|c: &Candidate| c.address() != "10.0.0.9")),
+        ..Default::default()
+    }))?;
+    let host = |address: &str, local_preference: u16| {
+        CandidateHostConfig {
+            base_config: CandidateConfig {
+                network: "udp".to_owned(),
+                address: address.to_owned(),
+                port: 1000,
+                component: 1,
+                priority: (126 << 24) | (u32::from(local_preference) << 8) | 255,
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+        .new_candidate_host()
+    };
+
+    // Filtered candidates don't count towards the cap
+    a.add_local_candidate(host("10.0.0.9", 500)?)?;
+    a.add_local_candidate(host("10.0.0.1", 100)?)?;
+    a.add_local_candidate(host("10.0.0.2", 300)?)?;
+    assert_eq!(a.local_candidates.len(), 2);
+
+    // Before pairing, a higher priority candidate replaces the lowest priority one
+    a.add_local_candidate(host("10.0.0.3", 200)?)?;
+    a.add_local_candidate(host("10.0.0.4", 50)?)?;
+    let addresses = |a: &Agent| {
+        let mut addresses: Vec<String> = a
+            .local_candidates
+            .iter()
+            .map(|c| c.address().to_owned())
+            .collect();
+        addresses.sort();
+        addresses
+    };
+    assert_eq!(addresses(&a), vec!["10.0.0.2", "10.0.0.3"]);
+
+    // Only the candidates that are kept are announced, and an announced candidate is kept
+    let mut announced = vec![];
+    while let Some(event) = a.poll_event() {
+        if let Event::NewCandidate(c) = event {
+            announced.push(c.address().to_owned());
+        }
+    }
+    announced.sort();
+    assert_eq!(announced, vec!["10.0.0.2", "10.0.0.3"]);
+    a.add_local_candidate(host("10.0.0.6", 400)?)?;
+    assert_eq!(addresses(&a), vec!["10.0.0.2", "10.0.0.3"]);
+    assert!(a.poll_event().is_none());
+
+    // Once paired, new candidates are ignored whatever their priority
+    a.add_remote_candidate(host("10.0.1.1", 100)?)?;
+    a.add_local_candidate(host("10.0.0.5", 400)?)?;
+    assert_eq!(addresses(&a), vec!["10.0.0.2", "10.0.0.3"]);
+    assert_eq!(a.candidate_pairs.len(), 2);
+
+    a.close()?;
+    Ok(())
+}
+
+#[test]
+fn test_remove_remote_candidate() -> Result<()> {
+    let (mut a_agent, mut b_agent) = pipe(None, None)?;
+    for port in [3000, 4000] {
+        b_agent.add_local_candidate(new_host_candidate("192.168.0.2", port)?)?;
+        a_agent.add_remote_candidate(new_host_candidate("192.168.0.2", port)?)?;
+    }
+    connect_agents(&mut a_agent, &mut b_agent)?;
+    assert_eq!(a_agent.connection_state, ConnectionState::Connected);
+    let (_, selected_remote) = a_agent
+        .get_selected_candidate_pair()
+        .expect("selected pair should be set");
+
+    // Removing a candidate which is not selected keeps the selected pair
+    let other_remote = a_agent
+        .remote_candidates
+        .iter()
+        .find(|c| !c.equal(&selected_remote))
+        .cloned()
+        .expect("another remote candidate");
+    a_agent.remove_remote_candidate(&other_remote)?;
+    assert_eq!(a_agent.remote_candidates.len(), 2);
+    assert_eq!(a_agent.candidate_pairs.len(), 2);
+    assert!(a_agent
+        .candidate_pairs
+        .iter()
+        .all(|p| !a_agent.remote_candidates[p.remote_index].equal(&other_remote)));
+    let (_, remote) = a_agent
+        .get_selected_candidate_pair()
+        .expect("selected pair should be kept");
+    assert!(remote.equal(&selected_remote));
+    assert_eq!(a_agent.connection_state, ConnectionState::Connected);
+
+    // Removing the selected candidate fails over to the remaining pair
+    a_agent.remove_remote_candidate(&selected_remote)?;
+    assert_eq!(a_agent.remote_candidates.len(), 1);
+    assert_eq!(a_agent.candidate_pairs.len(), 1);
+    assert!(a_agent.get_selected_pair().is_none());
+    assert_eq!(a_agent.connection_state, ConnectionState::Checking);
+
+    for _ in 0..16 {
+        if deliver_transmits(&mut a_agent, &mut b_agent)
+            + deliver_transmits(&mut b_agent, &mut a_agent)
+            == 0
+        {
+            a_agent.contact(Instant::now());
+        }
+        if a_agent.connection_state == ConnectionState::Connected {
+            break;
+        }
+    }
+    assert_eq!(a_agent.connection_state, ConnectionState::Connected);
+    let (_, remote) = a_agent
+        .get_selected_candidate_pair()
+        .expect("selected pair should be set after failover");
+    assert!(remote.equal(&a_agent.remote_candidates[0]));
+
+    a_agent.close()?;
+    b_agent.close()?;
+    Ok(())
+}
+
+#[test]
+fn test_poll_event() -> Result<()> {
+    let mut a = Agent::new(Arc::new(AgentConfig::default()))?;
+    assert!(a.poll_event().is_none());
+
+    // Events are queued in the order they occur
+    a.gather_candidates()?;
+    a.add_local_candidate(new_host_candidate("192.168.0.1", 1000)?)?;
+    a.add_local_candidate(new_host_candidate("192.168.0.1", 1001)?)?;
+    a.candidate_type_gathered(CandidateType::Host);
+    let mut ports = vec![];
+    while let Some(event) = a.poll_event() {
+        match event {
+            Event::NewCandidate(c) => ports.push(c.port()),
+            Event::GatheringComplete => break,
+            _ => panic!("unexpected event"),
+        }
+    }
+    assert_eq!(ports, vec![1000, 1001]);
+    assert!(a.poll_event().is_none());
+
+    // Duplicate candidates are not new
+    a.add_local_candidate(new_host_candidate("192.168.0.1", 1000)?)?;
+    assert!(a.poll_event().is_none());
+
+    a.close()?;
+    Ok(())
+}
+
+#[test]
+fn test_gathering_without_urls_only_waits_for_host() -> Result<()> {
+    let mut a = Agent::new(Arc::new(AgentConfig::default()))?;
+
+    a.gather_candidates()?;
+    assert!(a.gathering_state() == GatheringState::Gathering);
+
+    a.candidate_type_gathered(CandidateType::Host);
+    assert!(a.gathering_state() == GatheringState::Complete);
+
+    a.close()?;
+    Ok(())
+}
+
+#[test]
+fn test_add_remote_candidate_after_checks_started() -> Result<()> {
+    let mut a = Agent::new(Arc::new(AgentConfig::default()))?;
+    a.add_local_candidate(new_host_candidate("192.168.0.1", 1000)?)?;
+    a.start_connectivity_checks(true, "remoteufrag".to_owned(), "remotepwd".to_owned())?;
+    while a.poll_transmit().is_some() {}
+
+    a.add_remote_candidate(new_host_candidate("192.168.0.2", 2000)?)?;
+
+    let pair_index = a
+        .find_pair(0, 0)
+        .expect("pair should be formed on late candidate");
+    assert_eq!(
+        a.candidate_pairs[pair_index].state,
+        CandidatePairState::InProgress,
+        "late pair should be checked immediately"
+    );
+
+    let transmit = a
+        .poll_transmit()
+        .expect("connectivity check should be sent");
+    assert_eq!(
+        transmit.transport.peer_addr,
+        SocketAddr::from_str("192.168.0.2:2000")?
+    );
+
+    a.close()?;
+    Ok(())
+}
+
+#[test]
+fn test_late_remote_candidate_keeps_selected_pair() -> Result<()> {
+    let (mut a_agent, mut b_agent) = pipe(None, None)?;
+    connect_agents(&mut a_agent, &mut b_agent)?;
+
+    let selected_pair = a_agent.get_selected_pair();
+    assert!(selected_pair.is_some());
+    while a_agent.poll_event().is_some() {}
+
+    a_agent.add_remote_candidate(new_host_candidate("192.168.0.3", 3000)?)?;
+
+    let pair_index = a_agent
+        .find_pair(0, a_agent.remote_candidates.len() - 1)
+        .expect("late candidate should still be paired");
+    assert_eq!(
+        a_agent.candidate_pairs[pair_index].state,
+        CandidatePairState::Waiting
+    );
+    assert_eq!(a_agent.get_selected_pair(), selected_pair);
+    while let Some(event) = a_agent.poll_event() {
+        assert!(
+            !matches!(event, Event::SelectedCandidatePairChange(_, _)),
+            "late candidate must not change the selected pair"
+        );
+    }
+
+    a_agent.close()?;
+    b_agent.close()?;
+    Ok(())
+}
+
+#[test]
+fn test_set_remote_candidates() -> Result<()> {
+    let mut a = Agent::new(Arc::new(AgentConfig::default()))?;
+    a.add_local_candidate(new_host_candidate("192.168.0.1", 1000)?)?;
+    a.add_local_candidate(new_host_candidate("192.168.0.1", 1001)?)?;
+
+    a.set_remote_candidates(vec![
+        new_host_candidate("192.168.0.2", 2000)?,
+        new_host_candidate("192.168.0.2", 2001)?,
+        new_host_candidate("192.168.0.2", 2000)?,
+    ])?;
+
+    assert_eq!(a.remote_candidates.len(), 2, "duplicates should be dropped");
+    assert_eq!(a.candidate_pairs.len(), 4);
+    for local_index in 0..2 {
+        for remote_index in 0..2 {
+            assert!(a.find_pair(local_index, remote_index).is_some());
+        }
+    }
+
+    // Already known candidates are not paired again
+    a.set_remote_candidates(vec![new_host_candidate("192.168.0.2", 2001)?])?;
+    assert_eq!(a.remote_candidates.len(), 2);
+    assert_eq!(a.candidate_pairs.len(), 4);
+
+    a.close()?;
+    Ok(())
+}
+
+#[test]
+fn test_set_remote_candidates_rejects_mdns() -> Result<()> {
+    let mut a = Agent::new(Arc::new(AgentConfig::default()))?;
+    a.add_local_candidate(new_host_candidate("192.168.0.1", 1000)?)?;
+
+    let mut mdns_candidate = new_host_candidate("192.168.0.3", 3000)?;
+    mdns_candidate.address = "my-host.local".to_owned();
+
+    let result = a.set_remote_candidates(vec![
+        new_host_candidate("192.168.0.2", 2000)?,
+        mdns_candidate,
+    ]);
+    assert!(result.is_err());
+    assert!(
+        a.remote_candidates.is_empty(),
+        "no candidate should be added when validation fails"
+    );
+    assert!(a.candidate_pairs.is_empty());
+
+    a.close()?;
+    Ok(())
+}
+
+#[test]
+fn test_host_acceptance_min_wait_delays_host_checks() -> Result<()> {
+    let mut a = Agent::new(Arc::new(AgentConfig {
+        host_acceptance_min_wait: Some(Duration::from_secs(3600)),
+        ..Default::default()
+    }))?;
+    let b = Agent::new(Arc::new(AgentConfig::default()))?;
+
+    a.add_local_candidate(new_host_candidate("192.168.0.1", 1000)?)?;
+    a.add_remote_candidate(new_host_candidate("192.168.0.2", 2000)?)?;
+    let b_credentials = b.get_local_credentials().clone();
+    a.start_connectivity_checks(true, b_credentials.ufrag, b_credentials.pwd)?;
+
+    a.ping_all_candidates();
+    assert_eq!(a.candidate_pairs[0].state, CandidatePairState::Waiting);
+    assert!(
+        a.poll_transmit().is_none(),
+        "host pair must not be checked yet"
+    );
+
+    // Once the wait has elapsed the host pair is checked as usual
+    a.host_acceptance_min_wait = Duration::from_secs(0);
+    a.ping_all_candidates();
+    assert_eq!(a.candidate_pairs[0].state, CandidatePairState::InProgress);
+    assert!(a.poll_transmit().is_some());
+
+    a.close()?;
+    Ok(())
+}
+
+fn lite_config() -> AgentConfig {
+    AgentConfig {
+        lite: true,
+        candidate_types: vec![CandidateType::Host],
+        ..Default::default()
+    }
+}
+
+#[test]
+fn test_start_connectivity_checks_lite_role_constraints() -> Result<()> {
+    let (mut a_agent, b_agent) = pipe(Some(lite_config()), None)?;
+    let b_credentials = b_agent.get_local_credentials().clone();
+
+    // Neither side would ever send a check
+    a_agent.set_remote_lite(true);
+    let result = a_agent.start_connectivity_checks(
+        true,
+        b_credentials.ufrag.clone(),
+        b_credentials.pwd.clone(),
+    );
+    assert_eq!(result, Err(Error::ErrBothAgentsLite));
+
+    // A full agent facing a lite agent must be controlling
+    let (mut a_agent, b_agent) = pipe(None, Some(lite_config()))?;
+    let b_credentials = b_agent.get_local_credentials().clone();
+    a_agent.set_remote_lite(true);
+    assert!(a_agent.is_remote_lite());
+    let result = a_agent.start_connectivity_checks(
+        false,
+        b_credentials.ufrag.clone(),
+        b_credentials.pwd.clone(),
+    );
+    assert_eq!(result, Err(Error::ErrLiteRemoteRequiresControlling));
+    a_agent.start_connectivity_checks(true, b_credentials.ufrag, b_credentials.pwd)?;
+
+    Ok(())
+}
+
+#[test]
+fn test_full_controlling_with_lite_controlled_selects_pair() -> Result<()> {
+    let (mut a_agent, mut b_agent) = pipe(None, Some(lite_config()))?;
+    a_agent.set_remote_lite(true);
+
+    connect_agents(&mut a_agent, &mut b_agent)?;
+
+    // The lite agent never triggers a nomination, the next check tick of the full agent does
+    a_agent.contact_candidates();
+    deliver_transmits(&mut a_agent, &mut b_agent);
+    deliver_transmits(&mut b_agent, &mut a_agent);
+
+    for agent in [&a_agent, &b_agent] {
+        let pair_index = agent
+            .get_selected_pair()
+            .expect("selected pair should be set after nomination");
+        assert!(agent.candidate_pairs[pair_index].nominated);
+    }
+    // The lite agent only ever responded
+    assert_eq!(b_agent.pending_binding_requests.len(), 0);
+
+    a_agent.close()?;
+    b_agent.close()?;
+    Ok(())
+}
+
+#[test]
+fn test_lite_controlling_selects_responded_pair() -> Result<()> {
+    let (mut a_agent, mut b_agent) = pipe(Some(lite_config()), None)?;
+
+    a_agent.add_local_candidate(new_host_candidate("192.168.0.1", 1000)?)?;
+    b_agent.add_local_candidate(new_host_candidate("192.168.0.2", 2000)?)?;
+    a_agent.add_remote_candidate(new_host_candidate("192.168.0.2", 2000)?)?;
+    b_agent.add_remote_candidate(new_host_candidate("192.168.0.1", 1000)?)?;
+
+    let a_credentials = a_agent.get_local_credentials().clone();
+    let b_credentials = b_agent.get_local_credentials().clone();
+    a_agent.start_connectivity_checks(true, b_credentials.ufrag, b_credentials.pwd)?;
+    assert!(
+        a_agent.poll_transmit().is_none(),
+        "lite agent must not send checks"
+    );
+    b_agent.start_connectivity_checks(false, a_credentials.ufrag, a_credentials.pwd)?;
+
+    assert!(deliver_transmits(&mut b_agent, &mut a_agent) > 0);
+
+    let pair_index = a_agent
+        .get_selected_pair()
+        .expect("lite controlling agent should select the responded pair");
+    assert_eq!(
+        a_agent.candidate_pairs[pair_index].state,
+        CandidatePairState::Succeeded
+    );
+    // Only the binding success response went out, no checks and no nomination
+    assert_eq!(a_agent.pending_binding_requests.len(), 0);
+    assert_eq!(deliver_transmits(&mut a_agent, &mut b_agent), 1);
+
+    a_agent.close()?;
+    b_agent.close()?;
+    Ok(())
+}
+
+// Returns the transaction ids of all pending transmits
+fn poll_transaction_ids(agent: &mut Agent) -> Result<Vec<TransactionId>> {
+    let mut transaction_ids = vec![];
+    while let Some(transmit) = agent.poll_transmit() {
+        let mut m = Message::new();
+        m.raw = transmit.message.to_vec();
+        m.decode()?;
+        transaction_ids.push(m.transaction_id);
+    }
+    Ok(transaction_ids)
+}
+
+#[test]
+fn test_binding_request_retransmission() -> Result<()> {
+    let rto = Duration::from_millis(500);
+    let mut a = Agent::new(Arc::new(AgentConfig {
+        binding_request_rto: Some(rto),
+        ..Default::default()
+    }))?;
+    let b = Agent::new(Arc::new(AgentConfig::default()))?;
+
+    a.add_local_candidate(new_host_candidate("192.168.0.1", 1000)?)?;
+    a.add_remote_candidate(new_host_candidate("192.168.0.2", 2000)?)?;
+    let b_credentials = b.get_local_credentials().clone();
+    a.start_connectivity_checks(true, b_credentials.ufrag, b_credentials.pwd)?;
+
+    let transaction_ids = poll_transaction_ids(&mut a)?;
+    assert_eq!(transaction_ids.len(), 1);
+    let transaction_id = transaction_ids[0];
+    let sent_at = a.pending_binding_requests[&transaction_id].timestamp;
+    assert!(a
+        .poll_timeout()
+        .is_some_and(|timeout| timeout <= sent_at + rto));
+
+    // After RTO the same transaction is resent
+    a.retransmit_binding_requests(sent_at + rto);
+    assert_eq!(poll_transaction_ids(&mut a)?, vec![transaction_id]);
+    assert_eq!(a.pending_binding_requests[&transaction_id].attempts, 2);
+    assert_eq!(
+        a.pending_binding_requests[&transaction_id].next_retransmit,
+        Some(sent_at + rto * 3)
+    );
+
+    // The interval then doubles
+    a.retransmit_binding_requests(sent_at + rto * 2);
+    assert!(poll_transaction_ids(&mut a)?.is_empty());
+    a.retransmit_binding_requests(sent_at + rto * 3);
+    assert_eq!(poll_transaction_ids(&mut a)?, vec![transaction_id]);
+    assert_eq!(a.pending_binding_requests[&transaction_id].attempts, 3);
+
+    // A request that expired is not retransmitted anymore
+    a.retransmit_binding_requests(sent_at + MAX_BINDING_REQUEST_TIMEOUT);
+    assert!(poll_transaction_ids(&mut a)?.is_empty());
+    assert!(a.pending_binding_requests.is_empty());
+
+    a.close()?;
+    Ok(())
+}
+
+#[test]
+fn test_pending_binding_requests_limit() -> Result<()> {
+    let mut a = Agent::new(Arc::new(AgentConfig::default()))?;
+    a.add_local_candidate(new_host_candidate("192.168.0.1", 1000)?)?;
+    a.add_remote_candidate(new_host_candidate("192.168.0.2", 2000)?)?;
+
+    let send = |a: &mut Agent| {
+        let m = Message {
+            transaction_id: TransactionId::new(),
+            ..Default::default()
+        };
+        a.send_binding_request(&m, 0, 0);
+        m.transaction_id
+    };
+    let oldest = send(&mut a);
+    a.pending_binding_requests
+        .get_mut(&oldest)
+        .unwrap()
+        .timestamp -= Duration::from_secs(1);
+    for _ in 1..MAX_PENDING_BINDING_REQUESTS {
+        send(&mut a);
+    }
+    assert_eq!(
+        a.pending_binding_requests.len(),
+        MAX_PENDING_BINDING_REQUESTS
+    );
+
+    // Beyond the limit the oldest request is dropped
+    let newest = send(&mut a);
+    assert_eq!(
+        a.pending_binding_requests.len(),
+        MAX_PENDING_BINDING_REQUESTS
+    );
+    assert!(a.handle_inbound_binding_success(oldest).is_none());
+    assert!(a.handle_inbound_binding_success(newest).is_some());
+    assert_eq!(
+        a.pending_binding_requests.len(),
+        MAX_PENDING_BINDING_REQUESTS - 1
+    );
+
+    // Answered requests don't keep growing the send order
+    for _ in 0..3 * MAX_PENDING_BINDING_REQUESTS {
+        let id = send(&mut a);
+        assert!(a.handle_inbound_binding_success(id).is_some());
+    }
+    assert!(a.pending_binding_request_order.len() <= 2 * MAX_PENDING_BINDING_REQUESTS);
+
+    // A response to an expired request is ignored even before it is dropped
+    let expired = send(&mut a);
+    a.pending_binding_requests
+        .get_mut(&expired)
+        .unwrap()
+        .timestamp -= MAX_BINDING_REQUEST_TIMEOUT;
+    assert!(a.handle_inbound_binding_success(expired).is_none());
+
+    a.close()?;
+    Ok(())
+}
+
+#[test]
+fn test_binding_request_triggers_check() -> Result<()> {
+    let (mut a_agent, mut b_agent) = pipe(None, None)?;
+
+    a_agent.add_local_candidate(new_host_candidate("192.168.0.1", 1000)?)?;
+    b_agent.add_local_candidate(new_host_candidate("192.168.0.2", 2000)?)?;
+    a_agent.add_remote_candidate(new_host_candidate("192.168.0.2", 2000)?)?;
+    b_agent.add_remote_candidate(new_host_candidate("192.168.0.1", 1000)?)?;
+
+    let a_credentials = a_agent.get_local_credentials().clone();
+    let b_credentials = b_agent.get_local_credentials().clone();
+    a_agent.start_connectivity_checks(true, b_credentials.ufrag, b_credentials.pwd)?;
+    b_agent.start_connectivity_checks(false, a_credentials.ufrag, a_credentials.pwd)?;
+
+    // b's own check got lost and the pair failed
+    while b_agent.poll_transmit().is_some() {}
+    b_agent.candidate_pairs[0].state = CandidatePairState::Failed;
+
+    // The check from a is answered and triggers a check back on the same pair
+    assert_eq!(deliver_transmits(&mut a_agent, &mut b_agent), 1);
+    assert_eq!(
+        b_agent.candidate_pairs[0].state,
+        CandidatePairState::InProgress
+    );
+    assert_eq!(b_agent.candidate_pairs[0].binding_request_count, 1);
+    assert_eq!(b_agent.pending_binding_requests.len(), 2);
+    assert_eq!(deliver_transmits(&mut b_agent, &mut a_agent), 2);
+
+    // Once b's triggered check succeeded, further requests are only answered
+    deliver_transmits(&mut a_agent, &mut b_agent);
+    assert_eq!(
+        b_agent.candidate_pairs[0].state,
+        CandidatePairState::Succeeded
+    );
+    while deliver_transmits(&mut b_agent, &mut a_agent)
+        + deliver_transmits(&mut a_agent, &mut b_agent)
+        > 0
+    {}
+    a_agent.ping_candidate(0, 0);
+    assert_eq!(deliver_transmits(&mut a_agent, &mut b_agent), 1);
+    assert_eq!(deliver_transmits(&mut b_agent, &mut a_agent), 1);
+
+    a_agent.close()?;
+    b_agent.close()?;
+    Ok(())
+}
+
+#[test]
+fn test_controlled_accepts_nomination_after_check() -> Result<()> {
+    let (mut a_agent, mut b_agent) = pipe(None, None)?;
+
+    a_agent.add_local_candidate(new_host_candidate("192.168.0.1", 1000)?)?;
+    b_agent.add_local_candidate(new_host_candidate("192.168.0.2", 2000)?)?;
+    a_agent.add_remote_candidate(new_host_candidate("192.168.0.2", 2000)?)?;
+    b_agent.add_remote_candidate(new_host_candidate("192.168.0.1", 1000)?)?;
+
+    let a_credentials = a_agent.get_local_credentials().clone();
+    let b_credentials = b_agent.get_local_credentials().clone();
+    a_agent.start_connectivity_checks(true, b_credentials.ufrag, b_credentials.pwd)?;
+    b_agent.start_connectivity_checks(false, a_credentials.ufrag, a_credentials.pwd)?;
+    while a_agent.poll_transmit().is_some() {}
+    while b_agent.poll_transmit().is_some() {}
+
+    let mut msg = Message::new();
+    msg.build(&[
+        Box::new(BINDING_REQUEST),
+        Box::new(TransactionId::new()),
+        Box::new(UseCandidateAttr::new()),
+    ])?;
+
+    // The pair is not validated yet, the nomination waits for b's triggered check
+    b_agent.handle_binding_request(&msg, 0, 0);
+    assert!(b_agent.candidate_pairs[0].nominated);
+    assert_eq!(
+        b_agent.candidate_pairs[0].state,
+        CandidatePairState::InProgress
+    );
+    assert_eq!(b_agent.get_selected_pair(), None);
+
+    deliver_transmits(&mut b_agent, &mut a_agent);
+    deliver_transmits(&mut a_agent, &mut b_agent);
+    assert_eq!(
+        b_agent.candidate_pairs[0].state,
+        CandidatePairState::Succeeded
+    );
+    assert_eq!(b_agent.get_selected_pair(), Some(0));
+
+    // A nomination is dropped when the triggered check fails
+    b_agent.set_selected_pair(None);
+    b_agent.candidate_pairs[0].state = CandidatePairState::Failed;
+    b_agent.candidate_pairs[0].nominated = false;
+    b_agent.handle_binding_request(&msg, 0, 0);
+    assert!(b_agent.candidate_pairs[0].nominated);
+    while b_agent.poll_transmit().is_some() {}
+    b_agent.candidate_pairs[0].binding_request_count = b_agent.max_binding_requests + 1;
+    b_agent.ping_all_candidates();
+    assert_eq!(b_agent.candidate_pairs[0].state, CandidatePairState::Failed);
+    assert!(!b_agent.candidate_pairs[0].nominated);
+    assert_eq!(b_agent.get_selected_pair(), None);
+
+    a_agent.close()?;
+    b_agent.close()?;
+    Ok(())
+}
+
+#[test]
+fn test_unknown_comprehension_required_attribute() -> Result<()> {
+    let mut a = Agent::new(Arc::new(AgentConfig::default()))?;
+    a.add_local_candidate(new_host_candidate("192.168.0.2", 777)?)?;
+
+    let remote_addr = SocketAddr::from_str("192.168.0.3:999")?;
+    a.ufrag_pwd.remote_credentials = Some(Credentials {
+        ufrag: "".to_string(),
+        pwd: "".to_string(),
+    });
+    let username = a.ufrag_pwd.local_credentials.ufrag.to_owned() + ":";
+    let local_pwd = a.ufrag_pwd.local_credentials.pwd.clone();
+
+    let unknown_required = AttrType(0x7FF0);
+    let unknown_optional = AttrType(0xFFF0);
+    let mut msg = Message::new();
+    msg.build(&[
+        Box::new(BINDING_REQUEST),
+        Box::new(TransactionId::new()),
+        Box::new(Username::new(ATTR_USERNAME, username)),
+        Box::new(PriorityAttr(1)),
+        Box::new(RawAttribute {
+            typ: unknown_required,
+            length: 0,
+            value: vec![1, 2, 3, 4],
+        }),
+        Box::new(RawAttribute {
+            typ: unknown_optional,
+            length: 0,
+            value: vec![1, 2, 3, 4],
+        }),
+        Box::new(MessageIntegrity::new_short_term_integrity(local_pwd)),
+        Box::new(FINGERPRINT),
+    ])?;
+
+    a.handle_inbound(&mut msg, 0, remote_addr)?;
+
+    // Only the 420 response is sent, the request is not otherwise processed
+    let transmit = a.poll_transmit().expect("420 error response");
+    assert_eq!(transmit.transport.peer_addr, remote_addr);
+    assert!(a.poll_transmit().is_none());
+    assert!(a.remote_candidates.is_empty());
+    assert!(a.candidate_pairs.is_empty());
+
+    let mut res = Message::new();
+    res.raw = transmit.message.to_vec();
+    res.decode()?;
+    assert_eq!(res.typ, BINDING_ERROR);
+    assert_eq!(res.transaction_id, msg.transaction_id);
+
+    let mut error_code = ErrorCodeAttribute::default();
+    error_code.get_from(&res)?;
+    assert!(error_code.code == CODE_UNKNOWN_ATTRIBUTE);
+    let mut unknown_attributes = UnknownAttributes(vec![]);
+    unknown_attributes.get_from(&res)?;
+    assert_eq!(unknown_attributes.0, vec![unknown_required]);
+
+    a.close()?;
+    Ok(())
+}
+
+#[test]
+fn test_graceful_restart_retains_selected_pair() -> Result<()> {
+    let (mut a_agent, mut b_agent) = pipe(None, None)?;
+    connect_agents(&mut a_agent, &mut b_agent)?;
+    let selected_pair = a_agent
+        .get_selected_candidate_pair()
+        .expect("selected pair before restart");
+
+    a_agent.restart(String::new(), String::new(), true, true)?;
+    assert!(a_agent.get_selected_pair().is_none());
+    let (local, remote) = a_agent
+        .get_selected_candidate_pair()
+        .expect("retained pair during restart");
+    assert_eq!(local.addr(), selected_pair.0.addr());
+    assert_eq!(remote.addr(), selected_pair.1.addr());
+
+    // The retained pair is kept alive with the previous credentials, so b still accepts it
+    a_agent.check_retained_pair_keepalive(Instant::now() + a_agent.keepalive_interval);
+    assert_eq!(deliver_transmits(&mut a_agent, &mut b_agent), 1);
+    assert_eq!(deliver_transmits(&mut b_agent, &mut a_agent), 1);
+
+    // and a keeps answering b's checks made with the previous credentials
+    b_agent.ping_candidate(0, 0);
+    assert_eq!(deliver_transmits(&mut b_agent, &mut a_agent), 1);
+    assert_eq!(deliver_transmits(&mut a_agent, &mut b_agent), 1);
+    assert!(b_agent.pending_binding_requests.is_empty());
+
+    // Once the new session selects a pair, it replaces the retained one
+    b_agent.restart(String::new(), String::new(), true, false)?;
+    a_agent.add_remote_candidate(new_host_candidate("192.168.0.2", 2000)?)?;
+    b_agent.add_remote_candidate(new_host_candidate("192.168.0.1", 1000)?)?;
+    let a_credentials = a_agent.get_local_credentials().clone();
+    let b_credentials = b_agent.get_local_credentials().clone();
+    a_agent.start_connectivity_checks(true, b_credentials.ufrag, b_credentials.pwd)?;
+    b_agent.start_connectivity_checks(false, a_credentials.ufrag, a_credentials.pwd)?;
+    for _ in 0..16 {
+        let delivered = deliver_transmits(&mut a_agent, &mut b_agent)
+            + deliver_transmits(&mut b_agent, &mut a_agent);
+        if delivered == 0 {
+            break;
+        }
+    }
+
+    assert!(a_agent.get_selected_pair().is_some());
+    assert!(a_agent.retained_pair.is_none());
+
+    a_agent.close()?;
+    b_agent.close()?;
+    Ok(())
+}
+
+#[test]
+fn test_candidate_pair_round_trip_time_stats() -> Result<()> {
+    let (mut a_agent, mut b_agent) = pipe(None, None)?;
+    connect_agents(&mut a_agent, &mut b_agent)?;
+
+    for agent in [&a_agent, &b_agent] {
+        let pair_index = agent.get_selected_pair().expect("selected pair");
+        let p = &agent.candidate_pairs[pair_index];
+        assert!(p.responses_received > 0);
+        assert!(p.smoothed_round_trip_time().is_some());
+
+        let stats = agent.get_candidate_pairs_stats();
+        assert_eq!(stats[pair_index].responses_received, p.responses_received);
+        assert!(
+            stats[pair_index].total_round_trip_time >= stats[pair_index].current_round_trip_time
+        );
+    }
+
+    // A response to a retransmitted request is not measured
+    let mut binding_request = BindingRequest {
+        attempts: 2,
+        ..Default::default()
+    };
+    assert_eq!(binding_request.round_trip_time(), None);
+    binding_request.attempts = 1;
+    assert!(binding_request.round_trip_time().is_some());
+
+    a_agent.close()?;
+    b_agent.close()?;
+    Ok(())
+}
+
+#[test]
+fn test_pairs_only_same_network_type() -> Result<()> {
+    let mut a = Agent::new(Arc::new(AgentConfig::default()))?;
+
+    a.add_local_candidate(new_host_candidate("192.168.0.1", 1000)?)?;
+    a.add_local_candidate(new_host_candidate("fe80::1", 1000)?)?;
+    a.add_remote_candidate(new_host_candidate("192.168.0.2", 2000)?)?;
+    a.add_remote_candidate(new_host_candidate("fe80::2", 2000)?)?;
+
+    assert_eq!(a.candidate_pairs.len(), 2);
+    for p in &a.candidate_pairs {
+        assert_eq!(
+            a.local_candidates[p.local_index].network_type(),
+            a.remote_candidates[p.remote_index].network_type()
+        );
+    }
+
+    a.close()?;
+    Ok(())
+}
+
+#[test]
+fn test_candidate_filter() -> Result<()> {
+    let exclude_ipv6 = Arc::new(AtomicBool::new(false));
+    let filter_exclude_ipv6 = Arc::clone(&exclude_ipv6);
+    let mut a = Agent::new(Arc::new(AgentConfig {
+        candidate_filter: Some(Arc::new(move |c: &Candidate| {
+            c.address() != "10.0.0.1"
+                && !(filter_exclude_ipv6.load(Ordering::SeqCst) && c.network_type().is_ipv6())
+        })),
+        ..Default::default()
+    }))?;
+
+    a.add_local_candidate(new_host_candidate("192.168.0.1", 1000)?)?;
+    a.add_local_candidate(new_host_candidate("10.0.0.1", 1000)?)?;
+    a.add_local_candidate(new_host_candidate("fe80::1", 1000)?)?;
+    a.add_remote_candidate(new_host_candidate("10.0.0.2", 2000)?)?;
+
+    assert_eq!(a.local_candidates.len(), 2);
+    assert!(a.local_candidates.iter().all(|c| c.address() != "10.0.0.1"));
+    assert_eq!(a.candidate_pairs.len(), 1);
+
+    // The filter is applied again to the local candidates kept across a restart
+    exclude_ipv6.store(true, Ordering::SeqCst);
+    a.restart("".to_owned(), "".to_owned(), true, false)?;
+    assert_eq!(a.local_candidates.len(), 1);
+    assert_eq!(a.local_candidates[0].address(), "192.168.0.1");
+
+    a.close()?;
+    Ok(())
+}
+
+#[test]
+fn test_local_candidate_sdp_lines() -> Result<()> {
+    let mut a = Agent::new(Arc::new(AgentConfig::default()))?;
+    a.add_local_candidate(new_host_candidate("192.168.0.1", 1000)?)?;
+
+    let candidates = a.local_candidates();
+    a.add_local_candidate(new_host_candidate("192.168.0.2", 2000)?)?;
+    assert_eq!(candidates.len(), 1, "snapshot must not follow the agent");
+
+    let sdp_lines = a.local_candidate_sdp_lines();
+    assert_eq!(sdp_lines.len(), 2);
+    for (sdp_line, c) in sdp_lines.iter().zip(a.get_local_candidates()) {
+        assert!(sdp_line.starts_with("candidate:"));
+        assert!(unmarshal_candidate(sdp_line)?.equal(c));
+    }
+
+    a.close()?;
+    Ok(())
+}
+
+#[test]
+fn test_paced_connectivity_checks() -> Result<()> {
+    let ta_interval = Duration::from_millis(50);
+    let mut a = Agent::new(Arc::new(AgentConfig {
+        ta_interval: Some(ta_interval),
+        ..Default::default()
+    }))?;
+
+    a.add_local_candidate(new_host_candidate("192.168.0.1", 1000)?)?;
+    for port in [2000, 2001, 2002] {
+        a.add_remote_candidate(new_host_candidate("192.168.0.2", port)?)?;
+    }
+    a.start_connectivity_checks(false, "remoteufrag".to_owned(), "remotepwd".to_owned())?;
+
+    let mut by_priority: Vec<usize> = (0..a.candidate_pairs.len()).collect();
+    by_priority.sort_by_key(|&i| Reverse(a.candidate_pairs[i].priority(a.is_controlling)));
+    let expected: Vec<SocketAddr> = by_priority
+        .iter()
+        .map(|&i| a.remote_candidates[a.candidate_pairs[i].remote_index].addr())
+        .collect();
+
+    // Only one check per Ta interval, cycling through the pairs in priority order
+    assert_eq!(a.get_timeout_interval(), ta_interval);
+    let mut destinations = vec![];
+    for round in 0..6 {
+        if round > 0 {
+            a.last_paced_check = None;
+            a.contact_candidates();
+        }
+        a.contact_candidates();
+        let transmit = a.poll_transmit().expect("one check per Ta interval");
+        assert!(a.poll_transmit().is_none());
+        destinations.push(transmit.transport.peer_addr);
+    }
+    assert_eq!(destinations[..3], expected[..]);
+    assert_eq!(destinations[3..], expected[..]);
+
+    a.close()?;
+    Ok(())
+}
+
+#[test]
+fn test_connect_accept() -> Result<()> {
+    let (mut a_agent, mut b_agent) = pipe(None, None)?;
+
+    a_agent.add_local_candidate(new_host_candidate("192.168.0.1", 1000)?)?;
+    b_agent.add_local_candidate(new_host_candidate("192.168.0.2", 2000)?)?;
+    a_agent.add_remote_candidate(new_host_candidate("192.168.0.2", 2000)?)?;
+    b_agent.add_remote_candidate(new_host_candidate("192.168.0.1", 1000)?)?;
+
+    let a_credentials = a_agent.get_local_credentials().clone();
+    let b_credentials = b_agent.get_local_credentials().clone();
+    a_agent.connect(b_credentials.ufrag.clone(), b_credentials.pwd.clone())?;
+    b_agent.accept(a_credentials.ufrag.clone(), a_credentials.pwd.clone())?;
+
+    assert!(a_agent.is_controlling);
+    assert!(!b_agent.is_controlling);
+    for agent in [&a_agent, &b_agent] {
+        assert_eq!(agent.connection_state, ConnectionState::Checking);
+    }
+
+    assert_eq!(
+        a_agent.connect(b_credentials.ufrag, b_credentials.pwd),
+        Err(Error::ErrMultipleStart)
+    );
+    assert_eq!(
+        b_agent.accept(a_credentials.ufrag, a_credentials.pwd),
+        Err(Error::ErrMultipleStart)
+    );
+
+    while deliver_transmits(&mut a_agent, &mut b_agent)
+        + deliver_transmits(&mut b_agent, &mut a_agent)
+        > 0
+    {}
+    a_agent.contact_candidates();
+    while deliver_transmits(&mut a_agent, &mut b_agent)
+        + deliver_transmits(&mut b_agent, &mut a_agent)
+        > 0
+    {}
+    for agent in [&a_agent, &b_agent] {
+        assert_eq!(agent.connection_state, ConnectionState::Connected);
+    }
+
+    a_agent.close()?;
+    b_agent.close()?;
+    Ok(())
+}
+
+#[test]
+fn test_frozen_pairs_of_other_components() -> Result<()> {
+    let mut a = Agent::new(Arc::new(AgentConfig::default()))?;
+    let b = Agent::new(Arc::new(AgentConfig::default()))?;
+
+    let host_candidate = |address: &str, port: u16, component: u16| {
+        CandidateHostConfig {
+            base_config: CandidateConfig {
+                network: "udp".to_owned(),
+                address: address.to_owned(),
+                port,
+                component,
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+        .new_candidate_host()
+    };
+
+    // RTP and RTCP candidates share their foundations
+    a.add_local_candidate(host_candidate("192.168.0.1", 1000, 1)?)?;
+    a.add_local_candidate(host_candidate("192.168.0.1", 1001, 2)?)?;
+    a.add_remote_candidate(host_candidate("192.168.0.2", 2000, 1)?)?;
+    a.add_remote_candidate(host_candidate("192.168.0.2", 2001, 2)?)?;
+    assert_eq!(a.candidate_pairs.len(), 2, "pairs are per component");
+    assert_eq!(a.candidate_pairs[0].state, CandidatePairState::Waiting);
+    assert_eq!(a.candidate_pairs[1].state, CandidatePairState::Frozen);
+
+    let b_credentials = b.get_local_credentials().clone();
+    a.start_connectivity_checks(true, b_credentials.ufrag, b_credentials.pwd)?;
+    while a.poll_transmit().is_some() {}
+
+    // Only the RTP pair is checked while its check is pending
+    a.ping_candidates();
+    assert_eq!(a.candidate_pairs[0].state, CandidatePairState::InProgress);
+    assert_eq!(a.candidate_pairs[1].state, CandidatePairState::Frozen);
+    assert!(a.poll_transmit().is_some());
+    assert!(a.poll_transmit().is_none());
+
+    // Its success unfreezes the RTCP pair
+    a.candidate_pairs[0].state = CandidatePairState::Succeeded;
+    a.ping_candidates();
+    assert_eq!(a.candidate_pairs[1].state, CandidatePairState::InProgress);
+    assert!(a.poll_transmit().is_some());
+
+    // So does its failure, once nothing of the foundation is pending anymore
+    a.candidate_pairs[0].state = CandidatePairState::Failed;
+    a.candidate_pairs[1].state = CandidatePairState::Frozen;
+    a.ping_candidates();
+    assert_eq!(a.candidate_pairs[1].state, CandidatePairState::InProgress);
+
+    a.close()?;
+    Ok(())
+}
+
+#[test]
+fn test_pause_resume() -> Result<()> {
+    let (mut a_agent, mut b_agent) = pipe(None, None)?;
+    connect_agents(&mut a_agent, &mut b_agent)?;
+    let selected_pair = a_agent.get_selected_pair().unwrap();
+
+    a_agent.pause();
+    assert!(a_agent.is_paused());
+    assert!(a_agent.poll_timeout().is_none());
+
+    // Nothing is sent while paused, however late the timer fires
+    a_agent.handle_timeout(Instant::now() + Duration::from_secs(3600));
+    assert!(a_agent.poll_transmit().is_none());
+
+    // The peer's checks are still answered
+    let b_pair = b_agent.selected_pair.unwrap();
+    let (local_index, remote_index) = (
+        b_agent.candidate_pairs[b_pair].local_index,
+        b_agent.candidate_pairs[b_pair].remote_index,
+    );
+    b_agent.ping_candidate(local_index, remote_index);
+    assert_eq!(deliver_transmits(&mut b_agent, &mut a_agent), 1);
+    assert_eq!(deliver_transmits(&mut a_agent, &mut b_agent), 1);
+
+    // Resuming sends a keepalive right away and keeps the selected pair
+    a_agent.resume();
+    assert!(!a_agent.is_paused());
+    assert!(a_agent.poll_timeout().is_some());
+    assert!(deliver_transmits(&mut a_agent, &mut b_agent) > 0);
+    assert_eq!(a_agent.get_selected_pair(), Some(selected_pair));
+
+    a_agent.close()?;
+    b_agent.close()?;
+    Ok(())
+}
+
+#[test]
+fn test_renomination() -> Result<()> {
+    let renomination_config = || AgentConfig {
+        enable_renomination: true,
+        ..Default::default()
+    };
+    let (mut a_agent, mut b_agent) =
+        pipe(Some(renomination_config()), Some(renomination_config()))?;
+    a_agent.set_remote_renomination(true);
+    b_agent.set_remote_renomination(true);
+
+    // Two pairs, both of which get validated by the first checks
+    for port in [1000, 1001] {
+        a_agent.add_local_candidate(new_host_candidate("192.168.0.1", port)?)?;
+        b_agent.add_remote_candidate(new_host_candidate("192.168.0.1", port)?)?;
+    }
+    b_agent.add_local_candidate(new_host_candidate("192.168.0.2", 2000)?)?;
+    a_agent.add_remote_candidate(new_host_candidate("192.168.0.2", 2000)?)?;
+
+    let a_credentials = a_agent.get_local_credentials().clone();
+    let b_credentials = b_agent.get_local_credentials().clone();
+    a_agent.start_connectivity_checks(true, b_credentials.ufrag, b_credentials.pwd)?;
+    b_agent.start_connectivity_checks(false, a_credentials.ufrag, a_credentials.pwd)?;
+    let exchange = |a_agent: &mut Agent, b_agent: &mut Agent| {
+        for _ in 0..16 {
+            let delivered =
+                deliver_transmits(a_agent, b_agent) + deliver_transmits(b_agent, a_agent);
+            if delivered == 0 {
+                break;
+            }
+        }
+    };
+    exchange(&mut a_agent, &mut b_agent);
+
+    let (selected_local, _) = a_agent.get_selected_candidate_pair().unwrap();
+    let (_, b_selected_remote) = b_agent.get_selected_candidate_pair().unwrap();
+    assert!(selected_local.equal(&b_selected_remote));
+    let other_pair = a_agent
+        .candidate_pairs
+        .iter()
+        .find(|p| !a_agent.local_candidates[p.local_index].equal(&selected_local))
+        .copied()
+        .unwrap();
+    assert_eq!(other_pair.state, CandidatePairState::Succeeded);
+    let (other_local, other_remote) = (
+        a_agent.local_candidates[other_pair.local_index].clone(),
+        a_agent.remote_candidates[other_pair.remote_index].clone(),
+    );
+
+    // Only the controlling agent renominates
+    assert_eq!(
+        b_agent.renominate(&other_remote, &other_local),
+        Err(Error::ErrRenominationNotEnabled)
+    );
+
+    // Both agents move to the renominated pair, even though it isn't the best one
+    a_agent.renominate(&other_local, &other_remote)?;
+    exchange(&mut a_agent, &mut b_agent);
+    let (a_local, a_remote) = a_agent.get_selected_candidate_pair().unwrap();
+    assert!(a_local.equal(&other_local) && a_remote.equal(&other_remote));
+    let (b_local, b_remote) = b_agent.get_selected_candidate_pair().unwrap();
+    assert!(b_local.equal(&other_remote) && b_remote.equal(&other_local));
+    assert_eq!(b_agent.remote_nomination, a_agent.nomination);
+
+    // A nomination older than the one followed doesn't move the controlled agent back
+    b_agent.remote_nomination += 1;
+    a_agent.renominate(&selected_local, &other_remote)?;
+    exchange(&mut a_agent, &mut b_agent);
+    let (_, b_remote) = b_agent.get_selected_candidate_pair().unwrap();
+    assert!(b_remote.equal(&other_local));
+
+    // Move both agents back to the other pair, then renominate twice and deliver the
+    // nominations out of order. The controlled agent ignores the outdated one, and the
+    // controlling agent must not select its pair when the success response arrives.
+    a_agent.renominate(&other_local, &other_remote)?;
+    exchange(&mut a_agent, &mut b_agent);
+    let (a_local, _) = a_agent.get_selected_candidate_pair().unwrap();
+    assert!(a_local.equal(&other_local));
+    let (_, b_remote) = b_agent.get_selected_candidate_pair().unwrap();
+    assert!(b_remote.equal(&other_local));
+
+    let poll_transmits =
+        |agent: &mut Agent| std::iter::from_fn(|| agent.poll_transmit()).collect::<Vec<_>>();
+    a_agent.renominate(&selected_local, &other_remote)?;
+    let outdated = poll_transmits(&mut a_agent);
+    a_agent.renominate(&other_local, &other_remote)?;
+    let latest = poll_transmits(&mut a_agent);
+    let nomination = a_agent.nomination;
+    a_agent.renominate(&selected_local, &other_remote)?;
+    let _ = poll_transmits(&mut a_agent);
+    for transmit in latest.into_iter().chain(outdated) {
+        let _ = b_agent.handle_read(Transmit {
+            now: transmit.now,
+            transport: TransportContext {
+                local_addr: transmit.transport.peer_addr,
+                peer_addr: transmit.transport.local_addr,
+                ecn: transmit.transport.ecn,
+                protocol: transmit.transport.protocol,
+            },
+            message: transmit.message,
+        });
+    }
+    assert_eq!(b_agent.remote_nomination, nomination);
+    deliver_transmits(&mut b_agent, &mut a_agent);
+    let (a_local, _) = a_agent.get_selected_candidate_pair().unwrap();
+    assert!(a_local.equal(&other_local));
+    let (_, b_remote) = b_agent.get_selected_candidate_pair().unwrap();
+    assert!(b_remote.equal(&other_local));
+
+    a_agent.close()?;
+    b_agent.close()?;
+    Ok(())
+}
+
+#[test]
+fn test_renomination_requires_remote_support() -> Result<()> {
+    let (mut a_agent, mut b_agent) = pipe(
+        Some(AgentConfig {
+            enable_renomination: true,
+            ..Default::default()
+        }),
+        None,
+    )?;
+
+    // Nominations carry no NOMINATION attribute when the remote agent doesn't support it
+    let nominations = Arc::new(std::sync::Mutex::new(vec![]));
+    let nominations2 = Arc::clone(&nominations);
+    b_agent.on_stun_packet(move |direction, m, _| {
+        if direction == StunPacketDirection::Inbound && m.contains(ATTR_USE_CANDIDATE) {
+            nominations2
+                .lock()
+                .unwrap()
+                .push(m.contains(ATTR_NOMINATION));
+        }
+    });
+    connect_agents(&mut a_agent, &mut b_agent)?;
+    assert!(!a_agent.is_renomination_enabled());
+    assert!(!nominations.lock().unwrap().is_empty());
+    assert!(nominations
+        .lock()
+        .unwrap()
+        .iter()
+        .all(|&nomination| !nomination));
+
+    let (local, remote) = a_agent.get_selected_candidate_pair().unwrap();
+    assert_eq!(
+        a_agent.renominate(&local, &remote),
+        Err(Error::ErrRenominationNotEnabled)
+    );
+
+    a_agent.close()?;
+    b_agent.close()?;
+    Ok(())
+}
+
+#[test]
+fn test_candidate_writer() -> Result<()> {
+    let (mut a_agent, mut b_agent) = pipe(None, None)?;
+
+    let written = Arc::new(std::sync::Mutex::new(vec![]));
+    let written2 = Arc::clone(&written);
+    let mut local = new_host_candidate("192.168.0.1", 1000)?;
+    local.set_writer(move |buf, peer_addr| {
+        written2.lock().unwrap().push((buf.to_vec(), peer_addr));
+        Ok(())
+    });
+    a_agent.add_local_candidate(local)?;
+    a_agent.add_remote_candidate(new_host_candidate("192.168.0.2", 2000)?)?;
+
+    let b_credentials = b_agent.get_local_credentials().clone();
+    a_agent.start_connectivity_checks(true, b_credentials.ufrag, b_credentials.pwd)?;
+    a_agent.handle_timeout(Instant::now());
+
+    // The checks went through the writer rather than the transmit queue
+    assert!(a_agent.poll_transmit().is_none());
+    let written = written.lock().unwrap();
+    assert!(!written.is_empty());
+    for (buf, peer_addr) in written.iter() {
+        assert_eq!(*peer_addr, SocketAddr::from_str("192.168.0.2:2000")?);
+        let mut m = Message::new();
+        m.raw = buf.clone();
+        m.decode()?;
+        assert_eq!(m.typ, BINDING_REQUEST);
+    }
+
+    a_agent.close()?;
+    b_agent.close()?;
+    Ok(())
+}
+
+#[test]
+fn test_invalid_connection_state_transitions() -> Result<()> {
+    let (mut a_agent, mut b_agent) = pipe(None, None)?;
+    connect_agents(&mut a_agent, &mut b_agent)?;
+    assert_eq!(a_agent.connection_state, ConnectionState::Connected);
+
+    // A failed agent only comes back through a restart
+    assert!(a_agent.update_connection_state(ConnectionState::Failed));
+    assert!(!a_agent.update_connection_state(ConnectionState::Connected));
+    assert_eq!(a_agent.connection_state, ConnectionState::Failed);
+    a_agent.restart(String::new(), String::new(), false, false)?;
+    assert_eq!(a_agent.connection_state, ConnectionState::Checking);
+
+    // Nothing drags a closed agent back to life
+    a_agent.close()?;
+    while a_agent.poll_event().is_some() {}
+    assert!(!a_agent.update_connection_state(ConnectionState::Connected));
+    assert!(!a_agent.update_connection_state(ConnectionState::Checking));
+    assert_eq!(a_agent.connection_state, ConnectionState::Closed);
+    assert!(a_agent.poll_event().is_none());
+
+    b_agent.close()?;
+    Ok(())
+}
+
 /* TODO:
 fn gather_and_exchange_candidates(a_agent: &mut Agent, b_agent: &mut Agent) -> Result<()> {
     let wg = WaitGroup::new();
@@ -2,27 +2,34 @@
 mod agent_test;
 
 pub mod agent_config;
+mod agent_goog_ping;
 pub mod agent_selector;
 pub mod agent_stats;
 
 use agent_config::*;
 use bytes::BytesMut;
 use log::{debug, error, info, trace, warn};
-use std::collections::VecDeque;
+use rand::rngs::StdRng;
+use rand::{thread_rng, RngCore, SeedableRng};
+use std::cmp::Reverse;
+use std::collections::{HashMap, VecDeque};
 use std::net::{Ipv4Addr, SocketAddr};
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 use stun::attributes::*;
+use stun::error_code::*;
 use stun::fingerprint::*;
 use stun::integrity::*;
 use stun::message::*;
 use stun::textattrs::*;
+use stun::uattrs::*;
 use stun::xoraddr::*;
 
+use crate::attributes::{control::*, priority::*};
 use crate::candidate::candidate_peer_reflexive::CandidatePeerReflexiveConfig;
 use crate::candidate::{candidate_pair::*, *};
-use crate::network_type::NetworkType;
 use crate::rand::*;
+use crate::role::IceRole;
 use crate::state::*;
 use crate::url::*;
 use shared::error::*;
@@ -30,12 +37,39 @@ use shared::{Protocol, Transmit, TransportContext};
 
 const ZERO_DURATION: Duration = Duration::from_secs(0);
 
+/// Comprehension-required attributes the agent understands in a binding request.
+const KNOWN_REQUEST_ATTRIBUTES: [AttrType; 4] = [
+    ATTR_USERNAME,
+    ATTR_MESSAGE_INTEGRITY,
+    ATTR_PRIORITY,
+    ATTR_USE_CANDIDATE,
+];
+
 #[derive(Debug, Clone)]
 pub(crate) struct BindingRequest {
     pub(crate) timestamp: Instant,
     pub(crate) transaction_id: TransactionId,
     pub(crate) destination: SocketAddr,
     pub(crate) is_use_candidate: bool,
+    // the request as sent, kept to retransmit the same transaction
+    pub(crate) message: Message,
+    pub(crate) local_index: usize,
+    pub(crate) remote_index: usize,
+    // number of times the request has been sent, including the first transmission
+    pub(crate) attempts: u16,
+    pub(crate) next_retransmit: Option<Instant>,
+}
+
+impl BindingRequest {
+    /// Time elapsed since the request was sent, or None if it was retransmitted since a response
+    /// can't be matched to a transmission then (Karn's algorithm).
+    pub(crate) fn round_trip_time(&self) -> Option<Duration> {
+        if self.attempts > 1 {
+            None
+        } else {
+            Some(Instant::now().saturating_duration_since(self.timestamp))
+        }
+    }
 }
 
 impl Default for BindingRequest {
@@ -45,16 +79,38 @@ impl Default for BindingRequest {
             transaction_id: TransactionId::default(),
             destination: SocketAddr::new(Ipv4Addr::new(0, 0, 0, 0).into(), 0),
             is_use_candidate: false,
+            message: Message::default(),
+            local_index: 0,
+            remote_index: 0,
+            attempts: 0,
+            next_retransmit: None,
         }
     }
 }
 
+// Whether a binding request sent at timestamp is no longer waited for at now
+fn is_binding_request_expired(timestamp: Instant, now: Instant) -> bool {
+    now.checked_duration_since(timestamp)
+        .is_some_and(|duration| duration >= MAX_BINDING_REQUEST_TIMEOUT)
+}
+
 #[derive(Default, Clone)]
 pub struct Credentials {
     pub ufrag: String,
     pub pwd: String,
 }
 
+/// The selected pair of the previous session, kept as a fallback during a graceful restart
+/// until a pair of the new session is selected.
+pub(crate) struct RetainedPair {
+    pub(crate) local: Candidate,
+    pub(crate) remote: Candidate,
+    // credentials of the previous session, still used by the keepalives of the pair
+    pub(crate) local_credentials: Credentials,
+    pub(crate) remote_credentials: Credentials,
+    pub(crate) last_keepalive: Instant,
+}
+
 #[derive(Default, Clone)]
 pub(crate) struct UfragPwd {
     pub(crate) local_credentials: Credentials,
@@ -77,27 +133,85 @@ fn assert_inbound_username(m: &Message, expected_username: &str) -> Result<()> {
     Ok(())
 }
 
+fn validate_remote_candidate(c: &Candidate) -> Result<()> {
+    // If we have a mDNS Candidate lets fully resolve it before adding it locally
+    if c.candidate_type() == CandidateType::Host && c.address().ends_with(".local") {
+        warn!(
+            "remote mDNS candidate added, but mDNS is disabled: ({})",
+            c.address()
+        );
+        return Err(Error::ErrMulticastDnsNotSupported);
+    }
+
+    Ok(())
+}
+
+// ice-char = ALPHA / DIGIT / "+" / "/", RFC 8839 Section 5.4
+fn is_ice_chars(s: &str) -> bool {
+    s.bytes()
+        .all(|b| b.is_ascii_alphanumeric() || b == b'+' || b == b'/')
+}
+
 fn assert_inbound_message_integrity(m: &mut Message, key: &[u8]) -> Result<()> {
     let message_integrity_attr = MessageIntegrity(key.to_vec());
     message_integrity_attr.check(m)
 }
 
+/// Events of the agent, returned by `poll_event` in the order they occurred.
 pub enum Event {
     ConnectionStateChange(ConnectionState),
+    /// The local and remote candidates of the newly selected pair.
     SelectedCandidatePairChange(Box<Candidate>, Box<Candidate>),
+    /// A local candidate was added, to be signaled to the remote agent.
+    NewCandidate(Box<Candidate>),
+    /// Every expected candidate type was gathered, see `candidate_type_gathered`.
+    GatheringComplete,
 }
 
+/// Indicates whether an observed STUN packet was received or sent by the agent.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum StunPacketDirection {
+    Inbound,
+    Outbound,
+}
+
+/// Called for every STUN message the agent sends or receives, with the remote address.
+pub type OnStunPacketHdlrFn =
+    Box<dyn FnMut(StunPacketDirection, &Message, SocketAddr) + Send + Sync>;
+
+/// Called once the agent's candidate gathering process is complete.
+pub type OnGatheringCompleteHdlrFn = Box<dyn FnMut() + Send + Sync>;
+
 /// Represents the ICE agent.
 pub struct Agent {
     pub(crate) tie_breaker: u64,
     pub(crate) is_controlling: bool,
     pub(crate) lite: bool,
+    pub(crate) remote_is_lite: bool,
+    pub(crate) enable_renomination: bool,
+    pub(crate) enable_network_cost: bool,
+    pub(crate) include_fingerprint: bool,
+    pub(crate) software: Option<Software>,
+    pub(crate) keepalive_uses_indication: bool,
+    pub(crate) enable_goog_ping: bool,
+    pub(crate) max_local_candidates: Option<usize>,
+    // number of components of the media stream, each needs a selected pair to connect
+    pub(crate) components: u16,
+    pub(crate) remote_renomination: bool,
+    // value sent with the latest nomination of the controlling agent
+    pub(crate) nomination: u32,
+    // highest nomination value received by the controlled agent
+    pub(crate) remote_nomination: u32,
 
     pub(crate) start_time: Instant,
 
     pub(crate) connection_state: ConnectionState,
     pub(crate) last_connection_state: ConnectionState,
 
+    pub(crate) gathering_state: GatheringState,
+    // candidate types which are still being gathered by the application
+    pub(crate) pending_gathering_candidate_types: Vec<CandidateType>,
+
     //pub(crate) started_ch_tx: Mutex<Option<broadcast::Sender<()>>>,
     pub(crate) ufrag_pwd: UfragPwd,
 
@@ -106,13 +220,22 @@ pub struct Agent {
     pub(crate) candidate_pairs: Vec<CandidatePair>,
     pub(crate) nominated_pair: Option<usize>,
     pub(crate) selected_pair: Option<usize>,
-
-    // LRU of outbound Binding request Transaction IDs
-    pub(crate) pending_binding_requests: Vec<BindingRequest>,
+    // selected pairs of the components other than RTP, whose pair is selected_pair
+    pub(crate) component_pairs: HashMap<u16, usize>,
+    pub(crate) retained_pair: Option<RetainedPair>,
+
+    // Outbound Binding requests waiting for a response by Transaction ID, at most
+    // MAX_PENDING_BINDING_REQUESTS of them
+    pub(crate) pending_binding_requests: HashMap<TransactionId, BindingRequest>,
+    // Transaction IDs of the pending binding requests in the order they were sent, so that the
+    // expired and oldest ones are found at the front. Entries of requests that were answered or
+    // dropped are skipped once they reach the front.
+    pub(crate) pending_binding_request_order: VecDeque<(Instant, TransactionId)>,
 
     // the following variables won't be changed after init_with_defaults()
     pub(crate) insecure_skip_verify: bool,
     pub(crate) max_binding_requests: u16,
+    pub(crate) binding_request_rto: Duration,
     pub(crate) host_acceptance_min_wait: Duration,
     pub(crate) srflx_acceptance_min_wait: Duration,
     pub(crate) prflx_acceptance_min_wait: Duration,
@@ -126,16 +249,36 @@ pub struct Agent {
     // How often should we send keepalive packets?
     // 0 means never
     pub(crate) keepalive_interval: Duration,
+    // How long the selected pair can go without a consent check response
+    // 0 means never
+    pub(crate) consent_timeout: Duration,
     // How often should we run our internal taskLoop to check for state changes when connecting
     pub(crate) check_interval: Duration,
+    // Ta timer pacing connectivity checks, 0 means checks are not paced
+    pub(crate) ta_interval: Duration,
+    // the pair and time of the last paced check
+    pub(crate) last_paced_pair: Option<usize>,
+    pub(crate) last_paced_check: Option<Instant>,
     pub(crate) checking_duration: Instant,
     pub(crate) last_checking_time: Instant,
+    // since when connectivity checks and keepalives are paused, if they are
+    pub(crate) paused_at: Option<Instant>,
 
     pub(crate) candidate_types: Vec<CandidateType>,
+    pub(crate) candidate_filter: Option<CandidateFilterFn>,
     pub(crate) urls: Vec<Url>,
 
     pub(crate) transmits: VecDeque<Transmit<BytesMut>>,
+    // application data received from remote candidates, not yet read
+    pub(crate) incoming_data: VecDeque<BytesMut>,
+    pub(crate) incoming_data_size: usize,
     pub(crate) events: VecDeque<Event>,
+
+    pub(crate) on_stun_packet_hdlr: Option<OnStunPacketHdlrFn>,
+    pub(crate) on_gathering_complete_hdlr: Option<OnGatheringCompleteHdlrFn>,
+
+    // seeded rng from AgentConfig::rng_seed, the thread RNG is used if none
+    pub(crate) rng: Option<StdRng>,
 }
 
 impl Agent {
@@ -159,19 +302,46 @@ impl Agent {
             return Err(Error::ErrUselessUrlsProvided);
         }
 
+        let software = match config.software.as_deref().unwrap_or(DEFAULT_SOFTWARE) {
+            "" => None,
+            software => {
+                let software = Software::new(ATTR_SOFTWARE, software.to_owned());
+                // Fails with a text longer than the attribute allows
+                software.add_to(&mut Message::new())?;
+                Some(software)
+            }
+        };
+
         let mut agent = Self {
-            tie_breaker: rand::random::<u64>(),
+            tie_breaker: 0,
             is_controlling: config.is_controlling,
             lite: config.lite,
+            remote_is_lite: false,
+            enable_renomination: config.enable_renomination,
+            enable_network_cost: config.enable_network_cost,
+            include_fingerprint: config.include_fingerprint.unwrap_or(true),
+            software,
+            keepalive_uses_indication: config.keepalive_uses_indication,
+            enable_goog_ping: config.enable_goog_ping,
+            max_local_candidates: config.max_local_candidates,
+            components: u16::try_from(config.components.max(1)).unwrap_or(u16::MAX),
+            remote_renomination: false,
+            nomination: 0,
+            remote_nomination: 0,
 
             start_time: Instant::now(),
 
             nominated_pair: None,
             selected_pair: None,
+            component_pairs: HashMap::new(),
+            retained_pair: None,
             candidate_pairs: vec![],
 
             connection_state: ConnectionState::New,
 
+            gathering_state: GatheringState::New,
+            pending_gathering_candidate_types: vec![],
+
             insecure_skip_verify: config.insecure_skip_verify,
 
             //started_ch_tx: MuteSome(started_ch_tx)),
@@ -182,6 +352,11 @@ impl Agent {
             } else {
                 DEFAULT_MAX_BINDING_REQUESTS
             },
+            binding_request_rto: if let Some(binding_request_rto) = config.binding_request_rto {
+                binding_request_rto
+            } else {
+                DEFAULT_BINDING_REQUEST_RTO
+            },
             host_acceptance_min_wait: if let Some(host_acceptance_min_wait) =
                 config.host_acceptance_min_wait
             {
@@ -235,14 +410,22 @@ impl Agent {
                 DEFAULT_KEEPALIVE_INTERVAL
             },
 
+            // How long the selected pair can go without a consent check response
+            // 0 means never
+            consent_timeout: config.consent_timeout.unwrap_or(DEFAULT_CONSENT_TIMEOUT),
+
             // How often should we run our internal taskLoop to check for state changes when connecting
             check_interval: if config.check_interval == Duration::from_secs(0) {
                 DEFAULT_CHECK_INTERVAL
             } else {
                 config.check_interval
             },
+            ta_interval: config.ta_interval.unwrap_or(ZERO_DURATION),
+            last_paced_pair: None,
+            last_paced_check: None,
             checking_duration: Instant::now(),
             last_checking_time: Instant::now(),
+            paused_at: None,
             last_connection_state: ConnectionState::Unspecified,
 
             ufrag_pwd: UfragPwd::default(),
@@ -251,18 +434,32 @@ impl Agent {
             remote_candidates: vec![],
 
             // LRU of outbound Binding request Transaction IDs
-            pending_binding_requests: vec![],
+            pending_binding_requests: HashMap::new(),
+            pending_binding_request_order: VecDeque::new(),
 
             candidate_types,
+            candidate_filter: config.candidate_filter.clone(),
             urls: config.urls.clone(),
 
             transmits: VecDeque::new(),
+            incoming_data: VecDeque::new(),
+            incoming_data_size: 0,
             events: VecDeque::new(),
+
+            on_stun_packet_hdlr: None,
+            on_gathering_complete_hdlr: None,
+
+            rng: config.rng_seed.map(StdRng::seed_from_u64),
         };
+        agent.tie_breaker = agent.with_rng(|rng| rng.next_u64());
 
         // Restart is also used to initialize the agent for the first time
-        if let Err(err) = agent.restart(config.local_ufrag.clone(), config.local_pwd.clone(), false)
-        {
+        if let Err(err) = agent.restart(
+            config.local_ufrag.clone(),
+            config.local_pwd.clone(),
+            false,
+            false,
+        ) {
             let _ = agent.close();
             return Err(err);
         }
@@ -271,13 +468,37 @@ impl Agent {
     }
 
     /// Adds a new local candidate.
-    pub fn add_local_candidate(&mut self, c: Candidate) -> Result<()> {
+    ///
+    /// Candidates rejected by the configured `candidate_filter` are skipped, as are candidates
+    /// beyond `max_local_candidates`. Returns ErrClosed once the agent is closed.
+    pub fn add_local_candidate(&mut self, mut c: Candidate) -> Result<()> {
+        if self.is_closed() {
+            return Err(Error::ErrClosed);
+        }
+
+        if !self.is_candidate_allowed(&c) {
+            debug!("Ignore local candidate rejected by candidate filter: {c}");
+            return Ok(());
+        }
+
         for cand in &self.local_candidates {
             if cand.equal(&c) {
                 return Ok(());
             }
         }
 
+        if self
+            .max_local_candidates
+            .is_some_and(|max_local_candidates| self.local_candidates.len() >= max_local_candidates)
+            && !self.evict_local_candidate_below(c.priority())
+        {
+            debug!("Ignore local candidate beyond max_local_candidates: {c}");
+            return Ok(());
+        }
+
+        c.added_at = Instant::now();
+        self.events
+            .push_back(Event::NewCandidate(Box::new(c.clone())));
         self.local_candidates.push(c);
 
         for remote_index in 0..self.remote_candidates.len() {
@@ -290,22 +511,29 @@ impl Agent {
     }
 
     /// Adds a new remote candidate.
+    ///
+    /// It can be called at any time, including after connectivity checks have started (trickle
+    /// ICE): the candidate is paired with all local candidates and a connectivity check is
+    /// requested. If a pair is already selected, the new pairs are kept for a potential failover
+    /// without changing the current selection. Returns ErrClosed once the agent is closed.
     pub fn add_remote_candidate(&mut self, c: Candidate) -> Result<()> {
-        // If we have a mDNS Candidate lets fully resolve it before adding it locally
-        if c.candidate_type() == CandidateType::Host && c.address().ends_with(".local") {
-            warn!(
-                "remote mDNS candidate added, but mDNS is disabled: ({})",
-                c.address()
-            );
-            return Err(Error::ErrMulticastDnsNotSupported);
+        if self.is_closed() {
+            return Err(Error::ErrClosed);
         }
 
+        validate_remote_candidate(&c)?;
+
         for cand in &self.remote_candidates {
             if cand.equal(&c) {
                 return Ok(());
             }
         }
 
+        if let Some(remote_index) = self.find_replaced_peer_reflexive_candidate(&c) {
+            self.replace_peer_reflexive_candidate(remote_index, c);
+            return Ok(());
+        }
+
         self.remote_candidates.push(c);
 
         for local_index in 0..self.local_candidates.len() {
@@ -317,6 +545,206 @@ impl Agent {
         Ok(())
     }
 
+    /// Removes a remote candidate, e.g. when the peer signals that it is no longer valid.
+    ///
+    /// The pairs of the candidate and their pending connectivity checks are dropped. If one of
+    /// them was selected, the agent goes back to Checking and fails over to the next best pair,
+    /// failing only if none connects within the usual timeouts. Removing an unknown candidate
+    /// does nothing. Returns ErrClosed once the agent is closed.
+    pub fn remove_remote_candidate(&mut self, c: &Candidate) -> Result<()> {
+        if self.is_closed() {
+            return Err(Error::ErrClosed);
+        }
+
+        let Some(remote_index) = self.remote_candidates.iter().position(|cand| cand.equal(c))
+        else {
+            return Ok(());
+        };
+
+        // Pairs are referred to by index, so the indices held by the agent are remapped
+        let mut pair_indices = Vec::with_capacity(self.candidate_pairs.len());
+        let mut next_index = 0;
+        for p in &self.candidate_pairs {
+            if p.remote_index == remote_index {
+                pair_indices.push(None);
+            } else {
+                pair_indices.push(Some(next_index));
+                next_index += 1;
+            }
+        }
+        let remap = |pair_index: Option<usize>| pair_index.and_then(|i| pair_indices[i]);
+
+        let selected_pair_removed = self
+            .selected_pair
+            .is_some_and(|i| pair_indices[i].is_none())
+            || self
+                .component_pairs
+                .values()
+                .any(|&i| pair_indices[i].is_none());
+
+        self.nominated_pair = remap(self.nominated_pair);
+        self.selected_pair = remap(self.selected_pair);
+        self.last_paced_pair = remap(self.last_paced_pair);
+        self.component_pairs = self
+            .component_pairs
+            .iter()
+            .filter_map(|(&component, &i)| pair_indices[i].map(|i| (component, i)))
+            .collect();
+
+        self.candidate_pairs
+            .retain(|p| p.remote_index != remote_index);
+        for p in &mut self.candidate_pairs {
+            if p.remote_index > remote_index {
+                p.remote_index -= 1;
+            }
+        }
+        self.pending_binding_requests
+            .retain(|_, r| r.remote_index != remote_index);
+        for r in self.pending_binding_requests.values_mut() {
+            if r.remote_index > remote_index {
+                r.remote_index -= 1;
+            }
+        }
+        let removed = self.remote_candidates.remove(remote_index);
+        debug!("[{}]: Removed remote candidate {removed}", self.get_name());
+
+        if selected_pair_removed
+            && matches!(
+                self.connection_state,
+                ConnectionState::Connected
+                    | ConnectionState::Completed
+                    | ConnectionState::Disconnected
+            )
+        {
+            self.update_connection_state(ConnectionState::Checking);
+        }
+        self.request_connectivity_check();
+
+        Ok(())
+    }
+
+    /// Starts the candidate gathering process.
+    ///
+    /// The agent doesn't own any socket, so the application gathers the candidates itself, adds
+    /// them with `add_local_candidate` and reports every finished candidate type with
+    /// `candidate_type_gathered`. Server reflexive and relay candidates are only expected when a
+    /// STUN or TURN url is configured for them.
+    pub fn gather_candidates(&mut self) -> Result<()> {
+        if self.gathering_state == GatheringState::Gathering {
+            return Err(Error::ErrMultipleGatherAttempted);
+        }
+
+        self.pending_gathering_candidate_types = self
+            .candidate_types
+            .iter()
+            .copied()
+            .filter(|candidate_type| self.is_gatherable(*candidate_type))
+            .collect();
+
+        self.set_gathering_state(GatheringState::Gathering);
+        if self.pending_gathering_candidate_types.is_empty() {
+            self.set_gathering_state(GatheringState::Complete);
+        }
+
+        Ok(())
+    }
+
+    /// Marks the gathering of the given candidate type as attempted. Once all configured candidate
+    /// types have been attempted, the gathering state transitions to `Complete`.
+    pub fn candidate_type_gathered(&mut self, candidate_type: CandidateType) {
+        if self.gathering_state != GatheringState::Gathering {
+            return;
+        }
+
+        self.pending_gathering_candidate_types
+            .retain(|pending| *pending != candidate_type);
+        if self.pending_gathering_candidate_types.is_empty() {
+            self.set_gathering_state(GatheringState::Complete);
+        }
+    }
+
+    /// Returns the current state of the candidate gathering process.
+    pub fn gathering_state(&self) -> GatheringState {
+        self.gathering_state
+    }
+
+    /// Sets a handler that is fired once candidate gathering is complete.
+    pub fn on_gathering_complete(&mut self, f: impl FnMut() + Send + Sync + 'static) {
+        self.on_gathering_complete_hdlr = Some(Box::new(f));
+    }
+
+    fn is_gatherable(&self, candidate_type: CandidateType) -> bool {
+        match candidate_type {
+            CandidateType::Host => true,
+            CandidateType::ServerReflexive => self
+                .urls
+                .iter()
+                .any(|url| url.scheme == SchemeType::Stun || url.scheme == SchemeType::Stuns),
+            CandidateType::Relay => self
+                .urls
+                .iter()
+                .any(|url| url.scheme == SchemeType::Turn || url.scheme == SchemeType::Turns),
+            CandidateType::PeerReflexive | CandidateType::Unspecified => false,
+        }
+    }
+
+    fn set_gathering_state(&mut self, new_state: GatheringState) {
+        if self.gathering_state != new_state {
+            debug!(
+                "[{}]: Setting new gathering state: {}",
+                self.get_name(),
+                new_state
+            );
+            self.gathering_state = new_state;
+
+            if new_state == GatheringState::Complete {
+                self.events.push_back(Event::GatheringComplete);
+                if let Some(f) = &mut self.on_gathering_complete_hdlr {
+                    f();
+                }
+            }
+        }
+    }
+
+    /// Adds a batch of remote candidates, e.g. when all of them are known upfront without trickle
+    /// ICE. All candidates are validated before any of them is added, duplicates are dropped, and
+    /// a single connectivity check is requested once all pairs are formed. As with
+    /// `add_remote_candidate`, a candidate takes the place of the peer reflexive candidate already
+    /// learned for its address. Returns ErrClosed once the agent is closed.
+    pub fn set_remote_candidates(&mut self, candidates: Vec<Candidate>) -> Result<()> {
+        if self.is_closed() {
+            return Err(Error::ErrClosed);
+        }
+
+        for c in &candidates {
+            validate_remote_candidate(c)?;
+        }
+
+        let first_new_remote_index = self.remote_candidates.len();
+        for c in candidates {
+            if self.remote_candidates.iter().any(|cand| cand.equal(&c)) {
+                continue;
+            }
+            if let Some(remote_index) = self.find_replaced_peer_reflexive_candidate(&c) {
+                self.replace_peer_reflexive_candidate(remote_index, c);
+                continue;
+            }
+            self.remote_candidates.push(c);
+        }
+
+        for remote_index in first_new_remote_index..self.remote_candidates.len() {
+            for local_index in 0..self.local_candidates.len() {
+                self.add_pair(local_index, remote_index);
+            }
+        }
+
+        if first_new_remote_index != self.remote_candidates.len() {
+            self.request_connectivity_check();
+        }
+
+        Ok(())
+    }
+
     /// Sets the credentials of the remote agent.
     pub fn set_remote_credentials(
         &mut self,
@@ -329,6 +757,18 @@ impl Agent {
             return Err(Error::ErrRemotePwdEmpty);
         }
 
+        if remote_ufrag.len() > MAX_UFRAG_LEN {
+            return Err(Error::ErrRemoteUfragTooLong);
+        } else if remote_pwd.len() > MAX_PWD_LEN {
+            return Err(Error::ErrRemotePwdTooLong);
+        }
+
+        if !is_ice_chars(&remote_ufrag) {
+            return Err(Error::ErrRemoteUfragInvalidChars);
+        } else if !is_ice_chars(&remote_pwd) {
+            return Err(Error::ErrRemotePwdInvalidChars);
+        }
+
         self.ufrag_pwd.remote_credentials = Some(Credentials {
             ufrag: remote_ufrag,
             pwd: remote_pwd,
@@ -347,7 +787,81 @@ impl Agent {
         &self.ufrag_pwd.local_credentials
     }
 
-    pub fn handle_read(&mut self, msg: Transmit<BytesMut>) -> Result<()> {
+    /// Sets whether the remote agent is an ICE-lite implementation, as signaled by the
+    /// `a=ice-lite` session attribute.
+    pub fn set_remote_lite(&mut self, remote_is_lite: bool) {
+        self.remote_is_lite = remote_is_lite;
+    }
+
+    /// Returns whether the remote agent is an ICE-lite implementation.
+    pub fn is_remote_lite(&self) -> bool {
+        self.remote_is_lite
+    }
+
+    /// Sets whether the remote agent supports ICE renomination, as signaled by the
+    /// `a=ice-options:renomination` attribute.
+    pub fn set_remote_renomination(&mut self, remote_renomination: bool) {
+        self.remote_renomination = remote_renomination;
+    }
+
+    /// Returns whether the agent is currently controlling.
+    pub fn is_controlling(&self) -> bool {
+        self.is_controlling
+    }
+
+    /// Returns the current role of the agent.
+    pub fn role(&self) -> IceRole {
+        if self.is_controlling {
+            IceRole::Controlling
+        } else {
+            IceRole::Controlled
+        }
+    }
+
+    /// Returns whether renomination is used, i.e. both agents support it.
+    pub fn is_renomination_enabled(&self) -> bool {
+        self.enable_renomination && self.remote_renomination
+    }
+
+    /// Nominates the pair of the given candidates in place of the selected pair, so media moves
+    /// to it without an ICE restart. The pair must have succeeded a connectivity check, and it
+    /// is selected once the controlled agent acknowledges the nomination. Only the controlling
+    /// agent can renominate, when both agents support renomination.
+    pub fn renominate(&mut self, local: &Candidate, remote: &Candidate) -> Result<()> {
+        if !self.is_controlling || !self.is_renomination_enabled() {
+            return Err(Error::ErrRenominationNotEnabled);
+        }
+
+        let pair_index = self
+            .candidate_pairs
+            .iter()
+            .position(|p| {
+                self.local_candidates[p.local_index].equal(local)
+                    && self.remote_candidates[p.remote_index].equal(remote)
+            })
+            .ok_or(Error::ErrNoCandidatePairs)?;
+        if self.candidate_pairs[pair_index].state != CandidatePairState::Succeeded {
+            return Err(Error::ErrCandidatePairNotValid);
+        }
+
+        debug!(
+            "[{}]: renominate candidate pair {}",
+            self.get_name(),
+            self.candidate_pairs[pair_index]
+        );
+        self.nomination += 1;
+        self.nominated_pair = Some(pair_index);
+        self.nominate_pair();
+
+        Ok(())
+    }
+
+    pub fn handle_read(&mut self, mut msg: Transmit<BytesMut>) -> Result<()> {
+        msg.transport.peer_addr = without_zone(msg.transport.peer_addr);
+        if let Some(result) = self.handle_retained_pair_request(&msg) {
+            return result;
+        }
+
         if let Some(local_index) =
             self.find_local_candidate(msg.transport.local_addr, msg.transport.protocol)
         {
@@ -372,26 +886,82 @@ impl Agent {
         self.transmits.pop_front()
     }
 
+    /// Sends application data to the remote agent over the selected pair, and returns the
+    /// number of bytes written. Fails with ErrNoCandidatePairs until a pair is selected.
+    pub fn write(&mut self, data: &[u8]) -> Result<usize> {
+        let Some(pair_index) = self.selected_pair else {
+            return Err(Error::ErrNoCandidatePairs);
+        };
+        let p = &self.candidate_pairs[pair_index];
+        let (local_index, peer_addr) =
+            (p.local_index, self.remote_candidates[p.remote_index].addr());
+        let local_addr = self.local_candidates[local_index].addr();
+        let protocol = self.local_candidates[local_index]
+            .network_type()
+            .to_protocol();
+
+        self.push_transmit(data, local_addr, protocol, peer_addr)?;
+        self.local_candidates[local_index].seen(true);
+        Ok(data.len())
+    }
+
+    /// Returns the next application data received from the remote agent, in order of arrival.
+    pub fn read(&mut self) -> Option<BytesMut> {
+        let data = self.incoming_data.pop_front()?;
+        self.incoming_data_size -= data.len();
+        Some(data)
+    }
+
     pub fn handle_timeout(&mut self, now: Instant) {
-        if self.ufrag_pwd.remote_credentials.is_some()
-            && self.last_checking_time + self.get_timeout_interval() <= now
-        {
-            self.contact(now);
+        if self.paused_at.is_some() {
+            return;
+        }
+
+        if self.ufrag_pwd.remote_credentials.is_some() {
+            self.retransmit_binding_requests(now);
+            if self.last_checking_time + self.get_timeout_interval() <= now {
+                self.contact(now);
+            }
         }
     }
 
     pub fn poll_timeout(&self) -> Option<Instant> {
+        if self.paused_at.is_some() {
+            return None;
+        }
+
         if self.ufrag_pwd.remote_credentials.is_some() {
-            Some(self.last_checking_time + self.get_timeout_interval())
+            let checking_timeout = self.last_checking_time + self.get_timeout_interval();
+            let retransmit_timeout = self
+                .pending_binding_requests
+                .values()
+                .filter_map(|binding_request| binding_request.next_retransmit)
+                .min();
+            Some(match retransmit_timeout {
+                Some(retransmit_timeout) => checking_timeout.min(retransmit_timeout),
+                None => checking_timeout,
+            })
         } else {
             None
         }
     }
 
+    /// Returns the next event of the agent, if any. Events are queued whether handlers are set
+    /// or not, so that an application can poll them, like transmits and timeouts, instead of
+    /// setting handlers that can't borrow the agent.
     pub fn poll_event(&mut self) -> Option<Event> {
         self.events.pop_front()
     }
 
+    /// Sets a handler that is fired for every STUN message sent or received by the agent.
+    /// It is useful for tracing STUN traffic without enabling trace-level logging.
+    pub fn on_stun_packet(
+        &mut self,
+        f: impl FnMut(StunPacketDirection, &Message, SocketAddr) + Send + Sync + 'static,
+    ) {
+        self.on_stun_packet_hdlr = Some(Box::new(f));
+    }
+
     fn get_timeout_interval(&self) -> Duration {
         let (check_interval, keepalive_interval, disconnected_timeout, failed_timeout) = (
             self.check_interval,
@@ -411,6 +981,7 @@ impl Agent {
             ConnectionState::New | ConnectionState::Checking => {
                 // While connecting, check candidates more frequently
                 update_interval(check_interval);
+                update_interval(self.ta_interval);
             }
             ConnectionState::Connected | ConnectionState::Disconnected => {
                 update_interval(keepalive_interval);
@@ -423,16 +994,23 @@ impl Agent {
         interval
     }
 
+    /// Returns whether the agent was closed. A closed agent can't be restarted.
+    pub fn is_closed(&self) -> bool {
+        self.connection_state == ConnectionState::Closed
+    }
+
     /// Cleans up the Agent.
     pub fn close(&mut self) -> Result<()> {
         self.set_selected_pair(None);
+        self.retained_pair = None;
         self.delete_all_candidates(false);
         self.update_connection_state(ConnectionState::Closed);
 
         Ok(())
     }
 
-    /// Returns the selected pair (local_candidate, remote_candidate) or none
+    /// Returns the selected pair (local_candidate, remote_candidate) or none. During a graceful
+    /// restart, the pair selected before the restart is returned until a new one is selected.
     pub fn get_selected_candidate_pair(&self) -> Option<(Candidate, Candidate)> {
         if let Some(pair_index) = self.get_selected_pair() {
             let candidate_pair = &self.candidate_pairs[pair_index];
@@ -441,11 +1019,34 @@ impl Agent {
                 self.remote_candidates[candidate_pair.remote_index].clone(),
             ))
         } else {
-            None
+            self.retained_pair
+                .as_ref()
+                .map(|retained_pair| (retained_pair.local.clone(), retained_pair.remote.clone()))
         }
     }
 
+    /// Returns the selected pair (local_candidate, remote_candidate) of a component of the
+    /// media stream, e.g. 2 for RTCP, or none.
+    pub fn get_selected_candidate_pair_of(&self, component: u16) -> Option<(Candidate, Candidate)> {
+        self.selected_pair_of(component).map(|pair_index| {
+            let candidate_pair = &self.candidate_pairs[pair_index];
+            (
+                self.local_candidates[candidate_pair.local_index].clone(),
+                self.remote_candidates[candidate_pair.remote_index].clone(),
+            )
+        })
+    }
+
     /// start connectivity checks
+    ///
+    /// ICE-lite agents never send connectivity checks, so the roles are constrained
+    /// (RFC 8445 Section 6.1.1):
+    /// * if both agents are lite, nobody would check and `ErrBothAgentsLite` is returned;
+    /// * if only the remote agent is lite, the local full agent MUST be controlling,
+    ///   otherwise `ErrLiteRemoteRequiresControlling` is returned.
+    ///
+    /// A lite agent that ends up controlling selects the pair of the first binding request
+    /// it successfully responds to.
     pub fn start_connectivity_checks(
         &mut self,
         is_controlling: bool,
@@ -456,6 +1057,13 @@ impl Agent {
             "Started agent: isControlling? {}, remoteUfrag: {}, remotePwd: {}",
             is_controlling, remote_ufrag, remote_pwd
         );
+        if self.remote_is_lite {
+            if self.lite {
+                return Err(Error::ErrBothAgentsLite);
+            } else if !is_controlling {
+                return Err(Error::ErrLiteRemoteRequiresControlling);
+            }
+        }
         self.set_remote_credentials(remote_ufrag, remote_pwd)?;
         self.is_controlling = is_controlling;
         self.start();
@@ -466,19 +1074,87 @@ impl Agent {
         Ok(())
     }
 
+    /// Starts connectivity checks with the remote agent as the controlling agent, moving the
+    /// agent from New to Checking. Returns `ErrMultipleStart` if the agent was already started.
+    pub fn connect(&mut self, remote_ufrag: String, remote_pwd: String) -> Result<()> {
+        self.start_once(true, remote_ufrag, remote_pwd)
+    }
+
+    /// Starts connectivity checks with the remote agent as the controlled agent, moving the
+    /// agent from New to Checking. Returns `ErrMultipleStart` if the agent was already started.
+    pub fn accept(&mut self, remote_ufrag: String, remote_pwd: String) -> Result<()> {
+        self.start_once(false, remote_ufrag, remote_pwd)
+    }
+
+    fn start_once(
+        &mut self,
+        is_controlling: bool,
+        remote_ufrag: String,
+        remote_pwd: String,
+    ) -> Result<()> {
+        if self.connection_state != ConnectionState::New {
+            return Err(Error::ErrMultipleStart);
+        }
+        self.start_connectivity_checks(is_controlling, remote_ufrag, remote_pwd)
+    }
+
+    /// Pauses connectivity checks and keepalives, e.g. while the application is in the
+    /// background, without tearing down the agent. handle_timeout sends nothing until `resume`
+    /// is called, but inbound packets are still handled so the peer's checks are answered.
+    /// Candidates and the selected pair are kept.
+    pub fn pause(&mut self) {
+        if self.paused_at.is_none() {
+            debug!("[{}]: pause connectivity checks", self.get_name());
+            self.paused_at = Some(Instant::now());
+        }
+    }
+
+    /// Resumes connectivity checks and keepalives paused by `pause`, immediately sending a
+    /// keepalive on the selected pair, or connectivity checks while none is selected. The time
+    /// spent paused doesn't count towards the failed timeout of the checking state.
+    pub fn resume(&mut self) {
+        let Some(paused_at) = self.paused_at.take() else {
+            return;
+        };
+        debug!("[{}]: resume connectivity checks", self.get_name());
+
+        let now = Instant::now();
+        self.checking_duration += now.saturating_duration_since(paused_at);
+        if self.ufrag_pwd.remote_credentials.is_some() {
+            if let Some(pair_index) = self.selected_pair {
+                let p = &self.candidate_pairs[pair_index];
+                self.ping_candidate(p.local_index, p.remote_index);
+            }
+            self.contact(now);
+        }
+    }
+
+    /// Returns whether connectivity checks and keepalives are paused.
+    pub fn is_paused(&self) -> bool {
+        self.paused_at.is_some()
+    }
+
     /// Restarts the ICE Agent with the provided ufrag/pwd
     /// If no ufrag/pwd is provided the Agent will generate one itself.
+    /// If graceful is set, the currently selected pair is retained, and kept alive with the
+    /// previous credentials, until the new session selects a pair.
+    /// A closed agent can't be restarted and returns ErrClosed.
     pub fn restart(
         &mut self,
         mut ufrag: String,
         mut pwd: String,
         keep_local_candidates: bool,
+        graceful: bool,
     ) -> Result<()> {
+        if self.is_closed() {
+            return Err(Error::ErrClosed);
+        }
+
         if ufrag.is_empty() {
-            ufrag = generate_ufrag();
+            ufrag = self.with_rng(generate_ufrag_with_rng);
         }
         if pwd.is_empty() {
-            pwd = generate_pwd();
+            pwd = self.with_rng(generate_pwd_with_rng);
         }
 
         if ufrag.len() * 8 < 24 {
@@ -487,15 +1163,55 @@ impl Agent {
         if pwd.len() * 8 < 128 {
             return Err(Error::ErrLocalPwdInsufficientBits);
         }
+        if ufrag.len() > MAX_UFRAG_LEN {
+            return Err(Error::ErrLocalUfragTooLong);
+        }
+        if pwd.len() > MAX_PWD_LEN {
+            return Err(Error::ErrLocalPwdTooLong);
+        }
+        if !is_ice_chars(&ufrag) {
+            return Err(Error::ErrLocalUfragInvalidChars);
+        }
+        if !is_ice_chars(&pwd) {
+            return Err(Error::ErrLocalPwdInvalidChars);
+        }
+
+        if self.gathering_state == GatheringState::Gathering {
+            return Err(Error::ErrRestartWhenGathering);
+        }
+
+        self.retained_pair = None;
+        if graceful {
+            if let (Some(pair_index), Some(remote_credentials)) =
+                (self.selected_pair, &self.ufrag_pwd.remote_credentials)
+            {
+                let p = &self.candidate_pairs[pair_index];
+                self.retained_pair = Some(RetainedPair {
+                    local: self.local_candidates[p.local_index].clone(),
+                    remote: self.remote_candidates[p.remote_index].clone(),
+                    local_credentials: self.ufrag_pwd.local_credentials.clone(),
+                    remote_credentials: remote_credentials.clone(),
+                    last_keepalive: Instant::now(),
+                });
+            }
+        }
 
         // Clear all agent needed to take back to fresh state
         self.ufrag_pwd.local_credentials.ufrag = ufrag;
         self.ufrag_pwd.local_credentials.pwd = pwd;
         self.ufrag_pwd.remote_credentials = None;
 
-        self.pending_binding_requests = vec![];
+        self.pending_binding_requests.clear();
+        self.pending_binding_request_order.clear();
 
         self.candidate_pairs = vec![];
+        self.nominated_pair = None;
+        self.remote_nomination = 0;
+        self.last_paced_pair = None;
+        self.last_paced_check = None;
+
+        self.gathering_state = GatheringState::New;
+        self.pending_gathering_candidate_types = vec![];
 
         self.set_selected_pair(None);
         self.delete_all_candidates(keep_local_candidates);
@@ -515,6 +1231,21 @@ impl Agent {
         &self.local_candidates
     }
 
+    /// Returns a snapshot of the local candidates, so that the caller can hand them to the
+    /// signaling layer (e.g. for trickle ICE) without borrowing the agent.
+    pub fn local_candidates(&self) -> Vec<Candidate> {
+        self.local_candidates.clone()
+    }
+
+    /// Returns the SDP `candidate:` attribute value (RFC 8839) of every local candidate, in the
+    /// order they were added.
+    pub fn local_candidate_sdp_lines(&self) -> Vec<String> {
+        self.local_candidates
+            .iter()
+            .map(|c| format!("candidate:{}", c.marshal()))
+            .collect()
+    }
+
     fn contact(&mut self, now: Instant) {
         if self.connection_state == ConnectionState::Failed {
             // The connection is currently failed so don't send any checks
@@ -540,29 +1271,46 @@ impl Agent {
             }
         }
 
+        self.check_retained_pair_keepalive(now);
         self.contact_candidates();
 
         self.last_connection_state = self.connection_state;
         self.last_checking_time = now;
     }
 
-    pub(crate) fn update_connection_state(&mut self, new_state: ConnectionState) {
-        if self.connection_state != new_state {
-            // Connection has gone to failed, release all gathered candidates
-            if new_state == ConnectionState::Failed {
-                self.set_selected_pair(None);
-                self.delete_all_candidates(false);
-            }
-
-            info!(
-                "[{}]: Setting new connection state: {}",
+    /// Moves the agent to new_state, returning whether the transition was applied. Transitions
+    /// the ICE state machine doesn't allow, e.g. out of Closed, are logged and ignored.
+    pub(crate) fn update_connection_state(&mut self, new_state: ConnectionState) -> bool {
+        if self.connection_state == new_state {
+            return false;
+        }
+        if !self.connection_state.can_transition_to(new_state) {
+            warn!(
+                "[{}]: Ignoring invalid connection state transition: {} -> {}",
                 self.get_name(),
+                self.connection_state,
                 new_state
             );
-            self.connection_state = new_state;
-            self.events
-                .push_back(Event::ConnectionStateChange(new_state));
+            return false;
+        }
+
+        // Connection has gone to failed, release all gathered candidates
+        if new_state == ConnectionState::Failed {
+            self.set_selected_pair(None);
+            self.retained_pair = None;
+            self.delete_all_candidates(false);
         }
+
+        info!(
+            "[{}]: Setting new connection state: {}",
+            self.get_name(),
+            new_state
+        );
+        self.connection_state = new_state;
+        self.events
+            .push_back(Event::ConnectionStateChange(new_state));
+
+        true
     }
 
     pub(crate) fn set_selected_pair(&mut self, selected_pair: Option<usize>) {
@@ -574,9 +1322,18 @@ impl Agent {
             );
 
             self.candidate_pairs[pair_index].nominated = true;
-            self.selected_pair = Some(pair_index);
+            let component = self.pair_component(pair_index);
+            if component == COMPONENT_RTP {
+                self.selected_pair = Some(pair_index);
+                // A pair of the new session replaces the one retained by a graceful restart
+                self.retained_pair = None;
+            } else {
+                self.component_pairs.insert(component, pair_index);
+            }
 
-            self.update_connection_state(ConnectionState::Connected);
+            if self.pending_component().is_none() {
+                self.update_connection_state(ConnectionState::Connected);
+            }
 
             // Notify when the selected pair changes
             let candidate_pair = &self.candidate_pairs[pair_index];
@@ -586,42 +1343,60 @@ impl Agent {
             ));
         } else {
             self.selected_pair = None;
+            self.component_pairs.clear();
         }
     }
 
-    pub(crate) fn ping_all_candidates(&mut self) {
-        trace!("[{}]: pinging all candidates", self.get_name(),);
+    /// Returns the component of the candidates of a pair.
+    pub(crate) fn pair_component(&self, pair_index: usize) -> u16 {
+        normalize_component(
+            self.local_candidates[self.candidate_pairs[pair_index].local_index].component(),
+        )
+    }
 
-        let mut pairs: Vec<(usize, usize)> = vec![];
+    /// Returns the selected pair of a component of the media stream.
+    pub(crate) fn selected_pair_of(&self, component: u16) -> Option<usize> {
+        if component == COMPONENT_RTP {
+            self.selected_pair
+        } else {
+            self.component_pairs.get(&component).copied()
+        }
+    }
 
-        {
-            let name = self.get_name().to_string();
-            if self.candidate_pairs.is_empty() {
-                warn!(
+    /// Returns the lowest component of the media stream that has no selected pair yet, or none
+    /// once every component has one.
+    pub(crate) fn pending_component(&self) -> Option<u16> {
+        (COMPONENT_RTP..=self.components).find(|&c| self.selected_pair_of(c).is_none())
+    }
+
+    /// Sends the connectivity checks that are due: a single check when checks are paced by the
+    /// Ta timer, otherwise a check on every pair that needs one.
+    pub(crate) fn ping_candidates(&mut self) {
+        self.unfreeze_pairs();
+
+        if self.ta_interval == ZERO_DURATION {
+            self.ping_all_candidates();
+        } else {
+            self.ping_next_candidate();
+        }
+    }
+
+    pub(crate) fn ping_all_candidates(&mut self) {
+        trace!("[{}]: pinging all candidates", self.get_name(),);
+
+        if self.candidate_pairs.is_empty() {
+            warn!(
                 "[{}]: pingAllCandidates called with no candidate pairs. Connection is not possible yet.",
-                name,
+                self.get_name(),
             );
-            }
-            for p in &mut self.candidate_pairs {
-                if p.state == CandidatePairState::Waiting {
-                    p.state = CandidatePairState::InProgress;
-                } else if p.state != CandidatePairState::InProgress {
-                    continue;
-                }
+        }
 
-                if p.binding_request_count > self.max_binding_requests {
-                    trace!(
-                        "[{}]: max requests reached for pair {}, marking it as failed",
-                        name,
-                        *p
-                    );
-                    p.state = CandidatePairState::Failed;
-                } else {
-                    p.binding_request_count += 1;
-                    let local = p.local_index;
-                    let remote = p.remote_index;
-                    pairs.push((local, remote));
-                }
+        let now = Instant::now();
+        let mut pairs: Vec<(usize, usize)> = vec![];
+        for pair_index in self.checklist_order() {
+            if self.is_pair_checkable(pair_index, now) && self.start_pair_check(pair_index) {
+                let p = &self.candidate_pairs[pair_index];
+                pairs.push((p.local_index, p.remote_index));
             }
         }
 
@@ -630,7 +1405,170 @@ impl Agent {
         }
     }
 
+    /// Sends at most one check per Ta interval (RFC 8445 Section 6.1.4.2), on the next checkable
+    /// pair in priority order after the one checked last.
+    pub(crate) fn ping_next_candidate(&mut self) {
+        let now = Instant::now();
+        if self
+            .last_paced_check
+            .is_some_and(|last_paced_check| now < last_paced_check + self.ta_interval)
+        {
+            return;
+        }
+
+        let pair_indices = self.checklist_order();
+        let start = self
+            .last_paced_pair
+            .and_then(|last| {
+                pair_indices
+                    .iter()
+                    .position(|&pair_index| pair_index == last)
+            })
+            .map_or(0, |position| position + 1);
+
+        for offset in 0..pair_indices.len() {
+            let pair_index = pair_indices[(start + offset) % pair_indices.len()];
+            if self.is_pair_checkable(pair_index, now) && self.start_pair_check(pair_index) {
+                trace!(
+                    "[{}]: paced check on pair {}",
+                    self.get_name(),
+                    self.candidate_pairs[pair_index]
+                );
+                self.last_paced_pair = Some(pair_index);
+                self.last_paced_check = Some(now);
+                let p = &self.candidate_pairs[pair_index];
+                self.ping_candidate(p.local_index, p.remote_index);
+                return;
+            }
+        }
+    }
+
+    // Indices of the candidate pairs in the order they are checked: descending pair priority
+    // (RFC 8445 Section 6.1.2.3), ties broken by the order the pairs were formed in
+    pub(crate) fn checklist_order(&self) -> Vec<usize> {
+        let mut pair_indices: Vec<usize> = (0..self.candidate_pairs.len()).collect();
+        pair_indices.sort_by_key(|&pair_index| {
+            (
+                Reverse(self.candidate_pairs[pair_index].priority(self.is_controlling)),
+                pair_index,
+            )
+        });
+        pair_indices
+    }
+
+    // Whether the pair still needs connectivity checks and may be checked now
+    fn is_pair_checkable(&self, pair_index: usize, now: Instant) -> bool {
+        let p = &self.candidate_pairs[pair_index];
+
+        // Hold back host pairs for host_acceptance_min_wait after the local candidate
+        // was added, giving srflx/relay candidates a chance to be gathered first
+        let local = &self.local_candidates[p.local_index];
+        if local.candidate_type() == CandidateType::Host
+            && now.saturating_duration_since(local.added_at) < self.host_acceptance_min_wait
+        {
+            return false;
+        }
+
+        p.state == CandidatePairState::Waiting || p.state == CandidatePairState::InProgress
+    }
+
+    // Accounts for a new check on the pair, returns false if the pair ran out of binding
+    // requests and failed instead
+    fn start_pair_check(&mut self, pair_index: usize) -> bool {
+        let name = self.get_name().to_string();
+        let max_binding_requests = self.max_binding_requests;
+        let p = &mut self.candidate_pairs[pair_index];
+        p.state = CandidatePairState::InProgress;
+
+        if p.binding_request_count > max_binding_requests {
+            trace!(
+                "[{}]: max requests reached for pair {}, marking it as failed",
+                name,
+                *p
+            );
+            p.state = CandidatePairState::Failed;
+            // https://tools.ietf.org/html/rfc8445#section-7.3.1.5
+            // A nomination waiting for this pair's check to succeed is dropped
+            p.nominated = false;
+            false
+        } else {
+            p.binding_request_count += 1;
+            true
+        }
+    }
+
+    // Foundation of a pair, the foundations of its local and remote candidates
+    fn pair_foundation(&self, pair_index: usize) -> (String, String) {
+        let p = &self.candidate_pairs[pair_index];
+        (
+            self.local_candidates[p.local_index].foundation(),
+            self.remote_candidates[p.remote_index].foundation(),
+        )
+    }
+
+    // Moves Frozen pairs to Waiting once a pair with the same foundation succeeded, or none
+    // of them is Waiting or InProgress anymore (RFC 8445 Section 6.1.4.2 and 7.2.5.3.3)
+    fn unfreeze_pairs(&mut self) {
+        for pair_index in 0..self.candidate_pairs.len() {
+            if self.candidate_pairs[pair_index].state != CandidatePairState::Frozen {
+                continue;
+            }
+
+            let foundation = self.pair_foundation(pair_index);
+            let same_foundation: Vec<CandidatePairState> = (0..self.candidate_pairs.len())
+                .filter(|&other| other != pair_index && self.pair_foundation(other) == foundation)
+                .map(|other| self.candidate_pairs[other].state)
+                .collect();
+            let succeeded = same_foundation.contains(&CandidatePairState::Succeeded);
+            let pending = same_foundation.iter().any(|state| {
+                *state == CandidatePairState::Waiting || *state == CandidatePairState::InProgress
+            });
+            if succeeded || !pending {
+                trace!(
+                    "[{}]: unfreeze pair {}",
+                    self.get_name(),
+                    self.candidate_pairs[pair_index]
+                );
+                self.candidate_pairs[pair_index].state = CandidatePairState::Waiting;
+            }
+        }
+    }
+
     pub(crate) fn add_pair(&mut self, local_index: usize, remote_index: usize) {
+        // A pair across transports or address families (e.g. UDP4 with UDP6) can never connect
+        let (local_network_type, remote_network_type) = (
+            self.local_candidates[local_index].network_type(),
+            self.remote_candidates[remote_index].network_type(),
+        );
+        if local_network_type != remote_network_type {
+            trace!(
+                "[{}]: skip pair of {} local {} with {} remote {}",
+                self.get_name(),
+                local_network_type,
+                local_index,
+                remote_network_type,
+                remote_index
+            );
+            return;
+        }
+
+        // Candidates are only paired within a component (RFC 8445 Section 6.1.2.2)
+        let (local_component, remote_component) = (
+            normalize_component(self.local_candidates[local_index].component()),
+            normalize_component(self.remote_candidates[remote_index].component()),
+        );
+        if local_component != remote_component {
+            trace!(
+                "[{}]: skip pair of component {} local {} with component {} remote {}",
+                self.get_name(),
+                local_component,
+                local_index,
+                remote_component,
+                remote_index
+            );
+            return;
+        }
+
         let p = CandidatePair::new(
             local_index,
             remote_index,
@@ -639,6 +1577,25 @@ impl Agent {
             self.is_controlling,
         );
         self.candidate_pairs.push(p);
+
+        // A pair of another component with the same foundation is checked first, this one
+        // is frozen until that check concludes (RFC 8445 Section 6.1.2.6)
+        let pair_index = self.candidate_pairs.len() - 1;
+        let foundation = self.pair_foundation(pair_index);
+        let frozen = (0..pair_index).any(|other| {
+            let o = &self.candidate_pairs[other];
+            self.pair_component(other) != self.pair_component(pair_index)
+                && matches!(
+                    o.state,
+                    CandidatePairState::Waiting
+                        | CandidatePairState::InProgress
+                        | CandidatePairState::Frozen
+                )
+                && self.pair_foundation(other) == foundation
+        });
+        if frozen {
+            self.candidate_pairs[pair_index].state = CandidatePairState::Frozen;
+        }
     }
 
     pub(crate) fn find_pair(&self, local_index: usize, remote_index: usize) -> Option<usize> {
@@ -666,6 +1623,16 @@ impl Agent {
             )
         };
 
+        if valid && self.is_consent_expired() {
+            warn!(
+                "[{}]: consent expired, no consent check response for {:?}",
+                self.get_name(),
+                self.consent_timeout
+            );
+            self.update_connection_state(ConnectionState::Failed);
+            return false;
+        }
+
         if valid {
             // Only allow transitions to fail if a.failedTimeout is non-zero
             let mut total_time_to_failure = self.failed_timeout;
@@ -681,7 +1648,7 @@ impl Agent {
                 && disconnected_time > self.disconnected_timeout
             {
                 self.update_connection_state(ConnectionState::Disconnected);
-            } else {
+            } else if self.pending_component().is_none() {
                 self.update_connection_state(ConnectionState::Connected);
             }
         }
@@ -689,8 +1656,10 @@ impl Agent {
         valid
     }
 
-    /// Sends STUN Binding Indications to the selected pair.
-    /// if no packet has been sent on that pair in the last keepaliveInterval.
+    /// Sends STUN Binding Requests, or Indications with keepalive_uses_indication, to the
+    /// selected pair if no packet has been sent on that pair in the last keepaliveInterval.
+    /// Binding Requests are sent as GOOG-PING when both agents enable it, see
+    /// `AgentConfig::enable_goog_ping`.
     /// Note: the caller should hold the agent lock.
     pub(crate) fn check_keepalive(&mut self) {
         let (local_index, remote_index) = {
@@ -709,19 +1678,173 @@ impl Agent {
             let last_received =
                 Instant::now().duration_since(self.remote_candidates[remote_index].last_received());
 
-            if (self.keepalive_interval != Duration::from_secs(0))
+            if self.is_consent_check_due() {
+                // Consent is refreshed by a response to a binding request, whatever the traffic
+                // on the pair
+                self.keepalive_ping(local_index, remote_index);
+            } else if (self.keepalive_interval != Duration::from_secs(0))
                 && ((last_sent > self.keepalive_interval)
                     || (last_received > self.keepalive_interval))
             {
-                // we use binding request instead of indication to support refresh consent schemas
-                // see https://tools.ietf.org/html/rfc7675
-                self.ping_candidate(local_index, remote_index);
+                if self.keepalive_uses_indication {
+                    self.send_binding_indication(local_index, remote_index);
+                } else {
+                    // we use binding request instead of indication to support refresh consent
+                    // schemas, see https://tools.ietf.org/html/rfc7675
+                    self.keepalive_ping(local_index, remote_index);
+                }
             }
         }
     }
 
+    // Time since the latest consent check response on the selected pair, if consent is enforced.
+    // A lite agent sends no checks, so only its full peer enforces consent.
+    fn consent_age(&self) -> Option<Duration> {
+        if self.consent_timeout == Duration::from_secs(0) || self.lite {
+            return None;
+        }
+        let consent_refreshed_at = self.candidate_pairs[self.selected_pair?].consent_refreshed_at?;
+        Some(Instant::now().saturating_duration_since(consent_refreshed_at))
+    }
+
+    fn is_consent_check_due(&self) -> bool {
+        self.consent_age()
+            .is_some_and(|consent_age| consent_age > self.consent_timeout / 6)
+    }
+
+    pub(crate) fn is_consent_expired(&self) -> bool {
+        self.consent_age()
+            .is_some_and(|consent_age| consent_age > self.consent_timeout)
+    }
+
+    // Binding indications expect no response, so they are not tracked as pending requests
+    fn send_binding_indication(&mut self, local_index: usize, remote_index: usize) {
+        let transaction_id = self.new_transaction_id();
+        let mut setters: Vec<Box<dyn Setter>> = vec![
+            Box::new(MessageType::new(METHOD_BINDING, CLASS_INDICATION)),
+            Box::new(transaction_id),
+        ];
+        setters.extend(self.software());
+        setters.extend(self.fingerprint());
+        let mut msg = Message::new();
+        if let Err(err) = msg.build(&setters) {
+            error!("{}", err);
+            return;
+        }
+
+        self.send_stun(&msg, local_index, remote_index);
+    }
+
+    /// Keeps the pair retained by a graceful restart alive with the previous credentials.
+    pub(crate) fn check_retained_pair_keepalive(&mut self, now: Instant) {
+        let Some(retained_pair) = &mut self.retained_pair else {
+            return;
+        };
+        if self.keepalive_interval == Duration::from_secs(0)
+            || now.saturating_duration_since(retained_pair.last_keepalive) < self.keepalive_interval
+        {
+            return;
+        }
+        retained_pair.last_keepalive = now;
+
+        let transaction_id = self.new_transaction_id();
+        let Some(retained_pair) = &self.retained_pair else {
+            return;
+        };
+        let username = retained_pair.remote_credentials.ufrag.clone()
+            + ":"
+            + retained_pair.local_credentials.ufrag.as_str();
+        let control: Box<dyn Setter> = if self.is_controlling {
+            Box::new(AttrControlling(self.tie_breaker))
+        } else {
+            Box::new(AttrControlled(self.tie_breaker))
+        };
+        let mut setters: Vec<Box<dyn Setter>> = vec![
+            Box::new(BINDING_REQUEST),
+            Box::new(transaction_id),
+            Box::new(Username::new(ATTR_USERNAME, username)),
+            control,
+            Box::new(PriorityAttr(retained_pair.local.priority())),
+        ];
+        setters.extend(self.software());
+        setters.push(Box::new(MessageIntegrity::new_short_term_integrity(
+            retained_pair.remote_credentials.pwd.clone(),
+        )));
+        setters.extend(self.fingerprint());
+        let mut msg = Message::new();
+        if let Err(err) = msg.build(&setters) {
+            error!("{}", err);
+            return;
+        }
+
+        let (local_addr, protocol, peer_addr) = (
+            retained_pair.local.addr(),
+            retained_pair.local.network_type().to_protocol(),
+            retained_pair.remote.addr(),
+        );
+        self.push_stun_transmit(&msg, local_addr, protocol, peer_addr);
+    }
+
+    /// Answers the binding requests the remote agent sends on the pair retained by a graceful
+    /// restart, which still use the previous credentials. Returns None for any other message.
+    fn handle_retained_pair_request(&mut self, msg: &Transmit<BytesMut>) -> Option<Result<()>> {
+        let retained_pair = self.retained_pair.as_ref()?;
+        let (local_addr, peer_addr) = (retained_pair.local.addr(), retained_pair.remote.addr());
+        if msg.transport.local_addr != local_addr
+            || msg.transport.peer_addr != peer_addr
+            || !stun::message::is_message(&msg.message)
+        {
+            return None;
+        }
+
+        let mut m = Message::new();
+        m.raw = msg.message.to_vec();
+        if m.decode().is_err() || m.typ != BINDING_REQUEST {
+            return None;
+        }
+        let username = retained_pair.local_credentials.ufrag.clone()
+            + ":"
+            + retained_pair.remote_credentials.ufrag.as_str();
+        if assert_inbound_username(&m, &username).is_err() {
+            return None;
+        }
+
+        let local_pwd = retained_pair.local_credentials.pwd.clone();
+        let protocol = retained_pair.local.network_type().to_protocol();
+        if let Err(err) = assert_inbound_message_integrity(&mut m, local_pwd.as_bytes()) {
+            warn!(
+                "[{}]: discard message from ({}), {}",
+                self.get_name(),
+                peer_addr,
+                err
+            );
+            return Some(Err(err));
+        }
+
+        let mut setters: Vec<Box<dyn Setter>> = vec![
+            Box::new(m),
+            Box::new(BINDING_SUCCESS),
+            Box::new(XorMappedAddress {
+                ip: peer_addr.ip(),
+                port: peer_addr.port(),
+            }),
+        ];
+        setters.extend(self.software());
+        setters.push(Box::new(MessageIntegrity::new_short_term_integrity(
+            local_pwd,
+        )));
+        setters.extend(self.fingerprint());
+        let mut out = Message::new();
+        if let Err(err) = out.build(&setters) {
+            return Some(Err(err));
+        }
+        self.push_stun_transmit(&out, local_addr, protocol, peer_addr);
+
+        Some(Ok(()))
+    }
+
     fn request_connectivity_check(&mut self) {
-        if self.ufrag_pwd.remote_credentials.is_some() {
+        if self.paused_at.is_none() && self.ufrag_pwd.remote_credentials.is_some() {
             self.contact(Instant::now());
         }
     }
@@ -731,12 +1854,92 @@ impl Agent {
     ///
     /// This is used for restarts, failures and on close.
     pub(crate) fn delete_all_candidates(&mut self, keep_local_candidates: bool) {
-        if !keep_local_candidates {
+        if keep_local_candidates {
+            // the filter policy may have changed since the candidates were added
+            if let Some(candidate_filter) = &self.candidate_filter {
+                self.local_candidates.retain(|c| candidate_filter(c));
+            }
+        } else {
             self.local_candidates.clear();
         }
         self.remote_candidates.clear();
     }
 
+    // Index of the peer reflexive remote candidate that the signaled candidate c replaces, if any
+    fn find_replaced_peer_reflexive_candidate(&self, c: &Candidate) -> Option<usize> {
+        if c.candidate_type() == CandidateType::PeerReflexive {
+            return None;
+        }
+        self.remote_candidates.iter().position(|cand| {
+            cand.candidate_type() == CandidateType::PeerReflexive
+                && cand.network_type() == c.network_type()
+                && cand.addr() == c.addr()
+        })
+    }
+
+    // A peer reflexive candidate learned from a connectivity check can be signaled later on, once
+    // the peer gathered it. The signaled candidate takes its place, keeping its pairs and
+    // their checks, with the signaled type and priority.
+    fn replace_peer_reflexive_candidate(&mut self, remote_index: usize, mut c: Candidate) {
+        let prflx = &self.remote_candidates[remote_index];
+        debug!(
+            "[{}]: Replace peer reflexive candidate {prflx} with signaled candidate {c}",
+            self.get_name()
+        );
+        c.last_sent = prflx.last_sent;
+        c.last_received = prflx.last_received;
+        let remote_priority = c.priority();
+        self.remote_candidates[remote_index] = c;
+
+        for p in &mut self.candidate_pairs {
+            if p.remote_index == remote_index {
+                p.remote_priority = remote_priority;
+            }
+        }
+    }
+
+    // Makes room for a local candidate of the given priority by removing the lowest priority
+    // one, if it is lower. Candidates are only removed before any of them is paired, so that
+    // no index into local_candidates is held anywhere, and before their NewCandidate event is
+    // polled, so that the peer is never told about a candidate the agent no longer has.
+    fn evict_local_candidate_below(&mut self, priority: u32) -> bool {
+        if !self.candidate_pairs.is_empty() || !self.pending_binding_requests.is_empty() {
+            return false;
+        }
+
+        let is_announced = |c: &Candidate| {
+            !self.events.iter().any(|event| {
+                matches!(event, Event::NewCandidate(new_candidate) if new_candidate.id() == c.id())
+            })
+        };
+        let Some((index, lowest)) = self
+            .local_candidates
+            .iter()
+            .enumerate()
+            .filter(|(_, c)| !is_announced(c))
+            .min_by_key(|(_, c)| c.priority())
+        else {
+            return false;
+        };
+        if lowest.priority() >= priority {
+            return false;
+        }
+
+        debug!("Replace local candidate {lowest} beyond max_local_candidates");
+        let id = lowest.id();
+        self.events.retain(
+            |event| !matches!(event, Event::NewCandidate(new_candidate) if new_candidate.id() == id),
+        );
+        self.local_candidates.remove(index);
+        true
+    }
+
+    fn is_candidate_allowed(&self, c: &Candidate) -> bool {
+        self.candidate_filter
+            .as_ref()
+            .is_none_or(|candidate_filter| candidate_filter(c))
+    }
+
     pub(crate) fn find_remote_candidate(&self, addr: SocketAddr) -> Option<usize> {
         let (ip, port) = (addr.ip(), addr.port());
         for (index, c) in self.remote_candidates.iter().enumerate() {
@@ -773,18 +1976,105 @@ impl Agent {
             remote_index
         );
 
-        self.invalidate_pending_binding_requests(Instant::now());
+        let now = Instant::now();
+        self.invalidate_pending_binding_requests(now);
 
-        self.pending_binding_requests.push(BindingRequest {
-            timestamp: Instant::now(),
-            transaction_id: m.transaction_id,
-            destination: self.remote_candidates[remote_index].addr(),
-            is_use_candidate: m.contains(ATTR_USE_CANDIDATE),
-        });
+        if self.pending_binding_requests.len() >= MAX_PENDING_BINDING_REQUESTS {
+            while let Some((_, oldest)) = self.pending_binding_request_order.pop_front() {
+                if self.pending_binding_requests.remove(&oldest).is_some() {
+                    trace!(
+                        "[{}]: Discarded the oldest binding request, {} are pending",
+                        self.get_name(),
+                        MAX_PENDING_BINDING_REQUESTS
+                    );
+                    break;
+                }
+            }
+        }
+        // Answered requests leave their entry behind, drop them before they pile up
+        if self.pending_binding_request_order.len() >= 2 * MAX_PENDING_BINDING_REQUESTS {
+            let pending_binding_requests = &self.pending_binding_requests;
+            self.pending_binding_request_order
+                .retain(|(_, id)| pending_binding_requests.contains_key(id));
+        }
+
+        self.pending_binding_requests.insert(
+            m.transaction_id,
+            BindingRequest {
+                timestamp: now,
+                transaction_id: m.transaction_id,
+                destination: self.remote_candidates[remote_index].addr(),
+                is_use_candidate: m.contains(ATTR_USE_CANDIDATE),
+                message: m.clone(),
+                local_index,
+                remote_index,
+                attempts: 1,
+                next_retransmit: if self.binding_request_rto != ZERO_DURATION {
+                    Some(now + self.binding_request_rto)
+                } else {
+                    None
+                },
+            },
+        );
+        self.pending_binding_request_order
+            .push_back((now, m.transaction_id));
 
         self.send_stun(m, local_index, remote_index);
     }
 
+    /// Resends the pending binding requests whose retransmission timer expired, keeping their
+    /// transaction id. The interval doubles after every retransmission (RFC 5389 Section 7.2.1),
+    /// until the request expires after `MAX_BINDING_REQUEST_TIMEOUT`.
+    pub(crate) fn retransmit_binding_requests(&mut self, now: Instant) {
+        self.invalidate_pending_binding_requests(now);
+
+        let mut retransmits = vec![];
+        for binding_request in self.pending_binding_requests.values_mut() {
+            let Some(next_retransmit) = binding_request.next_retransmit else {
+                continue;
+            };
+            if next_retransmit > now {
+                continue;
+            }
+
+            let backoff = 1u32
+                .checked_shl(u32::from(binding_request.attempts))
+                .unwrap_or(u32::MAX);
+            binding_request.attempts += 1;
+            binding_request.next_retransmit =
+                Some(now + self.binding_request_rto.saturating_mul(backoff));
+            retransmits.push((
+                binding_request.timestamp,
+                binding_request.message.clone(),
+                binding_request.local_index,
+                binding_request.remote_index,
+                binding_request.destination,
+                binding_request.attempts,
+            ));
+        }
+
+        // in the order the requests were first sent
+        retransmits.sort_by_key(|(timestamp, ..)| *timestamp);
+        for (_, m, local_index, remote_index, destination, attempts) in retransmits {
+            // The candidates may have been deleted since the request was first sent
+            if local_index >= self.local_candidates.len()
+                || remote_index >= self.remote_candidates.len()
+                || self.remote_candidates[remote_index].addr() != destination
+            {
+                continue;
+            }
+
+            trace!(
+                "[{}]: retransmit STUN from {} to {}, attempt {}",
+                self.get_name(),
+                local_index,
+                remote_index,
+                attempts
+            );
+            self.send_stun(&m, local_index, remote_index);
+        }
+    }
+
     pub(crate) fn send_binding_success(
         &mut self,
         m: &Message,
@@ -796,14 +2086,19 @@ impl Agent {
         let local_pwd = self.ufrag_pwd.local_credentials.pwd.clone();
 
         let (out, result) = {
-            let mut out = Message::new();
-            let result = out.build(&[
+            let mut setters: Vec<Box<dyn Setter>> = vec![
                 Box::new(m.clone()),
                 Box::new(BINDING_SUCCESS),
                 Box::new(XorMappedAddress { ip, port }),
-                Box::new(MessageIntegrity::new_short_term_integrity(local_pwd)),
-                Box::new(FINGERPRINT),
-            ]);
+            ];
+            setters.extend(self.goog_misc_info_response(m));
+            setters.extend(self.software());
+            setters.push(Box::new(MessageIntegrity::new_short_term_integrity(
+                local_pwd,
+            )));
+            setters.extend(self.fingerprint());
+            let mut out = Message::new();
+            let result = out.build(&setters);
             (out, result)
         };
 
@@ -820,27 +2115,112 @@ impl Agent {
         }
     }
 
+    /// Responds to a binding request with a 420 (Unknown Attribute) error, listing the
+    /// comprehension-required attributes that were not understood.
+    pub(crate) fn send_unknown_attributes_error(
+        &mut self,
+        m: &Message,
+        local_index: usize,
+        remote_addr: SocketAddr,
+        unknown_attributes: Vec<AttrType>,
+    ) {
+        let local_pwd = self.ufrag_pwd.local_credentials.pwd.clone();
+
+        let mut setters: Vec<Box<dyn Setter>> = vec![
+            Box::new(m.clone()),
+            Box::new(BINDING_ERROR),
+            Box::new(CODE_UNKNOWN_ATTRIBUTE),
+            Box::new(UnknownAttributes(unknown_attributes)),
+        ];
+        setters.extend(self.software());
+        setters.push(Box::new(MessageIntegrity::new_short_term_integrity(
+            local_pwd,
+        )));
+        setters.extend(self.fingerprint());
+        let mut out = Message::new();
+        let result = out.build(&setters);
+
+        if let Err(err) = result {
+            warn!(
+                "[{}]: Failed to build error response to: {} error: {}",
+                self.get_name(),
+                remote_addr,
+                err
+            );
+        } else {
+            self.transmit_stun(&out, local_index, remote_addr);
+        }
+    }
+
+    /// Handles an error response to a binding request. A 487 (Role Conflict) means the remote
+    /// agent found both agents claiming the same role (RFC 8445 Section 7.2.5.1): the agent
+    /// switches to the role opposite to the one claimed in the request, and checks the pair again.
+    pub(crate) fn handle_error_response(
+        &mut self,
+        m: &Message,
+        remote_addr: SocketAddr,
+    ) -> Result<()> {
+        let mut error_code = ErrorCodeAttribute::default();
+        error_code.get_from(m)?;
+
+        let Some(pending_request) = self.handle_inbound_binding_success(m.transaction_id) else {
+            warn!(
+                "[{}]: discard error response from ({}), unknown TransactionID 0x{:?}",
+                self.get_name(),
+                remote_addr,
+                m.transaction_id
+            );
+            return Err(Error::ErrUnhandledStunpacket);
+        };
+
+        if error_code.code != CODE_ROLE_CONFLICT {
+            debug!(
+                "[{}]: binding request to ({}) failed: {}",
+                self.get_name(),
+                remote_addr,
+                error_code
+            );
+            return Ok(());
+        }
+
+        // Responses to several requests sent in the conflicting role only switch it once
+        if pending_request.message.contains(ATTR_ICE_CONTROLLING) == self.is_controlling {
+            info!(
+                "[{}]: role conflict reported by ({}), switching role",
+                self.get_name(),
+                remote_addr
+            );
+            self.is_controlling = !self.is_controlling;
+            self.nominated_pair = None;
+            self.start();
+        }
+
+        let (local_index, remote_index) =
+            (pending_request.local_index, pending_request.remote_index);
+        if let Some(pair_index) = self.find_pair(local_index, remote_index) {
+            self.candidate_pairs[pair_index].state = CandidatePairState::Waiting;
+            self.ping_candidate(local_index, remote_index);
+        }
+
+        Ok(())
+    }
+
     /// Removes pending binding requests that are over `maxBindingRequestTimeout` old Let HTO be the
     /// transaction timeout, which SHOULD be 2*RTT if RTT is known or 500 ms otherwise.
     ///
     /// reference: (IETF ref-8445)[https://tools.ietf.org/html/rfc8445#appendix-B.1].
     pub(crate) fn invalidate_pending_binding_requests(&mut self, filter_time: Instant) {
-        let pending_binding_requests = &mut self.pending_binding_requests;
-        let initial_size = pending_binding_requests.len();
-
-        let mut temp = vec![];
-        for binding_request in pending_binding_requests.drain(..) {
-            if filter_time
-                .checked_duration_since(binding_request.timestamp)
-                .map(|duration| duration < MAX_BINDING_REQUEST_TIMEOUT)
-                .unwrap_or(true)
-            {
-                temp.push(binding_request);
+        let initial_size = self.pending_binding_requests.len();
+
+        while let Some(&(timestamp, id)) = self.pending_binding_request_order.front() {
+            if !is_binding_request_expired(timestamp, filter_time) {
+                break;
             }
+            self.pending_binding_request_order.pop_front();
+            self.pending_binding_requests.remove(&id);
         }
 
-        *pending_binding_requests = temp;
-        let bind_requests_remaining = pending_binding_requests.len();
+        let bind_requests_remaining = self.pending_binding_requests.len();
         let bind_requests_removed = initial_size - bind_requests_remaining;
         if bind_requests_removed > 0 {
             trace!(
@@ -858,16 +2238,12 @@ impl Agent {
         &mut self,
         id: TransactionId,
     ) -> Option<BindingRequest> {
-        self.invalidate_pending_binding_requests(Instant::now());
-
-        let pending_binding_requests = &mut self.pending_binding_requests;
-        for i in 0..pending_binding_requests.len() {
-            if pending_binding_requests[i].transaction_id == id {
-                let valid_binding_request = pending_binding_requests.remove(i);
-                return Some(valid_binding_request);
-            }
-        }
-        None
+        // A response to an expired request that handle_timeout did not drop yet is ignored too
+        self.pending_binding_requests
+            .remove(&id)
+            .filter(|binding_request| {
+                !is_binding_request_expired(binding_request.timestamp, Instant::now())
+            })
     }
 
     /// Processes STUN traffic from a remote candidate.
@@ -877,8 +2253,17 @@ impl Agent {
         local_index: usize,
         remote_addr: SocketAddr,
     ) -> Result<()> {
+        if let Some(f) = &mut self.on_stun_packet_hdlr {
+            f(StunPacketDirection::Inbound, m, remote_addr);
+        }
+
+        if m.typ.method == METHOD_GOOG_PING && self.enable_goog_ping {
+            return self.handle_inbound_goog_ping(m, local_index, remote_addr);
+        }
+
         if m.typ.method != METHOD_BINDING
             || !(m.typ.class == CLASS_SUCCESS_RESPONSE
+                || m.typ.class == CLASS_ERROR_RESPONSE
                 || m.typ.class == CLASS_REQUEST
                 || m.typ.class == CLASS_INDICATION)
         {
@@ -938,6 +2323,7 @@ impl Agent {
 
             if let Some(remote_index) = &remote_candidate_index {
                 self.handle_success_response(m, local_index, *remote_index, remote_addr);
+                self.update_remote_goog_ping_support(m, local_index, *remote_index);
             } else {
                 warn!(
                     "[{}]: discard success message from ({}), no such remote",
@@ -946,6 +2332,19 @@ impl Agent {
                 );
                 return Err(Error::ErrUnhandledStunpacket);
             }
+        } else if m.typ.class == CLASS_ERROR_RESPONSE {
+            if let Err(err) = assert_inbound_message_integrity(m, remote_credentials.pwd.as_bytes())
+            {
+                warn!(
+                    "[{}]: discard message from ({}), {}",
+                    self.get_name(),
+                    remote_addr,
+                    err
+                );
+                return Err(err);
+            }
+
+            return self.handle_error_response(m, remote_addr);
         } else if m.typ.class == CLASS_REQUEST {
             {
                 let username = self.ufrag_pwd.local_credentials.ufrag.clone()
@@ -973,12 +2372,38 @@ impl Agent {
                 }
             }
 
+            // https://tools.ietf.org/html/rfc5389#section-7.3.1
+            // A request with unknown comprehension-required attributes is rejected with a
+            // 420 (Unknown Attribute) error response listing them.
+            let unknown_attributes: Vec<AttrType> = m
+                .attributes
+                .0
+                .iter()
+                .map(|attr| attr.typ)
+                .filter(|typ| typ.required() && !KNOWN_REQUEST_ATTRIBUTES.contains(typ))
+                .collect();
+            if !unknown_attributes.is_empty() {
+                debug!(
+                    "[{}]: reject request from ({}), unknown attributes {}",
+                    self.get_name(),
+                    remote_addr,
+                    UnknownAttributes(unknown_attributes.clone())
+                );
+                self.send_unknown_attributes_error(m, local_index, remote_addr, unknown_attributes);
+                return Ok(());
+            }
+
             if remote_candidate_index.is_none() {
-                let (ip, port, network_type) =
-                    (remote_addr.ip(), remote_addr.port(), NetworkType::Udp4);
+                // The peer reflexive candidate is reached over the transport of the local one
+                let (ip, port, network_type) = (
+                    remote_addr.ip(),
+                    remote_addr.port(),
+                    self.local_candidates[local_index].network_type(),
+                );
 
                 let prflx_candidate_config = CandidatePeerReflexiveConfig {
                     base_config: CandidateConfig {
+                        candidate_id: self.with_rng(generate_cand_id_with_rng),
                         network: network_type.to_string(),
                         address: ip.to_string(),
                         port,
@@ -1019,6 +2444,7 @@ impl Agent {
             );
 
             if let Some(remote_index) = &remote_candidate_index {
+                self.update_remote_network_cost(m, *remote_index);
                 self.handle_binding_request(m, local_index, *remote_index);
             }
         }
@@ -1042,12 +2468,79 @@ impl Agent {
 
     pub(crate) fn send_stun(&mut self, msg: &Message, local_index: usize, remote_index: usize) {
         let peer_addr = self.remote_candidates[remote_index].addr();
+        self.transmit_stun(msg, local_index, peer_addr);
+    }
+
+    fn transmit_stun(&mut self, msg: &Message, local_index: usize, peer_addr: SocketAddr) {
         let local_addr = self.local_candidates[local_index].addr();
-        let protocol = if self.local_candidates[local_index].network_type().is_tcp() {
-            Protocol::TCP
+        let protocol = self.local_candidates[local_index]
+            .network_type()
+            .to_protocol();
+        // Keepalives are timed from the last packet that actually left the candidate
+        if self.push_stun_transmit(msg, local_addr, protocol, peer_addr) {
+            self.local_candidates[local_index].seen(true);
+        }
+    }
+
+    // SOFTWARE setter of outbound STUN messages, unless AgentConfig::software is empty
+    pub(crate) fn software(&self) -> Option<Box<dyn Setter>> {
+        self.software
+            .clone()
+            .map(|software| Box::new(software) as Box<dyn Setter>)
+    }
+
+    // FINGERPRINT setter that ends every outbound STUN message, unless include_fingerprint
+    // is disabled
+    pub(crate) fn fingerprint(&self) -> Option<Box<dyn Setter>> {
+        if self.include_fingerprint {
+            Some(Box::new(FINGERPRINT))
         } else {
-            Protocol::UDP
-        };
+            None
+        }
+    }
+
+    // Sends or queues a STUN packet, returns whether it was sent
+    fn push_stun_transmit(
+        &mut self,
+        msg: &Message,
+        local_addr: SocketAddr,
+        protocol: Protocol,
+        peer_addr: SocketAddr,
+    ) -> bool {
+        let peer_addr = with_zone_of(local_addr, peer_addr);
+        if let Some(f) = &mut self.on_stun_packet_hdlr {
+            f(StunPacketDirection::Outbound, msg, peer_addr);
+        }
+
+        if let Err(err) = self.push_transmit(&msg.raw, local_addr, protocol, peer_addr) {
+            warn!(
+                "[{}]: failed to send STUN packet from {} to {}: {}",
+                self.get_name(),
+                local_addr,
+                peer_addr,
+                err
+            );
+            return false;
+        }
+        true
+    }
+
+    // Sends a packet with the writer of the local candidate, or queues it for poll_transmit
+    // when the candidate has none
+    fn push_transmit(
+        &mut self,
+        data: &[u8],
+        local_addr: SocketAddr,
+        protocol: Protocol,
+        peer_addr: SocketAddr,
+    ) -> Result<()> {
+        let peer_addr = with_zone_of(local_addr, peer_addr);
+        if let Some(writer) = self
+            .find_local_candidate(local_addr, protocol)
+            .and_then(|local_index| self.local_candidates[local_index].writer.as_ref())
+        {
+            return writer(data, peer_addr);
+        }
 
         self.transmits.push_back(Transmit {
             now: Instant::now(),
@@ -1057,10 +2550,9 @@ impl Agent {
                 ecn: None,
                 protocol,
             },
-            message: BytesMut::from(&msg.raw[..]),
+            message: BytesMut::from(data),
         });
-
-        self.local_candidates[local_index].seen(true);
+        Ok(())
     }
 
     fn handle_inbound_candidate_msg(
@@ -1097,14 +2589,21 @@ impl Agent {
                     self.get_name(),
                     remote_addr,
                 );
-            } else {
-                error!(
-                    "[{}]: non-STUN traffic message from a valid remote candidate from {}",
+                return Err(Error::ErrNonStunmessage);
+            }
+
+            // Application data is queued until it's read, up to MAX_BUFFER_SIZE bytes
+            if self.incoming_data_size + buf.len() > MAX_BUFFER_SIZE {
+                warn!(
+                    "[{}]: Discarded application data from {}, buffer is full",
                     self.get_name(),
                     remote_addr
                 );
+                return Err(Error::ErrBufferFull);
             }
-            Err(Error::ErrNonStunmessage)
+            self.incoming_data_size += buf.len();
+            self.incoming_data.push_back(BytesMut::from(buf));
+            Ok(())
         }
     }
 
@@ -1116,21 +2615,32 @@ impl Agent {
         }
     }
 
+    pub(crate) fn with_rng<T>(&mut self, f: impl FnOnce(&mut dyn RngCore) -> T) -> T {
+        match &mut self.rng {
+            Some(rng) => f(rng),
+            None => f(&mut thread_rng()),
+        }
+    }
+
+    pub(crate) fn new_transaction_id(&mut self) -> TransactionId {
+        self.with_rng(generate_transaction_id_with_rng)
+    }
+
     pub(crate) fn get_selected_pair(&self) -> Option<usize> {
         self.selected_pair
     }
 
-    pub(crate) fn get_best_available_candidate_pair(&self) -> Option<usize> {
+    pub(crate) fn get_best_available_candidate_pair(&self, component: u16) -> Option<usize> {
         let mut best_pair_index: Option<usize> = None;
 
         for (index, p) in self.candidate_pairs.iter().enumerate() {
-            if p.state == CandidatePairState::Failed {
+            if p.state == CandidatePairState::Failed || self.pair_component(index) != component {
                 continue;
             }
 
             if let Some(pair_index) = &mut best_pair_index {
                 let b = &self.candidate_pairs[*pair_index];
-                if b.priority() < p.priority() {
+                if b.priority(self.is_controlling) < p.priority(self.is_controlling) {
                     *pair_index = index;
                 }
             } else {
@@ -1141,17 +2651,40 @@ impl Agent {
         best_pair_index
     }
 
-    pub(crate) fn get_best_valid_candidate_pair(&self) -> Option<usize> {
+    pub(crate) fn get_best_valid_candidate_pair(&self, component: u16) -> Option<usize> {
         let mut best_pair_index: Option<usize> = None;
 
         for (index, p) in self.candidate_pairs.iter().enumerate() {
-            if p.state != CandidatePairState::Succeeded {
+            if p.state != CandidatePairState::Succeeded || self.pair_component(index) != component {
                 continue;
             }
 
             if let Some(pair_index) = &mut best_pair_index {
                 let b = &self.candidate_pairs[*pair_index];
-                if b.priority() < p.priority() {
+                if b.priority(self.is_controlling) < p.priority(self.is_controlling) {
+                    *pair_index = index;
+                }
+            } else {
+                best_pair_index = Some(index);
+            }
+        }
+
+        best_pair_index
+    }
+
+    pub(crate) fn get_best_nominated_candidate_pair(&self, component: u16) -> Option<usize> {
+        let mut best_pair_index: Option<usize> = None;
+
+        for (index, p) in self.candidate_pairs.iter().enumerate() {
+            if p.state != CandidatePairState::Succeeded
+                || !p.nominated
+                || self.pair_component(index) != component
+            {
+                continue;
+            }
+
+            if let Some(pair_index) = &mut best_pair_index {
+                if self.is_preferred_nominated_pair(index, *pair_index) {
                     *pair_index = index;
                 }
             } else {
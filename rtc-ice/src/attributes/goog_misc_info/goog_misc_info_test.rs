@@ -0,0 +1,32 @@
+use super::*;
+use shared::error::{Error, Result};
+
+#[test]
+fn test_goog_misc_info_get_from() -> Result<()> {
+    let mut m = Message::new();
+    let mut g = GoogMiscInfoAttr::default();
+    assert_eq!(g.get_from(&m), Err(Error::ErrAttributeNotFound));
+
+    m.build(&[
+        Box::new(BINDING_REQUEST),
+        Box::new(GoogMiscInfoAttr::goog_ping()),
+    ])?;
+
+    let mut m1 = Message::new();
+    m1.write(&m.raw)?;
+    g.get_from(&m1)?;
+    assert_eq!(g, GoogMiscInfoAttr(vec![GOOG_PING_VERSION]));
+    assert!(g.supports_goog_ping());
+    assert!(!GoogMiscInfoAttr(vec![]).supports_goog_ping());
+    assert!(!GoogMiscInfoAttr(vec![0, 1]).supports_goog_ping());
+
+    //"IncorrectSize"
+    {
+        let mut m2 = Message::new();
+        m2.add(ATTR_GOOG_MISC_INFO, &[0; 3]);
+        let result = GoogMiscInfoAttr::default().get_from(&m2);
+        assert_eq!(result, Err(Error::ErrAttributeSizeInvalid));
+    }
+
+    Ok(())
+}
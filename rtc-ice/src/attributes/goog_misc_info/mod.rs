@@ -0,0 +1,62 @@
+#[cfg(test)]
+mod goog_misc_info_test;
+
+use shared::error::*;
+use stun::attributes::ATTR_GOOG_MISC_INFO;
+use stun::message::*;
+
+/// Index of the GOOG-PING version in GOOG-MISC-INFO.
+pub const GOOG_MISC_INFO_SUPPORT_GOOG_PING_VERSION: usize = 0;
+
+/// Version of GOOG-PING announced by the agent, as by Chrome.
+pub const GOOG_PING_VERSION: u16 = 1;
+
+/// Represents the GOOG-MISC-INFO attribute of Google-flavored ICE, as sent by Chrome. It is a
+/// list of 16 bit values indexed by feature, used to announce support for extensions such as
+/// GOOG-PING in binding requests and responses.
+#[derive(Default, PartialEq, Eq, Debug, Clone)]
+pub struct GoogMiscInfoAttr(pub Vec<u16>);
+
+impl Setter for GoogMiscInfoAttr {
+    // add_to adds GOOG-MISC-INFO attribute to message.
+    fn add_to(&self, m: &mut Message) -> Result<()> {
+        let v: Vec<u8> = self
+            .0
+            .iter()
+            .flat_map(|value| value.to_be_bytes())
+            .collect();
+        m.add(ATTR_GOOG_MISC_INFO, &v);
+        Ok(())
+    }
+}
+
+impl GoogMiscInfoAttr {
+    /// GOOG-MISC-INFO announcing support for GOOG-PING.
+    pub fn goog_ping() -> Self {
+        let mut values = vec![0; GOOG_MISC_INFO_SUPPORT_GOOG_PING_VERSION + 1];
+        values[GOOG_MISC_INFO_SUPPORT_GOOG_PING_VERSION] = GOOG_PING_VERSION;
+        GoogMiscInfoAttr(values)
+    }
+
+    /// Decodes GOOG-MISC-INFO attribute from message.
+    pub fn get_from(&mut self, m: &Message) -> Result<()> {
+        let v = m.get(ATTR_GOOG_MISC_INFO)?;
+        if v.len() % 2 != 0 {
+            return Err(Error::ErrAttributeSizeInvalid);
+        }
+
+        self.0 = v
+            .chunks_exact(2)
+            .map(|value| u16::from_be_bytes([value[0], value[1]]))
+            .collect();
+
+        Ok(())
+    }
+
+    /// Returns whether the sender supports GOOG-PING.
+    pub fn supports_goog_ping(&self) -> bool {
+        self.0
+            .get(GOOG_MISC_INFO_SUPPORT_GOOG_PING_VERSION)
+            .is_some_and(|version| *version >= GOOG_PING_VERSION)
+    }
+}
@@ -1,3 +1,6 @@
 pub mod control;
+pub mod goog_misc_info;
+pub mod network_cost;
+pub mod nomination;
 pub mod priority;
 pub mod use_candidate;
@@ -0,0 +1,43 @@
+#[cfg(test)]
+mod network_cost_test;
+
+use shared::error::*;
+use stun::attributes::ATTR_NETWORK_COST;
+use stun::checks::*;
+use stun::message::*;
+
+/// Represents the NETWORK-COST attribute of Google-flavored ICE, as sent by Chrome. It carries
+/// the network id and the network cost of the sending candidate, letting the remote agent
+/// prefer cheaper networks, e.g. Wi-Fi over cellular.
+#[derive(Default, PartialEq, Eq, Debug, Copy, Clone)]
+pub struct NetworkCostAttr {
+    pub network_id: u16,
+    pub network_cost: u16,
+}
+
+const NETWORK_COST_SIZE: usize = 4; // 16 bit network id, 16 bit network cost
+
+impl Setter for NetworkCostAttr {
+    // add_to adds NETWORK-COST attribute to message.
+    fn add_to(&self, m: &mut Message) -> Result<()> {
+        let mut v = Vec::with_capacity(NETWORK_COST_SIZE);
+        v.extend_from_slice(&self.network_id.to_be_bytes());
+        v.extend_from_slice(&self.network_cost.to_be_bytes());
+        m.add(ATTR_NETWORK_COST, &v);
+        Ok(())
+    }
+}
+
+impl NetworkCostAttr {
+    /// Decodes NETWORK-COST attribute from message.
+    pub fn get_from(&mut self, m: &Message) -> Result<()> {
+        let v = m.get(ATTR_NETWORK_COST)?;
+
+        check_size(ATTR_NETWORK_COST, v.len(), NETWORK_COST_SIZE)?;
+
+        self.network_id = u16::from_be_bytes([v[0], v[1]]);
+        self.network_cost = u16::from_be_bytes([v[2], v[3]]);
+
+        Ok(())
+    }
+}
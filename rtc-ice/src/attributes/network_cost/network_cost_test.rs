@@ -0,0 +1,34 @@
+use super::*;
+use shared::error::{Error, Result};
+
+#[test]
+fn test_network_cost_get_from() -> Result<()> {
+    let mut m = Message::new();
+    let mut n = NetworkCostAttr::default();
+    assert_eq!(n.get_from(&m), Err(Error::ErrAttributeNotFound));
+
+    let attr = NetworkCostAttr {
+        network_id: 3,
+        network_cost: 999,
+    };
+    m.build(&[Box::new(BINDING_REQUEST), Box::new(attr)])?;
+
+    let mut m1 = Message::new();
+    m1.write(&m.raw)?;
+    n.get_from(&m1)?;
+    assert_eq!(n, attr);
+
+    //"IncorrectSize"
+    {
+        let mut m2 = Message::new();
+        m2.add(ATTR_NETWORK_COST, &[0; 100]);
+        let result = NetworkCostAttr::default().get_from(&m2);
+        if let Err(err) = result {
+            assert!(is_attr_size_invalid(&err), "should error");
+        } else {
+            panic!("expected error, but got ok");
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,36 @@
+#[cfg(test)]
+mod nomination_test;
+
+use shared::error::*;
+use stun::attributes::ATTR_NOMINATION;
+use stun::checks::*;
+use stun::message::*;
+
+/// Represents NOMINATION attribute of ICE renomination
+/// <https://datatracker.ietf.org/doc/html/draft-thatcher-ice-renomination-01>.
+/// The controlled agent selects the pair nominated with the highest value.
+#[derive(Default, PartialEq, Eq, Debug, Copy, Clone)]
+pub struct NominationAttr(pub u32);
+
+const NOMINATION_SIZE: usize = 4; // 32 bit
+
+impl Setter for NominationAttr {
+    // add_to adds NOMINATION attribute to message.
+    fn add_to(&self, m: &mut Message) -> Result<()> {
+        m.add(ATTR_NOMINATION, &self.0.to_be_bytes());
+        Ok(())
+    }
+}
+
+impl NominationAttr {
+    /// Decodes NOMINATION attribute from message.
+    pub fn get_from(&mut self, m: &Message) -> Result<()> {
+        let v = m.get(ATTR_NOMINATION)?;
+
+        check_size(ATTR_NOMINATION, v.len(), NOMINATION_SIZE)?;
+
+        self.0 = u32::from_be_bytes([v[0], v[1], v[2], v[3]]);
+
+        Ok(())
+    }
+}
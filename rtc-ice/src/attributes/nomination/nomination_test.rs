@@ -0,0 +1,30 @@
+use super::*;
+use shared::error::{Error, Result};
+
+#[test]
+fn test_nomination_get_from() -> Result<()> {
+    let mut m = Message::new();
+    let mut n = NominationAttr::default();
+    assert_eq!(n.get_from(&m), Err(Error::ErrAttributeNotFound));
+
+    m.build(&[Box::new(BINDING_REQUEST), Box::new(NominationAttr(7))])?;
+
+    let mut m1 = Message::new();
+    m1.write(&m.raw)?;
+    n.get_from(&m1)?;
+    assert_eq!(n, NominationAttr(7));
+
+    //"IncorrectSize"
+    {
+        let mut m2 = Message::new();
+        m2.add(ATTR_NOMINATION, &[0; 100]);
+        let result = NominationAttr::default().get_from(&m2);
+        if let Err(err) = result {
+            assert!(is_attr_size_invalid(&err), "should error");
+        } else {
+            panic!("expected error, but got ok");
+        }
+    }
+
+    Ok(())
+}
@@ -17,22 +17,27 @@ impl CandidateHostConfig {
             candidate_id = generate_cand_id();
         }
 
-        let ip: IpAddr = match self.base_config.address.parse() {
-            Ok(ip) => ip,
-            Err(_) => return Err(Error::ErrAddressParseFailed),
-        };
-        let network_type = determine_network_type(&self.base_config.network, &ip)?;
+        let (address, zone, resolved_addr) = parse_scoped_address(
+            &self.base_config.address,
+            self.base_config.port,
+            self.base_config.zone_resolver.as_ref(),
+        )?;
+        let network_type = determine_network_type(&self.base_config.network, &resolved_addr.ip())?;
 
         Ok(Candidate {
             id: candidate_id,
             network_type,
             candidate_type: CandidateType::Host,
-            address: self.base_config.address,
+            address,
+            zone,
             port: self.base_config.port,
-            resolved_addr: SocketAddr::new(ip, self.base_config.port),
-            component: self.base_config.component,
+            resolved_addr,
+            component: normalize_component(self.base_config.component),
             foundation_override: self.base_config.foundation,
             priority_override: self.base_config.priority,
+            local_preference_override: self.base_config.local_preference,
+            network_id: self.base_config.network_id,
+            network_cost: self.base_config.network_cost,
             network: self.base_config.network,
             tcp_type: self.tcp_type,
             ..Candidate::default()
@@ -1,29 +1,40 @@
+#[cfg(feature = "serde")]
 use serde::Serialize;
 use std::fmt;
+use std::time::{Duration, Instant};
+
+/// Weight of a new round trip time sample in the smoothed round trip time, as in RFC 6298.
+const ROUND_TRIP_TIME_ALPHA: f64 = 0.125;
 
 /// Represent the ICE candidate pair state.
-#[derive(Default, Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[derive(Default, Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
 pub enum CandidatePairState {
     #[default]
-    #[serde(rename = "unspecified")]
+    #[cfg_attr(feature = "serde", serde(rename = "unspecified"))]
     Unspecified = 0,
 
     /// Means a check has not been performed for this pair.
-    #[serde(rename = "waiting")]
+    #[cfg_attr(feature = "serde", serde(rename = "waiting"))]
     Waiting = 1,
 
     /// Means a check has been sent for this pair, but the transaction is in progress.
-    #[serde(rename = "in-progress")]
+    #[cfg_attr(feature = "serde", serde(rename = "in-progress"))]
     InProgress = 2,
 
     /// Means a check for this pair was already done and failed, either never producing any response
     /// or producing an unrecoverable failure response.
-    #[serde(rename = "failed")]
+    #[cfg_attr(feature = "serde", serde(rename = "failed"))]
     Failed = 3,
 
     /// Means a check for this pair was already done and produced a successful result.
-    #[serde(rename = "succeeded")]
+    #[cfg_attr(feature = "serde", serde(rename = "succeeded"))]
     Succeeded = 4,
+
+    /// Means a check for this pair waits until a pair with the same foundation on another
+    /// component succeeds, or no longer has a check pending (RFC 8445 Section 6.1.2.6).
+    #[cfg_attr(feature = "serde", serde(rename = "frozen"))]
+    Frozen = 5,
 }
 
 impl From<u8> for CandidatePairState {
@@ -33,6 +44,7 @@ impl From<u8> for CandidatePairState {
             2 => Self::InProgress,
             3 => Self::Failed,
             4 => Self::Succeeded,
+            5 => Self::Frozen,
             _ => Self::Unspecified,
         }
     }
@@ -45,6 +57,7 @@ impl fmt::Display for CandidatePairState {
             Self::InProgress => "in-progress",
             Self::Failed => "failed",
             Self::Succeeded => "succeeded",
+            Self::Frozen => "frozen",
             Self::Unspecified => "unspecified",
         };
 
@@ -63,6 +76,14 @@ pub struct CandidatePair {
     pub(crate) binding_request_count: u16,
     pub(crate) state: CandidatePairState,
     pub(crate) nominated: bool,
+    pub(crate) current_round_trip_time: Option<Duration>,
+    pub(crate) smoothed_round_trip_time: Option<Duration>,
+    pub(crate) total_round_trip_time: Duration,
+    pub(crate) responses_received: u64,
+    // time of the latest response to a binding request on this pair, which refreshes consent
+    pub(crate) consent_refreshed_at: Option<Instant>,
+    // whether the remote agent announced GOOG-PING support in a response on this pair
+    pub(crate) remote_supports_goog_ping: bool,
 }
 
 impl fmt::Debug for CandidatePair {
@@ -70,7 +91,7 @@ impl fmt::Debug for CandidatePair {
         write!(
             f,
             "prio {} (local, prio {}) {} <-> {} (remote, prio {})",
-            self.priority(),
+            self.priority(self.ice_role_controlling),
             self.local_priority,
             self.local_index,
             self.remote_index,
@@ -84,7 +105,7 @@ impl fmt::Display for CandidatePair {
         write!(
             f,
             "prio {} (local, prio {}) {} <-> {} (remote, prio {})",
-            self.priority(),
+            self.priority(self.ice_role_controlling),
             self.local_priority,
             self.local_index,
             self.remote_index,
@@ -117,16 +138,45 @@ impl CandidatePair {
             state: CandidatePairState::Waiting,
             binding_request_count: 0,
             nominated: false,
+            current_round_trip_time: None,
+            smoothed_round_trip_time: None,
+            total_round_trip_time: Duration::from_secs(0),
+            responses_received: 0,
+            consent_refreshed_at: None,
+            remote_supports_goog_ping: false,
         }
     }
 
+    /// Records the round trip time of a connectivity check that got a valid response. The
+    /// smoothed value is an exponentially weighted moving average, so that transient spikes
+    /// don't dominate it.
+    pub(crate) fn update_round_trip_time(&mut self, rtt: Duration) {
+        self.current_round_trip_time = Some(rtt);
+        self.smoothed_round_trip_time = Some(match self.smoothed_round_trip_time {
+            Some(srtt) => {
+                srtt.mul_f64(1.0 - ROUND_TRIP_TIME_ALPHA) + rtt.mul_f64(ROUND_TRIP_TIME_ALPHA)
+            }
+            None => rtt,
+        });
+        self.total_round_trip_time += rtt;
+        self.responses_received += 1;
+    }
+
+    /// Returns the smoothed round trip time of the pair, or None if no check got a response yet.
+    pub fn smoothed_round_trip_time(&self) -> Option<Duration> {
+        self.smoothed_round_trip_time
+    }
+
     /// RFC 5245 - 5.7.2.  Computing Pair Priority and Ordering Pairs
     /// Let G be the priority for the candidate provided by the controlling
     /// agent.  Let D be the priority for the candidate provided by the
     /// controlled agent.
     /// pair priority = 2^32*MIN(G,D) + 2*MAX(G,D) + (G>D?1:0)
-    pub fn priority(&self) -> u64 {
-        let (g, d) = if self.ice_role_controlling {
+    ///
+    /// is_controlling is the current role of the local agent, which may have changed since the
+    /// pair was formed, e.g. when the role is only set by start_connectivity_checks.
+    pub fn priority(&self, is_controlling: bool) -> u64 {
+        let (g, d) = if is_controlling {
             (self.local_priority, self.remote_priority)
         } else {
             (self.remote_priority, self.local_priority)
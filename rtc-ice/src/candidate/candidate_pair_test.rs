@@ -5,6 +5,7 @@ use crate::candidate::candidate_peer_reflexive::CandidatePeerReflexiveConfig;
 use crate::candidate::candidate_relay::CandidateRelayConfig;
 use crate::candidate::candidate_server_reflexive::CandidateServerReflexiveConfig;
 use crate::candidate::{Candidate, CandidateConfig};
+use std::time::Duration;
 
 pub(crate) fn host_candidate() -> Result<Candidate> {
     CandidateHostConfig {
@@ -156,7 +157,7 @@ fn test_candidate_pair_priority() -> Result<()> {
     ];
 
     for (pair, want) in tests {
-        let got = pair.priority();
+        let got = pair.priority(pair.ice_role_controlling);
         assert_eq!(
             got, want,
             "CandidatePair({pair}).Priority() = {got}, want {want}"
@@ -199,3 +200,56 @@ fn test_candidate_pair_equality() -> Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn test_candidate_pair_round_trip_time() {
+    let mut pair = CandidatePair::new(0, 0, 1, 2, false);
+    assert_eq!(pair.smoothed_round_trip_time(), None);
+
+    pair.update_round_trip_time(Duration::from_millis(100));
+    assert_eq!(
+        pair.smoothed_round_trip_time(),
+        Some(Duration::from_millis(100))
+    );
+
+    // A spike only moves the smoothed value by 1/8 of the difference
+    pair.update_round_trip_time(Duration::from_millis(900));
+    assert_eq!(
+        pair.current_round_trip_time,
+        Some(Duration::from_millis(900))
+    );
+    assert_eq!(
+        pair.smoothed_round_trip_time(),
+        Some(Duration::from_millis(200))
+    );
+    assert_eq!(pair.total_round_trip_time, Duration::from_millis(1000));
+    assert_eq!(pair.responses_received, 2);
+}
+
+#[test]
+fn test_candidate_pair_priority_ordering() {
+    let pair = |local_priority: u32, remote_priority: u32| {
+        CandidatePair::new(0, 0, local_priority, remote_priority, false)
+    };
+
+    // The pair with the highest minimum candidate priority comes first, whatever the role
+    for is_controlling in [true, false] {
+        let mut pairs = [pair(100, 200), pair(150, 150), pair(200, 99)];
+        pairs.sort_by_key(|p| std::cmp::Reverse(p.priority(is_controlling)));
+        let order: Vec<(u32, u32)> = pairs
+            .iter()
+            .map(|p| (p.local_priority, p.remote_priority))
+            .collect();
+        assert_eq!(order, vec![(150, 150), (100, 200), (200, 99)]);
+    }
+
+    // Same minimum and maximum: the pair whose controlling candidate has the higher
+    // priority wins
+    let (high_local, high_remote) = (pair(300, 100), pair(100, 300));
+    assert_eq!(
+        high_local.priority(true),
+        (1 << 32) * 100 - 100 + 2 * 300 + 1
+    );
+    assert!(high_local.priority(true) > high_remote.priority(true));
+    assert!(high_local.priority(false) < high_remote.priority(false));
+}
@@ -8,6 +8,9 @@ use shared::error::*;
 pub struct CandidatePeerReflexiveConfig {
     pub base_config: CandidateConfig,
 
+    /// The base the candidate was learned from. It is left empty when unknown, as for a remote
+    /// candidate learned from a connectivity check, and the candidate then has no related
+    /// address, like the same candidate signaled without raddr/rport.
     pub rel_addr: String,
     pub rel_port: u16,
 }
@@ -33,10 +36,13 @@ impl CandidatePeerReflexiveConfig {
             address: self.base_config.address,
             port: self.base_config.port,
             resolved_addr: SocketAddr::new(ip, self.base_config.port),
-            component: self.base_config.component,
+            component: normalize_component(self.base_config.component),
             foundation_override: self.base_config.foundation,
             priority_override: self.base_config.priority,
-            related_address: Some(CandidateRelatedAddress {
+            local_preference_override: self.base_config.local_preference,
+            network_id: self.base_config.network_id,
+            network_cost: self.base_config.network_cost,
+            related_address: (!self.rel_addr.is_empty()).then_some(CandidateRelatedAddress {
                 address: self.rel_addr,
                 port: self.rel_port,
             }),
@@ -33,9 +33,12 @@ impl CandidateServerReflexiveConfig {
             address: self.base_config.address,
             port: self.base_config.port,
             resolved_addr: SocketAddr::new(ip, self.base_config.port),
-            component: self.base_config.component,
+            component: normalize_component(self.base_config.component),
             foundation_override: self.base_config.foundation,
             priority_override: self.base_config.priority,
+            local_preference_override: self.base_config.local_preference,
+            network_id: self.base_config.network_id,
+            network_cost: self.base_config.network_cost,
             related_address: Some(CandidateRelatedAddress {
                 address: self.rel_addr,
                 port: self.rel_port,
@@ -1,7 +1,7 @@
 use super::*;
 use crate::candidate::candidate_pair::CandidatePairState;
-use crate::candidate::{unmarshal_candidate, Candidate};
-use std::time::Instant;
+use crate::candidate::{unmarshal_candidate, unmarshal_candidate_json, Candidate, CandidateInit};
+use std::time::{Duration, Instant};
 
 #[test]
 fn test_candidate_priority() -> Result<()> {
@@ -111,6 +111,42 @@ fn test_candidate_priority() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_candidate_local_preference() -> Result<()> {
+    let host_candidate = |address: &str, local_preference: Option<u16>, priority: u32| {
+        CandidateHostConfig {
+            base_config: CandidateConfig {
+                network: "udp".to_owned(),
+                address: address.to_owned(),
+                port: 1000,
+                component: COMPONENT_RTP,
+                priority,
+                local_preference,
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+        .new_candidate_host()
+    };
+
+    let default = host_candidate("192.168.0.1", None, 0)?;
+    assert_eq!(default.local_preference(), DEFAULT_LOCAL_PREFERENCE);
+    assert_eq!(default.priority(), 2130706431);
+
+    // Wi-Fi preferred over cellular
+    let wifi = host_candidate("192.168.0.1", Some(65000), 0)?;
+    let cellular = host_candidate("10.0.0.1", Some(1000), 0)?;
+    assert_eq!(wifi.local_preference(), 65000);
+    assert_eq!(wifi.priority(), (126 << 24) + (65000 << 8) + 255);
+    assert!(wifi.priority() > cellular.priority());
+
+    // An explicit priority wins over the local preference
+    let overridden = host_candidate("192.168.0.1", Some(1000), 500)?;
+    assert_eq!(overridden.priority(), 500);
+
+    Ok(())
+}
+
 #[test]
 fn test_candidate_last_sent() -> Result<()> {
     let mut candidate = Candidate::default();
@@ -230,6 +266,7 @@ fn test_candidate_foundation() -> Result<()> {
     Ok(())
 }
 
+#[cfg(feature = "serde")]
 #[test]
 fn test_candidate_pair_state_serialization() {
     let tests = vec![
@@ -263,6 +300,7 @@ fn test_candidate_pair_state_to_string() {
     }
 }
 
+#[cfg(feature = "serde")]
 #[test]
 fn test_candidate_type_serialization() {
     let tests = vec![
@@ -303,6 +341,7 @@ fn test_candidate_marshal() -> Result<()> {
             Some(Candidate{
                     network_type:       NetworkType::Udp6,
                     candidate_type:      CandidateType::Host,
+                    component:      COMPONENT_RTP,
                     address:            "fcd9:e3b8:12ce:9fc5:74a5:c6bb:d8b:e08a".to_owned(),
                     port:               53987,
                     priority_override:   500,
@@ -315,6 +354,7 @@ fn test_candidate_marshal() -> Result<()> {
             Some(Candidate{
                     network_type:   NetworkType::Udp4,
                     candidate_type: CandidateType::Host,
+                    component: COMPONENT_RTP,
                     address:       "10.0.75.1".to_owned(),
                     port:          53634,
                 ..Default::default()
@@ -325,6 +365,7 @@ fn test_candidate_marshal() -> Result<()> {
             Some(Candidate{
                     network_type:    NetworkType::Udp4,
                     candidate_type:  CandidateType::ServerReflexive,
+                    component:  COMPONENT_RTP,
                     address:        "191.228.238.68".to_owned(),
                     port:           53991,
                     related_address: Some(CandidateRelatedAddress{
@@ -339,6 +380,7 @@ fn test_candidate_marshal() -> Result<()> {
             Some(Candidate{
                     network_type:   NetworkType::Udp4,
                     candidate_type:  CandidateType::Relay,
+                    component:  COMPONENT_RTP,
                     address:        "50.0.0.1".to_owned(),
                     port:           5000,
                     related_address: Some(
@@ -354,6 +396,7 @@ fn test_candidate_marshal() -> Result<()> {
             Some(Candidate{
                     network_type:   NetworkType::Tcp4,
                     candidate_type: CandidateType::Host,
+                    component: COMPONENT_RTP,
                     address:       "192.168.0.196".to_owned(),
                     port:          0,
                     tcp_type:       TcpType::Active,
@@ -361,6 +404,24 @@ fn test_candidate_marshal() -> Result<()> {
             }),
             "1052353102 1 tcp 2128609279 192.168.0.196 0 typ host tcptype active",
         ),
+        (
+            Some(Candidate{
+                    network_type:    NetworkType::Tcp4,
+                    candidate_type:  CandidateType::ServerReflexive,
+                    component:  COMPONENT_RTP,
+                    address:        "191.228.238.68".to_owned(),
+                    port:           9,
+                    related_address: Some(CandidateRelatedAddress{
+                        address: "192.168.0.1".to_owned(),
+                        port:9
+                    }),
+                    tcp_type:       TcpType::Passive,
+                    priority_override: 1694498815,
+                    foundation_override: "647372371".to_owned(),
+                ..Default::default()
+            }),
+            "647372371 1 tcp 1694498815 191.228.238.68 9 typ srflx tcptype passive raddr 192.168.0.1 rport 9",
+        ),
         /*TODO: mDNS (
             Some(Candidate{
                     network_type:   NetworkType::Udp4,
@@ -383,6 +444,7 @@ fn test_candidate_marshal() -> Result<()> {
         (None, "4207374051 1 udp INVALID 10.0.75.1 53634 typ host"),
         (None, "4207374051 INVALID udp 2130706431 10.0.75.1 INVALID typ host"),
         (None, "4207374051 1 udp 2130706431 10.0.75.1 53634 typ INVALID"),
+        (None, "4207374051 1 udp 1685790463 191.228.238.68 53991 typ srflx raddr 192.168.0.278 port 53991"),
     ];
 
     for (candidate, marshaled) in tests {
@@ -406,3 +468,174 @@ fn test_candidate_marshal() -> Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn test_unmarshal_candidate_attribute() -> Result<()> {
+    let tests = vec![
+        (
+            "candidate:750 1 udp 500 10.0.75.1 53987 typ host generation 0 network-id 1",
+            "750 1 udp 500 10.0.75.1 53987 typ host",
+        ),
+        (
+            "candidate:647372371 1 udp 1694498815 191.228.238.68 53991 typ srflx raddr 192.168.0.1 rport 53991 generation 0",
+            "647372371 1 udp 1694498815 191.228.238.68 53991 typ srflx raddr 192.168.0.1 rport 53991",
+        ),
+        (
+            "candidate:848194626 1 tcp 16777215 50.0.0.1 5000 typ relay raddr 192.168.0.1 rport 5001 tcptype active",
+            "848194626 1 tcp 16777215 50.0.0.1 5000 typ relay tcptype active raddr 192.168.0.1 rport 5001",
+        ),
+    ];
+
+    for (attribute, marshaled) in tests {
+        let candidate = unmarshal_candidate(attribute)?;
+        assert_eq!(marshaled, candidate.marshal());
+        assert!(candidate.equal(&unmarshal_candidate(&candidate.marshal())?));
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_candidate_json() -> Result<()> {
+    let candidate = unmarshal_candidate(
+        "647372371 1 udp 1694498815 191.228.238.68 53991 typ srflx raddr 192.168.0.1 rport 53991",
+    )?;
+
+    let init = candidate.marshal_json();
+    assert_eq!(init.candidate, format!("candidate:{}", candidate.marshal()));
+    assert!(candidate.equal(&unmarshal_candidate_json(&init)?));
+
+    Ok(())
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn test_candidate_init_serde() -> Result<()> {
+    let candidate = unmarshal_candidate(
+        "647372371 1 udp 1694498815 191.228.238.68 53991 typ srflx raddr 192.168.0.1 rport 53991",
+    )?;
+
+    let init = CandidateInit {
+        sdp_mid: Some("0".to_owned()),
+        sdp_mline_index: Some(0),
+        username_fragment: Some("ufrag".to_owned()),
+        ..candidate.marshal_json()
+    };
+    let json = serde_json::to_string(&init).unwrap();
+    assert_eq!(
+        json,
+        r#"{"candidate":"candidate:647372371 1 udp 1694498815 191.228.238.68 53991 typ srflx raddr 192.168.0.1 rport 53991","sdpMid":"0","sdpMLineIndex":0,"usernameFragment":"ufrag"}"#
+    );
+
+    // As sent by a browser, with null fields
+    let browser_json = r#"{"candidate":"candidate:647372371 1 udp 1694498815 191.228.238.68 53991 typ srflx raddr 192.168.0.1 rport 53991 generation 0","sdpMid":null,"sdpMLineIndex":null,"usernameFragment":null}"#;
+    let init: CandidateInit = serde_json::from_str(browser_json).unwrap();
+    assert_eq!(init.sdp_mid, None);
+    assert!(candidate.equal(&unmarshal_candidate_json(&init)?));
+
+    Ok(())
+}
+
+#[test]
+fn test_candidate_equal_ignores_transient_state() -> Result<()> {
+    let mut a = unmarshal_candidate("1 1 udp 2130706431 10.0.0.1 5000 typ host")?;
+    let mut b = unmarshal_candidate("2 1 udp 2130706430 10.0.0.1 5000 typ host")?;
+    let now = Instant::now();
+    a.set_last_received(now);
+    a.set_last_sent(now);
+    b.set_last_received(now + Duration::from_secs(5));
+    assert!(a.equal(&b));
+    assert!(b.equal(&a));
+
+    // A different identity is never equal
+    for other in [
+        "1 2 udp 2130706431 10.0.0.1 5000 typ host",
+        "1 1 tcp 2130706431 10.0.0.1 5000 typ host tcptype passive",
+        "1 1 udp 2130706431 10.0.0.2 5000 typ host",
+        "1 1 udp 2130706431 10.0.0.1 5001 typ host",
+        "1 1 udp 1694498815 10.0.0.1 5000 typ srflx raddr 0.0.0.0 rport 0",
+    ] {
+        assert!(!a.equal(&unmarshal_candidate(other)?), "{other}");
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_candidate_link_local_zone() -> Result<()> {
+    let host_candidate = |address: &str| {
+        CandidateHostConfig {
+            base_config: CandidateConfig {
+                network: "udp".to_owned(),
+                address: address.to_owned(),
+                port: 1000,
+                component: COMPONENT_RTP,
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+        .new_candidate_host()
+    };
+
+    let candidate = host_candidate("fe80::1%2")?;
+    assert_eq!(candidate.network_type(), NetworkType::Udp6);
+    assert_eq!(candidate.address(), "fe80::1");
+    assert_eq!(candidate.zone(), "2");
+    assert_eq!(
+        candidate.addr(),
+        SocketAddr::V6(SocketAddrV6::new("fe80::1".parse().unwrap(), 1000, 0, 2))
+    );
+    assert_eq!(
+        candidate.marshal(),
+        format!(
+            "{} 1 udp 2130706431 fe80::1 1000 typ host",
+            candidate.foundation()
+        )
+    );
+
+    // The remote link-local address is scoped to the zone of the local candidate
+    let remote = unmarshal_candidate("1 1 udp 2130706431 fe80::2 2000 typ host")?;
+    assert_eq!(
+        candidate.peer_addr(&remote),
+        SocketAddr::V6(SocketAddrV6::new("fe80::2".parse().unwrap(), 2000, 0, 2))
+    );
+    let global = unmarshal_candidate("1 1 udp 2130706431 2001:db8::2 2000 typ host")?;
+    assert_eq!(candidate.peer_addr(&global), global.addr());
+
+    // Named zones are resolved by the caller, the candidate is skipped without a resolver
+    assert_eq!(
+        host_candidate("fe80::1%eth0").err(),
+        Some(Error::ErrZoneResolveFailed)
+    );
+    let named_host_candidate = |address: &str| {
+        CandidateHostConfig {
+            base_config: CandidateConfig {
+                network: "udp".to_owned(),
+                address: address.to_owned(),
+                port: 1000,
+                component: COMPONENT_RTP,
+                zone_resolver: Some(Arc::new(|zone: &str| (zone == "eth0").then_some(3))),
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+        .new_candidate_host()
+    };
+    let named = named_host_candidate("fe80::1%eth0")?;
+    assert_eq!(named.zone(), "eth0");
+    assert_eq!(
+        named.addr(),
+        SocketAddr::V6(SocketAddrV6::new("fe80::1".parse().unwrap(), 1000, 0, 3))
+    );
+    assert_eq!(named_host_candidate("fe80::1%2")?.addr(), candidate.addr());
+    assert_eq!(
+        named_host_candidate("fe80::1%no-such-interface0").err(),
+        Some(Error::ErrZoneResolveFailed)
+    );
+    assert_eq!(
+        host_candidate("2001:db8::1%2").err(),
+        Some(Error::ErrAddressParseFailed)
+    );
+
+    Ok(())
+}
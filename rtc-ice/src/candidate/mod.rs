@@ -18,10 +18,13 @@ pub mod candidate_server_reflexive;
 use crate::network_type::NetworkType;
 use crate::tcp_type::TcpType;
 use crc::{Crc, CRC_32_ISCSI};
-use serde::Serialize;
+use log::warn;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 use shared::error::*;
 use std::fmt;
-use std::net::{IpAddr, SocketAddr};
+use std::net::{IpAddr, Ipv6Addr, SocketAddr, SocketAddrV6};
+use std::sync::Arc;
 use std::time::Instant;
 
 use crate::candidate::candidate_host::CandidateHostConfig;
@@ -36,20 +39,30 @@ pub(crate) const DEFAULT_LOCAL_PREFERENCE: u16 = 65535;
 /// Indicates that the candidate is used for RTP.
 pub(crate) const COMPONENT_RTP: u16 = 1;
 /// Indicates that the candidate is used for RTCP.
-pub(crate) const COMPONENT_RTCP: u16 = 0;
+pub(crate) const COMPONENT_RTCP: u16 = 2;
+
+// Component 0, the default of CandidateConfig, stands for the RTP component
+pub(crate) fn normalize_component(component: u16) -> u16 {
+    if component == 0 {
+        COMPONENT_RTP
+    } else {
+        component
+    }
+}
 
 /// Represents the type of candidate `CandidateType` enum.
-#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
 pub enum CandidateType {
-    #[serde(rename = "unspecified")]
+    #[cfg_attr(feature = "serde", serde(rename = "unspecified"))]
     Unspecified,
-    #[serde(rename = "host")]
+    #[cfg_attr(feature = "serde", serde(rename = "host"))]
     Host,
-    #[serde(rename = "srflx")]
+    #[cfg_attr(feature = "serde", serde(rename = "srflx"))]
     ServerReflexive,
-    #[serde(rename = "prflx")]
+    #[cfg_attr(feature = "serde", serde(rename = "prflx"))]
     PeerReflexive,
-    #[serde(rename = "relay")]
+    #[cfg_attr(feature = "serde", serde(rename = "relay"))]
     Relay,
 }
 
@@ -129,8 +142,27 @@ pub struct CandidateConfig {
     pub component: u16,
     pub priority: u32,
     pub foundation: String,
+    /// Overrides the local preference of the priority formula (default 65535 for UDP), e.g. to
+    /// prefer one interface of a multihomed host over another. It has no effect when
+    /// `priority` is set, since that replaces the computed priority altogether.
+    pub local_preference: Option<u16>,
+    /// Identifies the network of the candidate in the NETWORK-COST attribute.
+    pub network_id: u16,
+    /// Cost of the network of the candidate sent in the NETWORK-COST attribute, higher for
+    /// e.g. cellular than Wi-Fi. 0 means unknown.
+    pub network_cost: u16,
+    /// Resolves an interface name used as the zone of a link-local IPv6 address, e.g. "eth0"
+    /// in "fe80::1%eth0", to its index. Numeric zones are used as the index as is, and without
+    /// a resolver a candidate with a named zone is skipped.
+    pub zone_resolver: Option<ZoneResolverFn>,
 }
 
+/// Sends a STUN packet from a candidate's socket to the given remote address.
+pub type CandidateWriterFn = Arc<dyn Fn(&[u8], SocketAddr) -> Result<()> + Send + Sync>;
+
+/// Resolves an interface name to its index, or None if there is no such interface.
+pub type ZoneResolverFn = Arc<dyn Fn(&str) -> Option<u32> + Send + Sync>;
+
 #[derive(Clone)]
 pub struct Candidate {
     pub(crate) id: String,
@@ -139,6 +171,7 @@ pub struct Candidate {
 
     pub(crate) component: u16,
     pub(crate) address: String,
+    pub(crate) zone: String,
     pub(crate) port: u16,
     pub(crate) related_address: Option<CandidateRelatedAddress>,
     pub(crate) tcp_type: TcpType,
@@ -147,11 +180,18 @@ pub struct Candidate {
 
     pub(crate) last_sent: Instant,
     pub(crate) last_received: Instant,
+    pub(crate) added_at: Instant,
 
     pub(crate) foundation_override: String,
     pub(crate) priority_override: u32,
+    pub(crate) local_preference_override: Option<u16>,
 
     pub(crate) network: String,
+    pub(crate) network_id: u16,
+    pub(crate) network_cost: u16,
+
+    // sends the STUN packets of the candidate instead of the agent's transmit queue
+    pub(crate) writer: Option<CandidateWriterFn>,
 }
 
 impl Default for Candidate {
@@ -163,6 +203,7 @@ impl Default for Candidate {
 
             component: 0,
             address: String::new(),
+            zone: String::new(),
             port: 0,
             related_address: None,
             tcp_type: TcpType::default(),
@@ -171,10 +212,16 @@ impl Default for Candidate {
 
             last_sent: Instant::now(),
             last_received: Instant::now(),
+            added_at: Instant::now(),
 
             foundation_override: String::new(),
             priority_override: 0,
+            local_preference_override: None,
             network: String::new(),
+            network_id: 0,
+            network_cost: 0,
+
+            writer: None,
         }
     }
 }
@@ -256,6 +303,12 @@ impl Candidate {
         self.address.as_str()
     }
 
+    /// Returns the zone of a link-local IPv6 candidate address, e.g. "eth0" for
+    /// "fe80::1%eth0", or an empty string. It is not part of the marshaled candidate.
+    pub fn zone(&self) -> &str {
+        self.zone.as_str()
+    }
+
     /// Returns Candidate Port.
     pub fn port(&self) -> u16 {
         self.port
@@ -292,6 +345,15 @@ impl Candidate {
         self.tcp_type
     }
 
+    /// Returns the JSON (RTCIceCandidateInit) form of the ICECandidate used by browsers for
+    /// signaling. The SDP media fields and the username fragment are left for the caller to set.
+    pub fn marshal_json(&self) -> CandidateInit {
+        CandidateInit {
+            candidate: format!("candidate:{}", self.marshal()),
+            ..Default::default()
+        }
+    }
+
     /// Returns the string representation of the ICECandidate.
     pub fn marshal(&self) -> String {
         let mut val = format!(
@@ -324,6 +386,26 @@ impl Candidate {
         self.resolved_addr
     }
 
+    /// Returns the address to send to the remote candidate from this local candidate. A
+    /// link-local IPv6 remote address is scoped to the zone of this candidate, since the
+    /// remote agent can't tell on which interface it is reached.
+    pub fn peer_addr(&self, remote: &Candidate) -> SocketAddr {
+        with_zone_of(self.addr(), remote.addr())
+    }
+
+    /// Sets a callback sending the STUN packets and application data of this local candidate
+    /// through the application's own socket, for embedding the agent in a custom I/O loop.
+    /// Such packets are written directly instead of being returned by `Agent::poll_transmit`.
+    pub fn set_writer(
+        &mut self,
+        writer: impl Fn(&[u8], SocketAddr) -> Result<()> + Send + Sync + 'static,
+    ) {
+        self.writer = Some(Arc::new(writer));
+    }
+
+    /// Records activity on the candidate: `outbound` updates `last_sent`, when a packet was
+    /// successfully sent from this local candidate, otherwise `last_received` is updated, when
+    /// a packet was received from this remote candidate.
     pub fn seen(&mut self, outbound: bool) {
         let now = Instant::now();
 
@@ -334,11 +416,16 @@ impl Candidate {
         }
     }
 
-    /// Used to compare two candidateBases.
+    /// Used to compare two candidateBases. Only the identity of the candidates is compared:
+    /// type, component, transport, address and related address. Transient state such as
+    /// `last_sent`/`last_received` and the signaled id, foundation and priority are ignored,
+    /// so re-adding a known candidate is recognized as a duplicate.
     pub fn equal(&self, other: &Candidate) -> bool {
         self.network_type() == other.network_type()
             && self.candidate_type() == other.candidate_type()
+            && self.component() == other.component()
             && self.address() == other.address()
+            && self.zone() == other.zone()
             && self.port() == other.port()
             && self.tcp_type() == other.tcp_type()
             && self.related_address() == other.related_address()
@@ -354,8 +441,23 @@ impl Candidate {
         self.last_sent = now;
     }
 
+    /// Returns the network id of the candidate, see `CandidateConfig::network_id`.
+    pub fn network_id(&self) -> u16 {
+        self.network_id
+    }
+
+    /// Returns the network cost of the candidate, see `CandidateConfig::network_cost`.
+    /// For a remote candidate, it is learned from the NETWORK-COST attribute of its checks.
+    pub fn network_cost(&self) -> u16 {
+        self.network_cost
+    }
+
     /// Returns the local preference for this candidate.
     pub fn local_preference(&self) -> u16 {
+        if let Some(local_preference) = self.local_preference_override {
+            return local_preference;
+        }
+
         if self.network_type().is_tcp() {
             // RFC 6544, section 4.2
             //
@@ -419,8 +521,96 @@ impl Candidate {
     }
 }
 
-/// Creates a Candidate from its string representation.
+fn is_link_local(ip: &Ipv6Addr) -> bool {
+    (ip.segments()[0] & 0xffc0) == 0xfe80
+}
+
+// Resolves the zone of a link-local IPv6 address, an interface index or name, to its scope id
+fn resolve_zone(zone: &str, zone_resolver: Option<&ZoneResolverFn>) -> Option<u32> {
+    match zone.parse::<u32>() {
+        Ok(scope_id) => Some(scope_id),
+        Err(_) => zone_resolver.and_then(|zone_resolver| zone_resolver(zone)),
+    }
+}
+
+// Parses a candidate address, which may carry the zone of a link-local IPv6 address, e.g.
+// "fe80::1%eth0". Returns the address without the zone, the zone and the socket address
+// scoped to it.
+pub(crate) fn parse_scoped_address(
+    address: &str,
+    port: u16,
+    zone_resolver: Option<&ZoneResolverFn>,
+) -> Result<(String, String, SocketAddr)> {
+    let Some((ip, zone)) = address.split_once('%') else {
+        let ip: IpAddr = address.parse().map_err(|_| Error::ErrAddressParseFailed)?;
+        return Ok((address.to_owned(), String::new(), SocketAddr::new(ip, port)));
+    };
+
+    let ip: Ipv6Addr = ip.parse().map_err(|_| Error::ErrAddressParseFailed)?;
+    if !is_link_local(&ip) {
+        return Err(Error::ErrAddressParseFailed);
+    }
+    let Some(scope_id) = resolve_zone(zone, zone_resolver) else {
+        warn!("skipping candidate {address}: unknown zone {zone}");
+        return Err(Error::ErrZoneResolveFailed);
+    };
+
+    Ok((
+        ip.to_string(),
+        zone.to_owned(),
+        SocketAddr::V6(SocketAddrV6::new(ip, port, 0, scope_id)),
+    ))
+}
+
+// Scopes a link-local IPv6 peer address to the zone of the local address it is sent from
+pub(crate) fn with_zone_of(local_addr: SocketAddr, peer_addr: SocketAddr) -> SocketAddr {
+    match (local_addr, peer_addr) {
+        (SocketAddr::V6(local_addr), SocketAddr::V6(mut peer_addr))
+            if peer_addr.scope_id() == 0 && is_link_local(peer_addr.ip()) =>
+        {
+            peer_addr.set_scope_id(local_addr.scope_id());
+            SocketAddr::V6(peer_addr)
+        }
+        _ => peer_addr,
+    }
+}
+
+// Drops the zone of a peer address received on a local candidate, the remote candidates
+// don't carry one
+pub(crate) fn without_zone(peer_addr: SocketAddr) -> SocketAddr {
+    match peer_addr {
+        SocketAddr::V6(mut peer_addr) if peer_addr.scope_id() != 0 => {
+            peer_addr.set_scope_id(0);
+            SocketAddr::V6(peer_addr)
+        }
+        _ => peer_addr,
+    }
+}
+
+/// CandidateInit is the JSON form of a candidate exchanged with browsers,
+/// as indicated by the spec <https://w3c.github.io/webrtc-pc/#dom-rtcicecandidateinit>
+#[derive(Default, Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "camelCase"))]
+pub struct CandidateInit {
+    /// The SDP `candidate:` attribute value
+    pub candidate: String,
+    pub sdp_mid: Option<String>,
+    #[cfg_attr(feature = "serde", serde(rename = "sdpMLineIndex"))]
+    pub sdp_mline_index: Option<u16>,
+    pub username_fragment: Option<String>,
+}
+
+/// Creates a Candidate from its JSON form, as produced by `Candidate::marshal_json`.
+pub fn unmarshal_candidate_json(init: &CandidateInit) -> Result<Candidate> {
+    unmarshal_candidate(&init.candidate)
+}
+
+/// Creates a Candidate from its string representation, as produced by `Candidate::marshal`.
+/// The `candidate:` prefix of the SDP attribute form is accepted and stripped.
 pub fn unmarshal_candidate(raw: &str) -> Result<Candidate> {
+    let raw = raw.trim_start();
+    let raw = raw.strip_prefix("candidate:").unwrap_or(raw);
     let split: Vec<&str> = raw.split_whitespace().collect();
     if split.len() < 8 {
         return Err(Error::Other(format!(
@@ -454,35 +644,43 @@ pub fn unmarshal_candidate(raw: &str) -> Result<Candidate> {
     let mut rel_port = 0;
     let mut tcp_type = TcpType::Unspecified;
 
-    if split.len() > 8 {
-        let split2 = &split[8..];
+    // Extension attributes are key/value pairs and may come in any order,
+    // e.g. "raddr <addr> rport <port> tcptype <type> generation 0"
+    let mut split2 = &split[8..];
+    while !split2.is_empty() {
+        match split2[0] {
+            "raddr" => {
+                if split2.len() < 4 || split2[2] != "rport" {
+                    return Err(Error::Other(format!(
+                        "{:?}: incorrect length",
+                        Error::ErrParseRelatedAddr
+                    )));
+                }
+
+                // RelatedAddress
+                split2[1].clone_into(&mut rel_addr);
 
-        if split2[0] == "raddr" {
-            if split2.len() < 4 {
-                return Err(Error::Other(format!(
-                    "{:?}: incorrect length",
-                    Error::ErrParseRelatedAddr
-                )));
+                // RelatedPort
+                rel_port = split2[3].parse()?;
+                split2 = &split2[4..];
             }
+            "tcptype" => {
+                if split2.len() < 2 {
+                    return Err(Error::Other(format!(
+                        "{:?}: incorrect length",
+                        Error::ErrParseType
+                    )));
+                }
 
-            // RelatedAddress
-            split2[1].clone_into(&mut rel_addr);
-
-            // RelatedPort
-            rel_port = split2[3].parse()?;
-        } else if split2[0] == "tcptype" {
-            if split2.len() < 2 {
-                return Err(Error::Other(format!(
-                    "{:?}: incorrect length",
-                    Error::ErrParseType
-                )));
+                tcp_type = TcpType::from(split2[1]);
+                split2 = &split2[2..];
             }
-
-            tcp_type = TcpType::from(split2[1]);
+            // Unknown extensions (generation, network-id, ufrag, ...) are ignored
+            _ => split2 = &split2[split2.len().min(2)..],
         }
     }
 
-    match typ {
+    let mut candidate = match typ {
         "host" => {
             let config = CandidateHostConfig {
                 base_config: CandidateConfig {
@@ -552,5 +750,10 @@ pub fn unmarshal_candidate(raw: &str) -> Result<Candidate> {
             Error::ErrUnknownCandidateType,
             typ
         ))),
-    }
+    }?;
+
+    // Only the host config takes a tcp type, but TCP srflx/relay candidates carry one too
+    candidate.tcp_type = tcp_type;
+
+    Ok(candidate)
 }
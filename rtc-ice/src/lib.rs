@@ -6,6 +6,7 @@ pub mod attributes;
 pub mod candidate;
 pub mod network_type;
 pub mod rand;
+pub mod role;
 pub mod state;
 pub mod stats;
 pub mod tcp_type;
@@ -14,5 +15,5 @@ pub mod url;
 pub use agent::{
     agent_config::AgentConfig,
     agent_stats::{CandidatePairStats, CandidateStats},
-    Agent, Credentials, Event,
+    Agent, Credentials, Event, StunPacketDirection,
 };
@@ -1,7 +1,8 @@
 #[cfg(test)]
 mod rand_test;
 
-use rand::{thread_rng, Rng};
+use rand::{thread_rng, Rng, RngCore};
+use stun::message::TransactionId;
 
 const RUNES_ALPHA: &[u8] = b"abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ";
 const RUNES_CANDIDATE_ID_FOUNDATION: &[u8] =
@@ -12,8 +13,15 @@ const LEN_PWD: usize = 32;
 
 //TODO: generates a random string for cryptographic usage.
 pub fn generate_crypto_random_string(n: usize, runes: &[u8]) -> String {
-    let mut rng = thread_rng();
+    generate_crypto_random_string_with_rng(&mut thread_rng(), n, runes)
+}
 
+/// Same as `generate_crypto_random_string`, but draws from the given rng.
+pub fn generate_crypto_random_string_with_rng(
+    rng: &mut dyn RngCore,
+    n: usize,
+    runes: &[u8],
+) -> String {
     let rand_string: String = (0..n)
         .map(|_| {
             let idx = rng.gen_range(0..runes.len());
@@ -29,20 +37,42 @@ pub fn generate_crypto_random_string(n: usize, runes: &[u8]) -> String {
 /// foundation   = 1*32ice-char
 /// ice-char     = ALPHA / DIGIT / "+" / "/"
 pub fn generate_cand_id() -> String {
+    generate_cand_id_with_rng(&mut thread_rng())
+}
+
+/// Same as `generate_cand_id`, but draws from the given rng.
+pub fn generate_cand_id_with_rng(rng: &mut dyn RngCore) -> String {
     format!(
         "candidate:{}",
-        generate_crypto_random_string(32, RUNES_CANDIDATE_ID_FOUNDATION)
+        generate_crypto_random_string_with_rng(rng, 32, RUNES_CANDIDATE_ID_FOUNDATION)
     )
 }
 
 /// Generates ICE pwd.
 /// This internally uses `generate_crypto_random_string`.
 pub fn generate_pwd() -> String {
-    generate_crypto_random_string(LEN_PWD, RUNES_ALPHA)
+    generate_pwd_with_rng(&mut thread_rng())
+}
+
+/// Same as `generate_pwd`, but draws from the given rng.
+pub fn generate_pwd_with_rng(rng: &mut dyn RngCore) -> String {
+    generate_crypto_random_string_with_rng(rng, LEN_PWD, RUNES_ALPHA)
 }
 
 /// ICE user fragment.
 /// This internally uses `generate_crypto_random_string`.
 pub fn generate_ufrag() -> String {
-    generate_crypto_random_string(LEN_UFRAG, RUNES_ALPHA)
+    generate_ufrag_with_rng(&mut thread_rng())
+}
+
+/// Same as `generate_ufrag`, but draws from the given rng.
+pub fn generate_ufrag_with_rng(rng: &mut dyn RngCore) -> String {
+    generate_crypto_random_string_with_rng(rng, LEN_UFRAG, RUNES_ALPHA)
+}
+
+/// STUN transaction ID drawn from the given rng, see `TransactionId::new`.
+pub fn generate_transaction_id_with_rng(rng: &mut dyn RngCore) -> TransactionId {
+    let mut transaction_id = TransactionId::default();
+    rng.fill_bytes(&mut transaction_id.0);
+    transaction_id
 }
@@ -0,0 +1,24 @@
+#[cfg(test)]
+mod role_test;
+
+use std::fmt;
+
+/// The role an ICE agent plays in selecting the candidate pair, as described in
+/// <https://tools.ietf.org/html/rfc8445#section-2.2>.
+#[derive(PartialEq, Eq, Debug, Copy, Clone)]
+pub enum IceRole {
+    /// The agent that nominates the candidate pair used for media.
+    Controlling,
+    /// The agent that waits for the controlling agent to nominate a candidate pair.
+    Controlled,
+}
+
+impl fmt::Display for IceRole {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match *self {
+            Self::Controlling => "controlling",
+            Self::Controlled => "controlled",
+        };
+        write!(f, "{s}")
+    }
+}
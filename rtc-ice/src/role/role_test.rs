@@ -0,0 +1,7 @@
+use super::*;
+
+#[test]
+fn test_ice_role_string() {
+    assert_eq!(IceRole::Controlling.to_string(), "controlling");
+    assert_eq!(IceRole::Controlled.to_string(), "controlled");
+}
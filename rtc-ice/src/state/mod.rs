@@ -52,6 +52,23 @@ impl fmt::Display for ConnectionState {
     }
 }
 
+impl ConnectionState {
+    /// Returns whether the ICE state machine allows moving from this state to `next`. Closed is
+    /// final, and a failed agent only comes back to life through a restart, which moves it to
+    /// Checking.
+    pub fn can_transition_to(self, next: Self) -> bool {
+        match (self, next) {
+            (Self::Closed, _) | (_, Self::Unspecified) | (_, Self::New) => false,
+            (_, Self::Closed) => true,
+            (Self::Unspecified, _) => true,
+            (Self::New, next) => next == Self::Checking,
+            (Self::Failed, next) => next == Self::Checking,
+            (Self::Checking, next) => next != Self::Disconnected,
+            (Self::Connected | Self::Completed | Self::Disconnected, _) => true,
+        }
+    }
+}
+
 impl From<u8> for ConnectionState {
     fn from(v: u8) -> Self {
         match v {
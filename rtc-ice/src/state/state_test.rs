@@ -25,6 +25,47 @@ fn test_connected_state_string() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_connection_state_transitions() {
+    let tests = vec![
+        (ConnectionState::New, ConnectionState::Checking, true),
+        (ConnectionState::New, ConnectionState::Connected, false),
+        (ConnectionState::New, ConnectionState::Closed, true),
+        (ConnectionState::Checking, ConnectionState::Connected, true),
+        (ConnectionState::Checking, ConnectionState::Failed, true),
+        (
+            ConnectionState::Checking,
+            ConnectionState::Disconnected,
+            false,
+        ),
+        (
+            ConnectionState::Connected,
+            ConnectionState::Disconnected,
+            true,
+        ),
+        (ConnectionState::Connected, ConnectionState::Checking, true),
+        (
+            ConnectionState::Disconnected,
+            ConnectionState::Connected,
+            true,
+        ),
+        (ConnectionState::Failed, ConnectionState::Connected, false),
+        (ConnectionState::Failed, ConnectionState::Checking, true),
+        (ConnectionState::Failed, ConnectionState::Closed, true),
+        (ConnectionState::Closed, ConnectionState::Connected, false),
+        (ConnectionState::Closed, ConnectionState::Checking, false),
+        (ConnectionState::Connected, ConnectionState::New, false),
+    ];
+
+    for (from, to, expected) in tests {
+        assert_eq!(
+            from.can_transition_to(to),
+            expected,
+            "testCase: {from} -> {to}"
+        );
+    }
+}
+
 #[test]
 fn test_gathering_state_string() -> Result<()> {
     let tests = vec![
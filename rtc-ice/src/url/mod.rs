@@ -115,7 +115,8 @@ pub struct Url {
 
 impl fmt::Display for Url {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let host = if self.host.contains("::") {
+        // IPv6 literals are the only hosts containing ':' and must be bracketed
+        let host = if self.host.contains(':') {
             "[".to_owned() + self.host.as_str() + "]"
         } else {
             self.host.clone()
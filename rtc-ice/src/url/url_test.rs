@@ -75,6 +75,42 @@ fn test_parse_url_success() -> Result<()> {
             5349,
             ProtoType::Tcp,
         ),
+        (
+            "turns:google.de:443?transport=tcp",
+            "turns:google.de:443?transport=tcp",
+            SchemeType::Turns,
+            true,
+            "google.de",
+            443,
+            ProtoType::Tcp,
+        ),
+        (
+            "turn:google.de:3479?transport=tcp",
+            "turn:google.de:3479?transport=tcp",
+            SchemeType::Turn,
+            false,
+            "google.de",
+            3479,
+            ProtoType::Tcp,
+        ),
+        (
+            "turn:[2001:db8::1]:3478?transport=udp",
+            "turn:[2001:db8::1]:3478?transport=udp",
+            SchemeType::Turn,
+            false,
+            "2001:db8::1",
+            3478,
+            ProtoType::Udp,
+        ),
+        (
+            "turns:[2001:db8:1:2:3:4:5:6]:5350",
+            "turns:[2001:db8:1:2:3:4:5:6]:5350?transport=tcp",
+            SchemeType::Turns,
+            true,
+            "2001:db8:1:2:3:4:5:6",
+            5350,
+            ProtoType::Tcp,
+        ),
     ];
 
     for (
@@ -123,6 +159,8 @@ fn test_parse_url_failure() -> Result<()> {
             Error::ErrInvalidQuery,
         ),
         ("turn:google.de?transport=ip", Error::ErrProtoType),
+        ("http:google.de", Error::ErrSchemeType),
+        ("turnx:google.de:3478", Error::ErrSchemeType),
     ];
 
     for (raw_url, expected_err) in tests {
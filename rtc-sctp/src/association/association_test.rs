@@ -318,7 +318,7 @@ fn handle_init_test(name: &str, initial_state: AssociationState, expect_err: boo
     };
     init.set_supported_extensions();
 
-    let result = a.handle_init(&pkt, &init);
+    let result = a.handle_init(&pkt, &init, Instant::now());
     if expect_err {
         assert!(result.is_err(), "{} should fail", name);
         return;
@@ -388,6 +388,80 @@ fn test_assoc_handle_init() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_assoc_handle_cookie_echo() -> Result<()> {
+    let mut a = create_association(
+        TransportConfig::default().with_cookie_lifetime(Duration::from_secs(60)),
+    );
+    let pkt = Packet {
+        common_header: CommonHeader {
+            source_port: 5001,
+            destination_port: 5002,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let mut init = ChunkInit {
+        initial_tsn: 1234,
+        num_outbound_streams: 1001,
+        num_inbound_streams: 1002,
+        initiate_tag: 5678,
+        advertised_receiver_window_credit: 512 * 1024,
+        ..Default::default()
+    };
+    init.set_supported_extensions();
+
+    let cookie_of = |packets: Vec<Packet>| -> Bytes {
+        let init_ack = packets[0].chunks[0]
+            .as_any()
+            .downcast_ref::<ChunkInit>()
+            .expect("should be an INIT ACK");
+        init_ack
+            .params
+            .iter()
+            .find_map(|p| p.as_any().downcast_ref::<ParamStateCookie>())
+            .expect("should carry a state cookie")
+            .cookie
+            .clone()
+    };
+    let now = Instant::now();
+    a.set_state(AssociationState::Closed);
+    let cookie = cookie_of(a.handle_init(&pkt, &init, now)?);
+
+    // A forged cookie is rejected
+    let mut forged = cookie.to_vec();
+    forged[8] ^= 0xff;
+    let echo = ChunkCookieEcho {
+        cookie: Bytes::from(forged),
+    };
+    assert!(a.handle_cookie_echo(&echo, now)?.is_empty());
+    assert_eq!(a.state(), AssociationState::Closed);
+
+    // So is a cookie older than its lifetime
+    let echo = ChunkCookieEcho {
+        cookie: cookie.clone(),
+    };
+    let later = now + Duration::from_secs(61);
+    assert!(a.handle_cookie_echo(&echo, later)?.is_empty());
+    assert_eq!(a.state(), AssociationState::Closed);
+
+    // A retransmitted INIT gets a fresh cookie, which is accepted
+    let fresh_cookie = cookie_of(a.handle_init(&pkt, &init, later)?);
+    assert_ne!(fresh_cookie, cookie);
+    let echo = ChunkCookieEcho {
+        cookie: fresh_cookie,
+    };
+    let packets = a.handle_cookie_echo(&echo, later + Duration::from_secs(1))?;
+    assert_eq!(packets.len(), 1);
+    assert!(packets[0].chunks[0]
+        .as_any()
+        .downcast_ref::<ChunkCookieAck>()
+        .is_some());
+    assert_eq!(a.state(), AssociationState::Established);
+
+    Ok(())
+}
+
 #[test]
 fn test_assoc_max_message_size_default() -> Result<()> {
     let mut a = create_association(TransportConfig::default().with_max_message_size(65536));
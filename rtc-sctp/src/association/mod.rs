@@ -10,17 +10,20 @@ use crate::chunk::{
     chunk_payload_data::ChunkPayloadData, chunk_payload_data::PayloadProtocolIdentifier,
     chunk_reconfig::ChunkReconfig, chunk_selective_ack::ChunkSelectiveAck,
     chunk_shutdown::ChunkShutdown, chunk_shutdown_ack::ChunkShutdownAck,
-    chunk_shutdown_complete::ChunkShutdownComplete, chunk_type::CT_FORWARD_TSN, Chunk,
-    ErrorCauseUnrecognizedChunkType, USER_INITIATED_ABORT,
+    chunk_shutdown_complete::ChunkShutdownComplete, Chunk, ErrorCauseUnrecognizedChunkType,
+    USER_INITIATED_ABORT,
+};
+use crate::config::{
+    ServerConfig, TransportConfig, COMMON_HEADER_SIZE, DATA_CHUNK_HEADER_SIZE,
+    DEFAULT_COOKIE_LIFETIME,
 };
-use crate::config::{ServerConfig, TransportConfig, COMMON_HEADER_SIZE, DATA_CHUNK_HEADER_SIZE};
 use crate::packet::{CommonHeader, Packet};
 use crate::param::{
+    negotiate_extensions,
     param_heartbeat_info::ParamHeartbeatInfo,
     param_outgoing_reset_request::ParamOutgoingResetRequest,
     param_reconfig_response::{ParamReconfigResponse, ReconfigResult},
     param_state_cookie::ParamStateCookie,
-    param_supported_extensions::ParamSupportedExtensions,
     Param,
 };
 use crate::queue::{payload_queue::PayloadQueue, pending_queue::PendingQueue};
@@ -154,6 +157,10 @@ pub struct Association {
     my_max_num_inbound_streams: u16,
     my_max_num_outbound_streams: u16,
     my_cookie: Option<ParamStateCookie>,
+    cookie_key: [u8; 32],
+    cookie_lifetime: Duration,
+    // origin of the timestamps carried by the state cookies
+    cookie_epoch: Option<Instant>,
 
     payload_queue: PayloadQueue,
     inflight_queue: PayloadQueue,
@@ -243,6 +250,9 @@ impl Default for Association {
             my_max_num_inbound_streams: 0,
             my_max_num_outbound_streams: 0,
             my_cookie: None,
+            cookie_key: [0; 32],
+            cookie_lifetime: DEFAULT_COOKIE_LIFETIME,
+            cookie_epoch: None,
 
             payload_queue: PayloadQueue::default(),
             inflight_queue: PayloadQueue::default(),
@@ -333,6 +343,9 @@ impl Association {
             my_max_num_outbound_streams: config.max_num_outbound_streams(),
             my_max_num_inbound_streams: config.max_num_inbound_streams(),
             max_payload_size,
+            cookie_key: random(),
+            cookie_lifetime: config.cookie_lifetime(),
+            cookie_epoch: Some(now),
 
             rto_mgr: RtoManager::new(),
             timers: TimerTable::new(config.timer_config()),
@@ -560,6 +573,13 @@ impl Association {
         self.remote_addr
     }
 
+    /// Whether both sides support Forward TSN (RFC 3758), as negotiated during the handshake.
+    /// Without it, streams can only be reliable: partially reliable data channels, with
+    /// `maxRetransmits` or `maxPacketLifeTime`, need it to abandon messages.
+    pub fn use_forward_tsn(&self) -> bool {
+        self.use_forward_tsn
+    }
+
     /// Current best estimate of this Association's latency (round-trip-time)
     pub fn rtt(&self) -> Duration {
         Duration::from_millis(self.rto_mgr.get_rto())
@@ -829,7 +849,7 @@ impl Association {
             if c.is_ack {
                 self.handle_init_ack(p, c, now)?
             } else {
-                self.handle_init(p, c)?
+                self.handle_init(p, c, now)?
             }
         } else if let Some(c) = chunk_any.downcast_ref::<ChunkAbort>() {
             let mut err_str = String::new();
@@ -851,7 +871,7 @@ impl Association {
         } else if let Some(c) = chunk_any.downcast_ref::<ChunkHeartbeat>() {
             self.handle_heartbeat(c)?
         } else if let Some(c) = chunk_any.downcast_ref::<ChunkCookieEcho>() {
-            self.handle_cookie_echo(c)?
+            self.handle_cookie_echo(c, now)?
         } else if chunk_any.downcast_ref::<ChunkCookieAck>().is_some() {
             self.handle_cookie_ack()?
         } else if let Some(c) = chunk_any.downcast_ref::<ChunkPayloadData>() {
@@ -881,7 +901,7 @@ impl Association {
         Ok(())
     }
 
-    fn handle_init(&mut self, p: &Packet, i: &ChunkInit) -> Result<Vec<Packet>> {
+    fn handle_init(&mut self, p: &Packet, i: &ChunkInit, now: Instant) -> Result<Vec<Packet>> {
         let state = self.state();
         debug!("[{}] chunkInit received in state '{}'", self.side, state);
 
@@ -920,17 +940,15 @@ impl Association {
             i.initial_tsn - 1
         };
 
-        for param in &i.params {
-            if let Some(v) = param.as_any().downcast_ref::<ParamSupportedExtensions>() {
-                for t in &v.chunk_types {
-                    if *t == CT_FORWARD_TSN {
-                        debug!("[{}] use ForwardTSN (on init)", self.side);
-                        self.use_forward_tsn = true;
-                    }
-                }
-            }
-        }
-        if !self.use_forward_tsn {
+        let (use_forward_tsn, supported_extensions) = negotiate_extensions(&i.params);
+        debug!(
+            "[{}] supported extensions (on init): {:?}",
+            self.side, supported_extensions
+        );
+        self.use_forward_tsn = use_forward_tsn;
+        if self.use_forward_tsn {
+            debug!("[{}] use ForwardTSN (on init)", self.side);
+        } else {
             warn!("[{}] not using ForwardTSN (on init)", self.side);
         }
 
@@ -953,9 +971,14 @@ impl Association {
             ..Default::default()
         };
 
-        if self.my_cookie.is_none() {
-            self.my_cookie = Some(ParamStateCookie::new());
-        }
+        // A fresh cookie is made for every INIT, so it carries the parameters of this INIT and
+        // doesn't outlive the cookie lifetime across retransmitted INITs
+        let created_at = now.saturating_duration_since(*self.cookie_epoch.get_or_insert(now));
+        self.my_cookie = Some(ParamStateCookie::new(
+            &self.cookie_key,
+            &self.cookie_tcb(),
+            created_at,
+        )?);
 
         if let Some(my_cookie) = &self.my_cookie {
             init_ack.params = vec![Box::new(my_cookie.clone())];
@@ -1022,20 +1045,19 @@ impl Association {
         self.timers.stop(Timer::T1Init);
         self.stored_init = None;
 
-        let mut cookie_param = None;
-        for param in &i.params {
-            if let Some(v) = param.as_any().downcast_ref::<ParamStateCookie>() {
-                cookie_param = Some(v);
-            } else if let Some(v) = param.as_any().downcast_ref::<ParamSupportedExtensions>() {
-                for t in &v.chunk_types {
-                    if *t == CT_FORWARD_TSN {
-                        debug!("[{}] use ForwardTSN (on initAck)", self.side);
-                        self.use_forward_tsn = true;
-                    }
-                }
-            }
-        }
-        if !self.use_forward_tsn {
+        let cookie_param = i
+            .params
+            .iter()
+            .find_map(|param| param.as_any().downcast_ref::<ParamStateCookie>());
+        let (use_forward_tsn, supported_extensions) = negotiate_extensions(&i.params);
+        debug!(
+            "[{}] supported extensions (on initAck): {:?}",
+            self.side, supported_extensions
+        );
+        self.use_forward_tsn = use_forward_tsn;
+        if self.use_forward_tsn {
+            debug!("[{}] use ForwardTSN (on initAck)", self.side);
+        } else {
             warn!("[{}] not using ForwardTSN (on initAck)", self.side);
         }
 
@@ -1084,7 +1106,7 @@ impl Association {
         Ok(vec![])
     }
 
-    fn handle_cookie_echo(&mut self, c: &ChunkCookieEcho) -> Result<Vec<Packet>> {
+    fn handle_cookie_echo(&mut self, c: &ChunkCookieEcho, now: Instant) -> Result<Vec<Packet>> {
         let state = self.state();
         debug!("[{}] COOKIE-ECHO received in state '{}'", self.side, state);
 
@@ -1098,7 +1120,9 @@ impl Association {
                 AssociationState::Closed
                 | AssociationState::CookieWait
                 | AssociationState::CookieEchoed => {
-                    if my_cookie.cookie != c.cookie {
+                    // https://tools.ietf.org/html/rfc4960#section-5.1.5
+                    if let Err(err) = self.verify_cookie(c, now) {
+                        debug!("[{}] COOKIE-ECHO rejected: {}", self.side, err);
                         return Ok(vec![]);
                     }
 
@@ -1129,6 +1153,32 @@ impl Association {
         }])
     }
 
+    /// The TCB state of a cookie is made of the verification tags of both sides and the
+    /// initial TSN of this side.
+    fn cookie_tcb(&self) -> Vec<u8> {
+        let mut tcb = Vec::with_capacity(12);
+        tcb.extend_from_slice(&self.my_verification_tag.to_be_bytes());
+        tcb.extend_from_slice(&self.peer_verification_tag.to_be_bytes());
+        tcb.extend_from_slice(&self.my_next_tsn.to_be_bytes());
+        tcb
+    }
+
+    /// Checks the cookie echoed by the peer: its HMAC, its lifetime, and that it was made for
+    /// this association.
+    fn verify_cookie(&self, c: &ChunkCookieEcho, now: Instant) -> Result<()> {
+        let cookie = ParamStateCookie {
+            cookie: c.cookie.clone(),
+        };
+        cookie.verify(&self.cookie_key)?;
+        let epoch = self.cookie_epoch.unwrap_or(now);
+        cookie.check_lifetime(now.saturating_duration_since(epoch), self.cookie_lifetime)?;
+        if cookie.tcb() != self.cookie_tcb().as_slice() {
+            return Err(Error::ErrStateCookieInvalid);
+        }
+
+        Ok(())
+    }
+
     fn handle_cookie_ack(&mut self) -> Result<Vec<Packet>> {
         let state = self.state();
         debug!("[{}] COOKIE-ACK received in state '{}'", self.side, state);
@@ -1552,7 +1602,17 @@ impl Association {
             self.reset_streams_if_any(p, true, reply)?;
             Ok(())
         } else if let Some(p) = raw.as_any().downcast_ref::<ParamReconfigResponse>() {
-            self.reconfigs.remove(&p.reconfig_response_sequence_number);
+            match p.result() {
+                ReconfigResult::SuccessNop | ReconfigResult::SuccessPerformed => {}
+                result => debug!(
+                    "[{}] reconfig request {} not performed: {}",
+                    self.side,
+                    p.reconfig_response_sequence_number(),
+                    result
+                ),
+            }
+            self.reconfigs
+                .remove(&p.reconfig_response_sequence_number());
             if self.reconfigs.is_empty() {
                 self.timers.stop(Timer::Reconfig);
             }
@@ -1905,12 +1965,12 @@ impl Association {
             let tsn = self.my_next_tsn - 1;
 
             let c = ChunkReconfig {
-                param_a: Some(Box::new(ParamOutgoingResetRequest {
-                    reconfig_request_sequence_number: rsn,
-                    reconfig_response_sequence_number: p.reconfig_request_sequence_number,
-                    sender_last_tsn: tsn,
-                    stream_identifiers: sis_to_reset,
-                })),
+                param_a: Some(Box::new(ParamOutgoingResetRequest::new(
+                    rsn,
+                    p.reconfig_request_sequence_number,
+                    tsn,
+                    &sis_to_reset,
+                ))),
                 ..Default::default()
             };
 
@@ -2120,12 +2180,12 @@ impl Association {
                 );
 
                 let c = ChunkReconfig {
-                    param_a: Some(Box::new(ParamOutgoingResetRequest {
-                        reconfig_request_sequence_number: rsn,
-                        sender_last_tsn: tsn,
-                        stream_identifiers: sis_to_reset,
-                        ..Default::default()
-                    })),
+                    param_a: Some(Box::new(ParamOutgoingResetRequest::new(
+                        rsn,
+                        0,
+                        tsn,
+                        &sis_to_reset,
+                    ))),
                     ..Default::default()
                 };
                 self.reconfigs.insert(rsn, c.clone()); // store in the map for retransmission
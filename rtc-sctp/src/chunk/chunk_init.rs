@@ -274,6 +274,10 @@ impl Chunk for ChunkInit {
     }
 }
 
+/// Chunk types of the extensions we list in the Supported Extensions parameter of our INIT and
+/// INIT ACK chunks.
+pub(crate) const SUPPORTED_EXTENSIONS: [ChunkType; 2] = [CT_RECONFIG, CT_FORWARD_TSN];
+
 impl ChunkInit {
     pub(crate) fn set_supported_extensions(&mut self) {
         // RFC5061 https://tools.ietf.org/html/rfc6525#section-5.2
@@ -281,7 +285,7 @@ impl ChunkInit {
         // extension MUST list the ASCONF, the ASCONF-ACK, and the AUTH chunks
         // in its INIT and INIT-ACK parameters.
         self.params.push(Box::new(ParamSupportedExtensions {
-            chunk_types: vec![CT_RECONFIG, CT_FORWARD_TSN],
+            chunk_types: SUPPORTED_EXTENSIONS.to_vec(),
         }));
     }
 }
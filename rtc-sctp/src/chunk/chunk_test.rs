@@ -600,7 +600,11 @@ fn test_init_marshal_unmarshal() -> Result<()> {
         params: vec![],
     };
 
-    let cookie = Box::new(ParamStateCookie::new());
+    let cookie = Box::new(ParamStateCookie::new(
+        &[0; 32],
+        &[],
+        std::time::Duration::ZERO,
+    )?);
     init_ack.params.push(cookie);
 
     p.chunks.push(Box::new(init_ack));
@@ -3,6 +3,7 @@ use crate::util::{AssociationIdGenerator, RandomAssociationIdGenerator};
 use crate::TimerConfig;
 use std::fmt;
 use std::sync::Arc;
+use std::time::Duration;
 
 /// MTU for inbound packet (from DTLS)
 pub(crate) const RECEIVE_MTU: usize = 8192;
@@ -12,6 +13,8 @@ pub(crate) const INITIAL_RECV_BUF_SIZE: u32 = 1024 * 1024;
 pub(crate) const COMMON_HEADER_SIZE: u32 = 12;
 pub(crate) const DATA_CHUNK_HEADER_SIZE: u32 = 16;
 pub(crate) const DEFAULT_MAX_MESSAGE_SIZE: u32 = 262144;
+/// Valid.Cookie.Life of RFC 4960 Section 15
+pub(crate) const DEFAULT_COOKIE_LIFETIME: Duration = Duration::from_secs(60);
 
 /// Config collects the arguments to create_association construction into
 /// a single structure
@@ -23,6 +26,7 @@ pub struct TransportConfig {
     max_num_outbound_streams: u16,
     max_num_inbound_streams: u16,
     timer_config: TimerConfig,
+    cookie_lifetime: Duration,
 }
 
 impl Default for TransportConfig {
@@ -34,6 +38,7 @@ impl Default for TransportConfig {
             max_num_outbound_streams: u16::MAX,
             max_num_inbound_streams: u16::MAX,
             timer_config: TimerConfig::default(),
+            cookie_lifetime: DEFAULT_COOKIE_LIFETIME,
         }
     }
 }
@@ -69,6 +74,13 @@ impl TransportConfig {
         self
     }
 
+    /// Sets how long a State Cookie sent in an INIT ACK stays valid. A COOKIE-ECHO carrying an
+    /// older cookie is rejected, defaults to 60 seconds.
+    pub fn with_cookie_lifetime(mut self, value: Duration) -> Self {
+        self.cookie_lifetime = value;
+        self
+    }
+
     pub fn sctp_port(&self) -> u16 {
         self.sctp_port
     }
@@ -92,6 +104,10 @@ impl TransportConfig {
     pub fn timer_config(&self) -> TimerConfig {
         self.timer_config
     }
+
+    pub fn cookie_lifetime(&self) -> Duration {
+        self.cookie_lifetime
+    }
 }
 
 /// Global configuration for the endpoint, affecting all associations
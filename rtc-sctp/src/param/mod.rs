@@ -14,6 +14,8 @@ pub(crate) mod param_supported_extensions;
 pub(crate) mod param_type;
 pub(crate) mod param_uknown;
 
+use crate::chunk::chunk_init::SUPPORTED_EXTENSIONS;
+use crate::chunk::chunk_type::{ChunkType, CT_FORWARD_TSN};
 use crate::param::{
     param_chunk_list::ParamChunkList, param_forward_tsn_supported::ParamForwardTsnSupported,
     param_heartbeat_info::ParamHeartbeatInfo,
@@ -54,6 +56,31 @@ impl Clone for Box<dyn Param> {
     }
 }
 
+/// Negotiates the extensions from the parameters of the peer's INIT or INIT ACK chunk. Returns
+/// whether Forward TSN (RFC 3758) is supported by both sides, which partially reliable data
+/// channels depend on, and the chunk types of the peer's Supported Extensions parameter that
+/// we support as well. We always advertise Forward TSN, so it is mutually supported when the
+/// peer advertises it, either with the Forward-TSN-Supported parameter or as a supported
+/// extension.
+pub(crate) fn negotiate_extensions(params: &[Box<dyn Param>]) -> (bool, Vec<ChunkType>) {
+    let mut use_forward_tsn = false;
+    let mut supported_extensions = vec![];
+    for param in params {
+        if param.as_any().is::<ParamForwardTsnSupported>() {
+            use_forward_tsn = true;
+        } else if let Some(v) = param.as_any().downcast_ref::<ParamSupportedExtensions>() {
+            for t in &v.chunk_types {
+                if SUPPORTED_EXTENSIONS.contains(t) && !supported_extensions.contains(t) {
+                    supported_extensions.push(*t);
+                }
+            }
+        }
+    }
+    use_forward_tsn |= supported_extensions.contains(&CT_FORWARD_TSN);
+
+    (use_forward_tsn, supported_extensions)
+}
+
 pub(crate) fn build_param(raw_param: &Bytes) -> Result<Box<dyn Param>> {
     if raw_param.len() < PARAM_HEADER_LENGTH {
         return Err(Error::ErrParamHeaderTooShort);
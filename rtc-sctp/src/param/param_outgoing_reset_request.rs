@@ -46,6 +46,25 @@ pub(crate) struct ParamOutgoingResetRequest {
     pub(crate) stream_identifiers: Vec<u16>,
 }
 
+impl ParamOutgoingResetRequest {
+    /// Creates a request to reset the given outgoing streams, or all outgoing streams if
+    /// `stream_identifiers` is empty. The parameter length covers the unpadded stream list;
+    /// padding to a 4-byte boundary is added by the enclosing chunk or packet when marshaled.
+    pub(crate) fn new(
+        reconfig_request_sequence_number: u32,
+        reconfig_response_sequence_number: u32,
+        sender_last_tsn: u32,
+        stream_identifiers: &[u16],
+    ) -> Self {
+        ParamOutgoingResetRequest {
+            reconfig_request_sequence_number,
+            reconfig_response_sequence_number,
+            sender_last_tsn,
+            stream_identifiers: stream_identifiers.to_vec(),
+        }
+    }
+}
+
 impl fmt::Display for ParamOutgoingResetRequest {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(
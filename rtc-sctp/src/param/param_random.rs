@@ -2,6 +2,9 @@ use super::{param_header::*, param_type::*, *};
 
 use bytes::{Bytes, BytesMut};
 
+/// RFC 4895 Section 3.1: the Random Number SHOULD be 32 bytes long
+pub(crate) const PARAM_RANDOM_MIN_LENGTH: usize = 32;
+
 #[derive(Default, Debug, Clone, PartialEq)]
 pub(crate) struct ParamRandom {
     pub(crate) random_data: Bytes,
@@ -23,6 +26,9 @@ impl Param for ParamRandom {
 
     fn unmarshal(raw: &Bytes) -> Result<Self> {
         let header = ParamHeader::unmarshal(raw)?;
+        if header.value_length() < PARAM_RANDOM_MIN_LENGTH {
+            return Err(Error::ErrParamRandomTooShort);
+        }
         let random_data =
             raw.slice(PARAM_HEADER_LENGTH..PARAM_HEADER_LENGTH + header.value_length());
         Ok(ParamRandom { random_data })
@@ -50,6 +50,12 @@ impl From<u32> for ReconfigResult {
     }
 }
 
+impl From<ReconfigResult> for u32 {
+    fn from(v: ReconfigResult) -> u32 {
+        v as u32
+    }
+}
+
 ///This parameter is used by the receiver of a Re-configuration Request
 ///Parameter to respond to the request.
 ///
@@ -76,6 +82,18 @@ pub(crate) struct ParamReconfigResponse {
     pub(crate) result: ReconfigResult,
 }
 
+impl ParamReconfigResponse {
+    /// Sequence number of the request this response belongs to
+    pub(crate) fn reconfig_response_sequence_number(&self) -> u32 {
+        self.reconfig_response_sequence_number
+    }
+
+    /// Result of processing the request
+    pub(crate) fn result(&self) -> ReconfigResult {
+        self.result
+    }
+}
+
 impl fmt::Display for ParamReconfigResponse {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(
@@ -117,7 +135,7 @@ impl Param for ParamReconfigResponse {
     fn marshal_to(&self, buf: &mut BytesMut) -> Result<usize> {
         self.header().marshal_to(buf)?;
         buf.put_u32(self.reconfig_response_sequence_number);
-        buf.put_u32(self.result as u32);
+        buf.put_u32(self.result.into());
         Ok(buf.len())
     }
 
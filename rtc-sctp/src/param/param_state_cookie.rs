@@ -1,8 +1,16 @@
 use super::{param_header::*, param_type::*, *};
 
 use bytes::{Bytes, BytesMut};
-use rand::Rng;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
 use std::fmt;
+use std::time::Duration;
+use subtle::ConstantTimeEq;
+
+type HmacSha256 = Hmac<Sha256>;
+
+const COOKIE_TIMESTAMP_LENGTH: usize = 8;
+const COOKIE_MAC_LENGTH: usize = 32;
 
 #[derive(Default, Debug, Clone, PartialEq)]
 pub(crate) struct ParamStateCookie {
@@ -50,13 +58,69 @@ impl Param for ParamStateCookie {
 }
 
 impl ParamStateCookie {
-    pub(crate) fn new() -> Self {
-        let mut cookie = BytesMut::new();
-        cookie.resize(32, 0);
-        rand::thread_rng().fill(cookie.as_mut());
+    /// Creates a cookie made of the creation timestamp (in milliseconds) and the TCB state,
+    /// followed by an HMAC-SHA256 over both under key, so that the cookie echoed back in a
+    /// COOKIE-ECHO can be checked with verify (RFC 4960 Section 5.1.3). The timestamp is the
+    /// time elapsed since an origin chosen by the caller, who checks the lifetime against it.
+    pub(crate) fn new(key: &[u8], tcb: &[u8], created_at: Duration) -> Result<Self> {
+        let timestamp = created_at.as_millis() as u64;
+
+        let mut cookie =
+            BytesMut::with_capacity(COOKIE_TIMESTAMP_LENGTH + tcb.len() + COOKIE_MAC_LENGTH);
+        cookie.extend_from_slice(&timestamp.to_be_bytes());
+        cookie.extend_from_slice(tcb);
+        let mac = cookie_mac(key, &cookie)?;
+        cookie.extend_from_slice(&mac);
 
-        ParamStateCookie {
+        Ok(ParamStateCookie {
             cookie: cookie.freeze(),
+        })
+    }
+
+    /// Checks the HMAC of the cookie under key, returning ErrStateCookieInvalid for a forged
+    /// or corrupted cookie.
+    pub(crate) fn verify(&self, key: &[u8]) -> Result<()> {
+        if self.cookie.len() < COOKIE_TIMESTAMP_LENGTH + COOKIE_MAC_LENGTH {
+            return Err(Error::ErrStateCookieInvalid);
+        }
+
+        let (data, mac) = self.cookie.split_at(self.cookie.len() - COOKIE_MAC_LENGTH);
+        if bool::from(cookie_mac(key, data)?.ct_eq(mac)) {
+            Ok(())
+        } else {
+            Err(Error::ErrStateCookieInvalid)
         }
     }
+
+    /// Returns ErrStaleCookie when the cookie was created more than lifetime before now, both
+    /// measured from the origin the cookie was created with. The cookie should be verified
+    /// first, since the timestamp is only authenticated by the HMAC.
+    pub(crate) fn check_lifetime(&self, now: Duration, lifetime: Duration) -> Result<()> {
+        if self.cookie.len() < COOKIE_TIMESTAMP_LENGTH {
+            return Err(Error::ErrStateCookieInvalid);
+        }
+
+        let mut timestamp = [0u8; COOKIE_TIMESTAMP_LENGTH];
+        timestamp.copy_from_slice(&self.cookie[..COOKIE_TIMESTAMP_LENGTH]);
+        let created_at = Duration::from_millis(u64::from_be_bytes(timestamp));
+        if now.saturating_sub(created_at) > lifetime {
+            Err(Error::ErrStaleCookie)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// The TCB state carried by the cookie, empty if the cookie is too short to hold one.
+    pub(crate) fn tcb(&self) -> &[u8] {
+        if self.cookie.len() < COOKIE_TIMESTAMP_LENGTH + COOKIE_MAC_LENGTH {
+            return &[];
+        }
+        &self.cookie[COOKIE_TIMESTAMP_LENGTH..self.cookie.len() - COOKIE_MAC_LENGTH]
+    }
+}
+
+fn cookie_mac(key: &[u8], data: &[u8]) -> Result<Vec<u8>> {
+    let mut mac = HmacSha256::new_from_slice(key).map_err(|e| Error::Other(e.to_string()))?;
+    mac.update(data);
+    Ok(mac.finalize().into_bytes().to_vec())
 }
@@ -149,6 +149,39 @@ fn test_param_outgoing_reset_request_success() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_param_outgoing_reset_request_new() -> Result<()> {
+    use crate::chunk::{chunk_reconfig::ChunkReconfig, Chunk};
+
+    let tests = vec![
+        (vec![4, 5, 6], CHUNK_RECONFIG_PARAM_A.clone()),
+        // An empty stream list resets all outgoing streams
+        (vec![], CHUNK_RECONFIG_PARAM_B.clone()),
+    ];
+
+    for (stream_identifiers, binary) in tests {
+        let p = ParamOutgoingResetRequest::new(1, 2, 3, &stream_identifiers);
+        assert_eq!(p.value_length(), 12 + 2 * stream_identifiers.len());
+        assert_eq!(p.marshal()?, binary);
+        assert_eq!(ParamOutgoingResetRequest::unmarshal(&binary)?, p);
+
+        // Round trip through the enclosing RECONFIG chunk
+        let c = ChunkReconfig {
+            param_a: Some(Box::new(p.clone())),
+            param_b: None,
+        };
+        let c = ChunkReconfig::unmarshal(&c.marshal()?)?;
+        let actual = c
+            .param_a
+            .as_ref()
+            .and_then(|a| a.as_any().downcast_ref::<ParamOutgoingResetRequest>())
+            .cloned();
+        assert_eq!(actual, Some(p));
+    }
+
+    Ok(())
+}
+
 #[test]
 fn test_param_outgoing_reset_request_failure() -> Result<()> {
     let tests = vec![
@@ -164,6 +197,82 @@ fn test_param_outgoing_reset_request_failure() -> Result<()> {
     Ok(())
 }
 
+///////////////////////////////////////////////////////////////////
+//param_random_test
+///////////////////////////////////////////////////////////////////
+use super::param_random::*;
+
+#[test]
+fn test_param_random() -> Result<()> {
+    let mut raw = BytesMut::new();
+    ParamHeader {
+        typ: ParamType::Random,
+        value_length: 4,
+    }
+    .marshal_to(&mut raw)?;
+    raw.extend_from_slice(&[0x1, 0x2, 0x3, 0x4]);
+    assert_eq!(
+        ParamRandom::unmarshal(&raw.freeze()),
+        Err(Error::ErrParamRandomTooShort)
+    );
+
+    let p = ParamRandom {
+        random_data: Bytes::from(vec![0xAB; PARAM_RANDOM_MIN_LENGTH]),
+    };
+    let actual = ParamRandom::unmarshal(&p.marshal()?)?;
+    assert_eq!(actual, p);
+
+    Ok(())
+}
+
+///////////////////////////////////////////////////////////////////
+//param_state_cookie_test
+///////////////////////////////////////////////////////////////////
+use super::param_state_cookie::*;
+use std::time::Duration;
+
+#[test]
+fn test_param_state_cookie() -> Result<()> {
+    let key = [0x42; 32];
+    let created_at = Duration::from_secs(10);
+    let cookie = ParamStateCookie::new(&key, &[0x1, 0x2, 0x3, 0x4], created_at)?;
+    cookie.verify(&key)?;
+    assert_eq!(cookie.tcb(), &[0x1, 0x2, 0x3, 0x4]);
+
+    // The cookie survives being echoed back through build_param
+    let echoed = build_param(&cookie.marshal()?)?;
+    let echoed = echoed
+        .as_any()
+        .downcast_ref::<ParamStateCookie>()
+        .expect("should be a state cookie");
+    echoed.verify(&key)?;
+
+    assert_eq!(
+        cookie.verify(&[0x43; 32]),
+        Err(Error::ErrStateCookieInvalid)
+    );
+    let mut forged = cookie.cookie.to_vec();
+    forged[8] ^= 0xff;
+    let forged = ParamStateCookie {
+        cookie: Bytes::from(forged),
+    };
+    assert_eq!(forged.verify(&key), Err(Error::ErrStateCookieInvalid));
+    let truncated = ParamStateCookie {
+        cookie: cookie.cookie.slice(..16),
+    };
+    assert_eq!(truncated.verify(&key), Err(Error::ErrStateCookieInvalid));
+    assert!(truncated.tcb().is_empty());
+
+    let lifetime = Duration::from_secs(60);
+    cookie.check_lifetime(created_at + Duration::from_secs(59), lifetime)?;
+    assert_eq!(
+        cookie.check_lifetime(created_at + Duration::from_secs(61), lifetime),
+        Err(Error::ErrStaleCookie)
+    );
+
+    Ok(())
+}
+
 ///////////////////////////////////////////////////////////////////
 //param_reconfig_response_test
 ///////////////////////////////////////////////////////////////////
@@ -237,6 +346,22 @@ fn test_reconfig_result_stringer() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_reconfig_result_u32_conversion() -> Result<()> {
+    for v in 0..=6u32 {
+        let result: ReconfigResult = v.into();
+        assert_ne!(result, ReconfigResult::Unknown);
+        assert_eq!(v, u32::from(result));
+    }
+    assert_eq!(ReconfigResult::Unknown, ReconfigResult::from(7));
+
+    let p = ParamReconfigResponse::unmarshal(&CHUNK_RECONFIG_RESPONCE)?;
+    assert_eq!(p.reconfig_response_sequence_number(), 1);
+    assert_eq!(p.result(), ReconfigResult::SuccessPerformed);
+
+    Ok(())
+}
+
 ///////////////////////////////////////////////////////////////////
 //param_test
 ///////////////////////////////////////////////////////////////////
@@ -268,3 +393,36 @@ fn test_build_param_failure() -> Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn test_negotiate_extensions() -> Result<()> {
+    use super::param_forward_tsn_supported::ParamForwardTsnSupported;
+    use super::param_supported_extensions::ParamSupportedExtensions;
+    use crate::chunk::chunk_type::*;
+
+    let (use_forward_tsn, supported_extensions) = negotiate_extensions(&[]);
+    assert!(!use_forward_tsn);
+    assert!(supported_extensions.is_empty());
+
+    let (use_forward_tsn, supported_extensions) =
+        negotiate_extensions(&[Box::new(ParamForwardTsnSupported {})]);
+    assert!(use_forward_tsn);
+    assert!(supported_extensions.is_empty());
+
+    let (use_forward_tsn, supported_extensions) =
+        negotiate_extensions(&[Box::new(ParamSupportedExtensions {
+            chunk_types: vec![CT_FORWARD_TSN],
+        })]);
+    assert!(use_forward_tsn);
+    assert_eq!(supported_extensions, vec![CT_FORWARD_TSN]);
+
+    // extensions we don't support are not negotiated
+    let (use_forward_tsn, supported_extensions) =
+        negotiate_extensions(&[Box::new(ParamSupportedExtensions {
+            chunk_types: vec![CT_CWR, CT_RECONFIG],
+        })]);
+    assert!(!use_forward_tsn);
+    assert_eq!(supported_extensions, vec![CT_RECONFIG]);
+
+    Ok(())
+}
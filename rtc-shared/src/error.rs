@@ -1,5 +1,6 @@
 #![allow(dead_code)]
 
+use crate::alert::{AlertDescription, AlertLevel};
 use std::io;
 use std::net;
 use std::net::SocketAddr;
@@ -282,6 +283,8 @@ pub enum Error {
     ErrShortSrtpMasterSalt,
     #[error("no such SRTP Profile")]
     ErrNoSuchSrtpProfile,
+    #[error("no SRTP protection profile was negotiated, both peers must offer SRTP profiles")]
+    ErrSrtpNotNegotiated,
     #[error("indexOverKdr > 0 is not supported yet")]
     ErrNonZeroKdrNotSupported,
     #[error("exporter called with wrong label")]
@@ -333,6 +336,8 @@ pub enum Error {
     SrtpTooSmall(usize, usize),
     #[error("too short SRTCP packet: only {0} bytes, expected > {1} bytes")]
     SrtcpTooSmall(usize, usize),
+    #[error("srtp mki does not match the mki of the context")]
+    SrtpMkiMismatch,
     #[error("failed to verify rtp auth tag")]
     RtpFailedToVerifyAuthTag,
     #[error("failed to verify rtcp auth tag")]
@@ -561,6 +566,22 @@ pub enum Error {
     #[error("local password is less than 128 bits long")]
     ErrLocalPwdInsufficientBits,
 
+    /// Indicates local username fragment is longer than 256 characters.
+    #[error("local username fragment is longer than 256 characters")]
+    ErrLocalUfragTooLong,
+
+    /// Indicates local password is longer than 256 characters.
+    #[error("local password is longer than 256 characters")]
+    ErrLocalPwdTooLong,
+
+    /// Indicates local username fragment contains characters outside of ice-char.
+    #[error("local username fragment contains invalid characters")]
+    ErrLocalUfragInvalidChars,
+
+    /// Indicates local password contains characters outside of ice-char.
+    #[error("local password contains invalid characters")]
+    ErrLocalPwdInvalidChars,
+
     /// Indicates an unsupported transport type was provided.
     #[error("invalid transport protocol type")]
     ErrProtoType,
@@ -569,6 +590,15 @@ pub enum Error {
     #[error("no candidate pairs available")]
     ErrNoCandidatePairs,
 
+    /// Indicates a candidate pair was renominated without both agents supporting
+    /// renomination, or by the controlled agent.
+    #[error("renomination is not enabled")]
+    ErrRenominationNotEnabled,
+
+    /// Indicates a candidate pair to nominate has not succeeded a connectivity check.
+    #[error("candidate pair is not valid")]
+    ErrCandidatePairNotValid,
+
     /// Indicates agent connection was canceled by the caller.
     #[error("connecting canceled by caller")]
     ErrCanceledByCaller,
@@ -585,6 +615,22 @@ pub enum Error {
     #[error("remote pwd is empty")]
     ErrRemotePwdEmpty,
 
+    /// Indicates agent was started with a remote ufrag longer than 256 characters.
+    #[error("remote ufrag is longer than 256 characters")]
+    ErrRemoteUfragTooLong,
+
+    /// Indicates agent was started with a remote pwd longer than 256 characters.
+    #[error("remote pwd is longer than 256 characters")]
+    ErrRemotePwdTooLong,
+
+    /// Indicates agent was started with a remote ufrag containing characters outside of ice-char.
+    #[error("remote ufrag contains invalid characters")]
+    ErrRemoteUfragInvalidChars,
+
+    /// Indicates agent was started with a remote pwd containing characters outside of ice-char.
+    #[error("remote pwd contains invalid characters")]
+    ErrRemotePwdInvalidChars,
+
     /// Indicates agent was started without on_candidate.
     #[error("no on_candidate provided")]
     ErrNoOnCandidateHandler,
@@ -605,6 +651,10 @@ pub enum Error {
     #[error("failed to parse address")]
     ErrAddressParseFailed,
 
+    /// Indicates the zone of a link-local candidate address is not a known interface.
+    #[error("failed to resolve the zone of a link-local address")]
+    ErrZoneResolveFailed,
+
     /// Indicates that non host candidates were selected for a lite agent.
     #[error("lite agents must only use host candidates")]
     ErrLiteUsingNonHostCandidates,
@@ -613,6 +663,14 @@ pub enum Error {
     #[error("lite support only")]
     ErrLiteSupportOnly,
 
+    /// Indicates that both the local and the remote agent are lite, so neither would check.
+    #[error("both ice agents are lite")]
+    ErrBothAgentsLite,
+
+    /// Indicates that a full agent tried to be controlled by a lite remote agent.
+    #[error("full agent must be controlling when the remote agent is lite")]
+    ErrLiteRemoteRequiresControlling,
+
     /// Indicates that one or more URL was provided to the agent but no host candidate required them.
     #[error("agent does not need URL with selected candidate types")]
     ErrUselessUrlsProvided,
@@ -724,6 +782,8 @@ pub enum Error {
     ErrDtlspacketInvalidLength,
     #[error("handshake is in progress")]
     ErrHandshakeInProgress,
+    #[error("handshake timed out")]
+    ErrHandshakeTimeout,
     #[error("invalid content type")]
     ErrInvalidContentType,
     #[error("invalid mac")]
@@ -738,18 +798,26 @@ pub enum Error {
     ErrCipherSuiteNoIntersection,
     #[error("server hello can not be created without a cipher suite")]
     ErrCipherSuiteUnset,
+    #[error("master secret must be 48 bytes")]
+    ErrInvalidMasterSecret,
     #[error("client sent certificate but did not verify it")]
     ErrClientCertificateNotVerified,
     #[error("server required client verification, but got none")]
     ErrClientCertificateRequired,
     #[error("server responded with SRTP Profile we do not support")]
     ErrClientNoMatchingSrtpProfile,
+    #[error("server responded with an SRTP MKI we did not offer")]
+    ErrClientNoMatchingSrtpMki,
     #[error("client required Extended Master Secret extension, but server does not support it")]
     ErrClientRequiredButNoServerEms,
     #[error("server hello can not be created without a compression method")]
     ErrCompressionMethodUnset,
     #[error("client+server cookie does not match")]
     ErrCookieMismatch,
+    #[error("state cookie failed the HMAC verification")]
+    ErrStateCookieInvalid,
+    #[error("state cookie is older than the cookie lifetime")]
+    ErrStaleCookie,
     #[error("cookie must not be longer then 255 bytes")]
     ErrCookieTooLong,
     #[error("PSK Identity Hint provided but PSK is nil")]
@@ -798,16 +866,22 @@ pub enum Error {
     ErrNoSupportedEllipticCurves,
     #[error("unsupported protocol version")]
     ErrUnsupportedProtocolVersion,
+    #[error("DTLS 1.3 was negotiated but is not implemented")]
+    ErrDtls13NotImplemented,
     #[error("Certificate and PSK provided")]
     ErrPskAndCertificate,
     #[error("PSK and PSK Identity Hint must both be set for client")]
     ErrPskAndIdentityMustBeSetForClient,
     #[error("SRTP support was requested but server did not respond with use_srtp extension")]
     ErrRequestedButNoSrtpExtension,
+    #[error("SRTP MKI must be at most 255 bytes")]
+    ErrSrtpMkiTooLong,
     #[error("Certificate is mandatory for server")]
     ErrServerMustHaveCertificate,
     #[error("client requested SRTP but we have no matching profiles")]
     ErrServerNoMatchingSrtpProfile,
+    #[error("no SRTP protection profile is supported by both peers")]
+    ErrNoMutualSrtpProfile,
     #[error(
         "server requires the Extended Master Secret extension, but the client does not support it"
     )]
@@ -844,10 +918,22 @@ pub enum Error {
     ErrEmptyFragment,
     #[error("Alert is Fatal or Close Notify")]
     ErrAlertFatalOrClose,
+    /// An alert received from the DTLS peer
+    #[error("DTLS alert received: {level}: {description}")]
+    DtlsAlert {
+        level: AlertLevel,
+        description: AlertDescription,
+    },
     #[error(
         "Fragment buffer overflow. New size {new_size} is greater than specified max {max_size}"
     )]
     ErrFragmentBufferOverflow { new_size: usize, max_size: usize },
+    #[error(
+        "Fragment buffer overflow. New fragment count {new_count} is greater than specified max {max_count}"
+    )]
+    ErrFragmentBufferTooManyFragments { new_count: usize, max_count: usize },
+    #[error("Handshake message length {length} is greater than specified max {max_length}")]
+    ErrHandshakeMessageTooLarge { length: usize, max_length: usize },
     #[error("Client transport is not set yet")]
     ErrClientTransportNotSet,
 
@@ -949,6 +1035,8 @@ pub enum Error {
     ErrSsnResetRequestParamTooShort,
     #[error("reconfig response parameter too short")]
     ErrReconfigRespParamTooShort,
+    #[error("random parameter shorter than 32 bytes")]
+    ErrParamRandomTooShort,
     #[error("invalid algorithm type")]
     ErrInvalidAlgorithmType,
 
@@ -1323,6 +1411,8 @@ pub enum Error {
     ErrDetachBeforeOpened,
     #[error("the DTLS transport has not started yet")]
     ErrDtlsTransportNotStarted,
+    #[error("the SRTP contexts are not ready, the DTLS handshake has not completed")]
+    ErrSrtpContextNotReady,
     #[error("failed extracting keys from DTLS for SRTP")]
     ErrDtlsKeyExtractionFailed,
     #[error("failed to start SRTP")]
@@ -1331,6 +1421,8 @@ pub enum Error {
     ErrFailedToStartSRTCP,
     #[error("attempted to start DTLSTransport that is not in new state")]
     ErrInvalidDTLSStart,
+    #[error("replay protection window must be a power of two")]
+    ErrInvalidReplayProtectionWindow,
     #[error("peer didn't provide certificate via DTLS")]
     ErrNoRemoteCertificate,
     #[error("identity provider is not implemented")]
@@ -10,6 +10,7 @@ pub mod marshal;
 #[cfg(feature = "replay")]
 pub mod replay_detector;
 
+pub mod alert;
 pub mod error;
 pub mod handler;
 pub mod util;
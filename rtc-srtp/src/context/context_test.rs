@@ -303,3 +303,58 @@ fn test_decrypt_rtcp() {
 
     assert_eq!(gotten_decrypted_rtcp_packet, *DECRYPTED_RTCP_PACKET)
 }
+
+#[test]
+fn test_context_mki() -> Result<()> {
+    let rtp_packet = [
+        0x80, 0x0f, 0x12, 0x34, 0xde, 0xca, 0xfb, 0xad, 0xca, 0xfe, 0xba, 0xbe, 0xab, 0xab, 0xab,
+        0xab,
+    ];
+    let rtcp_packet = [
+        0x81, 0xc8, 0x00, 0x03, 0xca, 0xfe, 0xba, 0xbe, 0xab, 0xab, 0xab, 0xab, 0xab, 0xab, 0xab,
+        0xab,
+    ];
+
+    for profile in [
+        ProtectionProfile::Aes128CmHmacSha1_80,
+        ProtectionProfile::AeadAes128Gcm,
+    ] {
+        let new_context = |mki: &[u8]| -> Result<Context> {
+            let mut c = Context::new(
+                &vec![0; profile.key_len()],
+                &vec![0; profile.salt_len()],
+                profile,
+                None,
+                None,
+            )?;
+            c.set_mki(mki.to_vec());
+            Ok(c)
+        };
+
+        let without_mki = new_context(&[])?.encrypt_rtp(&rtp_packet)?;
+        let with_mki = new_context(&[0x01, 0x02, 0x03])?.encrypt_rtp(&rtp_packet)?;
+        assert_eq!(with_mki.len(), without_mki.len() + 3);
+        let decrypted = new_context(&[0x01, 0x02, 0x03])?.decrypt_rtp(&with_mki)?;
+        assert_eq!(&decrypted[..], &rtp_packet[..], "{profile:?}");
+        assert_eq!(
+            new_context(&[0x01, 0x02, 0x04])?
+                .decrypt_rtp(&with_mki)
+                .unwrap_err(),
+            Error::SrtpMkiMismatch
+        );
+
+        let without_mki = new_context(&[])?.encrypt_rtcp(&rtcp_packet)?;
+        let with_mki = new_context(&[0x01, 0x02, 0x03])?.encrypt_rtcp(&rtcp_packet)?;
+        assert_eq!(with_mki.len(), without_mki.len() + 3);
+        let decrypted = new_context(&[0x01, 0x02, 0x03])?.decrypt_rtcp(&with_mki)?;
+        assert_eq!(&decrypted[..], &rtcp_packet[..], "{profile:?}");
+        assert_eq!(
+            new_context(&[0x01, 0x02, 0x04])?
+                .decrypt_rtcp(&with_mki)
+                .unwrap_err(),
+            Error::SrtpMkiMismatch
+        );
+    }
+
+    Ok(())
+}
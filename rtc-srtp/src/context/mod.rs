@@ -14,6 +14,8 @@ use shared::{
     replay_detector::*,
 };
 
+use bytes::BytesMut;
+use std::borrow::Cow;
 use std::collections::HashMap;
 
 pub mod srtcp;
@@ -109,6 +111,8 @@ pub struct Context {
 
     new_srtp_replay_detector: ContextOption,
     new_srtcp_replay_detector: ContextOption,
+
+    mki: Vec<u8>,
 }
 
 impl Context {
@@ -157,9 +161,55 @@ impl Context {
             srtcp_ssrc_states: HashMap::new(),
             new_srtp_replay_detector: srtp_ctx_opt,
             new_srtcp_replay_detector: srtcp_ctx_opt,
+            mki: vec![],
         })
     }
 
+    /// set_mki sets the Master Key Identifier carried by every SRTP and SRTCP packet
+    /// of this context, see https://tools.ietf.org/html/rfc3711#section-3.1
+    /// An empty MKI (the default) means packets carry none.
+    pub fn set_mki(&mut self, mki: Vec<u8>) {
+        self.mki = mki;
+    }
+
+    /// mki returns the Master Key Identifier of this context, empty if none
+    pub fn mki(&self) -> &[u8] {
+        &self.mki
+    }
+
+    // The MKI sits between the encrypted portion and the auth tag, and isn't covered by it,
+    // so it is added after the cipher ran and removed before it runs
+    fn insert_mki(&self, mut packet: BytesMut, auth_tag_len: usize) -> BytesMut {
+        if self.mki.is_empty() {
+            return packet;
+        }
+
+        let auth_tag = packet.split_off(packet.len() - auth_tag_len);
+        packet.extend_from_slice(&self.mki);
+        packet.extend_from_slice(&auth_tag);
+        packet
+    }
+
+    fn remove_mki<'a>(&self, packet: &'a [u8], auth_tag_len: usize) -> Result<Cow<'a, [u8]>> {
+        if self.mki.is_empty() {
+            return Ok(Cow::Borrowed(packet));
+        }
+
+        if packet.len() < self.mki.len() + auth_tag_len {
+            return Err(Error::SrtpMkiMismatch);
+        }
+        let mki_start = packet.len() - auth_tag_len - self.mki.len();
+        let mki_end = packet.len() - auth_tag_len;
+        if packet[mki_start..mki_end] != self.mki[..] {
+            return Err(Error::SrtpMkiMismatch);
+        }
+
+        let mut stripped = Vec::with_capacity(packet.len() - self.mki.len());
+        stripped.extend_from_slice(&packet[..mki_start]);
+        stripped.extend_from_slice(&packet[mki_end..]);
+        Ok(Cow::Owned(stripped))
+    }
+
     fn get_srtp_ssrc_state(&mut self, ssrc: u32) -> Option<&mut SrtpSsrcState> {
         let s = SrtpSsrcState {
             ssrc,
@@ -9,6 +9,9 @@ impl Context {
         let mut buf = encrypted;
         rtcp::header::Header::unmarshal(&mut buf)?;
 
+        let encrypted = self.remove_mki(encrypted, self.cipher.rtcp_auth_tag_len())?;
+        let encrypted = encrypted.as_ref();
+
         let index = self.cipher.get_rtcp_index(encrypted);
         let ssrc = u32::from_be_bytes([encrypted[4], encrypted[5], encrypted[6], encrypted[7]]);
 
@@ -58,6 +61,7 @@ impl Context {
             }
         }
 
-        self.cipher.encrypt_rtcp(decrypted, index, ssrc)
+        let dst = self.cipher.encrypt_rtcp(decrypted, index, ssrc)?;
+        Ok(self.insert_mki(dst, self.cipher.rtcp_auth_tag_len()))
     }
 }
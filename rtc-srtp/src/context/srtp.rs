@@ -12,6 +12,9 @@ impl Context {
         encrypted: &[u8],
         header: &rtp::header::Header,
     ) -> Result<BytesMut> {
+        let auth_tag_len = self.cipher.rtp_auth_tag_len();
+        let encrypted = self.remove_mki(encrypted, auth_tag_len)?;
+
         let roc;
         {
             if let Some(state) = self.get_srtp_ssrc_state(header.ssrc) {
@@ -30,7 +33,7 @@ impl Context {
             }
         }
 
-        let dst = self.cipher.decrypt_rtp(encrypted, header, roc)?;
+        let dst = self.cipher.decrypt_rtp(&encrypted, header, roc)?;
         {
             if let Some(state) = self.get_srtp_ssrc_state(header.ssrc) {
                 if let Some(replay_detector) = &mut state.replay_detector {
@@ -67,6 +70,7 @@ impl Context {
         let dst = self
             .cipher
             .encrypt_rtp(&plaintext[header.marshal_size()..], header, roc)?;
+        let dst = self.insert_mki(dst, self.cipher.rtp_auth_tag_len());
 
         {
             if let Some(state) = self.get_srtp_ssrc_state(header.ssrc) {
@@ -50,6 +50,10 @@ impl fmt::Display for AttrType {
             ATTR_USE_CANDIDATE => "USE-CANDIDATE",
             ATTR_ICE_CONTROLLED => "ICE-CONTROLLED",
             ATTR_ICE_CONTROLLING => "ICE-CONTROLLING",
+            ATTR_NOMINATION => "NOMINATION",
+            ATTR_NETWORK_COST => "NETWORK-COST",
+            ATTR_GOOG_MISC_INFO => "GOOG-MISC-INFO",
+            ATTR_GOOG_MESSAGE_INTEGRITY_32 => "GOOG-MESSAGE-INTEGRITY-32",
             ATTR_CHANNEL_NUMBER => "CHANNEL-NUMBER",
             ATTR_LIFETIME => "LIFETIME",
             ATTR_XOR_PEER_ADDRESS => "XOR-PEER-ADDRESS",
@@ -111,6 +115,9 @@ pub const ATTR_USE_CANDIDATE: AttrType = AttrType(0x0025); // USE-CANDIDATE
 pub const ATTR_ICE_CONTROLLED: AttrType = AttrType(0x8029); // ICE-CONTROLLED
 pub const ATTR_ICE_CONTROLLING: AttrType = AttrType(0x802A); // ICE-CONTROLLING
 pub const ATTR_NETWORK_COST: AttrType = AttrType(0xC057); // NETWORK-COST
+pub const ATTR_NOMINATION: AttrType = AttrType(0xC001); // NOMINATION (ICE renomination draft)
+pub const ATTR_GOOG_MISC_INFO: AttrType = AttrType(0xC059); // GOOG-MISC-INFO
+pub const ATTR_GOOG_MESSAGE_INTEGRITY_32: AttrType = AttrType(0xC060); // GOOG-MESSAGE-INTEGRITY-32
 
 /// Attributes from RFC 5766 TURN.
 pub const ATTR_CHANNEL_NUMBER: AttrType = AttrType(0x000C); // CHANNEL-NUMBER
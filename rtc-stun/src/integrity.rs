@@ -61,6 +61,7 @@ impl Setter for MessageIntegrity {
 }
 
 pub(crate) const MESSAGE_INTEGRITY_SIZE: usize = 20;
+pub(crate) const MESSAGE_INTEGRITY_32_SIZE: usize = 4;
 
 impl MessageIntegrity {
     // new_long_term_integrity returns new MessageIntegrity with key for long-term
@@ -111,3 +112,75 @@ impl MessageIntegrity {
         check_hmac(&v, &expected)
     }
 }
+
+// MessageIntegrity32 represents the GOOG-MESSAGE-INTEGRITY-32 attribute of Google-flavored ICE,
+// the HMAC-SHA1 of MESSAGE-INTEGRITY truncated to its first 32 bits. It authenticates the
+// compact GOOG-PING keepalives sent by Chrome.
+#[derive(Default, Clone)]
+pub struct MessageIntegrity32(pub Vec<u8>);
+
+impl Setter for MessageIntegrity32 {
+    // add_to adds GOOG-MESSAGE-INTEGRITY-32 attribute to message.
+    fn add_to(&self, m: &mut Message) -> Result<()> {
+        for a in &m.attributes.0 {
+            // Message should not contain FINGERPRINT attribute
+            // before GOOG-MESSAGE-INTEGRITY-32.
+            if a.typ == ATTR_FINGERPRINT {
+                return Err(Error::ErrFingerprintBeforeIntegrity);
+            }
+        }
+        let length = m.length;
+        m.length += (MESSAGE_INTEGRITY_32_SIZE + ATTRIBUTE_HEADER_SIZE) as u32;
+        m.write_length();
+        let v = new_hmac(&self.0, &m.raw);
+        m.length = length;
+
+        m.add(
+            ATTR_GOOG_MESSAGE_INTEGRITY_32,
+            &v[..MESSAGE_INTEGRITY_32_SIZE],
+        );
+
+        Ok(())
+    }
+}
+
+impl MessageIntegrity32 {
+    // new_short_term_integrity returns new MessageIntegrity32 with key for short-term
+    // credentials. Password must be SASL-prepared.
+    pub fn new_short_term_integrity(password: String) -> Self {
+        MessageIntegrity32(password.as_bytes().to_vec())
+    }
+
+    // Check checks GOOG-MESSAGE-INTEGRITY-32 attribute.
+    pub fn check(&self, m: &mut Message) -> Result<()> {
+        let v = m.get(ATTR_GOOG_MESSAGE_INTEGRITY_32)?;
+        check_size(
+            ATTR_GOOG_MESSAGE_INTEGRITY_32,
+            v.len(),
+            MESSAGE_INTEGRITY_32_SIZE,
+        )?;
+
+        let length = m.length as usize;
+        let mut after_integrity = false;
+        let mut size_reduced = 0;
+
+        for a in &m.attributes.0 {
+            if after_integrity {
+                size_reduced += nearest_padded_value_length(a.length as usize);
+                size_reduced += ATTRIBUTE_HEADER_SIZE;
+            }
+            if a.typ == ATTR_GOOG_MESSAGE_INTEGRITY_32 {
+                after_integrity = true;
+            }
+        }
+        m.length -= size_reduced as u32;
+        m.write_length();
+        let start_of_hmac = MESSAGE_HEADER_SIZE + m.length as usize
+            - (ATTRIBUTE_HEADER_SIZE + MESSAGE_INTEGRITY_32_SIZE);
+        let b = &m.raw[..start_of_hmac];
+        let expected = new_hmac(&self.0, b);
+        m.length = length as u32;
+        m.write_length();
+        check_hmac(&v, &expected[..MESSAGE_INTEGRITY_32_SIZE])
+    }
+}
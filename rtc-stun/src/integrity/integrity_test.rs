@@ -103,3 +103,33 @@ fn test_message_integrity_before_fingerprint() -> Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn test_message_integrity_32() -> Result<()> {
+    let i = MessageIntegrity32::new_short_term_integrity("password".to_owned());
+    let mut m = Message::new();
+    m.set_type(MessageType::new(METHOD_GOOG_PING, CLASS_REQUEST));
+    m.transaction_id = TransactionId::new();
+    m.write_header();
+    i.add_to(&mut m)?;
+    assert_eq!(m.get(ATTR_GOOG_MESSAGE_INTEGRITY_32)?.len(), 4);
+    assert_eq!(m.raw.len(), MESSAGE_HEADER_SIZE + 8);
+
+    let mut decoded = Message::new();
+    decoded.raw = m.raw.clone();
+    decoded.decode()?;
+    i.check(&mut decoded)?;
+
+    // Attributes after the integrity are not covered
+    FINGERPRINT.add_to(&mut decoded)?;
+    i.check(&mut decoded)?;
+
+    let wrong = MessageIntegrity32::new_short_term_integrity("wrong".to_owned());
+    assert_eq!(wrong.check(&mut decoded), Err(Error::ErrIntegrityMismatch));
+    assert!(
+        MessageIntegrity32::default().add_to(&mut decoded).is_err(),
+        "should error after FINGERPRINT"
+    );
+
+    Ok(())
+}
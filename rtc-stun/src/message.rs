@@ -507,6 +507,9 @@ pub const METHOD_CONNECT: Method = Method(0x000a);
 pub const METHOD_CONNECTION_BIND: Method = Method(0x000b);
 pub const METHOD_CONNECTION_ATTEMPT: Method = Method(0x000c);
 
+// Google-flavored ICE compact keepalive, as sent by Chrome.
+pub const METHOD_GOOG_PING: Method = Method(0x080);
+
 impl fmt::Display for Method {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let unknown = format!("0x{:x}", self.0);
@@ -524,6 +527,8 @@ impl fmt::Display for Method {
             METHOD_CONNECT => "Connect",
             METHOD_CONNECTION_BIND => "ConnectionBind",
             METHOD_CONNECTION_ATTEMPT => "ConnectionAttempt",
+
+            METHOD_GOOG_PING => "GoogPing",
             _ => unknown.as_str(),
         };
 
@@ -39,6 +39,52 @@ fn match_srtp(b: &[u8]) -> bool {
     match_range(128, 191, b)
 }
 
+/// match_stun is a MatchFunc that accepts packets with the first byte in [0..3]
+/// as defied in RFC7983
+fn match_stun(b: &[u8]) -> bool {
+    match_range(0, 3, b)
+}
+
+/// match_zrtp is a MatchFunc that accepts packets with the first byte in [16..19]
+/// as defied in RFC7983
+fn match_zrtp(b: &[u8]) -> bool {
+    match_range(16, 19, b)
+}
+
+/// match_turn_channel is a MatchFunc that accepts packets with the first byte in [64..79]
+/// as defied in RFC7983
+fn match_turn_channel(b: &[u8]) -> bool {
+    match_range(64, 79, b)
+}
+
+/// PacketKind is the protocol of a packet received on a multiplexed port
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum PacketKind {
+    Stun,
+    Zrtp,
+    Dtls,
+    TurnChannel,
+    Rtp,
+    Unknown,
+}
+
+/// demux classifies a packet by its first byte as described in RFC7983
+pub fn demux(buf: &[u8]) -> PacketKind {
+    if match_stun(buf) {
+        PacketKind::Stun
+    } else if match_zrtp(buf) {
+        PacketKind::Zrtp
+    } else if match_dtls(buf) {
+        PacketKind::Dtls
+    } else if match_turn_channel(buf) {
+        PacketKind::TurnChannel
+    } else if match_srtp(buf) {
+        PacketKind::Rtp
+    } else {
+        PacketKind::Unknown
+    }
+}
+
 /// DemuxerHandler implements demuxing of STUN/DTLS/RTP/RTCP Protocol packets
 #[derive(Default)]
 pub struct Demuxer {
@@ -67,25 +113,29 @@ impl RTCHandler for Demuxer {
         if let RTCMessage::Raw(message) = msg.message {
             if message.is_empty() {
                 error!("drop invalid packet due to zero length");
-            } else if match_dtls(&message) {
-                self.routs.push_back(Transmit {
-                    now: msg.now,
-                    transport: msg.transport,
-                    message: RTCMessage::Dtls(DTLSMessage::Raw(message)),
-                });
-            } else if match_srtp(&message) {
-                self.routs.push_back(Transmit {
-                    now: msg.now,
-                    transport: msg.transport,
-                    message: RTCMessage::Rtp(RTPMessage::Raw(message)),
-                });
-            } else {
-                self.routs.push_back(Transmit {
-                    now: msg.now,
-                    transport: msg.transport,
-                    message: RTCMessage::Stun(STUNMessage::Raw(message)),
-                });
+                return Ok(());
             }
+
+            let message = match demux(&message) {
+                PacketKind::Stun => RTCMessage::Stun(STUNMessage::Raw(message)),
+                PacketKind::Dtls => RTCMessage::Dtls(DTLSMessage::Raw(message)),
+                PacketKind::Rtp => RTCMessage::Rtp(RTPMessage::Raw(message)),
+                // No handler of the pipeline is a ZRTP or TURN client, these are forwarded as is
+                // to the application
+                PacketKind::Zrtp | PacketKind::TurnChannel => RTCMessage::Raw(message),
+                PacketKind::Unknown => {
+                    debug!(
+                        "drop packet with unknown first byte {} from {:?}",
+                        message[0], msg.transport.peer_addr
+                    );
+                    return Ok(());
+                }
+            };
+            self.routs.push_back(Transmit {
+                now: msg.now,
+                transport: msg.transport,
+                message,
+            });
         } else {
             debug!("drop non-RAW packet {:?}", msg.message);
         }
@@ -101,7 +151,8 @@ impl RTCHandler for Demuxer {
         match msg.message {
             RTCMessage::Stun(STUNMessage::Raw(message))
             | RTCMessage::Dtls(DTLSMessage::Raw(message))
-            | RTCMessage::Rtp(RTPMessage::Raw(message)) => self.wouts.push_back(Transmit {
+            | RTCMessage::Rtp(RTPMessage::Raw(message))
+            | RTCMessage::Raw(message) => self.wouts.push_back(Transmit {
                 now: msg.now,
                 transport: msg.transport,
                 message: RTCMessage::Raw(message),
@@ -118,3 +169,76 @@ impl RTCHandler for Demuxer {
         self.wouts.pop_front()
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_demux() {
+        let tests = vec![
+            (vec![], PacketKind::Unknown),
+            (vec![0], PacketKind::Stun),
+            (vec![3], PacketKind::Stun),
+            (vec![4], PacketKind::Unknown),
+            (vec![15], PacketKind::Unknown),
+            (vec![16], PacketKind::Zrtp),
+            (vec![19], PacketKind::Zrtp),
+            (vec![20], PacketKind::Dtls),
+            (vec![63], PacketKind::Dtls),
+            (vec![64], PacketKind::TurnChannel),
+            (vec![79], PacketKind::TurnChannel),
+            (vec![80], PacketKind::Unknown),
+            (vec![127], PacketKind::Unknown),
+            (vec![128], PacketKind::Rtp),
+            (vec![191], PacketKind::Rtp),
+            (vec![192], PacketKind::Unknown),
+            (vec![255], PacketKind::Unknown),
+        ];
+
+        for (buf, expected) in tests {
+            assert_eq!(demux(&buf), expected, "first byte {:?}", buf.first());
+        }
+    }
+
+    #[test]
+    fn test_demuxer_routes_by_first_byte() -> Result<()> {
+        use bytes::BytesMut;
+        use shared::{Protocol, TransportContext};
+        use std::time::Instant;
+
+        let mut demuxer = Demuxer::new();
+        let read = |demuxer: &mut Demuxer, first_byte: u8| -> Result<Option<RTCMessage>> {
+            demuxer.handle_read(Transmit {
+                now: Instant::now(),
+                transport: TransportContext {
+                    local_addr: "127.0.0.1:5000".parse().unwrap(),
+                    peer_addr: "127.0.0.1:5001".parse().unwrap(),
+                    protocol: Protocol::UDP,
+                    ecn: None,
+                },
+                message: RTCMessage::Raw(BytesMut::from(&[first_byte, 0, 0, 0][..])),
+            })?;
+            Ok(demuxer.poll_read().map(|transmit| transmit.message))
+        };
+
+        assert!(matches!(
+            read(&mut demuxer, 0)?,
+            Some(RTCMessage::Stun(STUNMessage::Raw(_)))
+        ));
+        assert!(matches!(
+            read(&mut demuxer, 22)?,
+            Some(RTCMessage::Dtls(DTLSMessage::Raw(_)))
+        ));
+        assert!(matches!(
+            read(&mut demuxer, 128)?,
+            Some(RTCMessage::Rtp(RTPMessage::Raw(_)))
+        ));
+        // TURN ChannelData and ZRTP are forwarded, not dropped
+        assert!(matches!(read(&mut demuxer, 64)?, Some(RTCMessage::Raw(_))));
+        assert!(matches!(read(&mut demuxer, 16)?, Some(RTCMessage::Raw(_))));
+        assert!(read(&mut demuxer, 100)?.is_none());
+
+        Ok(())
+    }
+}
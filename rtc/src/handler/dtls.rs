@@ -4,18 +4,25 @@ use std::sync::Arc;
 use std::time::{Duration, Instant};
 
 use crate::api::setting_engine::SettingEngine;
-use crate::messages::{DTLSMessage, RTCEvent, RTCMessage};
+use crate::messages::{DTLSMessage, RTCEvent, RTCMessage, RTPMessage};
+use crate::transport::dtls_transport::dtls_transport_state::RTCDtlsTransportState;
 use crate::transport::dtls_transport::RTCDtlsTransport;
+use dtls::alert::{AlertDescription, AlertLevel};
 use dtls::endpoint::EndpointEvent;
 use dtls::extension::extension_use_srtp::SrtpProtectionProfile;
 use dtls::state::State;
 use log::{debug, error, warn};
 use shared::error::{Error, Result};
 use shared::handler::RTCHandler;
+use shared::marshal::Unmarshal;
+use shared::util::is_rtcp;
 use shared::Transmit;
 use srtp::option::{srtcp_replay_protection, srtp_no_replay_protection, srtp_replay_protection};
 use srtp::protection_profile::ProtectionProfile;
 
+// Time a handshake may spend in the same state before poll_timeout logs its progress
+const HANDSHAKE_PROGRESS_LOG_THRESHOLD: Duration = Duration::from_secs(5);
+
 impl RTCHandler for RTCDtlsTransport {
     type Ein = ();
     type Eout = RTCEvent;
@@ -45,19 +52,7 @@ impl RTCHandler for RTCDtlsTransport {
                     )? {
                         match message {
                             EndpointEvent::HandshakeComplete => {
-                                if let Some(state) =
-                                    dtls_endpoint.get_connection_state(msg.transport.peer_addr)
-                                {
-                                    debug!("recv dtls handshake complete");
-                                    let (local_context, remote_context) =
-                                        update_srtp_contexts(state, &self.setting_engine)?;
-                                    contexts.push((local_context, remote_context));
-                                } else {
-                                    warn!(
-                                        "Unable to find connection state for {}",
-                                        msg.transport.peer_addr
-                                    );
-                                }
+                                debug!("recv dtls handshake complete");
                             }
                             EndpointEvent::ApplicationData(message) => {
                                 debug!("recv dtls application RAW {:?}", msg.transport.peer_addr);
@@ -66,6 +61,43 @@ impl RTCHandler for RTCDtlsTransport {
                         }
                     }
 
+                    // SRTP keys are exported from the DTLS master secret, so they are derived
+                    // again whenever the handshake completes at a new epoch, e.g. after a rekey
+                    let peer_addr = msg.transport.peer_addr;
+                    if dtls_endpoint.is_handshake_completed(peer_addr) == Some(true) {
+                        let epoch = dtls_endpoint.epoch(peer_addr);
+                        if epoch.is_some() && epoch != self.srtp_epoch {
+                            if let Some(state) = dtls_endpoint.get_connection_state(peer_addr) {
+                                // The epoch is recorded even without SRTP contexts, e.g. when no
+                                // SRTP profile was negotiated for a data channel only session,
+                                // so that this is only logged once per epoch
+                                match update_srtp_contexts(
+                                    state,
+                                    &self.setting_engine,
+                                    self.srtp_replay_protection_window,
+                                    self.srtcp_replay_protection_window,
+                                ) {
+                                    Ok(srtp_contexts) => {
+                                        contexts.push((epoch, Some(srtp_contexts)))
+                                    }
+                                    Err(Error::ErrSrtpNotNegotiated) => {
+                                        debug!(
+                                            "no srtp protection profile negotiated with {}",
+                                            peer_addr
+                                        );
+                                        contexts.push((epoch, None));
+                                    }
+                                    Err(err) => {
+                                        error!("update_srtp_contexts with error {}", err);
+                                        contexts.push((epoch, None));
+                                    }
+                                }
+                            } else {
+                                warn!("Unable to find connection state for {}", peer_addr);
+                            }
+                        }
+                    }
+
                     while let Some(transmit) = dtls_endpoint.poll_transmit() {
                         self.wouts.push_back(Transmit {
                             now: transmit.now,
@@ -75,9 +107,14 @@ impl RTCHandler for RTCDtlsTransport {
                     }
                 }
 
-                for (local_context, remote_context) in contexts {
-                    self.set_local_srtp_context(local_context);
-                    self.set_remote_srtp_context(remote_context);
+                for (epoch, srtp_contexts) in contexts {
+                    if let Some((local_context, remote_context)) = srtp_contexts {
+                        if self.srtp_epoch.is_some() {
+                            debug!("dtls rekeyed at epoch {:?}, replacing srtp contexts", epoch);
+                        }
+                        self.set_srtp_contexts(local_context, remote_context);
+                    }
+                    self.srtp_epoch = epoch;
                 }
 
                 Ok(messages)
@@ -96,19 +133,69 @@ impl RTCHandler for RTCDtlsTransport {
                 }
                 Err(err) => {
                     error!("try_read with error {}", err);
-                    if err == Error::ErrAlertFatalOrClose {
-                        if let Some(mut dtls_endpoint) = self.dtls_endpoint.take() {
-                            let _ = dtls_endpoint.close();
+                    let state = match &err {
+                        // close_notify from the peer closes the transport gracefully
+                        Error::DtlsAlert {
+                            description: AlertDescription::CloseNotify,
+                            ..
+                        } => RTCDtlsTransportState::Closed,
+                        Error::DtlsAlert {
+                            level: AlertLevel::Fatal,
+                            ..
+                        }
+                        | Error::ErrAlertFatalOrClose => RTCDtlsTransportState::Failed,
+                        // warning alerts leave the connection usable
+                        Error::DtlsAlert { .. } => return Ok(()),
+                        _ => return Err(err),
+                    };
+                    if let Some(mut dtls_endpoint) = self.dtls_endpoint.take() {
+                        let _ = dtls_endpoint.close();
+                        while let Some(transmit) = dtls_endpoint.poll_transmit() {
+                            self.wouts.push_back(Transmit {
+                                now: transmit.now,
+                                transport: transmit.transport,
+                                message: RTCMessage::Dtls(DTLSMessage::Raw(transmit.message)),
+                            });
                         }
-                    } else {
+                    }
+                    self.state_change(state);
+                    // a failure is also returned to the pipeline, as the other handlers do
+                    if state == RTCDtlsTransportState::Failed {
                         return Err(err);
                     }
                 }
             };
-        } else {
-            // Bypass
+        } else if let RTCMessage::Rtp(RTPMessage::Raw(message)) = msg.message {
+            // SRTP and SRTCP routed by the demuxer are decrypted with the remote context, which
+            // falls back to the one of the previous DTLS epoch right after a rekey
+            let decrypted = if is_rtcp(&message) {
+                self.decrypt_rtcp(&message).and_then(|mut decrypted| {
+                    Ok(RTPMessage::Rtcp(rtcp::packet::unmarshal(&mut decrypted)?))
+                })
+            } else {
+                self.decrypt_rtp(&message).and_then(|mut decrypted| {
+                    Ok(RTPMessage::Rtp(rtp::packet::Packet::unmarshal(
+                        &mut decrypted,
+                    )?))
+                })
+            };
+            match decrypted {
+                Ok(message) => self.routs.push_back(Transmit {
+                    now: msg.now,
+                    transport: msg.transport,
+                    message: RTCMessage::Rtp(message),
+                }),
+                Err(err) => debug!(
+                    "drop srtp packet from {:?} with error {}",
+                    msg.transport.peer_addr, err
+                ),
+            }
+        } else if matches!(msg.message, RTCMessage::Rtp(_) | RTCMessage::Raw(_)) {
+            // Routed by the demuxer to the handlers above
             debug!("bypass dtls read {:?}", msg.transport.peer_addr);
             self.routs.push_back(msg);
+        } else {
+            debug!("drop unexpected packet {:?}", msg.message);
         }
 
         Ok(())
@@ -162,14 +249,18 @@ impl RTCHandler for RTCDtlsTransport {
     }
 
     fn handle_timeout(&mut self, now: Instant) -> Result<()> {
-        let mut try_timeout = || -> Result<()> {
+        let mut try_timeout = || -> Result<bool> {
             let dtls_endpoint = self
                 .dtls_endpoint
                 .as_mut()
                 .ok_or(Error::ErrInvalidDTLSStart)?;
+            let mut handshake_timed_out = false;
             let remotes: Vec<SocketAddr> = dtls_endpoint.get_connections_keys().copied().collect();
             for remote in remotes {
-                let _ = dtls_endpoint.handle_timeout(remote, now);
+                if let Err(Error::ErrHandshakeTimeout) = dtls_endpoint.handle_timeout(remote, now) {
+                    error!("dtls handshake with {} timed out", remote);
+                    handshake_timed_out = true;
+                }
             }
             while let Some(transmit) = dtls_endpoint.poll_transmit() {
                 self.wouts.push_back(Transmit {
@@ -179,10 +270,14 @@ impl RTCHandler for RTCDtlsTransport {
                 });
             }
 
-            Ok(())
+            Ok(handshake_timed_out)
         };
         match try_timeout() {
-            Ok(_) => Ok(()),
+            Ok(false) => Ok(()),
+            Ok(true) => {
+                self.state_change(RTCDtlsTransportState::Failed);
+                Err(Error::ErrHandshakeTimeout)
+            }
             Err(err) => {
                 error!("try_timeout with error {}", err);
                 Err(err)
@@ -196,7 +291,24 @@ impl RTCHandler for RTCDtlsTransport {
             let mut eto = Instant::now() + Duration::from_secs(86400); // 1 day
             for remote in remotes {
                 let _ = dtls_endpoint.poll_timeout(*remote, &mut eto);
+                if dtls_endpoint.is_handshake_completed(*remote) == Some(false) {
+                    if let Some(progress) = dtls_endpoint.handshake_progress(*remote) {
+                        let logged = self.stalled_handshake_logs.get(remote);
+                        if progress.time_in_state > HANDSHAKE_PROGRESS_LOG_THRESHOLD
+                            && logged != Some(&(progress.flight.clone(), progress.state))
+                        {
+                            debug!(
+                                "dtls handshake with {} stalled in {} {} for {:?}",
+                                remote, progress.flight, progress.state, progress.time_in_state
+                            );
+                            self.stalled_handshake_logs
+                                .insert(*remote, (progress.flight, progress.state));
+                        }
+                    }
+                }
             }
+            self.stalled_handshake_logs
+                .retain(|remote, _| dtls_endpoint.is_handshake_completed(*remote) == Some(false));
             Some(eto)
         } else {
             None
@@ -204,15 +316,20 @@ impl RTCHandler for RTCDtlsTransport {
     }
 }
 
+/// Derives the SRTP contexts from the DTLS state. A non-zero srtp_replay_protection_window or
+/// srtcp_replay_protection_window overrides the setting engine for the remote context.
 pub(crate) fn update_srtp_contexts(
     state: &State,
     setting_engine: &Arc<SettingEngine>,
+    srtp_replay_protection_window: usize,
+    srtcp_replay_protection_window: usize,
 ) -> Result<(srtp::context::Context, srtp::context::Context)> {
     let profile = match state.srtp_protection_profile() {
         SrtpProtectionProfile::Srtp_Aes128_Cm_Hmac_Sha1_80 => {
             ProtectionProfile::Aes128CmHmacSha1_80
         }
         SrtpProtectionProfile::Srtp_Aead_Aes_128_Gcm => ProtectionProfile::AeadAes128Gcm,
+        SrtpProtectionProfile::Unsupported => return Err(Error::ErrSrtpNotNegotiated),
         _ => return Err(Error::ErrNoSuchSrtpProfile),
     };
 
@@ -220,16 +337,38 @@ pub(crate) fn update_srtp_contexts(
         profile,
         ..Default::default()
     };
-    if setting_engine.replay_protection.srtp != 0 {
+    if srtp_replay_protection_window != 0 {
+        srtp_config.remote_rtp_options =
+            Some(srtp_replay_protection(srtp_replay_protection_window));
+    } else if setting_engine.replay_protection.srtp != 0 {
         srtp_config.remote_rtp_options = Some(srtp_replay_protection(
             setting_engine.replay_protection.srtp,
         ));
     } else if setting_engine.disable_srtp_replay_protection {
         srtp_config.remote_rtp_options = Some(srtp_no_replay_protection());
     }
+    if srtcp_replay_protection_window != 0 {
+        srtp_config.remote_rtcp_options =
+            Some(srtcp_replay_protection(srtcp_replay_protection_window));
+    }
 
     srtp_config.extract_session_keys_from_dtls(state, false)?;
 
+    let (mut local_context, mut remote_context) = srtp_contexts_from_keys(srtp_config)?;
+
+    // Both directions use the MKI negotiated in use_srtp, if any
+    local_context.set_mki(state.srtp_mki().to_vec());
+    remote_context.set_mki(state.srtp_mki().to_vec());
+
+    Ok((local_context, remote_context))
+}
+
+/// Builds the local and remote SRTP contexts from the keys, profile and options of srtp_config,
+/// for keys exchanged out of band, e.g. with SDES, rather than exported from DTLS. Unless set
+/// in srtp_config, replay protection of the remote context uses the default session windows.
+pub fn srtp_contexts_from_keys(
+    srtp_config: srtp::config::Config,
+) -> Result<(srtp::context::Context, srtp::context::Context)> {
     let local_context = srtp::context::Context::new(
         &srtp_config.keys.local_master_key,
         &srtp_config.keys.local_master_salt,
@@ -260,3 +399,420 @@ pub(crate) fn update_srtp_contexts(
 
     Ok((local_context, remote_context))
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_srtp_contexts_from_keys() -> Result<()> {
+        let profile = ProtectionProfile::Aes128CmHmacSha1_80;
+        let keys = |local: u8, remote: u8| srtp::config::SessionKeys {
+            local_master_key: vec![local; profile.key_len()],
+            local_master_salt: vec![local; profile.salt_len()],
+            remote_master_key: vec![remote; profile.key_len()],
+            remote_master_salt: vec![remote; profile.salt_len()],
+        };
+        let (mut a_local, _) = srtp_contexts_from_keys(srtp::config::Config {
+            keys: keys(1, 2),
+            profile,
+            ..Default::default()
+        })?;
+        let (_, mut b_remote) = srtp_contexts_from_keys(srtp::config::Config {
+            keys: keys(2, 1),
+            profile,
+            ..Default::default()
+        })?;
+
+        // What one side encrypts with its local keys, the other decrypts with its remote keys
+        let rtp = [
+            0x80, 0x60, 0x00, 0x01, 0x00, 0x00, 0x00, 0x01, 0x12, 0x34, 0x56, 0x78, 0xca, 0xfe,
+        ];
+        let encrypted = a_local.encrypt_rtp(&rtp)?;
+        assert_ne!(&encrypted[..], &rtp[..]);
+        assert_eq!(&b_remote.decrypt_rtp(&encrypted)?[..], &rtp[..]);
+
+        // Keys of the wrong length are rejected
+        assert!(srtp_contexts_from_keys(srtp::config::Config {
+            keys: srtp::config::SessionKeys::default(),
+            profile,
+            ..Default::default()
+        })
+        .is_err());
+
+        Ok(())
+    }
+
+    // Runs a DTLS handshake negotiating SRTP, returning the client and server states
+    fn srtp_handshake() -> Result<(State, State)> {
+        use dtls::config::ConfigBuilder;
+        use dtls::crypto::Certificate;
+        use dtls::endpoint::Endpoint;
+
+        let client_addr: SocketAddr = "127.0.0.1:5000".parse().unwrap();
+        let server_addr: SocketAddr = "127.0.0.1:5001".parse().unwrap();
+        let profiles = vec![SrtpProtectionProfile::Srtp_Aes128_Cm_Hmac_Sha1_80];
+        let mut client = Endpoint::new(client_addr, shared::Protocol::UDP, None);
+        let mut server = Endpoint::new(
+            server_addr,
+            shared::Protocol::UDP,
+            Some(Arc::new(
+                ConfigBuilder::default()
+                    .with_certificates(vec![Certificate::generate_self_signed(vec![
+                        "localhost".to_owned()
+                    ])?])
+                    .with_srtp_protection_profiles(profiles.clone())
+                    .build(false, None)?,
+            )),
+        );
+        client.connect(
+            server_addr,
+            Arc::new(
+                ConfigBuilder::default()
+                    .with_insecure_skip_verify(true)
+                    .with_srtp_protection_profiles(profiles)
+                    .build(true, Some(server_addr))?,
+            ),
+            None,
+        )?;
+        let now = Instant::now();
+        for _ in 0..10 {
+            while let Some(transmit) = client.poll_transmit() {
+                server.read(now, client_addr, None, transmit.message)?;
+            }
+            while let Some(transmit) = server.poll_transmit() {
+                client.read(now, server_addr, None, transmit.message)?;
+            }
+        }
+
+        let mut client_state = State::default();
+        client_state.unmarshal_binary(
+            &client
+                .get_connection_state(server_addr)
+                .ok_or(Error::ErrConnectionClosed)?
+                .marshal_binary()?,
+        )?;
+        let mut server_state = State::default();
+        server_state.unmarshal_binary(
+            &server
+                .get_connection_state(client_addr)
+                .ok_or(Error::ErrConnectionClosed)?
+                .marshal_binary()?,
+        )?;
+        Ok((client_state, server_state))
+    }
+
+    #[test]
+    fn test_dtls_state() -> Result<()> {
+        use crate::transport::dtls_transport::dtls_transport_state::RTCDtlsTransportState;
+        use dtls::config::ConfigBuilder;
+        use dtls::crypto::Certificate;
+        use dtls::endpoint::Endpoint;
+        use shared::TransportContext;
+
+        let client_addr: SocketAddr = "127.0.0.1:5000".parse().unwrap();
+        let server_addr: SocketAddr = "127.0.0.1:5001".parse().unwrap();
+        let profiles = vec![SrtpProtectionProfile::Srtp_Aes128_Cm_Hmac_Sha1_80];
+        let client_config = || {
+            ConfigBuilder::default()
+                .with_insecure_skip_verify(true)
+                .with_srtp_protection_profiles(profiles.clone())
+                .with_handshake_timeout(Duration::from_secs(5))
+                .build(true, Some(server_addr))
+        };
+        let start = |transport: &mut RTCDtlsTransport| -> Result<()> {
+            let mut dtls_endpoint = Endpoint::new(client_addr, shared::Protocol::UDP, None);
+            dtls_endpoint.connect(server_addr, Arc::new(client_config()?), None)?;
+            transport.dtls_endpoint = Some(dtls_endpoint);
+            transport.state_change(RTCDtlsTransportState::Connecting);
+            Ok(())
+        };
+
+        let mut transport = RTCDtlsTransport::new(vec![], Arc::new(SettingEngine::default()));
+        assert_eq!(transport.dtls_state(), RTCDtlsTransportState::New);
+        start(&mut transport)?;
+        assert_eq!(transport.dtls_state(), RTCDtlsTransportState::Connecting);
+
+        // The handshake completes while the transport itself is still connecting
+        let mut server = Endpoint::new(
+            server_addr,
+            shared::Protocol::UDP,
+            Some(Arc::new(
+                ConfigBuilder::default()
+                    .with_certificates(vec![Certificate::generate_self_signed(vec![
+                        "localhost".to_owned()
+                    ])?])
+                    .with_srtp_protection_profiles(profiles.clone())
+                    .build(false, None)?,
+            )),
+        );
+        let now = Instant::now();
+        // Flushes the ClientHello queued by connect
+        transport.handle_timeout(now)?;
+        for _ in 0..10 {
+            while let Some(transmit) = transport.poll_write() {
+                if let RTCMessage::Dtls(DTLSMessage::Raw(message)) = transmit.message {
+                    server.read(now, client_addr, None, message)?;
+                }
+            }
+            while let Some(transmit) = server.poll_transmit() {
+                transport.handle_read(Transmit {
+                    now,
+                    transport: TransportContext {
+                        local_addr: client_addr,
+                        peer_addr: server_addr,
+                        protocol: shared::Protocol::UDP,
+                        ecn: None,
+                    },
+                    message: RTCMessage::Dtls(DTLSMessage::Raw(transmit.message)),
+                })?;
+            }
+        }
+        assert_eq!(transport.state(), RTCDtlsTransportState::Connecting);
+        assert_eq!(transport.dtls_state(), RTCDtlsTransportState::Connected);
+
+        // A handshake the server never answers times out
+        let mut transport = RTCDtlsTransport::new(vec![], Arc::new(SettingEngine::default()));
+        start(&mut transport)?;
+        while transport.poll_write().is_some() {}
+        assert_eq!(
+            transport.handle_timeout(Instant::now() + Duration::from_secs(5)),
+            Err(Error::ErrHandshakeTimeout)
+        );
+        assert_eq!(transport.dtls_state(), RTCDtlsTransportState::Failed);
+
+        // A fatal alert from the server fails the transport and is raised to the pipeline
+        let mut transport = RTCDtlsTransport::new(vec![], Arc::new(SettingEngine::default()));
+        start(&mut transport)?;
+        let alert = [
+            21, 0xfe, 0xfd, 0, 0, 0, 0, 0, 0, 0, 0, 0, 2, // record layer header
+            2, 40, // fatal handshake_failure
+        ];
+        let result = transport.handle_read(Transmit {
+            now,
+            transport: TransportContext {
+                local_addr: client_addr,
+                peer_addr: server_addr,
+                protocol: shared::Protocol::UDP,
+                ecn: None,
+            },
+            message: RTCMessage::Dtls(DTLSMessage::Raw(BytesMut::from(&alert[..]))),
+        });
+        assert!(
+            matches!(
+                result,
+                Err(Error::DtlsAlert {
+                    level: AlertLevel::Fatal,
+                    ..
+                })
+            ),
+            "{result:?}"
+        );
+        assert_eq!(transport.dtls_state(), RTCDtlsTransportState::Failed);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_srtp_replay_protection_window_override() -> Result<()> {
+        let mut transport = RTCDtlsTransport::default();
+        assert_eq!(
+            transport.set_srtp_replay_protection_window(100),
+            Err(Error::ErrInvalidReplayProtectionWindow)
+        );
+        assert_eq!(
+            transport.set_srtcp_replay_protection_window(100),
+            Err(Error::ErrInvalidReplayProtectionWindow)
+        );
+        transport.set_srtp_replay_protection_window(256)?;
+        transport.set_srtcp_replay_protection_window(256)?;
+
+        let (client_state, server_state) = srtp_handshake()?;
+        let setting_engine = Arc::new(SettingEngine::default());
+        let rtp = |sequence_number: u16| {
+            let mut rtp = [
+                0x80, 0x60, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01, 0x12, 0x34, 0x56, 0x78, 0xca, 0xfe,
+            ];
+            rtp[2..4].copy_from_slice(&sequence_number.to_be_bytes());
+            rtp
+        };
+        // Whether a packet 100 sequence numbers behind the latest one is still accepted
+        let accepts_late_packet = |transport: &RTCDtlsTransport| -> Result<bool> {
+            // The client sends, the server receives
+            let mut client_config = srtp::config::Config {
+                profile: ProtectionProfile::Aes128CmHmacSha1_80,
+                ..Default::default()
+            };
+            client_config.extract_session_keys_from_dtls(&client_state, true)?;
+            let (mut local, _) = srtp_contexts_from_keys(client_config)?;
+            let (_, mut remote) = update_srtp_contexts(
+                &server_state,
+                &setting_engine,
+                transport.srtp_replay_protection_window,
+                transport.srtcp_replay_protection_window,
+            )?;
+            let late = local.encrypt_rtp(&rtp(100))?;
+            let latest = local.encrypt_rtp(&rtp(200))?;
+            remote.decrypt_rtp(&latest)?;
+            Ok(remote.decrypt_rtp(&late).is_ok())
+        };
+        assert!(accepts_late_packet(&transport)?);
+
+        // 0 clears the override, the default window is too small for the late packet
+        transport.set_srtp_replay_protection_window(0)?;
+        transport.set_srtcp_replay_protection_window(0)?;
+        assert_eq!(transport.srtcp_replay_protection_window, 0);
+        assert!(!accepts_late_packet(&transport)?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_application_data_without_srtp() -> Result<()> {
+        use dtls::config::ConfigBuilder;
+        use dtls::crypto::Certificate;
+        use dtls::endpoint::Endpoint;
+        use shared::TransportContext;
+
+        let client_addr: SocketAddr = "127.0.0.1:5000".parse().unwrap();
+        let server_addr: SocketAddr = "127.0.0.1:5001".parse().unwrap();
+        let mut dtls_endpoint = Endpoint::new(client_addr, shared::Protocol::UDP, None);
+        dtls_endpoint.connect(
+            server_addr,
+            Arc::new(
+                ConfigBuilder::default()
+                    .with_insecure_skip_verify(true)
+                    .build(true, Some(server_addr))?,
+            ),
+            None,
+        )?;
+        let mut transport = RTCDtlsTransport::new(vec![], Arc::new(SettingEngine::default()));
+        transport.dtls_endpoint = Some(dtls_endpoint);
+        let mut server = Endpoint::new(
+            server_addr,
+            shared::Protocol::UDP,
+            Some(Arc::new(
+                ConfigBuilder::default()
+                    .with_certificates(vec![Certificate::generate_self_signed(vec![
+                        "localhost".to_owned()
+                    ])?])
+                    .build(false, None)?,
+            )),
+        );
+
+        let now = Instant::now();
+        let read = |transport: &mut RTCDtlsTransport, server: &mut Endpoint| -> Result<()> {
+            while let Some(transmit) = transport.poll_write() {
+                if let RTCMessage::Dtls(DTLSMessage::Raw(message)) = transmit.message {
+                    server.read(now, client_addr, None, message)?;
+                }
+            }
+            while let Some(transmit) = server.poll_transmit() {
+                transport.handle_read(Transmit {
+                    now,
+                    transport: TransportContext {
+                        local_addr: client_addr,
+                        peer_addr: server_addr,
+                        protocol: shared::Protocol::UDP,
+                        ecn: None,
+                    },
+                    message: RTCMessage::Dtls(DTLSMessage::Raw(transmit.message)),
+                })?;
+            }
+            Ok(())
+        };
+        transport.handle_timeout(now)?;
+        for _ in 0..10 {
+            read(&mut transport, &mut server)?;
+        }
+        assert_eq!(server.is_handshake_completed(client_addr), Some(true));
+        assert_eq!(transport.srtp_epoch, Some((1, 1)));
+        assert!(transport.local_srtp_context.is_none());
+        assert!(transport.remote_srtp_context.is_none());
+
+        // Application data is still delivered, on every read
+        for data in [&b"hello"[..], &b"world"[..]] {
+            server.write(client_addr, data)?;
+            read(&mut transport, &mut server)?;
+            match transport.poll_read().map(|transmit| transmit.message) {
+                Some(RTCMessage::Dtls(DTLSMessage::Raw(message))) => assert_eq!(&message[..], data),
+                message => panic!("unexpected {message:?}"),
+            }
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_srtp_contexts_handover_on_rekey() -> Result<()> {
+        use shared::TransportContext;
+
+        let profile = ProtectionProfile::Aes128CmHmacSha1_80;
+        let contexts = |local: u8, remote: u8| {
+            srtp_contexts_from_keys(srtp::config::Config {
+                keys: srtp::config::SessionKeys {
+                    local_master_key: vec![local; profile.key_len()],
+                    local_master_salt: vec![local; profile.salt_len()],
+                    remote_master_key: vec![remote; profile.key_len()],
+                    remote_master_salt: vec![remote; profile.salt_len()],
+                },
+                profile,
+                ..Default::default()
+            })
+        };
+        let rtp = |sequence_number: u8| {
+            let mut rtp = [
+                0x80, 0x60, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01, 0x12, 0x34, 0x56, 0x78, 0xca, 0xfe,
+            ];
+            rtp[3] = sequence_number;
+            rtp
+        };
+
+        let mut transport = RTCDtlsTransport::default();
+        assert_eq!(
+            transport.decrypt_rtp(&rtp(1)).err(),
+            Some(Error::ErrSrtpContextNotReady)
+        );
+        let srtp = |encrypted: BytesMut| Transmit {
+            now: Instant::now(),
+            transport: TransportContext {
+                local_addr: "127.0.0.1:5000".parse().unwrap(),
+                peer_addr: "127.0.0.1:5001".parse().unwrap(),
+                protocol: shared::Protocol::UDP,
+                ecn: None,
+            },
+            message: RTCMessage::Rtp(RTPMessage::Raw(encrypted)),
+        };
+        // An SRTP packet before the contexts are derived is dropped
+        transport.handle_read(srtp(BytesMut::from(&rtp(1)[..])))?;
+        assert!(transport.poll_read().is_none());
+
+        let (mut peer_local, _) = contexts(2, 1)?;
+        let (local, remote) = contexts(1, 2)?;
+        transport.set_srtp_contexts(local, remote);
+        let encrypted = peer_local.encrypt_rtp(&rtp(1))?;
+        assert_eq!(&transport.decrypt_rtp(&encrypted)?[..], &rtp(1)[..]);
+
+        // After a rekey, packets the peer sent with the old keys still decrypt
+        let in_flight = peer_local.encrypt_rtp(&rtp(2))?;
+        let (mut peer_local, _) = contexts(4, 3)?;
+        let (local, remote) = contexts(3, 4)?;
+        transport.set_srtp_contexts(local, remote);
+        assert_eq!(&transport.decrypt_rtp(&in_flight)?[..], &rtp(2)[..]);
+        assert!(transport.previous_remote_srtp_context.is_some());
+
+        // Until the new keys authenticate a packet, read here through the SRTP receive path
+        let encrypted = peer_local.encrypt_rtp(&rtp(3))?;
+        transport.handle_read(srtp(encrypted))?;
+        match transport.poll_read().map(|transmit| transmit.message) {
+            Some(RTCMessage::Rtp(RTPMessage::Rtp(packet))) => {
+                assert_eq!(packet.header.sequence_number, 3);
+                assert_eq!(&packet.payload[..], &[0xca, 0xfe]);
+            }
+            message => panic!("unexpected {message:?}"),
+        }
+        assert!(transport.previous_remote_srtp_context.is_none());
+
+        Ok(())
+    }
+}
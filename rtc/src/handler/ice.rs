@@ -1,5 +1,6 @@
 use crate::messages::{RTCEvent, RTCMessage, STUNMessage};
 use crate::transport::ice_transport::ice_candidate_pair::RTCIceCandidatePair;
+use crate::transport::ice_transport::ice_gatherer::IceGathererEvent;
 use crate::transport::ice_transport::{IceTransportEvent, RTCIceTransport};
 use bytes::BytesMut;
 use ice::Event;
@@ -69,22 +70,31 @@ impl RTCHandler for RTCIceTransport {
     }
 
     fn poll_event(&mut self) -> Option<RTCEvent> {
-        if let Some(event) = self.gatherer.agent.poll_event() {
+        while let Some(event) = self.gatherer.agent.poll_event() {
             match event {
-                Event::ConnectionStateChange(state) => Some(RTCEvent::IceTransportEvent(
-                    IceTransportEvent::OnConnectionStateChange(state.into()),
-                )),
+                Event::ConnectionStateChange(state) => {
+                    return Some(RTCEvent::IceTransportEvent(
+                        IceTransportEvent::OnConnectionStateChange(state.into()),
+                    ))
+                }
                 Event::SelectedCandidatePairChange(local, remote) => {
-                    Some(RTCEvent::IceTransportEvent(
+                    return Some(RTCEvent::IceTransportEvent(
                         IceTransportEvent::OnSelectedCandidatePairChange(Box::new(
                             RTCIceCandidatePair::new((&*local).into(), (&*remote).into()),
                         )),
                     ))
                 }
+                Event::NewCandidate(candidate) => self
+                    .gatherer
+                    .events
+                    .push_back(IceGathererEvent::OnLocalCandidate((&*candidate).into())),
+                Event::GatheringComplete => self
+                    .gatherer
+                    .events
+                    .push_back(IceGathererEvent::OnGatheringComplete),
             }
-        } else {
-            None
         }
+        None
     }
 
     /// Handles a timeout event
@@ -0,0 +1,152 @@
+use super::append_candidates;
+use ice::state::GatheringState;
+use ice::Agent;
+use sdp::description::common::Attribute;
+use sdp::description::media::MediaDescription;
+
+const ATTR_KEY_ICE_UFRAG: &str = "ice-ufrag";
+const ATTR_KEY_ICE_PWD: &str = "ice-pwd";
+const ATTR_KEY_ICE_OPTIONS: &str = "ice-options";
+const ICE_OPTION_TRICKLE: &str = "trickle";
+
+/// Writes the ICE state of the agent into the media description: its local credentials,
+/// `a=ice-options:trickle`, a `candidate` attribute for every local candidate and
+/// `a=end-of-candidates` once the agent completed gathering. It can be called again as
+/// candidates are gathered, only the candidates not in the media description yet are appended.
+pub fn add_ice_to_media_description(m: &mut MediaDescription, agent: &Agent) {
+    let credentials = agent.get_local_credentials();
+    set_value_attribute(m, ATTR_KEY_ICE_UFRAG, &credentials.ufrag);
+    set_value_attribute(m, ATTR_KEY_ICE_PWD, &credentials.pwd);
+    add_ice_option(m, ICE_OPTION_TRICKLE);
+
+    *m = append_candidates(
+        std::mem::take(m),
+        agent.get_local_candidates(),
+        agent.gathering_state() == GatheringState::Complete,
+    );
+}
+
+// Sets the value of the attribute, e.g. with the new credentials after an ICE restart, or
+// appends it if the media description doesn't have it yet
+fn set_value_attribute(m: &mut MediaDescription, key: &str, value: &str) {
+    if let Some(a) = m.attributes.iter_mut().find(|a| a.key == key) {
+        a.value = Some(value.to_owned());
+    } else {
+        m.attributes
+            .push(Attribute::new(key.to_owned(), Some(value.to_owned())));
+    }
+}
+
+// Adds the option to the space separated ice-options attribute, keeping the other options
+fn add_ice_option(m: &mut MediaDescription, option: &str) {
+    if let Some(a) = m
+        .attributes
+        .iter_mut()
+        .find(|a| a.key == ATTR_KEY_ICE_OPTIONS)
+    {
+        let options = a.value.get_or_insert_with(String::new);
+        if !options.split_whitespace().any(|o| o == option) {
+            if !options.is_empty() {
+                options.push(' ');
+            }
+            options.push_str(option);
+        }
+    } else {
+        m.attributes.push(Attribute::new(
+            ATTR_KEY_ICE_OPTIONS.to_owned(),
+            Some(option.to_owned()),
+        ));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use ice::candidate::candidate_host::CandidateHostConfig;
+    use ice::candidate::{CandidateConfig, CandidateType};
+    use ice::AgentConfig;
+    use sdp::description::session::{ATTR_KEY_CANDIDATE, ATTR_KEY_END_OF_CANDIDATES};
+    use shared::error::Result;
+    use std::sync::Arc;
+
+    fn new_host_candidate(port: u16) -> Result<ice::candidate::Candidate> {
+        CandidateHostConfig {
+            base_config: CandidateConfig {
+                network: "udp".to_owned(),
+                address: "192.168.0.1".to_owned(),
+                port,
+                component: 1,
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+        .new_candidate_host()
+    }
+
+    #[test]
+    fn test_add_ice_to_media_description() -> Result<()> {
+        let mut agent = Agent::new(Arc::new(AgentConfig::default()))?;
+        let mut m = MediaDescription::default()
+            .with_value_attribute(ATTR_KEY_ICE_OPTIONS.to_owned(), "renomination".to_owned());
+
+        agent.gather_candidates()?;
+        agent.add_local_candidate(new_host_candidate(5000)?)?;
+        add_ice_to_media_description(&mut m, &agent);
+
+        let credentials = agent.get_local_credentials();
+        assert_eq!(
+            m.attribute(ATTR_KEY_ICE_UFRAG),
+            Some(Some(credentials.ufrag.as_str()))
+        );
+        assert_eq!(
+            m.attribute(ATTR_KEY_ICE_PWD),
+            Some(Some(credentials.pwd.as_str()))
+        );
+        assert_eq!(
+            m.attribute(ATTR_KEY_ICE_OPTIONS),
+            Some(Some("renomination trickle"))
+        );
+        let candidates = |m: &MediaDescription| {
+            m.attributes
+                .iter()
+                .filter(|a| a.key == ATTR_KEY_CANDIDATE)
+                .filter_map(|a| a.value.clone())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            candidates(&m),
+            vec![agent.get_local_candidates()[0].marshal()]
+        );
+        assert_eq!(m.attribute(ATTR_KEY_END_OF_CANDIDATES), None);
+
+        // Only the new candidate is appended, and end-of-candidates once gathering completed
+        agent.add_local_candidate(new_host_candidate(5001)?)?;
+        agent.candidate_type_gathered(CandidateType::Host);
+        assert!(agent.gathering_state() == GatheringState::Complete);
+        add_ice_to_media_description(&mut m, &agent);
+        add_ice_to_media_description(&mut m, &agent);
+
+        assert_eq!(
+            candidates(&m),
+            agent
+                .get_local_candidates()
+                .iter()
+                .map(|c| c.marshal())
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(m.attribute(ATTR_KEY_END_OF_CANDIDATES), Some(None));
+        assert_eq!(
+            m.attributes
+                .iter()
+                .filter(|a| a.key == ATTR_KEY_ICE_UFRAG || a.key == ATTR_KEY_END_OF_CANDIDATES)
+                .count(),
+            2
+        );
+        assert_eq!(
+            m.attribute(ATTR_KEY_ICE_OPTIONS),
+            Some(Some("renomination trickle"))
+        );
+
+        Ok(())
+    }
+}
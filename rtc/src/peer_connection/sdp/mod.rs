@@ -17,6 +17,7 @@ use crate::rtp_transceiver::{Mid, PayloadType, RTCPFeedback, RTCRtpTransceiver};
 
 use shared::error::{Error, Result};
 
+pub mod ice_candidates;
 pub mod sdp_type;
 pub mod session_description;
 
@@ -294,42 +295,50 @@ pub(crate) fn get_rids(media: &MediaDescription) -> Vec<SimulcastRid> {
 
 pub(crate) fn add_candidates_to_media_descriptions(
     candidates: &[RTCIceCandidate],
-    mut m: MediaDescription,
+    m: MediaDescription,
     ice_gathering_state: RTCIceGatheringState,
 ) -> Result<MediaDescription> {
-    let append_candidate_if_new = |c: &Candidate, m: MediaDescription| -> MediaDescription {
-        let marshaled = c.marshal();
-        for a in &m.attributes {
-            if let Some(value) = &a.value {
-                if &marshaled == value {
-                    return m;
-                }
-            }
-        }
-
-        m.with_value_attribute("candidate".to_owned(), marshaled)
-    };
-
+    let mut ice_candidates = Vec::with_capacity(candidates.len() * 2);
     for c in candidates {
         let mut candidate = c.to_ice()?;
 
         candidate.set_component(1);
-        m = append_candidate_if_new(&candidate, m);
+        ice_candidates.push(candidate.clone());
 
         candidate.set_component(2);
-        m = append_candidate_if_new(&candidate, m);
+        ice_candidates.push(candidate);
     }
 
-    if ice_gathering_state != RTCIceGatheringState::Complete {
-        return Ok(m);
-    }
-    for a in &m.attributes {
-        if &a.key == "end-of-candidates" {
-            return Ok(m);
+    Ok(append_candidates(
+        m,
+        &ice_candidates,
+        ice_gathering_state == RTCIceGatheringState::Complete,
+    ))
+}
+
+// Appends a candidate attribute for each candidate not in the media description yet, and
+// end-of-candidates once gathering is complete unless it is already there
+pub(crate) fn append_candidates<'a>(
+    mut m: MediaDescription,
+    candidates: impl IntoIterator<Item = &'a Candidate>,
+    gathering_complete: bool,
+) -> MediaDescription {
+    for c in candidates {
+        let marshaled = c.marshal();
+        let is_new = !m
+            .attributes
+            .iter()
+            .any(|a| a.key == ATTR_KEY_CANDIDATE && a.value.as_deref() == Some(marshaled.as_str()));
+        if is_new {
+            m = m.with_value_attribute(ATTR_KEY_CANDIDATE.to_owned(), marshaled);
         }
     }
 
-    Ok(m.with_property_attribute("end-of-candidates".to_owned()))
+    if gathering_complete && m.attribute(ATTR_KEY_END_OF_CANDIDATES).is_none() {
+        m = m.with_property_attribute(ATTR_KEY_END_OF_CANDIDATES.to_owned());
+    }
+
+    m
 }
 
 pub(crate) struct AddDataMediaSectionParams {
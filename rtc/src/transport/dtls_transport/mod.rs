@@ -1,11 +1,13 @@
-use std::collections::{/*HashMap,*/ VecDeque};
+use std::collections::{HashMap, VecDeque};
+use std::net::SocketAddr;
 use std::sync::Arc;
 
-use bytes::Bytes;
+use bytes::{Bytes, BytesMut};
 //use retty::transport::Protocol;
 //use dtls::config::ClientAuthType;
 //use dtls::conn::DTLSConn;
 use dtls::extension::extension_use_srtp::SrtpProtectionProfile;
+use dtls::handshaker::HandshakeState;
 use dtls_role::*;
 //use interceptor::stream_info::StreamInfo;
 //use interceptor::{Interceptor, RTCPReader, RTPReader};
@@ -24,7 +26,7 @@ use crate::transports::ice_transport::RTCIceTransport;*/
 use crate::peer_connection::certificate::RTCCertificate;
 //use crate::rtp_transceiver::SSRC;
 use crate::constants::DEFAULT_DTLS_REPLAY_PROTECTION_WINDOW;
-use crate::messages::RTCMessage;
+use crate::messages::{DTLSMessage, RTCMessage};
 use crate::stats::stats_collector::StatsCollector;
 use shared::error::{Error, Result};
 use shared::Transmit;
@@ -64,8 +66,19 @@ pub struct RTCDtlsTransport {
     pub(crate) srtp_protection_profile: ProtectionProfile,
     pub(crate) local_srtp_context: Option<Context>,
     pub(crate) remote_srtp_context: Option<Context>,
+    // remote context of the previous DTLS epoch, kept after a rekey for the packets the peer
+    // sent with the old keys until the new context authenticates a packet
+    pub(crate) previous_remote_srtp_context: Option<Context>,
+    // (local, remote) DTLS epoch the SRTP contexts were derived at
+    pub(crate) srtp_epoch: Option<(u16, u16)>,
+    // replay protection windows overriding the setting engine for this transport, 0 if unset
+    pub(crate) srtp_replay_protection_window: usize,
+    pub(crate) srtcp_replay_protection_window: usize,
 
     pub(crate) dtls_endpoint: Option<dtls::endpoint::Endpoint>,
+    // (flight, state) a stalled handshake was last logged in per remote, so that poll_timeout
+    // logs it once per flight and state
+    pub(crate) stalled_handshake_logs: HashMap<SocketAddr, (String, HandshakeState)>,
 
     pub(crate) events: VecDeque<DtlsTransportEvent>,
     pub(crate) routs: VecDeque<Transmit<RTCMessage>>,
@@ -86,7 +99,7 @@ impl RTCDtlsTransport {
     }
 
     /// state_change requires the caller holds the lock
-    fn state_change(&mut self, state: RTCDtlsTransportState) {
+    pub(crate) fn state_change(&mut self, state: RTCDtlsTransportState) {
         self.state = state;
         self.events
             .push_back(DtlsTransportEvent::OnDtlsTransportStateChange(state));
@@ -97,6 +110,49 @@ impl RTCDtlsTransport {
         self.state
     }
 
+    /// dtls_state returns the DTLS handshake progress derived from the underlying endpoint,
+    /// which distinguishes a handshake in flight from one that has completed.
+    pub fn dtls_state(&self) -> RTCDtlsTransportState {
+        if self.state != RTCDtlsTransportState::Connecting {
+            return self.state;
+        }
+
+        if let Some(dtls_endpoint) = &self.dtls_endpoint {
+            let is_handshake_completed = dtls_endpoint.get_connections_keys().any(|remote| {
+                dtls_endpoint
+                    .is_handshake_completed(*remote)
+                    .unwrap_or(false)
+            });
+            if is_handshake_completed {
+                return RTCDtlsTransportState::Connected;
+            }
+        }
+
+        RTCDtlsTransportState::Connecting
+    }
+
+    /// set_srtp_replay_protection_window sets the replay attack protection window size of the
+    /// srtp session of this transport, taking precedence over the setting engine.
+    /// The window size must be a power of two, or 0 to fall back to the setting engine.
+    pub fn set_srtp_replay_protection_window(&mut self, n: usize) -> Result<()> {
+        if n != 0 && !n.is_power_of_two() {
+            return Err(Error::ErrInvalidReplayProtectionWindow);
+        }
+        self.srtp_replay_protection_window = n;
+        Ok(())
+    }
+
+    /// set_srtcp_replay_protection_window sets the replay attack protection window size of the
+    /// srtcp session of this transport, taking precedence over the setting engine.
+    /// The window size must be a power of two, or 0 to fall back to the setting engine.
+    pub fn set_srtcp_replay_protection_window(&mut self, n: usize) -> Result<()> {
+        if n != 0 && !n.is_power_of_two() {
+            return Err(Error::ErrInvalidReplayProtectionWindow);
+        }
+        self.srtcp_replay_protection_window = n;
+        Ok(())
+    }
+
     /// get_local_parameters returns the DTLS parameters of the local DTLSTransport upon construction.
     pub fn get_local_parameters(&self) -> Result<DTLSParameters> {
         let mut fingerprints = vec![];
@@ -183,22 +239,73 @@ impl RTCDtlsTransport {
         Ok(handshake_config)
     }
 
-    pub(crate) fn set_local_srtp_context(&mut self, local_srtp_context: Context) {
+    /// Replaces both SRTP contexts together, so that both directions always use keys exported
+    /// at the same DTLS epoch. The replaced remote context keeps decrypting the packets the peer
+    /// sent before it rekeyed, until the new one authenticates a packet.
+    pub(crate) fn set_srtp_contexts(
+        &mut self,
+        local_srtp_context: Context,
+        remote_srtp_context: Context,
+    ) {
         self.local_srtp_context = Some(local_srtp_context);
+        self.previous_remote_srtp_context = self.remote_srtp_context.replace(remote_srtp_context);
+    }
+
+    /// Decrypts an SRTP packet with the remote context, or with the one of the previous DTLS
+    /// epoch for a packet sent before the peer rekeyed.
+    pub(crate) fn decrypt_rtp(&mut self, encrypted: &[u8]) -> Result<BytesMut> {
+        self.decrypt_with_remote_srtp_context(|context| context.decrypt_rtp(encrypted))
     }
 
-    pub(crate) fn set_remote_srtp_context(&mut self, remote_srtp_context: Context) {
-        self.remote_srtp_context = Some(remote_srtp_context);
+    /// Decrypts an SRTCP packet with the remote context, or with the one of the previous DTLS
+    /// epoch for a packet sent before the peer rekeyed.
+    pub(crate) fn decrypt_rtcp(&mut self, encrypted: &[u8]) -> Result<BytesMut> {
+        self.decrypt_with_remote_srtp_context(|context| context.decrypt_rtcp(encrypted))
+    }
+
+    fn decrypt_with_remote_srtp_context(
+        &mut self,
+        mut decrypt: impl FnMut(&mut Context) -> Result<BytesMut>,
+    ) -> Result<BytesMut> {
+        let remote_srtp_context = self
+            .remote_srtp_context
+            .as_mut()
+            .ok_or(Error::ErrSrtpContextNotReady)?;
+        match decrypt(remote_srtp_context) {
+            Ok(decrypted) => {
+                // The peer switched to the new keys, packets of the old epoch are stale now
+                self.previous_remote_srtp_context = None;
+                Ok(decrypted)
+            }
+            Err(err) => match self.previous_remote_srtp_context.as_mut() {
+                Some(previous_remote_srtp_context) => decrypt(previous_remote_srtp_context),
+                None => Err(err),
+            },
+        }
     }
 
     /// stop the DTLSTransport object.
     pub fn stop(&mut self) -> Result<()> {
         // Try closing everything and collect the errors
+        let result = if let Some(mut dtls_endpoint) = self.dtls_endpoint.take() {
+            let result = dtls_endpoint.close();
+            // Flush the queued CloseNotify alerts before the transport goes inactive
+            while let Some(transmit) = dtls_endpoint.poll_transmit() {
+                self.wouts.push_back(Transmit {
+                    now: transmit.now,
+                    transport: transmit.transport,
+                    message: RTCMessage::Dtls(DTLSMessage::Raw(transmit.message)),
+                });
+            }
+            result
+        } else {
+            Ok(())
+        };
+        self.srtp_epoch = None;
+        self.previous_remote_srtp_context = None;
+        self.stalled_handshake_logs.clear();
         self.state_change(RTCDtlsTransportState::Closed);
-        if let Some(mut dtls_endpoint) = self.dtls_endpoint.take() {
-            dtls_endpoint.close()?;
-        }
-        Ok(())
+        result
     }
 
     pub(crate) fn validate_fingerprint(&self, remote_cert: &[u8]) -> Result<()> {
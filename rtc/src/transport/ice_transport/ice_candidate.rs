@@ -72,6 +72,7 @@ impl RTCIceCandidate {
                         component: self.component,
                         foundation: self.foundation.clone(),
                         priority: self.priority,
+                        ..Default::default()
                     },
                     ..Default::default()
                 };
@@ -87,6 +88,7 @@ impl RTCIceCandidate {
                         component: self.component,
                         foundation: self.foundation.clone(),
                         priority: self.priority,
+                        ..Default::default()
                     },
                     rel_addr: self.related_address.clone(),
                     rel_port: self.related_port,
@@ -103,6 +105,7 @@ impl RTCIceCandidate {
                         component: self.component,
                         foundation: self.foundation.clone(),
                         priority: self.priority,
+                        ..Default::default()
                     },
                     rel_addr: self.related_address.clone(),
                     rel_port: self.related_port,
@@ -119,6 +122,7 @@ impl RTCIceCandidate {
                         component: self.component,
                         foundation: self.foundation.clone(),
                         priority: self.priority,
+                        ..Default::default()
                     },
                     rel_addr: self.related_address.clone(),
                     rel_port: self.related_port,
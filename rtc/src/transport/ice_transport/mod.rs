@@ -188,7 +188,7 @@ impl RTCIceTransport {
                 .clone(),
             self.gatherer.setting_engine.candidates.password.clone(),
         );
-        self.gatherer.agent.restart(ufrag, pwd, false)?;
+        self.gatherer.agent.restart(ufrag, pwd, false, false)?;
 
         //TODO: self.gatherer.gather()
         Ok(())